@@ -61,6 +61,39 @@ fn bench_eval_expr_2(c: &mut Criterion) {
     }));
 }
 
+fn bench_interpreter_recursion(c: &mut Criterion) {
+    let mut runtime = Runtime::new();
+    let function = "fib num = case num of \n 0 -> 0 \n 1 -> 1 \n _ -> fib (num - 1) + fib (num - 2)";
+
+    runtime.eval_statement(function).unwrap();
+
+    c.bench_function("interpreter_fib_15", move |b| b.iter(|| {
+        runtime.eval_expr("fib 15").expect("Test error")
+    }));
+}
+
+fn bench_interpreter_loop(c: &mut Criterion) {
+    let mut runtime = Runtime::new();
+    let function = "count n acc = if n == 0 then acc else count (n - 1) (acc + 1)";
+
+    runtime.eval_statement(function).unwrap();
+
+    c.bench_function("interpreter_loop_10000", move |b| b.iter(|| {
+        runtime.eval_expr("count 10000 0").expect("Test error")
+    }));
+}
+
+fn bench_interpreter_closures(c: &mut Criterion) {
+    let mut runtime = Runtime::new();
+    let function = "sumWith n = List.foldl (\\x acc -> x + acc + n) 0 [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]";
+
+    runtime.eval_statement(function).unwrap();
+
+    c.bench_function("interpreter_closures", move |b| b.iter(|| {
+        runtime.eval_expr("sumWith 0").expect("Test error")
+    }));
+}
+
 fn bench_runtime_init(c: &mut Criterion) {
     c.bench_function("runtime_init_1", move |b| b.iter(|| {
         Runtime::new()
@@ -71,5 +104,6 @@ criterion_group!(tokenizer_benches, bench_tokenize_small_file, bench_tokenize_me
 criterion_group!(parser_benches, bench_parser_small_file, bench_parser_medium_file);
 criterion_group!(eval_benches, bench_eval_expr_1, bench_eval_expr_2); // fails
 criterion_group!(init_benches, bench_runtime_init); // takes 500 seg to run
+criterion_group!(interpreter_benches, bench_interpreter_recursion, bench_interpreter_loop, bench_interpreter_closures);
 
-criterion_main!(tokenizer_benches, parser_benches, init_benches);
\ No newline at end of file
+criterion_main!(tokenizer_benches, parser_benches, init_benches, interpreter_benches);
\ No newline at end of file
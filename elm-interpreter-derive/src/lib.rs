@@ -0,0 +1,277 @@
+//! Implementation of `#[derive(ElmValue)]`, structs with named fields become Elm records
+//! (`type alias`) and enums or tuple structs become Elm custom types (`type`)
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Fields;
+use syn::Ident;
+
+#[proc_macro_derive(ElmValue)]
+pub fn derive_elm_value(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    let result = if !input.generics.params.is_empty() {
+        Err(syn::Error::new_spanned(&input.generics, "ElmValue cannot be derived for generic types"))
+    } else {
+        match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(_) => Ok(derive_record(&input.ident, &data.fields)),
+                _ => {
+                    let name = &input.ident;
+                    Ok(derive_custom_type(name, &[Variant { name: name.clone(), fields: data.fields.clone(), path: quote!(#name) }]))
+                }
+            },
+            Data::Enum(data) => {
+                let name = &input.ident;
+                let variants = data.variants.iter()
+                    .map(|variant| {
+                        let variant_name = &variant.ident;
+                        Variant { name: variant.ident.clone(), fields: variant.fields.clone(), path: quote!(#name::#variant_name) }
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(derive_custom_type(name, &variants))
+            }
+            Data::Union(_) => Err(syn::Error::new_spanned(&input.ident, "ElmValue cannot be derived for unions")),
+        }
+    };
+
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+struct Variant {
+    name: Ident,
+    fields: Fields,
+    /// Path used to build and match the values, `Enum::Variant` or `Struct`
+    path: TokenStream2,
+}
+
+/// Struct with named fields, `struct Point { x: Int }` becomes `type alias Point = { x : Int }`
+fn derive_record(name: &Ident, fields: &Fields) -> TokenStream2 {
+    let elm_name = name.to_string();
+    let idents = fields.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>();
+    let elm_fields = idents.iter().map(|ident| camel_case(&ident.to_string())).collect::<Vec<_>>();
+    let types = fields.iter().map(|field| field.ty.clone()).collect::<Vec<_>>();
+
+    quote! {
+        impl ::elm_interpreter::rust_interop::elm_value::ElmValue for #name {
+            fn elm_type() -> ::elm_interpreter::ast::Type {
+                ::elm_interpreter::ast::Type::Tag(#elm_name.to_string(), vec![])
+            }
+
+            fn elm_declarations() -> Vec<String> {
+                ::elm_interpreter::rust_interop::elm_value::declarations_of(#elm_name, || {
+                    let record = ::elm_interpreter::ast::Type::Record(vec![
+                        #((#elm_fields.to_string(), <#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::elm_type()),)*
+                    ]);
+
+                    ::elm_interpreter::rust_interop::elm_value::merge_declarations(vec![
+                        #(<#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::elm_declarations(),)*
+                        vec![format!("type alias {} = {}", #elm_name, ::elm_interpreter::rust_interop::elm_value::type_source(&record))],
+                    ])
+                })
+            }
+
+            fn to_elm(&self) -> ::elm_interpreter::types::Value {
+                ::elm_interpreter::rust_interop::elm_value::record_value(vec![
+                    #((#elm_fields, ::elm_interpreter::rust_interop::elm_value::ElmValue::to_elm(&self.#idents)),)*
+                ])
+            }
+
+            fn from_elm(value: &::elm_interpreter::types::Value) -> Result<Self, ::elm_interpreter::errors::ElmError> {
+                Ok(#name {
+                    #(#idents: <#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::from_elm(
+                        ::elm_interpreter::rust_interop::elm_value::record_field(value, #elm_fields)?
+                    )?,)*
+                })
+            }
+        }
+    }
+}
+
+/// Enums and tuple structs, variants with named fields have a record as the only value
+fn derive_custom_type(name: &Ident, variants: &[Variant]) -> TokenStream2 {
+    let elm_name = name.to_string();
+    let variant_names = variants.iter().map(|variant| variant.name.to_string()).collect::<Vec<_>>();
+
+    let variant_types = variants.iter()
+        .map(|variant| {
+            let types = variant.fields.iter().map(|field| field.ty.clone()).collect::<Vec<_>>();
+
+            match &variant.fields {
+                Fields::Named(_) => {
+                    let elm_fields = variant.fields.iter()
+                        .map(|field| camel_case(&field.ident.as_ref().unwrap().to_string()));
+
+                    quote! {
+                        vec![::elm_interpreter::ast::Type::Record(vec![
+                            #((#elm_fields.to_string(), <#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::elm_type()),)*
+                        ])]
+                    }
+                }
+                _ => quote! {
+                    vec![#(<#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::elm_type(),)*]
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let field_types = variants.iter()
+        .flat_map(|variant| variant.fields.iter().map(|field| field.ty.clone()))
+        .collect::<Vec<_>>();
+
+    let to_elm_arms = variants.iter().map(to_elm_arm).collect::<Vec<_>>();
+    let from_elm_arms = variants.iter().map(from_elm_arm).collect::<Vec<_>>();
+
+    quote! {
+        impl ::elm_interpreter::rust_interop::elm_value::ElmValue for #name {
+            fn elm_type() -> ::elm_interpreter::ast::Type {
+                ::elm_interpreter::ast::Type::Tag(#elm_name.to_string(), vec![])
+            }
+
+            fn elm_declarations() -> Vec<String> {
+                ::elm_interpreter::rust_interop::elm_value::declarations_of(#elm_name, || {
+                    let variants: Vec<(&str, Vec<::elm_interpreter::ast::Type>)> = vec![
+                        #((#variant_names, #variant_types),)*
+                    ];
+
+                    let source = variants.iter()
+                        .map(|(name, types)| {
+                            let mut source = name.to_string();
+                            for ty in types {
+                                source.push(' ');
+                                source.push_str(&::elm_interpreter::rust_interop::elm_value::argument_source(ty));
+                            }
+                            source
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" | ");
+
+                    ::elm_interpreter::rust_interop::elm_value::merge_declarations(vec![
+                        #(<#field_types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::elm_declarations(),)*
+                        vec![format!("type {} = {}", #elm_name, source)],
+                    ])
+                })
+            }
+
+            fn to_elm(&self) -> ::elm_interpreter::types::Value {
+                static ADT: ::std::sync::OnceLock<::std::sync::Arc<::elm_interpreter::types::Adt>> = ::std::sync::OnceLock::new();
+
+                let adt = ADT.get_or_init(|| {
+                    ::elm_interpreter::rust_interop::elm_value::custom_type(#elm_name, &[], vec![
+                        #((#variant_names, #variant_types),)*
+                    ])
+                });
+
+                match self {
+                    #(#to_elm_arms)*
+                }
+            }
+
+            fn from_elm(value: &::elm_interpreter::types::Value) -> Result<Self, ::elm_interpreter::errors::ElmError> {
+                let (variant, values) = ::elm_interpreter::rust_interop::elm_value::adt_variant(value, #elm_name)?;
+
+                match variant {
+                    #(#from_elm_arms)*
+                    _ => Err(::elm_interpreter::errors::Wrappable::wrap(::elm_interpreter::errors::InterpreterError::ImpossibleConversion)),
+                }
+            }
+        }
+    }
+}
+
+fn to_elm_arm(variant: &Variant) -> TokenStream2 {
+    let path = &variant.path;
+    let elm_name = variant.name.to_string();
+
+    match &variant.fields {
+        Fields::Named(_) => {
+            let idents = variant.fields.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>();
+            let elm_fields = idents.iter().map(|ident| camel_case(&ident.to_string()));
+
+            quote! {
+                #path { #(#idents,)* } => ::elm_interpreter::rust_interop::elm_value::adt_value(adt, #elm_name, vec![
+                    ::elm_interpreter::rust_interop::elm_value::record_value(vec![
+                        #((#elm_fields, ::elm_interpreter::rust_interop::elm_value::ElmValue::to_elm(#idents)),)*
+                    ])
+                ]),
+            }
+        }
+        Fields::Unnamed(_) => {
+            let idents = (0..variant.fields.len()).map(|index| format_ident!("value{}", index)).collect::<Vec<_>>();
+
+            quote! {
+                #path(#(#idents,)*) => ::elm_interpreter::rust_interop::elm_value::adt_value(adt, #elm_name, vec![
+                    #(::elm_interpreter::rust_interop::elm_value::ElmValue::to_elm(#idents),)*
+                ]),
+            }
+        }
+        Fields::Unit => quote! {
+            #path => ::elm_interpreter::rust_interop::elm_value::adt_value(adt, #elm_name, vec![]),
+        },
+    }
+}
+
+fn from_elm_arm(variant: &Variant) -> TokenStream2 {
+    let path = &variant.path;
+    let elm_name = variant.name.to_string();
+    let types = variant.fields.iter().map(|field| field.ty.clone()).collect::<Vec<_>>();
+
+    match &variant.fields {
+        Fields::Named(_) => {
+            let idents = variant.fields.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>();
+            let elm_fields = idents.iter().map(|ident| camel_case(&ident.to_string()));
+
+            quote! {
+                #elm_name if values.len() == 1 => Ok(#path {
+                    #(#idents: <#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::from_elm(
+                        ::elm_interpreter::rust_interop::elm_value::record_field(&values[0], #elm_fields)?
+                    )?,)*
+                }),
+            }
+        }
+        Fields::Unnamed(_) => {
+            let count = types.len();
+            let indices = 0..count;
+
+            quote! {
+                #elm_name if values.len() == #count => Ok(#path(
+                    #(<#types as ::elm_interpreter::rust_interop::elm_value::ElmValue>::from_elm(&values[#indices])?,)*
+                )),
+            }
+        }
+        Fields::Unit => quote! {
+            #elm_name => Ok(#path),
+        },
+    }
+}
+
+/// Elm code uses camel case for record fields, `selected_item` becomes `selectedItem`
+fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+
+    for c in name.trim_start_matches("r#").chars() {
+        if c == '_' {
+            upper = !result.is_empty();
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
@@ -147,10 +147,9 @@ impl Analyzer {
                         map.insert(Type::Var(alias.variables[i].clone()), params[i].clone());
                     }
 
+                    // Aliases used in the replacement are already expanded
                     let sub = Substitution(map);
-                    let new_params: Vec<Type> = params.into_iter().map(|it| sub.replace(it)).collect();
-
-                    Type::Tag(a, new_params)
+                    apply_substitution_ty(&sub, &alias.replacement)
                 } else {
                     Type::Tag(a, params)
                 }
@@ -361,6 +360,7 @@ fn annotate_pattern(env: &mut Env, pat: &Pattern) -> Result<TypedPattern, TypeEr
                 *span,
                 env.next_type(),
                 adt_type,
+                name.clone(),
                 vec_map(env, items, annotate_pattern)?,
             )
         }
@@ -554,6 +554,13 @@ fn annotate_expr(env: &mut Env, expr: &Expr) -> Result<TypedExpr, TypeError> {
             let (entries, expr) = env.block(|env| {
                 let mut entries = vec![];
 
+                // Allow local definitions with type annotation to reference each other
+                for decl in decls {
+                    if let LetDeclaration::Def(Definition { header: Some(ty), name, .. }) = decl {
+                        env.set(name, ty.clone());
+                    }
+                }
+
                 for decl in decls {
                     match decl {
                         LetDeclaration::Def(def) => {
@@ -624,7 +631,7 @@ fn collect_type_definition_constraints(res: &mut Vec<Constraint>, ty: &Type, pat
 fn collect_pattern_constraints(res: &mut Vec<Constraint>, pat: &TypedPattern) {
     match pat {
         TypedPattern::Var(_, _, _) => {}
-        TypedPattern::Adt(_, ty, ctor_type, items) => {
+        TypedPattern::Adt(_, ty, ctor_type, _, items) => {
             let adt_type = unpack_types(ctor_type).into_iter().last().unwrap();
             let mut ctor = vec![];
 
@@ -685,6 +692,7 @@ fn collect_expr_constraints(res: &mut Vec<Constraint>, expr: &TypedExpr) {
     match expr {
         TypedExpr::Ref(_, _, _) => { /* ignore */ }
         TypedExpr::Const(_, _, _) => { /* ignore */ }
+        TypedExpr::Resolved(..) | TypedExpr::Closure(..) => { /* created after the analysis */ }
         TypedExpr::Tuple(_, ty, exprs) => {
             res.push(Constraint::new(expr.get_span(), ty, &Type::Tuple(exprs.map(expr_type))));
             for expr in exprs {
@@ -785,12 +793,40 @@ fn collect_expr_constraints(res: &mut Vec<Constraint>, expr: &TypedExpr) {
             collect_expr_constraints(res, a);
             collect_expr_constraints(res, b);
         }
-        TypedExpr::Let(_, _, _, expr) => {
+        TypedExpr::Let(_, _, entries, expr) => {
+            for entry in entries {
+                if let LetEntry::Pattern(pat, value) = entry {
+                    res.push(Constraint::new(value.get_span(), &pat.get_type(), &value.get_type()));
+                    collect_pattern_constraints(res, pat);
+                    collect_expr_constraints(res, value);
+                }
+            }
             collect_expr_constraints(res, expr);
         }
     }
 }
 
+/// Checks if a value of type `found` can be used where `expected` is required, the type
+/// variables of both types are unrelated
+pub fn is_assignable(expected: &Type, found: &Type) -> bool {
+    unify_one(&Constraint::new((0, 0), expected, &rename_vars(found))).is_ok()
+}
+
+/// Adds a suffix to the type variables, keeping the `number` prefix
+fn rename_vars(ty: &Type) -> Type {
+    let rename = |name: &String| if name == "_" { name.clone() } else { format!("{}_", name) };
+
+    match ty {
+        Type::Unit => Type::Unit,
+        Type::Var(name) => Type::Var(rename(name)),
+        Type::Tag(name, items) => Type::Tag(name.clone(), items.map(rename_vars)),
+        Type::Fun(a, b) => Type::Fun(Box::new(rename_vars(a)), Box::new(rename_vars(b))),
+        Type::Tuple(items) => Type::Tuple(items.map(rename_vars)),
+        Type::Record(items) => Type::Record(items.map(|(s, i)| (s.clone(), rename_vars(i)))),
+        Type::RecExt(name, items) => Type::RecExt(rename(name), items.map(|(s, i)| (s.clone(), rename_vars(i)))),
+    }
+}
+
 fn unify_constraints(constraints: &[Constraint]) -> Result<Substitution, TypeError> {
     if constraints.is_empty() {
         return Ok(Substitution::empty());
@@ -857,7 +893,11 @@ fn unify_one(constraint: &Constraint) -> Result<Substitution, TypeError> {
                 }
 
                 if !found {
-                    panic!("Missing: {:?} in {:?}", name1, param2);
+                    return Err(TypeError::TypeMatchingError {
+                        span: constraint.span,
+                        expected: constraint.left.clone(),
+                        found: constraint.right.clone(),
+                    });
                 }
             }
 
@@ -878,7 +918,11 @@ fn unify_one(constraint: &Constraint) -> Result<Substitution, TypeError> {
                 }
 
                 if !found {
-                    panic!("Missing: {:?} in {:?}", name1, param2);
+                    return Err(TypeError::TypeMatchingError {
+                        span: constraint.span,
+                        expected: constraint.left.clone(),
+                        found: constraint.right.clone(),
+                    });
                 }
             }
 
@@ -1012,12 +1056,13 @@ fn replace_pattern_types(sub: &Substitution, annotated: TypedPattern) -> TypedPa
         TypedPattern::Var(a, b, c) => {
             TypedPattern::Var(a, sub.replace(b), c)
         }
-        TypedPattern::Adt(a, b, c, d) => {
+        TypedPattern::Adt(a, b, c, d, e) => {
             TypedPattern::Adt(
                 a,
                 sub.replace(b),
                 sub.replace(c),
-                d.into_iter().map(|it| replace_pattern_types(sub, it)).collect(),
+                d,
+                e.into_iter().map(|it| replace_pattern_types(sub, it)).collect(),
             )
         }
         TypedPattern::Wildcard(a) => {
@@ -1072,10 +1117,21 @@ fn replace_pattern_types(sub: &Substitution, annotated: TypedPattern) -> TypedPa
     }
 }
 
+/// Number literals become an Int or a Float when the inferred type is known
+fn specialize_number(ty: &Type, value: Value) -> Value {
+    match (value, ty) {
+        (Value::Number(n), Type::Tag(name, _)) if name == "Int" => Value::Int(n),
+        (Value::Number(n), Type::Tag(name, _)) if name == "Float" => Value::Float(n as f32),
+        (value, _) => value,
+    }
+}
+
 fn replace_expr_types(sub: &Substitution, annotated: TypedExpr) -> TypedExpr {
     match annotated {
         TypedExpr::Const(span, ty, a) => {
-            TypedExpr::Const(span, sub.replace(ty), a)
+            let ty = sub.replace(ty);
+            let value = specialize_number(&ty, a);
+            TypedExpr::Const(span, ty, value)
         }
         TypedExpr::Tuple(span, ty, a) => {
             TypedExpr::Tuple(
@@ -1109,6 +1165,10 @@ fn replace_expr_types(sub: &Substitution, annotated: TypedExpr) -> TypedExpr {
         TypedExpr::Ref(span, ty, a) => {
             TypedExpr::Ref(span, sub.replace(ty), a)
         }
+        TypedExpr::Resolved(span, ty, a, b) => {
+            TypedExpr::Resolved(span, sub.replace(ty), a, b)
+        }
+        expr @ TypedExpr::Closure(..) => expr,
         TypedExpr::RecordField(span, ty, a, b) => {
             TypedExpr::RecordField(span, sub.replace(ty), Box::new(replace_expr_types(sub, *a)), b)
         }
@@ -1129,14 +1189,14 @@ fn replace_expr_types(sub: &Substitution, annotated: TypedExpr) -> TypedExpr {
                 span,
                 sub.replace(ty),
                 Box::new(replace_expr_types(sub, *a)),
-                b.into_iter().map(|(s, a)| (s, replace_expr_types(sub, a))).to_vec(),
+                b.into_iter().map(|(s, a)| (replace_pattern_types(sub, s), replace_expr_types(sub, a))).to_vec(),
             )
         }
         TypedExpr::Lambda(span, ty, a, b) => {
             TypedExpr::Lambda(
                 span,
                 sub.replace(ty),
-                a,
+                a.into_iter().map(|it| replace_pattern_types(sub, it)).collect(),
                 Box::new(replace_expr_types(sub, *b)),
             )
         }
@@ -1152,7 +1212,12 @@ fn replace_expr_types(sub: &Substitution, annotated: TypedExpr) -> TypedExpr {
             TypedExpr::Let(
                 span,
                 sub.replace(ty),
-                a,
+                a.into_iter().map(|entry| match entry {
+                    LetEntry::Pattern(pat, expr) => {
+                        LetEntry::Pattern(replace_pattern_types(sub, pat), replace_expr_types(sub, expr))
+                    }
+                    def => def
+                }).to_vec(),
                 Box::new(replace_expr_types(sub, *b)))
         }
     }
@@ -1163,7 +1228,7 @@ fn add_pattern_vars_to_env(env: &mut Env, pat: &TypedPattern) {
         TypedPattern::Var(_, ty, name) => {
             env.set(name, ty.clone());
         }
-        TypedPattern::Adt(_, _, _, items) => {
+        TypedPattern::Adt(_, _, _, _, items) => {
             items.for_each(|it| add_pattern_vars_to_env(env, it));
         }
         TypedPattern::Wildcard(_) => {}
@@ -1312,6 +1377,15 @@ mod tests {
         }), typed_expr);
     }
 
+    #[test]
+    fn test_is_assignable() {
+        assert!(is_assignable(&type_of("Maybe Int"), &type_of("Maybe a")));
+        assert!(is_assignable(&type_of("number"), &type_of("Float")));
+        assert!(is_assignable(&type_of("(a, List a)"), &type_of("(Int, List a)")));
+        assert!(!is_assignable(&type_of("Int"), &type_of("String")));
+        assert!(!is_assignable(&type_of("{ x : Int }"), &type_of("{ y : Int }")));
+    }
+
     #[test]
     fn test_infer_type_of_duplicated_vars() {
         let expr = Test::definition("a = ((+), (+))");
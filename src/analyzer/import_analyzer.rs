@@ -6,7 +6,6 @@ use ast::Exposing;
 use ast::Import;
 use ast::ModuleExposing;
 use ast::Statement;
-use ast::Type;
 use constructors::type_unary_minus;
 use errors::ElmError;
 use errors::InterpreterError;
@@ -68,6 +67,24 @@ impl Analyzer {
             exposing: None,
         };
 
+        let platform = Import {
+            path: vec!["Platform".to_string()],
+            alias: None,
+            exposing: Some(ModuleExposing::Just(vec![Exposing::Type("Program".to_string())])),
+        };
+
+        let cmd = Import {
+            path: vec!["Platform".to_string(), "Cmd".to_string()],
+            alias: Some("Cmd".to_string()),
+            exposing: Some(ModuleExposing::Just(vec![Exposing::Type("Cmd".to_string())])),
+        };
+
+        let sub = Import {
+            path: vec!["Platform".to_string(), "Sub".to_string()],
+            alias: Some("Sub".to_string()),
+            exposing: Some(ModuleExposing::Just(vec![Exposing::Type("Sub".to_string())])),
+        };
+
         self.analyze_import(modules, &mut module_imports, &basic)?;
         self.analyze_import(modules, &mut module_imports, &list)?;
         self.analyze_import(modules, &mut module_imports, &maybe)?;
@@ -76,6 +93,9 @@ impl Analyzer {
         self.analyze_import(modules, &mut module_imports, &char_)?;
         self.analyze_import(modules, &mut module_imports, &tuple)?;
         self.analyze_import(modules, &mut module_imports, &debug)?;
+        self.analyze_import(modules, &mut module_imports, &platform)?;
+        self.analyze_import(modules, &mut module_imports, &cmd)?;
+        self.analyze_import(modules, &mut module_imports, &sub)?;
 
         Ok(module_imports)
     }
@@ -182,11 +202,11 @@ impl Analyzer {
     pub fn analyze_module_declarations(&mut self, statements: &Vec<Statement>) -> Result<Vec<Declaration>, Vec<ElmError>> {
         let mut statements = statements.iter().collect::<Vec<_>>();
 
-        // Sort by type
+        // Sort by type, aliases are expanded when used, so custom types can reference them
         statements.sort_by_key(|stm| {
             match *stm {
-                Statement::Adt(_, _, _) => 1,
-                Statement::Alias(_, _, _) => 2,
+                Statement::Alias(_, _, _) => 1,
+                Statement::Adt(_, _, _) => 2,
                 Statement::Port(_, _, _) => 3,
                 Statement::Infix(_, _, _, _) => 4,
                 Statement::Def(_) => 5,
@@ -263,39 +283,6 @@ impl Analyzer {
         for exp in exposed.iter() {
             match exp {
                 Exposing::Adt(name, adt_exp) => {
-                    match adt_exp {
-                        AdtExposing::Variants(variants) => {
-                            for it in all_decls.iter() {
-                                let (variant_name, ty) = match it {
-                                    Declaration::Definition(variant_name, def) => (variant_name, &def.header),
-                                    Declaration::Port(variant_name, ty) => (variant_name, ty),
-                                    _ => continue
-                                };
-                                if variants.contains(variant_name) {
-                                    if let Type::Tag(tag_name, _) = Self::get_fun_return(ty) {
-                                        if &tag_name == name {
-                                            exposed_decls.push(it.clone());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        AdtExposing::All => {
-                            for it in all_decls.iter() {
-                                let ty = match it {
-                                    Declaration::Definition(_, def) => &def.header,
-                                    Declaration::Port(_, ty) => ty,
-                                    _ => continue
-                                };
-                                if let Type::Tag(tag_name, _) = Self::get_fun_return(ty) {
-                                    if &tag_name == name {
-                                        exposed_decls.push(it.clone());
-                                    }
-                                }
-                            }
-                        }
-                    }
-
                     let decl = all_decls.iter()
                         .find(|decl| {
                             if let Declaration::Adt(adt_name, _) = decl {
@@ -307,6 +294,25 @@ impl Analyzer {
                         .map(|decl| decl.clone())
                         .ok_or_else(|| InterpreterError::MissingExposing(name.clone(), all_decls.clone()))?;
 
+                    // Constructors are ports named after the variants of the type
+                    if let Declaration::Adt(_, adt) = &decl {
+                        for it in all_decls.iter() {
+                            let variant_name = match it {
+                                Declaration::Port(variant_name, _) => variant_name,
+                                _ => continue
+                            };
+
+                            let exposed = match adt_exp {
+                                AdtExposing::Variants(variants) => variants.contains(variant_name),
+                                AdtExposing::All => true,
+                            };
+
+                            if exposed && adt.variants.iter().any(|variant| &variant.name == variant_name) {
+                                exposed_decls.push(it.clone());
+                            }
+                        }
+                    }
+
                     exposed_decls.push(decl);
                 }
                 Exposing::Type(name) => {
@@ -359,12 +365,52 @@ impl Analyzer {
 
         Ok(exposed_decls)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use constructors::type_of;
+    use types::Adt;
+    use types::AdtVariant;
+
+    use super::*;
+
+    fn maybe_declarations() -> Vec<Declaration> {
+        let adt = Adt {
+            name: "Maybe".to_string(),
+            types: vec!["a".to_string()],
+            variants: vec![
+                AdtVariant { name: "Just".to_string(), types: vec![type_of("a")] },
+                AdtVariant { name: "Nothing".to_string(), types: vec![] },
+            ],
+        };
+
+        vec![
+            Declaration::Adt("Maybe".to_string(), Arc::new(adt)),
+            Declaration::Port("Just".to_string(), type_of("a -> Maybe a")),
+            Declaration::Port("Nothing".to_string(), type_of("Maybe a")),
+            Declaration::Port("andThen".to_string(), type_of("(a -> Maybe b) -> Maybe a -> Maybe b")),
+        ]
+    }
 
-    fn get_fun_return(ty: &Type) -> Type {
-        if let Type::Fun(_, ty) = ty {
-            Self::get_fun_return(&*ty)
-        } else {
-            ty.clone()
-        }
+    fn exposed_names(exposing: Exposing) -> Vec<String> {
+        Analyzer::get_exposed_decls(&maybe_declarations(), &vec![exposing]).unwrap()
+            .iter()
+            .map(|decl| declaration_name(decl).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn check_adt_exposing_only_variants() {
+        assert_eq!(
+            exposed_names(Exposing::Adt("Maybe".to_string(), AdtExposing::All)),
+            vec!["Just", "Nothing", "Maybe"]
+        );
+        assert_eq!(
+            exposed_names(Exposing::Adt("Maybe".to_string(), AdtExposing::Variants(vec!["Just".to_string()]))),
+            vec!["Just", "Maybe"]
+        );
     }
-}
\ No newline at end of file
+}
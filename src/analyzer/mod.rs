@@ -15,6 +15,8 @@ mod import_analyzer;
 mod definition_analyzer;
 mod env;
 
+pub use analyzer::definition_analyzer::is_assignable;
+
 #[derive(Debug)]
 pub struct Analyzer {
     pub e: Env,
@@ -42,6 +44,11 @@ impl Analyzer {
         self.e.set_canonical_type_name(name, canonical.to_string());
     }
 
+    /// Declares a type without constructors, values of this type can only be created by the host
+    pub fn add_opaque_type(&mut self, name: &str) {
+        self.add_canonical_type_name(name, name);
+    }
+
     pub fn analyze_statement(&mut self, stm: &Statement) -> Result<Vec<Declaration>, ElmError> {
         let decls = match stm {
             Statement::Alias(name, vars, ty) => {
@@ -76,6 +83,25 @@ impl Analyzer {
         Ok(decls)
     }
 
+    /// Registers the type aliases and the declared types of the definitions before analyzing
+    /// the module, to avoid problems with statement order
+    pub fn declare_module_types(&mut self, statements: &Vec<Statement>) {
+        // Type aliases can be used in the headers of the definitions
+        for stm in statements {
+            if let Statement::Alias(name, vars, ty) = stm {
+                let alias = self.type_alias(name, vars, ty);
+                self.add_type_alias(alias);
+            }
+        }
+
+        for stm in statements {
+            if let Some(ty) = declared_statement_type(stm) {
+                let ty = self.replace_type_alias(ty.clone());
+                self.e.set(declared_statement_name(stm).unwrap(), ty);
+            }
+        }
+    }
+
     pub fn analyze_module(&mut self, modules: &HashMap<String, AnalyzedModule>, module: &LoadedModule)
                           -> Result<AnalyzedModule, ElmError> {
         let imports = if ELM_CORE_MODULES.contains(&module.src.name.as_str()) {
@@ -86,12 +112,7 @@ impl Analyzer {
             imports
         };
 
-        // Avoid problems with statement order
-        for stm in &module.ast.statements {
-            if let Some(ty) = declared_statement_type(stm) {
-                self.e.set(declared_statement_name(stm).unwrap(), ty.clone());
-            }
-        }
+        self.declare_module_types(&module.ast.statements);
 
         // Custom behaviour for binary operators
         for stm in &module.ast.statements {
@@ -187,9 +208,18 @@ pub fn type_of_value(value: &Value) -> Type {
             if items.is_empty() {
                 Type::Tag("List".to_string(), vec![Type::Var("a".to_string())])
             } else {
-                Type::Tag("List".to_string(), vec![type_of_value(items.first().unwrap())])
+                Type::Tag("List".to_string(), vec![type_of_value(items.head().unwrap())])
             }
         }
+        Value::Array(items) => {
+            match items.front() {
+                Some(first) => Type::Tag("Array".to_string(), vec![type_of_value(first)]),
+                None => Type::Tag("Array".to_string(), vec![Type::Var("a".to_string())]),
+            }
+        }
+        Value::Json(_) => {
+            Type::Tag("Value".to_string(), vec![])
+        }
         Value::Tuple(items) => {
             Type::Tuple(items.iter().map(|i| type_of_value(i)).collect())
         }
@@ -206,6 +236,9 @@ pub fn type_of_value(value: &Value) -> Type {
         Value::Fun { fun, args, .. } => {
             strip_fun_args(args.len(), &fun.get_type()).clone()
         }
+        Value::Opaque(opaque) => {
+            Type::Tag(opaque.type_name.clone(), vec![])
+        }
     }
 }
 
@@ -225,6 +258,7 @@ fn strip_fun_args(args: usize, ty: &Type) -> &Type {
 mod tests {
     use ast::Statement;
     use constructors::type_of;
+    use loader::{declaration_name, declaration_type};
     use test_utils::Test;
     use util::{build_fun_type, StringConversion};
 
@@ -332,6 +366,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_replace_nested_type_alias() {
+        let mut analyzer = Analyzer::new(SourceCode::from_str("type alias Pair a = ( a, a )"));
+        analyzer.add_type_alias(TypeAlias {
+            name: "Pair".s(),
+            variables: vec!["a".s()],
+            replacement: type_of("( a, a )"),
+        });
+
+        assert_eq!(
+            analyzer.replace_type_alias(type_of("List (Pair Int)")),
+            type_of("List ( Int, Int )"),
+        );
+    }
+
+    #[test]
+    fn check_adt_before_type_alias() {
+        let module = Test::module("module Shapes exposing (..)\n\n\
+                                   type Shape = Dot Point\n\n\
+                                   type alias Point = ( (), () )\n");
+        let mut analyzer = Analyzer::new(SourceCode::from_str("module Shapes exposing (..)"));
+
+        let decls = analyzer.analyze_module_declarations(&module.statements).unwrap();
+        let dot = decls.iter()
+            .find(|decl| declaration_name(decl) == "Dot")
+            .and_then(|decl| declaration_type(decl).cloned());
+
+        assert_eq!(dot, Some(type_of("( (), () ) -> Shape")));
+    }
+
+    #[test]
+    fn check_type_alias_in_header() {
+        let module = Test::module("module Shapes exposing (..)\n\n\
+                                   origin : Point\n\
+                                   origin = ( (), () )\n\n\
+                                   type alias Point = ( (), () )\n");
+        let mut analyzer = Analyzer::new(SourceCode::from_str("module Shapes exposing (..)"));
+        analyzer.declare_module_types(&module.statements);
+
+        assert_eq!(analyzer.e.get("origin"), Some(&type_of("( (), () )")));
+    }
+
 //    #[test]
 //    fn analyze_patterns_1() {
 //        analyze_pattern_test(
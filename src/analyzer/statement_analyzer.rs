@@ -50,11 +50,7 @@ impl Analyzer {
         }
 
         let mut decls: Vec<Declaration> = vec![
-            Declaration::Alias(TypeAlias {
-                name: name.to_string(),
-                variables: decl_vars.clone(),
-                replacement: ty.clone(),
-            })
+            Declaration::Alias(self.type_alias(name, decl_vars, ty))
         ];
 
         // If the type alias is for an record, a auxiliary constructor function is created
@@ -71,6 +67,20 @@ impl Analyzer {
         Ok(decls)
     }
 
+    /// Creates a type alias, other aliases in the replacement are expanded and the type names
+    /// are made canonical if they are already known, so the alias doesn't depend on the imports
+    /// of the module that uses it
+    pub fn type_alias(&self, name: &str, decl_vars: &Vec<String>, ty: &Type) -> TypeAlias {
+        let replacement = self.check_type((0, 0), ty.clone())
+            .unwrap_or_else(|_| self.replace_type_alias(ty.clone()));
+
+        TypeAlias {
+            name: name.to_string(),
+            variables: decl_vars.clone(),
+            replacement,
+        }
+    }
+
     pub fn analyze_statement_adt(&mut self, name: &String, decl_vars: &Vec<String>, variants: &Vec<(Span, String, Vec<Type>)>) -> Result<Vec<Declaration>, ElmError> {
         let mut decls = vec![];
         let vars: Vec<Type> = decl_vars.iter()
@@ -147,7 +157,10 @@ impl Analyzer {
     }
 
     pub fn analyze_statement_definition(&mut self, def: &Definition) -> Result<Vec<Declaration>, ElmError> {
-        let typed_def = self.analyze_definition(def)?;
+        let mut def = def.clone();
+        def.header = def.header.map(|ty| self.replace_type_alias(ty));
+
+        let typed_def = self.analyze_definition(&def)?;
         Ok(vec![
             Declaration::Definition(def.name.clone(), typed_def)
         ])
@@ -0,0 +1,21 @@
+extern crate elm_interpreter;
+
+use std::io::stdin;
+use std::io::stdout;
+use std::process::exit;
+
+use elm_interpreter::lsp;
+
+/// Language server for Elm, the client talks to it using the standard input and output
+fn main() {
+    let input = stdin();
+    let output = stdout();
+
+    match lsp::run(input.lock(), output.lock()) {
+        Ok(code) => exit(code),
+        Err(error) => {
+            eprintln!("elm-ls: {}", error);
+            exit(1);
+        }
+    }
+}
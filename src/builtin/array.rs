@@ -0,0 +1,222 @@
+use im::Vector;
+
+use ast::Int;
+use ast::Type;
+use builtin::func_of;
+use errors::ElmError;
+use interpreter::Interpreter;
+use rust_interop::conversions::array_of;
+use rust_interop::conversions::bool_of;
+use rust_interop::conversions::int_of;
+use rust_interop::conversions::list_of;
+use types::Value;
+
+pub fn get_array_funs() -> Vec<(&'static str, Type, Value)> {
+    vec![
+        func_of("initialize", "Int -> (Int -> a) -> Array a", initialize),
+        func_of("repeat", "Int -> a -> Array a", repeat),
+        func_of("fromList", "List a -> Array a", from_list),
+        func_of("toList", "Array a -> List a", to_list),
+        func_of("toIndexedList", "Array a -> List (Int, a)", to_indexed_list),
+        func_of("isEmpty", "Array a -> Bool", is_empty),
+        func_of("length", "Array a -> Int", length),
+        func_of("get", "Int -> Array a -> Maybe a", get),
+        func_of("set", "Int -> a -> Array a -> Array a", set),
+        func_of("push", "a -> Array a -> Array a", push),
+        func_of("append", "Array a -> Array a -> Array a", append),
+        func_of("slice", "Int -> Int -> Array a -> Array a", slice),
+        func_of("map", "(a -> b) -> Array a -> Array b", map),
+        func_of("indexedMap", "(Int -> a -> b) -> Array a -> Array b", indexed_map),
+        func_of("foldl", "(a -> b -> b) -> b -> Array a -> b", foldl),
+        func_of("foldr", "(a -> b -> b) -> b -> Array a -> b", foldr),
+        func_of("filter", "(a -> Bool) -> Array a -> Array a", filter),
+    ]
+}
+
+fn initialize(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let len = array_length(i, &args[0])?;
+    let func = &args[1];
+    let mut result = Vector::new();
+
+    for index in 0..len as Int {
+        result.push_back(i.apply_function(func.clone(), &[Value::Int(index)])?);
+    }
+
+    Ok(Value::Array(result))
+}
+
+fn repeat(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let len = array_length(i, &args[0])?;
+    let result = (0..len).map(|_| args[1].clone()).collect();
+
+    Ok(Value::Array(result))
+}
+
+/// Length of a new array, checked before the elements are created. Like in Elm, negative
+/// lengths create an empty array
+fn array_length(i: &Interpreter, len: &Value) -> Result<usize, ElmError> {
+    let len = int_of(len)?.max(0) as usize;
+    i.check_list_size(len)?;
+    Ok(len)
+}
+
+fn from_list(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let list = list_of(&args[0])?;
+
+    Ok(Value::Array(list.iter().cloned().collect()))
+}
+
+fn to_list(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[0])?;
+
+    Ok(Value::List(array.iter().cloned().collect()))
+}
+
+fn to_indexed_list(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[0])?;
+    let result = array.iter()
+        .enumerate()
+        .map(|(index, value)| Value::Tuple(vec![Value::Int(index as i32), value.clone()]))
+        .collect();
+
+    Ok(Value::List(result))
+}
+
+fn is_empty(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[0])?;
+
+    if array.is_empty() {
+        Ok(i.true_value())
+    } else {
+        Ok(i.false_value())
+    }
+}
+
+fn length(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[0])?;
+
+    Ok(Value::Int(array.len() as i32))
+}
+
+fn get(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let index = int_of(&args[0])?;
+    let array = array_of(&args[1])?;
+
+    match index_in(index, array) {
+        Some(index) => i.adt_value("Just", &[array[index].clone()]),
+        None => i.adt_value("Nothing", &[]),
+    }
+}
+
+fn set(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let index = int_of(&args[0])?;
+    let array = array_of(&args[2])?;
+
+    // Out of range indices return the array unchanged
+    match index_in(index, array) {
+        Some(index) => Ok(Value::Array(array.update(index, args[1].clone()))),
+        None => Ok(args[2].clone()),
+    }
+}
+
+fn push(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let mut array = array_of(&args[1])?.clone();
+    array.push_back(args[0].clone());
+
+    Ok(Value::Array(array))
+}
+
+fn append(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let mut array = array_of(&args[0])?.clone();
+    array.append(array_of(&args[1])?.clone());
+
+    Ok(Value::Array(array))
+}
+
+fn slice(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[2])?;
+    let start = slice_index(int_of(&args[0])?, array.len());
+    let end = slice_index(int_of(&args[1])?, array.len());
+
+    if start >= end {
+        return Ok(Value::Array(Vector::new()));
+    }
+
+    Ok(Value::Array(array.clone().slice(start..end)))
+}
+
+fn map(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let func = &args[0];
+    let array = array_of(&args[1])?;
+    let mut result = Vector::new();
+
+    for value in array {
+        result.push_back(i.apply_function(func.clone(), &[value.clone()])?);
+    }
+
+    Ok(Value::Array(result))
+}
+
+fn indexed_map(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let func = &args[0];
+    let array = array_of(&args[1])?;
+    let mut result = Vector::new();
+
+    for (index, value) in array.iter().enumerate() {
+        result.push_back(i.apply_function(func.clone(), &[Value::Int(index as i32), value.clone()])?);
+    }
+
+    Ok(Value::Array(result))
+}
+
+fn foldl(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let func = &args[0];
+    let mut result = args[1].clone();
+
+    for value in array_of(&args[2])? {
+        result = i.apply_function(func.clone(), &[value.clone(), result])?;
+    }
+
+    Ok(result)
+}
+
+fn foldr(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let func = &args[0];
+    let mut result = args[1].clone();
+
+    for value in array_of(&args[2])?.iter().rev() {
+        result = i.apply_function(func.clone(), &[value.clone(), result])?;
+    }
+
+    Ok(result)
+}
+
+fn filter(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let func = &args[0];
+    let mut result = Vector::new();
+
+    for value in array_of(&args[1])? {
+        if bool_of(&i.apply_function(func.clone(), &[value.clone()])?)? {
+            result.push_back(value.clone());
+        }
+    }
+
+    Ok(Value::Array(result))
+}
+
+fn index_in(index: i32, array: &Vector<Value>) -> Option<usize> {
+    if index >= 0 && (index as usize) < array.len() {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+/// Negative indices count from the end of the array, like in elm/core
+fn slice_index(index: i32, len: usize) -> usize {
+    if index < 0 {
+        (len as i32 + index).max(0) as usize
+    } else {
+        (index as usize).min(len)
+    }
+}
@@ -0,0 +1,516 @@
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Number;
+use serde_json::ser::PrettyFormatter;
+use serde_json::Serializer;
+use serde_json::Value as JsonValue;
+
+use ast::Type;
+use builtin::func_of;
+use constructors::type_of;
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use interpreter::Interpreter;
+use rust_interop::conversions::bool_of;
+use rust_interop::conversions::float_of;
+use rust_interop::conversions::int_of;
+use rust_interop::conversions::json_of;
+use rust_interop::conversions::list_of;
+use rust_interop::conversions::str_of;
+use types::Adt;
+use types::AdtVariant;
+use types::ElmFn;
+use types::Value;
+
+pub fn get_json_funs() -> Vec<(&'static str, Type, Value)> {
+    vec![
+        // Decoders
+        decoder_of("string", "Decoder String", decode_string),
+        decoder_of("bool", "Decoder Bool", decode_bool),
+        decoder_of("int", "Decoder Int", decode_int),
+        decoder_of("float", "Decoder Float", decode_float),
+        decoder_of("value", "Decoder Value", decode_value),
+        decoder_of("null", "a -> Decoder a", decode_null),
+        decoder_of("list", "Decoder a -> Decoder (List a)", decode_list),
+        decoder_of("array", "Decoder a -> Decoder (Array a)", decode_array),
+        decoder_of("field", "String -> Decoder a -> Decoder a", decode_field),
+        decoder_of("index", "Int -> Decoder a -> Decoder a", decode_index),
+        decoder_of("keyValuePairs", "Decoder a -> Decoder (List (String, a))", decode_key_value_pairs),
+        decoder_of("succeed", "a -> Decoder a", succeed),
+        decoder_of("fail", "String -> Decoder a", fail),
+        decoder_of("map", "(a -> value) -> Decoder a -> Decoder value", map_n),
+        decoder_of("map2", "(a -> b -> value) -> Decoder a -> Decoder b -> Decoder value", map_n),
+        decoder_of("map3", "(a -> b -> c -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder value", map_n),
+        decoder_of("map4", "(a -> b -> c -> d -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder d -> Decoder value", map_n),
+        decoder_of("map5", "(a -> b -> c -> d -> e -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder d -> Decoder e -> Decoder value", map_n),
+        decoder_of("map6", "(a -> b -> c -> d -> e -> f -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder d -> Decoder e -> Decoder f -> Decoder value", map_n),
+        decoder_of("map7", "(a -> b -> c -> d -> e -> f -> g -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder d -> Decoder e -> Decoder f -> Decoder g -> Decoder value", map_n),
+        decoder_of("map8", "(a -> b -> c -> d -> e -> f -> g -> h -> value) -> Decoder a -> Decoder b -> Decoder c -> Decoder d -> Decoder e -> Decoder f -> Decoder g -> Decoder h -> Decoder value", map_n),
+        decoder_of("andThen", "(a -> Decoder b) -> Decoder a -> Decoder b", and_then),
+        decoder_of("oneOf", "List (Decoder a) -> Decoder a", one_of),
+        func_of("run", "Decoder a -> Value -> Result Error a", run),
+        func_of("runOnString", "Decoder a -> String -> Result Error a", run_on_string),
+        func_of("errorToString", "Error -> String", error_to_string),
+        // Encoders
+        func_of("encode", "Int -> Value -> String", encode),
+        func_of("encodeString", "String -> Value", encode_string),
+        func_of("encodeInt", "Int -> Value", encode_int),
+        func_of("encodeFloat", "Float -> Value", encode_float),
+        func_of("encodeBool", "Bool -> Value", encode_bool),
+        func_of("encodeNull", "() -> Value", encode_null),
+        func_of("encodeList", "List Value -> Value", encode_list),
+        func_of("encodeObject", "List (String, Value) -> Value", encode_object),
+    ]
+}
+
+/// Decoders are functions waiting for the JSON value to decode, so the function takes one
+/// argument more than its type shows
+fn decoder_of(name: &'static str, ty: &'static str, fun: ElmFn) -> (&'static str, Type, Value) {
+    match func_of(name, ty, fun) {
+        (name, ty, Value::Fun { arg_count, args, fun }) => {
+            (name, ty, Value::Fun { arg_count: arg_count + 1, args, fun })
+        }
+        _ => unreachable!()
+    }
+}
+
+fn decode_string(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match json_of(&args[0])?.as_str() {
+        Some(string) => ok(i, Value::String(string.to_string())),
+        None => failure(i, "Expecting a STRING", &args[0]),
+    }
+}
+
+fn decode_bool(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match json_of(&args[0])?.as_bool() {
+        Some(true) => {
+            let value = i.true_value();
+            ok(i, value)
+        }
+        Some(false) => {
+            let value = i.false_value();
+            ok(i, value)
+        }
+        None => failure(i, "Expecting a BOOL", &args[0]),
+    }
+}
+
+fn decode_int(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let json = json_of(&args[0])?;
+
+    // Floats without decimals are valid integers in JS
+    let int = json.as_i64()
+        .or_else(|| json.as_f64().filter(|float| float.fract() == 0.0).map(|float| float as i64));
+
+    match int {
+        Some(int) if int >= i32::min_value() as i64 && int <= i32::max_value() as i64 => {
+            ok(i, Value::Int(int as i32))
+        }
+        _ => failure(i, "Expecting an INT", &args[0]),
+    }
+}
+
+fn decode_float(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match json_of(&args[0])?.as_f64() {
+        Some(float) => ok(i, Value::Float(float as f32)),
+        None => failure(i, "Expecting a FLOAT", &args[0]),
+    }
+}
+
+fn decode_value(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    json_of(&args[0])?;
+
+    ok(i, args[0].clone())
+}
+
+fn decode_null(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    if json_of(&args[1])?.is_null() {
+        ok(i, args[0].clone())
+    } else {
+        failure(i, "Expecting null", &args[1])
+    }
+}
+
+fn decode_list(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match decode_items(i, &args[0], &args[1], "Expecting a LIST")? {
+        Ok(items) => ok(i, Value::List(items.into())),
+        Err(error) => err(i, error),
+    }
+}
+
+fn decode_array(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match decode_items(i, &args[0], &args[1], "Expecting an ARRAY")? {
+        Ok(items) => ok(i, Value::Array(items.into())),
+        Err(error) => err(i, error),
+    }
+}
+
+fn decode_items(i: &mut Interpreter, decoder: &Value, json: &Value, msg: &str) -> Result<Result<Vec<Value>, Value>, ElmError> {
+    let items = match json_of(json)?.as_array() {
+        Some(items) => items,
+        None => return Ok(Err(failure_error(msg, json))),
+    };
+
+    let mut result = vec![];
+
+    for (index, item) in items.iter().enumerate() {
+        match decode(i, decoder, json_value(item))? {
+            Ok(value) => result.push(value),
+            Err(error) => {
+                return Ok(Err(error_value("Index", vec![Value::Int(index as i32), error])));
+            }
+        }
+    }
+
+    Ok(Ok(result))
+}
+
+fn decode_field(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let name = str_of(&args[0])?;
+
+    let item = match json_of(&args[2])?.as_object().and_then(|object| object.get(name)) {
+        Some(item) => json_value(item),
+        None => {
+            let msg = format!("Expecting an OBJECT with a field named `{}`", name);
+            return failure(i, &msg, &args[2]);
+        }
+    };
+
+    match decode(i, &args[1], item)? {
+        Ok(value) => ok(i, value),
+        Err(error) => err(i, error_value("Field", vec![args[0].clone(), error])),
+    }
+}
+
+fn decode_index(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let index = int_of(&args[0])?;
+
+    let items = match json_of(&args[2])?.as_array() {
+        Some(items) => items,
+        None => return failure(i, "Expecting an ARRAY", &args[2]),
+    };
+
+    if index < 0 || index as usize >= items.len() {
+        let msg = format!("Expecting a LONGER array. Need index {} but only see {} entries", index, items.len());
+        return failure(i, &msg, &args[2]);
+    }
+
+    match decode(i, &args[1], json_value(&items[index as usize]))? {
+        Ok(value) => ok(i, value),
+        Err(error) => err(i, error_value("Index", vec![Value::Int(index), error])),
+    }
+}
+
+fn decode_key_value_pairs(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let object = match json_of(&args[1])?.as_object() {
+        Some(object) => object,
+        None => return failure(i, "Expecting an OBJECT", &args[1]),
+    };
+
+    let mut result = vec![];
+
+    for (key, item) in object {
+        match decode(i, &args[0], json_value(item))? {
+            Ok(value) => result.push(Value::Tuple(vec![Value::String(key.clone()), value])),
+            Err(error) => {
+                return err(i, error_value("Field", vec![Value::String(key.clone()), error]));
+            }
+        }
+    }
+
+    ok(i, Value::List(result.into()))
+}
+
+fn succeed(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    ok(i, args[0].clone())
+}
+
+fn fail(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let msg = str_of(&args[0])?;
+
+    failure(i, msg, &args[1])
+}
+
+// map, map2, ..., map8 only differ in the number of decoders
+fn map_n(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let json = &args[args.len() - 1];
+    let mut values = vec![];
+
+    for decoder in &args[1..args.len() - 1] {
+        match decode(i, decoder, json.clone())? {
+            Ok(value) => values.push(value),
+            Err(error) => return err(i, error),
+        }
+    }
+
+    let result = i.apply_function(args[0].clone(), &values)?;
+    ok(i, result)
+}
+
+fn and_then(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match decode(i, &args[1], args[2].clone())? {
+        Ok(value) => {
+            let decoder = i.apply_function(args[0].clone(), &[value])?;
+            i.apply_function(decoder, &[args[2].clone()])
+        }
+        Err(error) => err(i, error),
+    }
+}
+
+fn one_of(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let mut errors = vec![];
+
+    for decoder in list_of(&args[0])? {
+        match decode(i, decoder, args[1].clone())? {
+            Ok(value) => return ok(i, value),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    err(i, error_value("OneOf", vec![Value::List(errors.into())]))
+}
+
+fn run(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    json_of(&args[1])?;
+
+    i.apply_function(args[0].clone(), &[args[1].clone()])
+}
+
+fn run_on_string(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let string = str_of(&args[1])?;
+
+    match serde_json::from_str::<JsonValue>(string) {
+        Ok(json) => i.apply_function(args[0].clone(), &[Value::Json(Arc::new(json))]),
+        Err(error) => {
+            let msg = format!("This is not valid JSON! {}", error);
+            failure(i, &msg, &Value::Json(Arc::new(JsonValue::String(string.to_string()))))
+        }
+    }
+}
+
+fn error_to_string(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(Value::String(error_message(&args[0], vec![])?))
+}
+
+// Same messages as errorToString in elm/json
+fn error_message(error: &Value, mut context: Vec<String>) -> Result<String, ElmError> {
+    let (variant, values) = match error {
+        Value::Adt(variant, values, _) => (variant.as_str(), values),
+        _ => return Err(InterpreterError::ExpectedAdt(error.clone()).wrap()),
+    };
+
+    match variant {
+        "Field" => {
+            let field = str_of(&values[0])?;
+            let mut chars = field.chars();
+            let is_simple = match chars.next() {
+                Some(first) => first.is_ascii_alphabetic() && chars.all(|c| c.is_ascii_alphanumeric()),
+                None => false,
+            };
+
+            if is_simple {
+                context.push(format!(".{}", field));
+            } else {
+                context.push(format!("['{}']", field));
+            }
+
+            error_message(&values[1], context)
+        }
+        "Index" => {
+            context.push(format!("[{}]", int_of(&values[0])?));
+            error_message(&values[1], context)
+        }
+        "OneOf" => {
+            let errors = list_of(&values[0])?;
+
+            match errors.len() {
+                0 if context.is_empty() => Ok("Ran into a Json.Decode.oneOf with no possibilities!".to_string()),
+                0 => Ok(format!("Ran into a Json.Decode.oneOf with no possibilities at json{}", context.concat())),
+                1 => error_message(errors.head().unwrap(), context),
+                _ => {
+                    let mut msg = if context.is_empty() {
+                        "Json.Decode.oneOf".to_string()
+                    } else {
+                        format!("The Json.Decode.oneOf at json{}", context.concat())
+                    };
+
+                    msg.push_str(&format!(" failed in the following {} ways:", errors.len()));
+
+                    for (index, error) in errors.iter().enumerate() {
+                        msg.push_str(&format!("\n\n\n\n({}) {}", index + 1, indent(&error_message(error, vec![])?)));
+                    }
+
+                    Ok(msg)
+                }
+            }
+        }
+        "Failure" => {
+            let msg = str_of(&values[0])?;
+            let json = json_of(&values[1])?;
+
+            let introduction = if context.is_empty() {
+                "Problem with the given value:\n\n".to_string()
+            } else {
+                format!("Problem with the value at json{}:\n\n    ", context.concat())
+            };
+
+            Ok(format!("{}{}\n\n{}", introduction, indent(&json_to_string(json, 4)), msg))
+        }
+        _ => Err(InterpreterError::ExpectedAdt(error.clone()).wrap())
+    }
+}
+
+fn indent(string: &str) -> String {
+    string.replace("\n", "\n    ")
+}
+
+fn encode(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let indent = int_of(&args[0])?;
+    let json = json_of(&args[1])?;
+
+    Ok(Value::String(json_to_string(json, indent)))
+}
+
+fn encode_string(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let string = str_of(&args[0])?;
+
+    Ok(Value::Json(Arc::new(JsonValue::String(string.to_string()))))
+}
+
+fn encode_int(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let int = int_of(&args[0])?;
+
+    Ok(Value::Json(Arc::new(JsonValue::from(int))))
+}
+
+fn encode_float(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let float = float_of(&args[0])?;
+
+    Ok(Value::Json(Arc::new(float_to_json(float))))
+}
+
+fn encode_bool(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let boolean = bool_of(&args[0])?;
+
+    Ok(Value::Json(Arc::new(JsonValue::Bool(boolean))))
+}
+
+fn encode_null(_: &mut Interpreter, _args: &[Value]) -> Result<Value, ElmError> {
+    Ok(Value::Json(Arc::new(JsonValue::Null)))
+}
+
+fn encode_list(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let items = list_of(&args[0])?.iter()
+        .map(|item| json_of(item).map(|json| json.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Value::Json(Arc::new(JsonValue::Array(items))))
+}
+
+fn encode_object(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let mut object = Map::new();
+
+    for entry in list_of(&args[0])? {
+        match entry {
+            Value::Tuple(pair) if pair.len() == 2 => {
+                object.insert(str_of(&pair[0])?.to_string(), json_of(&pair[1])?.clone());
+            }
+            _ => return Err(InterpreterError::ExpectedTuple(entry.clone()).wrap()),
+        }
+    }
+
+    Ok(Value::Json(Arc::new(JsonValue::Object(object))))
+}
+
+/// Floats are printed like in JS, without decimals if they are integers and as null if they are
+/// not finite
+fn float_to_json(float: f32) -> JsonValue {
+    if float.fract() == 0.0 && float.abs() < 1e15 {
+        return JsonValue::from(float as i64);
+    }
+
+    // Going through the text representation avoids the noise of widening f32 to f64
+    float.to_string().parse::<f64>().ok()
+        .and_then(Number::from_f64)
+        .map(JsonValue::Number)
+        .unwrap_or(JsonValue::Null)
+}
+
+fn json_to_string(json: &JsonValue, indent: i32) -> String {
+    if indent <= 0 {
+        return json.to_string();
+    }
+
+    let indent = " ".repeat(indent as usize);
+    let mut buffer = vec![];
+    let mut serializer = Serializer::with_formatter(&mut buffer, PrettyFormatter::with_indent(indent.as_bytes()));
+
+    json.serialize(&mut serializer).expect("Json values are always serializable");
+    String::from_utf8(buffer).expect("Json is always valid UTF-8")
+}
+
+fn json_value(json: &JsonValue) -> Value {
+    Value::Json(Arc::new(json.clone()))
+}
+
+/// Applies a decoder to a JSON value and unwraps the returned Result
+fn decode(i: &mut Interpreter, decoder: &Value, json: Value) -> Result<Result<Value, Value>, ElmError> {
+    match i.apply_function(decoder.clone(), &[json])? {
+        Value::Adt(name, mut values, adt) => {
+            match (name.as_str(), values.pop()) {
+                ("Ok", Some(value)) => Ok(Ok(value)),
+                ("Err", Some(error)) => Ok(Err(error)),
+                (_, value) => {
+                    values.extend(value);
+                    Err(InterpreterError::ExpectedAdt(Value::Adt(name, values, adt)).wrap())
+                }
+            }
+        }
+        value => Err(InterpreterError::ExpectedAdt(value).wrap()),
+    }
+}
+
+fn ok(i: &mut Interpreter, value: Value) -> Result<Value, ElmError> {
+    i.adt_value("Ok", &[value])
+}
+
+fn err(i: &mut Interpreter, error: Value) -> Result<Value, ElmError> {
+    i.adt_value("Err", &[error])
+}
+
+fn failure(i: &mut Interpreter, msg: &str, json: &Value) -> Result<Value, ElmError> {
+    err(i, failure_error(msg, json))
+}
+
+fn failure_error(msg: &str, json: &Value) -> Value {
+    error_value("Failure", vec![Value::String(msg.to_string()), json.clone()])
+}
+
+static ERROR_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+
+/// Creates a Json.Decode.Error, the definition matches the one in the Json.Decode module
+fn error_value(variant: &str, values: Vec<Value>) -> Value {
+    let adt = ERROR_ADT.get_or_init(|| {
+        let variant = |name: &str, types: &[&str]| AdtVariant {
+            name: name.to_string(),
+            types: types.iter().map(|ty| type_of(ty)).collect(),
+        };
+
+        Arc::new(Adt {
+            name: "Error".to_string(),
+            types: vec![],
+            variants: vec![
+                variant("Field", &["String", "Error"]),
+                variant("Index", &["Int", "Error"]),
+                variant("OneOf", &["List Error"]),
+                variant("Failure", &["String", "Value"]),
+            ],
+        })
+    });
+
+    Value::Adt(variant.to_string(), values, adt.clone())
+}
@@ -5,6 +5,7 @@ use builtin::func_of;
 use builtin::utils::compare_values;
 use errors::*;
 use interpreter::Interpreter;
+use rust_interop::conversions::array_of;
 use rust_interop::conversions::list_of;
 use types::Value;
 
@@ -16,26 +17,17 @@ pub fn get_list_funs() -> Vec<(&'static str, Type, Value)> {
         func_of("map3", "(a -> b -> c -> result) -> List a -> List b -> List c -> List result", map3),
         func_of("map4", "(a -> b -> c -> d -> result) -> List a -> List b -> List c -> List d -> List result", map4),
         func_of("map5", "(a -> b -> c -> d -> e -> result) -> List a -> List b -> List c -> List d -> List e -> List result", map5),
-        func_of("fromArray", "Array a -> List a", temp_do_nothing),
-        func_of("toArray", "List a -> Array a", temp_do_nothing),
+        func_of("fromArray", "Array a -> List a", from_array),
+        func_of("toArray", "List a -> Array a", to_array),
         func_of("sortBy", "(a -> comparable) ->  List a -> List a", sort_by),
         func_of("sortWith", "(a -> a -> Order) ->  List a -> List a", sort_with),
     ]
 }
 
-fn temp_do_nothing(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    Ok(args[0].clone())
-}
-
 fn cons(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    let mut result = vec![args[0].clone()];
     let list = list_of(&args[1])?;
 
-    for val in list {
-        result.push(val.clone());
-    }
-
-    Ok(Value::List(result))
+    Ok(Value::List(list.prepend(args[0].clone())))
 }
 
 fn map2(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -43,18 +35,17 @@ fn map2(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let func = &args[0];
     let list_a = list_of(&args[1])?;
     let list_b = list_of(&args[2])?;
-    let len = list_a.len().min(list_b.len());
 
-    for index in 0..len {
+    for (a, b) in list_a.iter().zip(list_b) {
         let res = i.apply_function(func.clone(), &[
-            list_a[index].clone(),
-            list_b[index].clone(),
+            a.clone(),
+            b.clone(),
         ])?;
 
         result.push(res);
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn map3(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -63,19 +54,18 @@ fn map3(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let list_a = list_of(&args[1])?;
     let list_b = list_of(&args[2])?;
     let list_c = list_of(&args[3])?;
-    let len = list_a.len().min(list_b.len()).min(list_c.len());
 
-    for index in 0..len {
+    for ((a, b), c) in list_a.iter().zip(list_b).zip(list_c) {
         let res = i.apply_function(func.clone(), &[
-            list_a[index].clone(),
-            list_b[index].clone(),
-            list_c[index].clone(),
+            a.clone(),
+            b.clone(),
+            c.clone(),
         ])?;
 
         result.push(res);
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn map4(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -85,20 +75,19 @@ fn map4(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let list_b = list_of(&args[2])?;
     let list_c = list_of(&args[3])?;
     let list_d = list_of(&args[4])?;
-    let len = list_a.len().min(list_b.len()).min(list_c.len()).min(list_d.len());
 
-    for index in 0..len {
+    for (((a, b), c), d) in list_a.iter().zip(list_b).zip(list_c).zip(list_d) {
         let res = i.apply_function(func.clone(), &[
-            list_a[index].clone(),
-            list_b[index].clone(),
-            list_c[index].clone(),
-            list_d[index].clone(),
+            a.clone(),
+            b.clone(),
+            c.clone(),
+            d.clone(),
         ])?;
 
         result.push(res);
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn map5(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -109,30 +98,33 @@ fn map5(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let list_c = list_of(&args[3])?;
     let list_d = list_of(&args[4])?;
     let list_e = list_of(&args[5])?;
-    let len = list_a.len().min(list_b.len()).min(list_c.len()).min(list_d.len()).min(list_e.len());
 
-    for index in 0..len {
+    for ((((a, b), c), d), e) in list_a.iter().zip(list_b).zip(list_c).zip(list_d).zip(list_e) {
         let res = i.apply_function(func.clone(), &[
-            list_a[index].clone(),
-            list_b[index].clone(),
-            list_c[index].clone(),
-            list_d[index].clone(),
-            list_e[index].clone(),
+            a.clone(),
+            b.clone(),
+            c.clone(),
+            d.clone(),
+            e.clone(),
         ])?;
 
         result.push(res);
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
+fn from_array(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let array = array_of(&args[0])?;
 
-//fn from_array(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-//    let mut result = vec![];
-//    let func = &args[0];
-//
-//    Ok(Value::List(result))
-//}
+    Ok(Value::List(array.iter().cloned().collect()))
+}
+
+fn to_array(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    let list = list_of(&args[0])?;
+
+    Ok(Value::Array(list.iter().cloned().collect()))
+}
 
 fn sort_by(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let func = &args[0];
@@ -155,7 +147,7 @@ fn sort_by(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     if !errors.is_empty() {
         Err(ElmError::List(errors))
     } else {
-        Ok(Value::List(list))
+        Ok(Value::List(list.into()))
     }
 }
 
@@ -194,6 +186,6 @@ fn sort_with(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     if !errors.is_empty() {
         Err(ElmError::List(errors))
     } else {
-        Ok(Value::List(list))
+        Ok(Value::List(list.into()))
     }
 }
\ No newline at end of file
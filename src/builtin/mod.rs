@@ -2,16 +2,21 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use ast::Type;
+use builtin::array::get_array_funs;
 use builtin::basics::get_basics_funs;
 use builtin::bitwise::get_bitwise_funs;
 use builtin::char::get_char_funs;
 use builtin::debug::get_debug_funs;
+use builtin::json::get_json_funs;
 use builtin::list::get_list_funs;
+use builtin::platform::get_platform_funs;
+use builtin::scheduler::get_scheduler_funs;
 use builtin::string::get_string_funs;
 use builtin::utils::get_utils_funs;
 use constructors::type_fun;
 use constructors::type_of;
 use constructors::type_tag_args;
+use constructors::type_var;
 use errors::ElmError;
 use errors::InterpreterError;
 use errors::Wrappable;
@@ -35,9 +40,19 @@ mod string;
 mod list;
 mod bitwise;
 mod utils;
+mod array;
+mod json;
+pub mod platform;
+pub mod scheduler;
+
+pub const ELM_CORE_MODULES: [&str; 17] = [
+    "Basics", "Bitwise", "Char", "Maybe", "Result", "List", "Array", "String", "Debug", "Dict", "Set", "Tuple",
+    "Platform.Cmd", "Platform.Sub", "Platform", "Task", "Process"
+];
 
-pub const ELM_CORE_MODULES: [&str; 11] = [
-    "Basics", "Bitwise", "Char", "Maybe", "Result", "List", "String", "Debug", "Dict", "Set", "Tuple"
+/// Modules of elm/json, they are loaded after the core modules
+pub const ELM_JSON_MODULES: [&str; 2] = [
+    "Json.Encode", "Json.Decode"
 ];
 
 /// Returns a list of the Elm Core kernel modules, adding the basic building blocks of the language
@@ -51,6 +66,10 @@ pub fn get_core_kernel_modules() -> Vec<(&'static str, AnalyzedModule, RuntimeMo
         core_kernel_module("Elm.Kernel.Char", get_char_funs),
         core_kernel_module("Elm.Kernel.List", get_list_funs),
         core_kernel_module("Elm.Kernel.String", get_string_funs),
+        core_kernel_module("Elm.Kernel.Array", get_array_funs),
+        core_kernel_module("Elm.Kernel.Json", get_json_funs),
+        core_kernel_module("Elm.Kernel.Platform", get_platform_funs),
+        core_kernel_module("Elm.Kernel.Scheduler", get_scheduler_funs),
     ]
 }
 
@@ -115,12 +134,19 @@ pub fn builtin_record_access() -> ExternalFunc {
 }
 
 pub fn adt_constructor(adt: Arc<Adt>, variant: &AdtVariant) -> Value {
+    // Variants without arguments are values, not functions
+    if variant.types.is_empty() {
+        return Value::Adt(variant.name.to_string(), vec![], adt);
+    }
+
+    let adt_type = type_tag_args(&adt.name, adt.types.iter().map(|ty| type_var(ty)).collect());
     let mut func_types = vec![type_tag_args(&variant.name, vec![])];
     func_types.extend(variant.types.clone().into_iter());
-    func_types.push(type_tag_args(&variant.name, variant.types.clone()));
+    func_types.push(adt_type);
 
     Value::Fun {
-        arg_count: func_types.len() as u32,
+        // The first argument is the variant itself, the rest are the variant values
+        arg_count: (func_types.len() - 1) as u32,
         args: vec![Value::Adt(variant.name.to_string(), vec![], adt)],
         fun: Arc::new(Function::External(next_fun_id(), builtin_adt_constructor(), type_fun(func_types))),
     }
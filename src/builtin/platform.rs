@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use ast::Type;
+use builtin::func_of;
+use constructors::type_of;
+use errors::ElmError;
+use interpreter::Interpreter;
+use rust_interop::conversions::list_of;
+use types::Adt;
+use types::AdtVariant;
+use types::ElmFn;
+use types::ExternalFunc;
+use types::Function;
+use types::next_fun_id;
+use types::Value;
+
+pub fn get_platform_funs() -> Vec<(&'static str, Type, Value)> {
+    vec![
+        func_of("worker", "{ init : flags -> ( model, Cmd msg ), update : msg -> model -> ( model, Cmd msg ), subscriptions : model -> Sub msg } -> Program flags model msg", worker),
+        func_of("batchCmd", "List (Cmd msg) -> Cmd msg", batch_cmd),
+        func_of("mapCmd", "(a -> msg) -> Cmd a -> Cmd msg", map_cmd),
+        func_of("performCmd", "Task Never msg -> Cmd msg", perform_cmd),
+        func_of("batchSub", "List (Sub msg) -> Sub msg", batch_sub),
+        func_of("mapSub", "(a -> msg) -> Sub a -> Sub msg", map_sub),
+    ]
+}
+
+fn worker(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(Value::Adt("Program".to_string(), vec![args[0].clone()], program_adt()))
+}
+
+fn batch_cmd(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    list_of(&args[0])?;
+    Ok(cmd_value("Batch", vec![args[0].clone()]))
+}
+
+fn map_cmd(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(cmd_value("Map", vec![args[0].clone(), args[1].clone()]))
+}
+
+fn perform_cmd(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(cmd_value("Perform", vec![args[0].clone()]))
+}
+
+fn batch_sub(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    list_of(&args[0])?;
+    Ok(sub_value("Batch", vec![args[0].clone()]))
+}
+
+fn map_sub(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(sub_value("Map", vec![args[0].clone(), args[1].clone()]))
+}
+
+/// Direction of a port declared with `port name : type`
+#[derive(Debug, PartialEq, Clone)]
+pub enum PortKind {
+    /// `port send : a -> Cmd msg`, sends values to the host
+    Outgoing(Type),
+    /// `port receive : (a -> msg) -> Sub msg`, receives values from the host
+    Incoming(Type),
+}
+
+/// Finds the direction of a port and the type of the values it sends or receives, ports are the
+/// only lowercase declarations without definition in a module
+pub fn port_kind(name: &str, ty: &Type) -> Option<PortKind> {
+    if !name.starts_with(|c: char| c.is_lowercase()) {
+        return None;
+    }
+
+    match ty {
+        Type::Fun(arg, ret) => {
+            match (arg.as_ref(), ret.as_ref()) {
+                (Type::Fun(value, _), Type::Tag(tag, _)) if tag == "Sub" => Some(PortKind::Incoming(value.as_ref().clone())),
+                (value, Type::Tag(tag, _)) if tag == "Cmd" => Some(PortKind::Outgoing(value.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Creates the function of a port, the command or subscription returned keeps the qualified
+/// port name, `Module.name`, so ports with the same name in different modules don't collide
+pub fn port_value(module: &str, name: &str, ty: &Type) -> Option<Value> {
+    let fun: ElmFn = match port_kind(name, ty)? {
+        PortKind::Outgoing(_) => |_, args| Ok(cmd_value("Port", vec![args[0].clone(), args[1].clone()])),
+        PortKind::Incoming(_) => |_, args| Ok(sub_value("Port", vec![args[0].clone(), args[1].clone()])),
+    };
+
+    let external = ExternalFunc { name: name.to_string(), fun };
+
+    Some(Value::Fun {
+        arg_count: 2,
+        args: vec![Value::String(format!("{}.{}", module, name))],
+        fun: Arc::new(Function::External(next_fun_id(), external, ty.clone())),
+    })
+}
+
+static PROGRAM_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+static CMD_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+static SUB_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+
+fn program_adt() -> Arc<Adt> {
+    PROGRAM_ADT.get_or_init(|| {
+        Arc::new(Adt {
+            name: "Program".to_string(),
+            types: vec!["flags".to_string(), "model".to_string(), "msg".to_string()],
+            variants: vec![
+                variant("Program", &["{ init : flags -> ( model, Cmd msg ), update : msg -> model -> ( model, Cmd msg ), subscriptions : model -> Sub msg }"]),
+            ],
+        })
+    }).clone()
+}
+
+/// Creates a command, commands form a tree where `Batch` and `Map` are the branches and the
+/// leaves are the effects sent to the host
+pub fn cmd_value(name: &str, values: Vec<Value>) -> Value {
+    let adt = CMD_ADT.get_or_init(|| {
+        let mut adt = effect_adt("Cmd", "a");
+        adt.variants.push(variant("Perform", &["Task Never msg"]));
+        Arc::new(adt)
+    });
+
+    Value::Adt(name.to_string(), values, adt.clone())
+}
+
+/// Creates a subscription, they follow the same structure as commands
+pub fn sub_value(name: &str, values: Vec<Value>) -> Value {
+    let adt = SUB_ADT.get_or_init(|| Arc::new(effect_adt("Sub", "a -> msg")));
+
+    Value::Adt(name.to_string(), values, adt.clone())
+}
+
+fn effect_adt(name: &str, port_type: &str) -> Adt {
+    Adt {
+        name: name.to_string(),
+        types: vec!["msg".to_string()],
+        variants: vec![
+            variant("Batch", &[&format!("List ({} msg)", name)]),
+            variant("Map", &["a -> msg", &format!("{} a", name)]),
+            variant("Port", &["String", port_type]),
+        ],
+    }
+}
+
+fn variant(name: &str, types: &[&str]) -> AdtVariant {
+    AdtVariant {
+        name: name.to_string(),
+        types: types.iter().map(|ty| type_of(ty)).collect(),
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use ast::Int;
+use ast::Type;
+use builtin::func_of;
+use constructors::type_of;
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use interpreter::Interpreter;
+use rust_interop::conversions::float_of;
+use rust_interop::conversions::int_of;
+use types::Adt;
+use types::AdtVariant;
+use types::Value;
+
+pub fn get_scheduler_funs() -> Vec<(&'static str, Type, Value)> {
+    vec![
+        func_of("succeed", "a -> Task x a", succeed),
+        func_of("fail", "x -> Task x a", fail),
+        func_of("andThen", "(a -> Task x b) -> Task x a -> Task x b", and_then),
+        func_of("onError", "(x -> Task y a) -> Task x a -> Task y a", on_error),
+        func_of("spawn", "Task x a -> Task y Id", spawn),
+        func_of("sleep", "Float -> Task x ()", sleep),
+        func_of("kill", "Id -> Task x ()", kill),
+    ]
+}
+
+fn succeed(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(task_value("Succeed", vec![args[0].clone()]))
+}
+
+fn fail(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(task_value("Fail", vec![args[0].clone()]))
+}
+
+fn and_then(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(task_value("AndThen", vec![args[0].clone(), args[1].clone()]))
+}
+
+fn on_error(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(task_value("OnError", vec![args[0].clone(), args[1].clone()]))
+}
+
+fn spawn(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    Ok(task_value("Spawn", vec![args[0].clone()]))
+}
+
+fn sleep(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    float_of(&args[0])?;
+    Ok(task_value("Sleep", vec![args[0].clone()]))
+}
+
+fn kill(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    process_id_of(&args[0])?;
+    Ok(task_value("Kill", vec![args[0].clone()]))
+}
+
+static TASK_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+static ID_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+
+/// Creates a task, tasks are descriptions of the work to do, the scheduler runs them
+pub fn task_value(name: &str, values: Vec<Value>) -> Value {
+    let adt = TASK_ADT.get_or_init(|| {
+        Arc::new(Adt {
+            name: "Task".to_string(),
+            types: vec!["x".to_string(), "a".to_string()],
+            variants: vec![
+                variant("Succeed", &["a"]),
+                variant("Fail", &["x"]),
+                variant("AndThen", &["b -> Task x a", "Task x b"]),
+                variant("OnError", &["y -> Task x a", "Task y a"]),
+                variant("Spawn", &["Task y b"]),
+                variant("Sleep", &["Float"]),
+                variant("Kill", &["Id"]),
+            ],
+        })
+    });
+
+    Value::Adt(name.to_string(), values, adt.clone())
+}
+
+/// Creates the `Process.Id` of a process
+pub fn process_id_value(id: Int) -> Value {
+    let adt = ID_ADT.get_or_init(|| {
+        Arc::new(Adt {
+            name: "Id".to_string(),
+            types: vec![],
+            variants: vec![variant("Id", &["Int"])],
+        })
+    });
+
+    Value::Adt("Id".to_string(), vec![Value::Int(id)], adt.clone())
+}
+
+pub fn process_id_of(value: &Value) -> Result<Int, ElmError> {
+    match value {
+        Value::Adt(name, values, _) if name == "Id" && values.len() == 1 => int_of(&values[0]),
+        _ => Err(InterpreterError::ExpectedAdt(value.clone()).wrap()),
+    }
+}
+
+fn variant(name: &str, types: &[&str]) -> AdtVariant {
+    AdtVariant {
+        name: name.to_string(),
+        types: types.iter().map(|ty| type_of(ty)).collect(),
+    }
+}
@@ -6,12 +6,14 @@ use errors::ElmError;
 use errors::InterpreterError;
 use errors::Wrappable;
 use interpreter::Interpreter;
+use rust_interop::conversions::array_of;
 use rust_interop::conversions::bool_of;
 use rust_interop::conversions::char_of;
 use rust_interop::conversions::int_of;
 use rust_interop::conversions::list_of;
 use rust_interop::conversions::str_of;
 use types::Value;
+use util::persistent_list::List;
 
 pub fn get_string_funs() -> Vec<(&'static str, Type, Value)> {
     vec![
@@ -157,29 +159,25 @@ fn split(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let pattern = str_of(&args[0])?;
     let string = str_of(&args[1])?;
 
-    let mut result = vec![];
-
-    for sub in string.split(pattern) {
-        result.push(Value::String(sub.to_string()));
-    }
+    let result = string.split(pattern)
+        .map(|sub| Value::String(sub.to_string()))
+        .collect();
 
-    // TODO array not list
-    Ok(Value::List(result))
+    Ok(Value::Array(result))
 }
 
 fn join(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let glue = str_of(&args[0])?;
-    // TODO array not list
-    let array = list_of(&args[1])?;
+    let array = array_of(&args[1])?;
 
     let mut result = String::new();
 
     for (i, item) in array.iter().enumerate() {
-        result.push_str(str_of(item)?);
-
-        if i == array.len() - 1 {
+        if i != 0 {
             result.push_str(glue);
         }
+
+        result.push_str(str_of(item)?);
     }
 
     Ok(Value::String(result))
@@ -223,7 +221,7 @@ fn words(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
         result.push(Value::String(line.to_string()))
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn lines(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -234,7 +232,7 @@ fn lines(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
         result.push(Value::String(line.to_string()))
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn to_upper(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
@@ -319,7 +317,7 @@ fn indexes(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     let sub_len = sub.len();
 
     if sub_len < 1 {
-        return Ok(Value::List(vec![]));
+        return Ok(Value::List(List::new()));
     }
 
     let mut i = 0;
@@ -330,7 +328,7 @@ fn indexes(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
         i = new_i + sub_len;
     }
 
-    Ok(Value::List(result))
+    Ok(Value::List(result.into()))
 }
 
 fn from_number(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
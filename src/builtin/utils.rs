@@ -1,4 +1,7 @@
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
 
 use ast::Type;
 use builtin::func_of;
@@ -47,17 +50,16 @@ fn not_equal(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
     }
 }
 
-fn compare(_: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    // TODO Orders instead of Int
-    match compare_values(&args[1], &args[1]) {
-        Ordering::Less => Ok(Value::Int(-1)),
-        Ordering::Equal => Ok(Value::Int(0)),
-        Ordering::Greater => Ok(Value::Int(1)),
+fn compare(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
+    match compare_values(&args[0], &args[1]) {
+        Ordering::Less => i.adt_value("LT", &[]),
+        Ordering::Equal => i.adt_value("EQ", &[]),
+        Ordering::Greater => i.adt_value("GT", &[]),
     }
 }
 
 fn lt(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    if compare_values(&args[1], &args[1]) == Ordering::Less {
+    if compare_values(&args[0], &args[1]) == Ordering::Less {
         Ok(i.true_value())
     } else {
         Ok(i.false_value())
@@ -65,7 +67,7 @@ fn lt(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
 }
 
 fn le(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    if compare_values(&args[1], &args[1]) != Ordering::Greater {
+    if compare_values(&args[0], &args[1]) != Ordering::Greater {
         Ok(i.true_value())
     } else {
         Ok(i.false_value())
@@ -73,7 +75,7 @@ fn le(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
 }
 
 fn gt(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    if compare_values(&args[1], &args[1]) == Ordering::Less {
+    if compare_values(&args[0], &args[1]) == Ordering::Greater {
         Ok(i.true_value())
     } else {
         Ok(i.false_value())
@@ -81,7 +83,7 @@ fn gt(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
 }
 
 fn ge(i: &mut Interpreter, args: &[Value]) -> Result<Value, ElmError> {
-    if compare_values(&args[1], &args[1]) != Ordering::Less {
+    if compare_values(&args[0], &args[1]) != Ordering::Less {
         Ok(i.true_value())
     } else {
         Ok(i.false_value())
@@ -135,9 +137,25 @@ pub fn compare_values(a: &Value, b: &Value) -> Ordering {
         },
         Value::Unit => Ordering::Less,
         Value::List(_) => Ordering::Less,
+        Value::Array(_) => Ordering::Less,
+        Value::Json(_) => Ordering::Less,
         Value::Tuple(_) => Ordering::Less,
         Value::Record(_) => Ordering::Less,
         Value::Adt(_, _, _) => Ordering::Less,
         Value::Fun { .. } => Ordering::Less,
+        Value::Opaque(na) => {
+            if let Value::Opaque(nb) = b {
+                // The host data has no order, but sorting must be consistent
+                na.type_name.cmp(&nb.type_name).then_with(|| hash_of(na).cmp(&hash_of(nb)))
+            } else {
+                Ordering::Less
+            }
+        },
     }
+}
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
 }
\ No newline at end of file
@@ -0,0 +1,382 @@
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::ops::Range;
+
+use ast::Module;
+use ast::Span;
+use errors::ElmError;
+use parsers::Parser;
+use source::SourceCode;
+use tokenizer::Token;
+use tokenizer::TokenInfo;
+use tokenizer::Tokenizer;
+
+/// A lossless syntax tree, every byte of the source code belongs to a token or to the trivia
+/// around a token, so the tree can be printed back without changes. The tree can be lowered into
+/// the `Module` AST used by the analyzer
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxTree {
+    pub source: SourceCode,
+    /// Tokens without indentation, the last one is always `Token::Eof`
+    pub tokens: Vec<CstToken>,
+    /// Top level blocks, each one starts at column 0
+    pub items: Vec<CstItem>,
+}
+
+/// A token with the text that surrounds it. Trailing trivia goes until the end of the line,
+/// the rest belongs to the leading trivia of the next token
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstToken {
+    pub token: Token,
+    pub span: Span,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces and line breaks
+    Whitespace,
+    /// `-- comment`
+    LineComment,
+    /// `{- comment -}`
+    BlockComment,
+    /// `{-| documentation -}`
+    DocComment,
+    /// Characters that the tokenizer is unable to read
+    Unknown,
+}
+
+/// Range of tokens of a top level block, like an import or a definition with its type annotation
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstItem {
+    pub kind: CstItemKind,
+    pub tokens: Range<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CstItemKind {
+    ModuleHeader,
+    Import,
+    Declaration,
+}
+
+impl SyntaxTree {
+    /// Creates the tree of a file, the lexical errors are returned with the tree and the invalid
+    /// characters are stored as `TriviaKind::Unknown`
+    pub fn parse(code: &SourceCode) -> (SyntaxTree, Vec<ElmError>) {
+        let (infos, errors) = Tokenizer::new(code).read_all();
+        let text = &code.as_str()[..code.len()];
+
+        let mut tokens: Vec<CstToken> = vec![];
+        let mut last_end = 0;
+
+        for info in infos {
+            if let Token::Indent(_) = info.token {
+                continue;
+            }
+
+            // The end of file can be placed after the padding of the source code
+            let start = (info.span.0 as usize).min(text.len());
+            let end = (info.span.1 as usize).min(text.len()).max(start);
+            let mut gap = read_trivia(text, last_end, start).into_iter().peekable();
+
+            if let Some(previous) = tokens.last_mut() {
+                while let Some(trivia) = gap.peek() {
+                    if trivia.kind == TriviaKind::Whitespace && trivia.text.contains('\n') {
+                        break;
+                    }
+                    previous.trailing.push(gap.next().unwrap());
+                }
+            }
+
+            tokens.push(CstToken {
+                token: info.token,
+                span: info.span,
+                text: text[start..end].to_string(),
+                leading: gap.collect(),
+                trailing: vec![],
+            });
+            last_end = end;
+        }
+
+        let mut tree = SyntaxTree { source: code.clone(), tokens, items: vec![] };
+        tree.items = tree.find_items();
+        (tree, errors)
+    }
+
+    /// Returns the tokens with the indentation tokens expected by the parser, the same tokens
+    /// that `Tokenizer::read_all` returns
+    pub fn token_infos(&self) -> Vec<TokenInfo> {
+        let mut infos = vec![];
+        let mut last_end = 0;
+
+        for (index, token) in self.tokens.iter().enumerate() {
+            for trivia in self.trivia_before(index) {
+                match trivia.kind {
+                    TriviaKind::Whitespace if trivia.text.contains('\n') => {
+                        let indent = trivia.text.len() - trivia.text.rfind('\n').unwrap() - 1;
+                        infos.push(TokenInfo { span: (last_end, trivia.span.1), token: Token::Indent(indent as u32) });
+                        last_end = trivia.span.1;
+                    }
+                    // The tokenizer skips invalid characters and starts again after them
+                    TriviaKind::Unknown => last_end = trivia.span.1,
+                    _ => {}
+                }
+            }
+
+            infos.push(TokenInfo { span: token.span, token: token.token.clone() });
+            last_end = token.span.1;
+        }
+
+        infos
+    }
+
+    /// Converts the tree into the AST used by the analyzer
+    pub fn lower(&self) -> Result<Module, ElmError> {
+        Parser::parse_module_tokens(self.source.clone(), self.token_infos())
+    }
+
+    /// Returns all the comments in order
+    pub fn comments(&self) -> Vec<&Trivia> {
+        self.tokens.iter()
+            .flat_map(|token| token.leading.iter().chain(token.trailing.iter()))
+            .filter(|trivia| trivia.kind != TriviaKind::Whitespace && trivia.kind != TriviaKind::Unknown)
+            .collect()
+    }
+
+    /// Returns the source code of an item, with the comments inside but without the leading
+    /// and trailing trivia
+    pub fn item_source(&self, item: &CstItem) -> String {
+        let tokens = &self.tokens[item.tokens.clone()];
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => self.source.as_str()[first.span.0 as usize..last.span.1 as usize].to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Trivia between the previous token and the token at `index`
+    fn trivia_before(&self, index: usize) -> impl Iterator<Item=&Trivia> {
+        let previous = if index > 0 { &self.tokens[index - 1].trailing[..] } else { &[] };
+
+        previous.iter().chain(self.tokens[index].leading.iter())
+    }
+
+    fn find_items(&self) -> Vec<CstItem> {
+        let mut items: Vec<CstItem> = vec![];
+
+        for (index, token) in self.tokens.iter().enumerate() {
+            if token.token == Token::Eof {
+                break;
+            }
+
+            let starts_line = index == 0 || self.trivia_before(index)
+                .filter(|trivia| trivia.kind == TriviaKind::Whitespace && trivia.text.contains('\n'))
+                .last()
+                .map(|trivia| trivia.text.ends_with('\n'))
+                .unwrap_or(false);
+
+            match items.last_mut() {
+                Some(item) if !starts_line => item.tokens.end = index + 1,
+                _ => {
+                    let next = self.tokens.get(index + 1).map(|it| &it.token);
+                    let kind = match (&token.token, next) {
+                        (Token::ModuleTk, _) | (Token::EffectTk, _) | (Token::Port, Some(Token::ModuleTk)) => CstItemKind::ModuleHeader,
+                        (Token::ImportTk, _) => CstItemKind::Import,
+                        _ => CstItemKind::Declaration,
+                    };
+
+                    items.push(CstItem { kind, tokens: index..index + 1 });
+                }
+            }
+        }
+
+        items
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                write!(f, "{}", trivia.text)?;
+            }
+            write!(f, "{}", token.text)?;
+            for trivia in &token.trailing {
+                write!(f, "{}", trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits the text between two tokens into whitespace, comments and unknown characters
+fn read_trivia(text: &str, start: usize, end: usize) -> Vec<Trivia> {
+    let gap = &text[start..end];
+    let bytes = gap.as_bytes();
+    let mut trivia = vec![];
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let (kind, len) = if bytes[pos] == b' ' || bytes[pos] == b'\n' {
+            let len = bytes[pos..].iter().take_while(|&&b| b == b' ' || b == b'\n').count();
+            (TriviaKind::Whitespace, len)
+        } else if gap[pos..].starts_with("{-") {
+            let kind = if gap[pos..].starts_with("{-|") { TriviaKind::DocComment } else { TriviaKind::BlockComment };
+            (kind, block_comment_len(&bytes[pos..]))
+        } else if gap[pos..].starts_with("--") {
+            let len = bytes[pos..].iter().take_while(|&&b| b != b'\n' && b != b'\r').count();
+            (TriviaKind::LineComment, len)
+        } else {
+            let len = gap[pos..].chars()
+                .take_while(|&c| c != ' ' && c != '\n' && c != '{' && c != '-')
+                .map(|c| c.len_utf8())
+                .sum::<usize>();
+            (TriviaKind::Unknown, len.max(gap[pos..].chars().next().unwrap().len_utf8()))
+        };
+
+        trivia.push(Trivia {
+            kind,
+            span: ((start + pos) as u32, (start + pos + len) as u32),
+            text: gap[pos..pos + len].to_string(),
+        });
+        pos += len;
+    }
+
+    trivia
+}
+
+/// Length of a block comment, it must match the comments skipped by the tokenizer
+fn block_comment_len(bytes: &[u8]) -> usize {
+    let byte = |pos: usize| bytes.get(pos).cloned().unwrap_or(b'\0');
+    let mut nesting = 0;
+    let mut pos = 0;
+
+    loop {
+        if pos >= bytes.len() {
+            return bytes.len();
+        }
+
+        if byte(pos) == b'{' && byte(pos + 1) == b'-' {
+            nesting += 1;
+        }
+
+        if nesting == 0 { break; }
+
+        if byte(pos) == b'-' && byte(pos + 1) == b'}' {
+            nesting -= 1;
+            pos += 2;
+            if nesting == 0 { break; }
+        }
+
+        pos += 1;
+    }
+
+    pos.min(bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const COMMENTED: &str = "\
+module Main exposing (main, Model)\n\
+\n\
+{-| The model\n    \
+{- nested -}\n\
+-}\n\
+type alias Model = { count : Int } -- counter\n\
+\n\
+-- Entry point\n\
+main : Model\n\
+main =\n    \
+{- inline -} { count = 1 }\n        \
+-- indented comment\n\
+\n";
+
+    fn check_file(code: &str) {
+        let source = SourceCode::from_str(code);
+        let (tree, errors) = SyntaxTree::parse(&source);
+        let (infos, tokenizer_errors) = Tokenizer::new(&source).read_all();
+
+        assert_eq!(tree.to_string(), code);
+        assert_eq!(errors, tokenizer_errors);
+        assert_eq!(tree.token_infos(), infos);
+
+        if errors.is_empty() {
+            assert_eq!(tree.lower(), Parser::new(Tokenizer::new(&source)).parse_module());
+        }
+    }
+
+    #[test]
+    fn check_round_trip() {
+        check_file(COMMENTED);
+        check_file("");
+        check_file("x = 1");
+        check_file("  \n\nx = 1 --");
+        check_file("x =\r\n\t1 ¤ {- unclosed");
+        check_file("x = {-}-} 1\n\ny {- {- a -}b -} = 2\n");
+        check_file(include_str!("../resources/benches/tokenizer_1.elm"));
+        check_file(include_str!("../resources/benches/tokenizer_2.elm"));
+    }
+
+    #[test]
+    fn check_round_trip_project() {
+        let mut folders = vec!["resources/test".to_string()];
+
+        while let Some(folder) = folders.pop() {
+            for entry in fs::read_dir(&folder).unwrap() {
+                let path = entry.unwrap().path();
+
+                if path.is_dir() {
+                    folders.push(path.to_string_lossy().to_string());
+                } else if path.extension().map(|ext| ext == "elm").unwrap_or(false) {
+                    check_file(&fs::read_to_string(&path).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_trivia() {
+        let (tree, _) = SyntaxTree::parse(&SourceCode::from_str(COMMENTED));
+
+        let type_tk = tree.tokens.iter().find(|it| it.token == Token::TypeTk).unwrap();
+        let kinds = type_tk.leading.iter().map(|it| it.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TriviaKind::Whitespace, TriviaKind::DocComment, TriviaKind::Whitespace]);
+        assert!(type_tk.leading[1].text.ends_with("{- nested -}\n-}"));
+
+        let brace = tree.tokens.iter().find(|it| it.token == Token::RightBrace).unwrap();
+        assert_eq!(brace.trailing[1].text, "-- counter");
+
+        let comments = tree.comments().iter().map(|it| it.kind).collect::<Vec<_>>();
+        assert_eq!(comments, vec![
+            TriviaKind::DocComment,
+            TriviaKind::LineComment,
+            TriviaKind::LineComment,
+            TriviaKind::BlockComment,
+            TriviaKind::LineComment,
+        ]);
+    }
+
+    #[test]
+    fn check_items() {
+        let (tree, _) = SyntaxTree::parse(&SourceCode::from_str(COMMENTED));
+
+        let kinds = tree.items.iter().map(|it| it.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![CstItemKind::ModuleHeader, CstItemKind::Declaration, CstItemKind::Declaration, CstItemKind::Declaration]);
+        assert_eq!(tree.item_source(&tree.items[2]), "main : Model");
+        assert_eq!(tree.item_source(&tree.items[3]), "main =\n    {- inline -} { count = 1 }");
+    }
+}
@@ -6,14 +6,18 @@ use std::fmt::Write;
 use std::sync::Arc;
 
 use ast::Expr;
+use ast::Int;
 use ast::Pattern;
 use ast::Span;
 use ast::Type;
+use interpreter::limits::ExecutionLimit;
+use interpreter::stack_trace::StackTrace;
 use loader::Declaration;
 use loader::declaration_name;
 use loader::SourceFile;
 use source::SOURCE_CODE_PADDING;
 use source::SourceCode;
+use source::SourceSpan;
 use tokenizer::Token;
 use typed_ast::{expr_type, TypedPattern};
 use typed_ast::TypedExpr;
@@ -21,12 +25,14 @@ use types::{Function, Value};
 use util::expression_fold::ExprTreeError;
 use util::format::print_vec;
 
+pub mod report;
+
 #[derive(PartialEq, Clone)]
 pub enum ElmError {
     Tokenizer(SourceCode, LexicalError),
     Parser(SourceCode, ParseError),
     Analyser(SourceCode, TypeError),
-    Interpreter(InterpreterError),
+    Interpreter(StackTrace, InterpreterError),
     Interop(InteropError),
     Loader(LoaderError),
     List(Vec<ElmError>),
@@ -111,6 +117,10 @@ pub enum InterpreterError {
     ExpectedAdt(Value),
     ExpectedTuple(Value),
     ExpectedList(Value),
+    ExpectedArray(Value),
+    ExpectedJson(Value),
+    ExpectedProgram(Value),
+    ExpectedTask(Value),
     ExpectedFloat(Value),
     ExpectedInt(Value),
     ExpectedChar(Value),
@@ -118,6 +128,7 @@ pub enum InterpreterError {
     ExpectedBoolean(Value),
     ExpectedNumber(Value),
     ExpectedNonEmptyList(Value),
+    ExpectedOpaque(String, Value),
     UnknownOperatorPattern(String),
     InternalErrorRecordAccess(Value),
     InternalErrorAdtCreation(Value),
@@ -128,6 +139,9 @@ pub enum InterpreterError {
     CyclicModuleDependency(Vec<Vec<String>>),
     MissingExposing(String, Vec<Declaration>),
     FunctionTODO(String),
+    ExecutionLimitExceeded(ExecutionLimit),
+    Interrupted,
+    ProcessKilled(Int),
     InternalError,
 }
 
@@ -138,6 +152,12 @@ pub enum InteropError {
     FunctionNotFound(String),
     FunRegistrationUnknownTypeArg(usize),
     FunRegistrationUnknownTypeRet,
+    PortNotFound(String),
+    PortTypeMismatch(String, Type),
+    /// Error reported by a serde implementation while converting values
+    Serde(String),
+    /// A registered closure needs a context of this type, but it was not set
+    ContextNotFound(String),
 }
 
 #[derive(Clone, Debug)]
@@ -149,12 +169,92 @@ pub enum LoaderError {
     ModulePacking { msg: String, path: String },
 }
 
+impl ElmError {
+    /// Returns the span of the code that caused the error, for runtime errors it's the span of
+    /// the innermost call frame
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ElmError::Tokenizer(_, info) => Some(info.span()),
+            ElmError::Parser(_, info) => Some(info.span()),
+            ElmError::Analyser(_, info) => info.span(),
+            ElmError::Interpreter(trace, _) => trace.first().map(|frame| frame.span),
+            ElmError::List(list) => list.iter().filter_map(|error| error.span()).next(),
+            ElmError::Interop(_) | ElmError::Loader(_) => None,
+        }
+    }
+
+    /// Resolves the span of the error to a file, line and column
+    pub fn location(&self) -> Option<SourceSpan> {
+        match self {
+            ElmError::Tokenizer(code, _) | ElmError::Parser(code, _) | ElmError::Analyser(code, _) => {
+                self.span().map(|span| code.locate(span))
+            }
+            ElmError::Interpreter(trace, _) => trace.first().map(|frame| frame.location()),
+            ElmError::List(list) => list.iter().filter_map(|error| error.location()).next(),
+            ElmError::Interop(_) | ElmError::Loader(_) => None,
+        }
+    }
+}
+
+impl LexicalError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexicalError::ReachedEnd { pos } => (*pos, *pos + 1),
+            LexicalError::UnableToTokenize { span } => *span,
+        }
+    }
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Expected { span, .. }
+            | ParseError::ExpectedInt { span, .. }
+            | ParseError::ExpectedId { span, .. }
+            | ParseError::ExpectedUpperId { span, .. }
+            | ParseError::ExpectedBinaryOperator { span, .. }
+            | ParseError::ExpectedIndentationLevel { span, .. }
+            | ParseError::ExpectedIndentation { span, .. }
+            | ParseError::UnmatchedToken { span, .. } => *span,
+        }
+    }
+}
+
+impl TypeError {
+    /// Returns None for the errors that are not related to an expression
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            TypeError::PatternMatchingError { span, .. }
+            | TypeError::MissingDefinition { span, .. }
+            | TypeError::ListNotHomogeneous { span, .. }
+            | TypeError::IfWithNonBoolCondition { span, .. }
+            | TypeError::IfBranchesDoesntMatch { span, .. }
+            | TypeError::ArgumentsDoNotMatch { span, .. }
+            | TypeError::NotAFunction { span, .. }
+            | TypeError::InvalidOperandChain { span, .. }
+            | TypeError::RecordUpdateOnNonRecord { span, .. }
+            | TypeError::RecordUpdateUnknownField { span, .. }
+            | TypeError::CaseBranchDontMatchReturnType { span, .. }
+            | TypeError::DefinitionTypeAndReturnTypeMismatch { span, .. }
+            | TypeError::VariableNameShadowed { span, .. }
+            | TypeError::TypeMatchingError { span, .. }
+            | TypeError::RecursiveTypeDefinition { span, .. }
+            | TypeError::UnknownType { span, .. } => Some(*span),
+            TypeError::UndeclaredTypeVariables { .. }
+            | TypeError::UnusedTypeVariables { .. }
+            | TypeError::InvalidFunctionPatternAmount { .. }
+            | TypeError::CyclicStatementDependency { .. }
+            | TypeError::ExpectingRecordWithName { .. } => None,
+        }
+    }
+}
+
 pub fn format_error(error: &ElmError) -> String {
     match error {
         ElmError::Tokenizer(code, info) => format_lexical_error(code, info),
         ElmError::Parser(code, info) => format_parse_error(code, info),
         ElmError::Analyser(code, info) => format_type_error(code, info),
-        ElmError::Interpreter(info) => format_runtime_error(info),
+        ElmError::Interpreter(trace, info) => format_runtime_error(trace, info),
         ElmError::Interop(info) => format_interop_error(info),
         ElmError::Loader(info) => format_loader_error(info),
         ElmError::List(list) => {
@@ -321,7 +421,7 @@ pub fn format_type_error(code: &SourceCode, error: &TypeError) -> String {
     msg
 }
 
-pub fn format_runtime_error(error: &InterpreterError) -> String {
+pub fn format_runtime_error(trace: &StackTrace, error: &InterpreterError) -> String {
     let mut msg = String::new();
     match error {
         InterpreterError::MissingDefinition(name) => {
@@ -379,6 +479,22 @@ pub fn format_runtime_error(error: &InterpreterError) -> String {
             write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
             write!(&mut msg, "I was expecting a list but found:\n\n{}\n\n", value).unwrap();
         }
+        InterpreterError::ExpectedArray(value) => {
+            write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "I was expecting an array but found:\n\n{}\n\n", value).unwrap();
+        }
+        InterpreterError::ExpectedJson(value) => {
+            write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "I was expecting a json value but found:\n\n{}\n\n", value).unwrap();
+        }
+        InterpreterError::ExpectedProgram(value) => {
+            write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "I was expecting a program created with `Platform.worker` but found:\n\n{}\n\n", value).unwrap();
+        }
+        InterpreterError::ExpectedTask(value) => {
+            write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "I was expecting a task but found:\n\n{}\n\n", value).unwrap();
+        }
         InterpreterError::ExpectedFloat(value) => {
             write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
             write!(&mut msg, "I was expecting a float but found:\n\n{}\n\n", value).unwrap();
@@ -401,6 +517,10 @@ pub fn format_runtime_error(error: &InterpreterError) -> String {
             write!(&mut msg, "I was expecting a non empty list, but found:\n\n{}\n\n", value).unwrap();
             write!(&mut msg, "Try adding a extra branch for []").unwrap();
         }
+        InterpreterError::ExpectedOpaque(name, value) => {
+            write!(&mut msg, "-- TYPE MISMATCH ----------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "I was expecting a `{}` from the host but found:\n\n{}\n\n", name, value).unwrap();
+        }
         InterpreterError::UnknownOperatorPattern(name) => {
             write!(&mut msg, "-- PARSE ERROR ------------------------------------------------------------- elm\n\n").unwrap();
             write!(&mut msg, "I cannot use the `{}` operator\n\n", name).unwrap();
@@ -414,6 +534,34 @@ pub fn format_runtime_error(error: &InterpreterError) -> String {
             write!(&mut msg, "-- RUNTIME ERROR ------------------------------------------------------------ elm\n\n").unwrap();
             write!(&mut msg, "Unable to expose '{}', available names: \n\n{:?}\n\n", name, names).unwrap();
         }
+        InterpreterError::ExecutionLimitExceeded(limit) => {
+            write!(&mut msg, "-- EXECUTION LIMIT ---------------------------------------------------------- elm\n\n").unwrap();
+            match limit {
+                ExecutionLimit::Steps(max) => {
+                    write!(&mut msg, "The evaluation did not finish after {} steps.\n\n", max).unwrap();
+                    write!(&mut msg, "Hint: Check for infinite loops, or reset the budget to continue.").unwrap();
+                }
+                ExecutionLimit::CallDepth(max) => {
+                    write!(&mut msg, "The evaluation reached the maximum depth of {} nested function calls.\n\n", max).unwrap();
+                    write!(&mut msg, "Hint: Check for infinite recursion, or try to make the recursive call the last \
+                                      operation of the function so it runs as a loop.").unwrap();
+                }
+                ExecutionLimit::ListSize(max) => {
+                    write!(&mut msg, "A list exceeded the maximum size of {} elements.\n\n", max).unwrap();
+                }
+                ExecutionLimit::StringSize(max) => {
+                    write!(&mut msg, "A string exceeded the maximum size of {} bytes.\n\n", max).unwrap();
+                }
+            }
+        }
+        InterpreterError::Interrupted => {
+            write!(&mut msg, "-- INTERRUPTED -------------------------------------------------------------- elm\n\n").unwrap();
+            write!(&mut msg, "The evaluation was interrupted before it finished.").unwrap();
+        }
+        InterpreterError::ProcessKilled(id) => {
+            write!(&mut msg, "-- RUNTIME ERROR ------------------------------------------------------------ elm\n\n").unwrap();
+            write!(&mut msg, "The process {} was killed before its task finished.", id).unwrap();
+        }
 //        RuntimeError::InternalErrorRecordAccess(_) => {}
 //        RuntimeError::InternalErrorAdtCreation(_) => {}
 //        RuntimeError::UnknownBuiltinFunction(_) => {}
@@ -423,9 +571,36 @@ pub fn format_runtime_error(error: &InterpreterError) -> String {
             write!(&mut msg, "{:?}", error).unwrap();
         }
     }
+
+    if !trace.is_empty() {
+        writeln!(&mut msg, "\n\nStack trace:").unwrap();
+        format_stack_trace(&mut msg, trace);
+    }
     msg
 }
 
+/// Max number of frames printed in a stack trace, the outermost frames are omitted
+const MAX_PRINTED_FRAMES: usize = 10;
+
+fn format_stack_trace(msg: &mut String, trace: &StackTrace) {
+    for frame in trace.iter().take(MAX_PRINTED_FRAMES) {
+        if frame.module.is_empty() {
+            writeln!(msg, "\nin `{}`:", frame.function).unwrap();
+        } else {
+            writeln!(msg, "\nin `{}` ({}):", frame.function, frame.module).unwrap();
+        }
+
+        // Packed modules don't include the source code
+        if frame.source.len() > 0 {
+            writeln!(msg, "{}", print_code_location(frame.source.as_str(), &frame.span)).unwrap();
+        }
+    }
+
+    if trace.len() > MAX_PRINTED_FRAMES {
+        writeln!(msg, "\n... {} more frames", trace.len() - MAX_PRINTED_FRAMES).unwrap();
+    }
+}
+
 pub fn format_interop_error(error: &InteropError) -> String {
     let mut msg = String::new();
     write!(&mut msg, "-- RUST INTEROP ERROR ------------------------------------------------------------ elm\n").unwrap();
@@ -552,6 +727,8 @@ impl Debug for ElmError {
     }
 }
 
+impl std::error::Error for ElmError {}
+
 impl PartialEq for LoaderError {
     fn eq(&self, other: &LoaderError) -> bool {
         match self {
@@ -585,7 +762,7 @@ impl Wrappable for InterpreterError {
     type Wrapper = ElmError;
 
     fn wrap(self) -> ElmError {
-        ElmError::Interpreter(self)
+        ElmError::Interpreter(vec![], self)
     }
 }
 
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Value as JsonValue;
+
+use errors::ElmError;
+use errors::format_error;
+use errors::LoaderError;
+use source::LineColumn;
+
+/// Error or warning with its location, used by editors and CI tools instead of the text from
+/// `format_error`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Path of the file, or 'inline' for code that was not loaded from a file
+    pub path: Option<String>,
+    /// Name of the module, from the module header or the call frame
+    pub module: Option<String>,
+    pub region: Option<Region>,
+    pub severity: Severity,
+    /// Short description like `TYPE MISMATCH`
+    pub title: String,
+    pub message: Vec<MessagePart>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Range of code, lines and columns start at 1 like in the official compiler
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Part of a message, plain text or text with a style
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum MessagePart {
+    Text(String),
+    Styled {
+        bold: bool,
+        underline: bool,
+        color: Option<String>,
+        string: String,
+    },
+}
+
+/// Converts an error into diagnostics, `ElmError::List` produces one diagnostic per error
+pub fn diagnostics(error: &ElmError) -> Vec<Diagnostic> {
+    let mut result = vec![];
+    collect_diagnostics(error, &mut result);
+    result
+}
+
+fn collect_diagnostics(error: &ElmError, result: &mut Vec<Diagnostic>) {
+    let (source, span) = match error {
+        ElmError::List(list) => {
+            for error in list {
+                collect_diagnostics(error, result);
+            }
+            return;
+        }
+        ElmError::Tokenizer(code, _) | ElmError::Parser(code, _) | ElmError::Analyser(code, _) => {
+            (Some(code.clone()), error.span())
+        }
+        ElmError::Interpreter(trace, _) => (trace.first().map(|frame| frame.source.clone()), error.span()),
+        ElmError::Interop(_) => (None, None),
+        ElmError::Loader(info) => {
+            match info {
+                LoaderError::MissingDependencies { src, .. } => (Some(src.source.clone()), None),
+                _ => (None, None),
+            }
+        }
+    };
+
+    let (title, body) = split_header(&format_error(error));
+
+    let module = match error {
+        ElmError::Interpreter(trace, _) => trace.first()
+            .map(|frame| frame.module.clone())
+            .filter(|module| !module.is_empty()),
+        _ => source.as_ref().and_then(|code| module_name(code.as_str())),
+    };
+
+    let path = match error {
+        ElmError::Loader(LoaderError::MissingDependencies { src, .. }) => Some(src.path.clone()),
+        _ => source.as_ref().map(|code| code.path().to_string()),
+    };
+
+    let region = match (&source, span) {
+        (Some(code), Some(span)) => {
+            let location = code.locate(span);
+            Some(Region { start: position(location.start), end: position(location.end) })
+        }
+        _ => None,
+    };
+
+    result.push(Diagnostic {
+        path,
+        module,
+        region,
+        severity: Severity::Error,
+        title,
+        message: vec![MessagePart::Text(body)],
+    });
+}
+
+/// Creates a report with the JSON format of `elm make --report=json`. A single error without a
+/// location produces an `error` report, everything else produces `compile-errors` grouped by file
+pub fn json_report(error: &ElmError) -> JsonValue {
+    let diagnostics = diagnostics(error);
+
+    if let [diagnostic] = diagnostics.as_slice() {
+        if diagnostic.region.is_none() {
+            return json!({
+                "type": "error",
+                "path": diagnostic.path,
+                "title": diagnostic.title,
+                "message": diagnostic.message,
+            });
+        }
+    }
+
+    // Files in the order of their first error
+    let mut files: Vec<(Option<String>, Option<String>, Vec<JsonValue>)> = vec![];
+
+    for diagnostic in &diagnostics {
+        let problem = json!({
+            "title": diagnostic.title,
+            "region": diagnostic.region.unwrap_or(Region {
+                start: Position { line: 1, column: 1 },
+                end: Position { line: 1, column: 1 },
+            }),
+            "message": diagnostic.message,
+        });
+
+        match files.iter_mut().find(|(path, _, _)| path == &diagnostic.path) {
+            Some((_, _, problems)) => problems.push(problem),
+            None => files.push((diagnostic.path.clone(), diagnostic.module.clone(), vec![problem])),
+        }
+    }
+
+    let errors = files.into_iter()
+        .map(|(path, module, problems)| json!({ "path": path, "name": module, "problems": problems }))
+        .collect::<Vec<_>>();
+
+    json!({ "type": "compile-errors", "errors": errors })
+}
+
+/// Same as `json_report` but as a string, ready to print
+pub fn format_json_report(error: &ElmError) -> String {
+    json_report(error).to_string()
+}
+
+/// Splits the text of an error in the title from the header `-- TITLE ---- elm` and the body
+fn split_header(text: &str) -> (String, String) {
+    let (header, body) = match text.find('\n') {
+        Some(index) => (&text[..index], &text[index + 1..]),
+        None => (text, ""),
+    };
+
+    let title = header
+        .trim_start_matches('-')
+        .trim_end_matches("elm")
+        .trim()
+        .trim_end_matches('-')
+        .trim();
+
+    (title.to_string(), body.trim().to_string())
+}
+
+fn position(position: LineColumn) -> Position {
+    Position { line: position.line, column: position.column }
+}
+
+/// Reads the module name from the header, `module Main exposing (..)` returns `Main`
+fn module_name(code: &str) -> Option<String> {
+    let mut words = code.split_whitespace();
+    let mut word = words.next()?;
+
+    if word == "port" || word == "effect" {
+        word = words.next()?;
+    }
+
+    if word == "module" {
+        words.next().map(|name| name.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::ParseError;
+    use Runtime;
+    use source::SourceCode;
+
+    use super::*;
+
+    #[test]
+    fn check_parse_error_report() {
+        let code = SourceCode::from_string("module Main exposing (..)\n\nx = \n\ny = if".to_string(), "src/Main.elm");
+        let error = ElmError::Parser(code, ParseError::ExpectedInt { span: (37, 39), found: ::tokenizer::Token::If });
+
+        let report = json_report(&error);
+        assert_eq!(report["type"], "compile-errors");
+        assert_eq!(report["errors"][0]["path"], "src/Main.elm");
+        assert_eq!(report["errors"][0]["name"], "Main");
+
+        let problem = &report["errors"][0]["problems"][0];
+        assert_eq!(problem["title"], "PARSE ERROR");
+        assert_eq!(problem["region"], json!({ "start": { "line": 5, "column": 5 }, "end": { "line": 5, "column": 7 } }));
+        assert!(problem["message"][0].as_str().unwrap().starts_with("Expected integer"));
+    }
+
+    #[test]
+    fn check_error_list_report() {
+        let error = Runtime::new()
+            .eval_module("module Main exposing (..)\n\nx : Int\nx = \"a\"\n\ny = z\n", "Main")
+            .unwrap_err();
+
+        let diagnostics = diagnostics(&error);
+        assert!(!diagnostics.is_empty());
+        assert!(diagnostics.iter().all(|it| it.region.is_some() && it.severity == Severity::Error));
+
+        let report = json_report(&error);
+        assert_eq!(report["type"], "compile-errors");
+        assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn check_general_error_report() {
+        let error = ElmError::Loader(LoaderError::MissingModule { module: "Main".to_string() });
+
+        assert_eq!(json_report(&error), json!({
+            "type": "error",
+            "path": null,
+            "title": "MODULE LOADING ERROR",
+            "message": ["Missing module 'Main'"],
+        }));
+    }
+}
@@ -0,0 +1,1299 @@
+use ast::AdtExposing;
+use ast::Definition;
+use ast::Exposing;
+use ast::Expr;
+use ast::Import;
+use ast::LetDeclaration;
+use ast::Literal;
+use ast::Module;
+use ast::ModuleExposing;
+use ast::ModuleHeader;
+use ast::Pattern;
+use ast::Span;
+use ast::Statement;
+use ast::Type;
+use cst::CstItemKind;
+use cst::SyntaxTree;
+use cst::Trivia;
+use cst::TriviaKind;
+use errors::ElmError;
+use source::SourceCode;
+use tokenizer::Token;
+
+/// Formats the code of a module with the style of `elm-format`, the comments are kept in
+/// the closest place where the new layout allows a comment
+pub fn format_source(code: &SourceCode) -> Result<String, ElmError> {
+    let (tree, mut errors) = SyntaxTree::parse(code);
+
+    if errors.len() == 1 {
+        return Err(errors.remove(0));
+    } else if !errors.is_empty() {
+        return Err(ElmError::List(errors));
+    }
+
+    let module = tree.lower()?;
+    let mut printer = Printer::new(Some(&tree));
+    printer.module(&module);
+
+    Ok(printer.finish())
+}
+
+/// Same as `format_source` for code that is not stored in a file
+pub fn format_code(code: &str) -> Result<String, ElmError> {
+    format_source(&SourceCode::from_str(code))
+}
+
+/// Prints a module without its original source code, like the packed core modules,
+/// the layout only depends on the AST and there are no comments
+pub fn format_module(module: &Module) -> String {
+    let mut printer = Printer::new(None);
+    printer.module(module);
+    printer.finish()
+}
+
+struct Printer<'a> {
+    tree: Option<&'a SyntaxTree>,
+    comments: Vec<&'a Trivia>,
+    next_comment: usize,
+    out: String,
+    /// Indentation of the current line, written before the first character of the line
+    pending_indent: Option<usize>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(tree: Option<&'a SyntaxTree>) -> Self {
+        Printer {
+            tree,
+            comments: tree.map(|tree| tree.comments()).unwrap_or_default(),
+            next_comment: 0,
+            out: String::new(),
+            pending_indent: None,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        while self.out.ends_with('\n') {
+            self.out.pop();
+        }
+        self.out.push('\n');
+        self.out
+    }
+
+    // Output
+
+    fn write(&mut self, text: &str) {
+        if let Some(indent) = self.pending_indent.take() {
+            self.out.extend((0..indent).map(|_| ' '));
+        }
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.pending_indent = Some(indent);
+    }
+
+    /// Starts a new line, the comments before `pos` are placed in their own lines
+    fn line_break(&mut self, indent: usize, pos: u32) {
+        self.newline(indent);
+
+        while let Some(comment) = self.next_comment_before(pos) {
+            self.comment(comment);
+            self.newline(indent);
+        }
+    }
+
+    /// Leaves `count` empty lines after the last text
+    fn blank_lines(&mut self, count: usize) {
+        while self.out.ends_with('\n') {
+            self.out.pop();
+        }
+        if !self.out.is_empty() {
+            self.out.extend((0..=count).map(|_| '\n'));
+        }
+        self.pending_indent = None;
+    }
+
+    fn column(&self) -> usize {
+        match self.pending_indent {
+            Some(indent) => indent,
+            None => self.out.chars().rev().take_while(|&c| c != '\n').count(),
+        }
+    }
+
+    // Comments
+
+    fn next_comment_before(&mut self, pos: u32) -> Option<&'a Trivia> {
+        let comment = *self.comments.get(self.next_comment)?;
+
+        if comment.span.0 < pos {
+            self.next_comment += 1;
+            Some(comment)
+        } else {
+            None
+        }
+    }
+
+    fn take_comments(&mut self, pos: u32) -> Vec<&'a Trivia> {
+        let mut comments = vec![];
+        while let Some(comment) = self.next_comment_before(pos) {
+            comments.push(comment);
+        }
+        comments
+    }
+
+    /// Writes a comment at the current column, the lines of block comments keep their
+    /// indentation relative to the first line
+    fn comment(&mut self, comment: &Trivia) {
+        let source = self.source();
+        let line_start = source[..comment.span.0 as usize].rfind('\n').map(|it| it + 1).unwrap_or(0);
+        let original_column = source[line_start..comment.span.0 as usize].chars().count();
+        let indent = self.column();
+
+        for (index, line) in comment.text.trim_end().lines().enumerate() {
+            if index == 0 {
+                self.write(line);
+                continue;
+            }
+
+            let spaces = line.chars().take(original_column).take_while(|&c| c == ' ').count();
+            let line = line[spaces..].trim_end();
+
+            if line.is_empty() {
+                self.newline(0);
+            } else {
+                self.newline(indent);
+                self.write(line);
+            }
+        }
+    }
+
+    // Source code
+
+    fn source(&self) -> &'a str {
+        self.tree.map(|tree| tree.source.as_str()).unwrap_or("")
+    }
+
+    /// Spans of expressions can include the spaces after the last token, this returns the end
+    /// of the last token before `pos`
+    fn real_end(&self, pos: u32) -> u32 {
+        match self.tree {
+            Some(tree) => {
+                let index = tree.tokens.partition_point(|token| token.span.1 <= pos);
+                if index == 0 { pos } else { tree.tokens[index - 1].span.1 }
+            }
+            None => pos,
+        }
+    }
+
+    /// Checks if the original code had a line break or a comment between `start` and `end`
+    fn breaks(&self, start: u32, end: u32) -> bool {
+        if start >= end || self.tree.is_none() {
+            return false;
+        }
+
+        let text = &self.source()[start as usize..(end as usize).min(self.source().len())];
+        let first = self.comments.partition_point(|comment| comment.span.0 < start);
+
+        text.contains('\n') || self.comments.get(first).map(|comment| comment.span.0 < end).unwrap_or(false)
+    }
+
+    fn has_blank_line(&self, start: u32, end: u32) -> bool {
+        start < end && self.source()[start as usize..end as usize].matches('\n').count() > 1
+    }
+
+    fn end_of(&self, expr: &Expr) -> u32 {
+        self.real_end(expr.get_span().1)
+    }
+
+    /// Spans of the header, imports and statements of a module, from the top level items of
+    /// the syntax tree
+    fn item_spans(&self, module: &Module) -> (Option<Span>, Vec<Span>, Vec<Span>) {
+        let tree = match self.tree {
+            Some(tree) => tree,
+            None => return (None, vec![(0, 0); module.imports.len()], vec![(0, 0); module.statements.len()]),
+        };
+
+        let span_of = |range: &::std::ops::Range<usize>| {
+            (tree.tokens[range.start].span.0, tree.tokens[range.end - 1].span.1)
+        };
+
+        let mut header = None;
+        let mut imports = vec![];
+        let mut declarations = vec![];
+
+        for item in &tree.items {
+            match item.kind {
+                CstItemKind::ModuleHeader => header = Some(span_of(&item.tokens)),
+                CstItemKind::Import => imports.push(span_of(&item.tokens)),
+                CstItemKind::Declaration => declarations.push(span_of(&item.tokens)),
+            }
+        }
+
+        // Type annotations are in a different line than the definition
+        let eof = self.source().len() as u32;
+        let mut declarations = declarations.into_iter();
+        let statements = module.statements.iter()
+            .map(|stm| {
+                let first = declarations.next().unwrap_or((eof, eof));
+                match stm {
+                    Statement::Def(Definition { header: Some(_), .. }) => {
+                        (first.0, declarations.next().map(|it| it.1).unwrap_or(first.1))
+                    }
+                    _ => first
+                }
+            })
+            .collect();
+
+        imports.resize(module.imports.len(), (eof, eof));
+        (header, imports, statements)
+    }
+
+    // Module
+
+    fn module(&mut self, module: &Module) {
+        let (header_span, import_spans, statement_spans) = self.item_spans(module);
+        let mut last_end = 0;
+
+        if let Some(header) = &module.header {
+            let span = header_span.unwrap_or((0, 0));
+
+            for comment in self.take_comments(span.0) {
+                self.comment(comment);
+                self.newline(0);
+            }
+
+            self.module_header(header, span);
+            last_end = span.1;
+        }
+
+        if !module.imports.is_empty() {
+            let mut imports = module.imports.iter()
+                .zip(import_spans.iter())
+                .map(|(import, span)| (self.take_comments(span.0), import))
+                .collect::<Vec<_>>();
+
+            // Comments before the first import are documentation of the module, except the ones
+            // that are attached to the import
+            let first_import = import_spans[0].0;
+            let docs_len = imports[0].0.iter()
+                .enumerate()
+                .filter(|(index, comment)| {
+                    let next = imports[0].0.get(index + 1).map(|it| it.span.0).unwrap_or(first_import);
+                    comment.kind == TriviaKind::DocComment || self.has_blank_line(comment.span.1, next)
+                })
+                .map(|(index, _)| index + 1)
+                .last()
+                .unwrap_or(0);
+            let docs = imports[0].0.drain(..docs_len).collect::<Vec<_>>();
+
+            if !docs.is_empty() {
+                self.blank_lines(1);
+                for comment in docs {
+                    self.comment(comment);
+                    self.newline(0);
+                }
+            }
+
+            self.blank_lines(1);
+            imports.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+
+            for (comments, import) in imports {
+                for comment in comments {
+                    self.comment(comment);
+                    self.newline(0);
+                }
+                self.import(import);
+                self.newline(0);
+            }
+
+            last_end = import_spans.last().unwrap().1;
+        }
+
+        for (stm, span) in module.statements.iter().zip(statement_spans.iter()) {
+            let comments = self.take_comments(span.0);
+
+            self.blank_lines(2);
+
+            // Groups of comments separated by empty lines are sections, like `-- MODEL`,
+            // the last group is attached to the statement if there is no empty line between them
+            let separated = comments.iter().enumerate()
+                .map(|(index, comment)| {
+                    let next = comments.get(index + 1).map(|it| it.span.0).unwrap_or(span.0);
+                    // Comments inside the previous item are moved after it
+                    (comment.span.0 < last_end && next >= last_end) || self.has_blank_line(comment.span.1, next)
+                })
+                .collect::<Vec<_>>();
+
+            for (index, comment) in comments.iter().enumerate() {
+                if index == 0 || separated[index - 1] {
+                    let section = separated[index..].contains(&true);
+                    self.blank_lines(if section { 3 } else { 2 });
+                }
+
+                self.comment(comment);
+                self.newline(0);
+
+                if separated[index] {
+                    self.blank_lines(2);
+                }
+            }
+
+            self.statement(stm, *span);
+            last_end = span.1;
+        }
+
+        let comments = self.take_comments(u32::max_value());
+        if !comments.is_empty() {
+            self.blank_lines(3);
+            for comment in comments {
+                self.comment(comment);
+                self.newline(0);
+            }
+        }
+    }
+
+    fn module_header(&mut self, header: &ModuleHeader, span: Span) {
+        let tokens = self.tree
+            .map(|tree| tree.tokens.iter().filter(|tk| tk.span.0 >= span.0 && tk.span.1 <= span.1).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        match tokens.first().map(|tk| &tk.token) {
+            Some(Token::Port) => self.write("port module "),
+            Some(Token::EffectTk) => self.write("effect module "),
+            _ => self.write("module "),
+        }
+
+        self.write(&header.name);
+
+        // The AST doesn't store the `where` of effect modules
+        let clause = tokens.iter()
+            .skip_while(|tk| tk.token != Token::WhereTk)
+            .take_while(|tk| tk.token != Token::ExposingTk)
+            .map(|tk| tk.text.as_str())
+            .collect::<Vec<_>>();
+
+        if !clause.is_empty() {
+            self.write(" ");
+            self.write(&clause.join(" "));
+        }
+
+        self.write(" exposing ");
+        self.write(&exposing(&header.exposing));
+    }
+
+    fn import(&mut self, import: &Import) {
+        self.write("import ");
+        self.write(&import.path.join("."));
+
+        if let Some(alias) = &import.alias {
+            self.write(" as ");
+            self.write(alias);
+        }
+
+        if let Some(list) = &import.exposing {
+            self.write(" exposing ");
+            self.write(&exposing(list));
+        }
+    }
+
+    // Statements
+
+    fn statement(&mut self, stm: &Statement, span: Span) {
+        match stm {
+            Statement::Alias(name, vars, ty) => {
+                self.write("type alias ");
+                self.write(&type_header(name, vars));
+                self.write(" =");
+                self.newline(4);
+
+                if self.breaks_record_type(span) {
+                    self.record_type(ty);
+                } else {
+                    self.write(&type_to_string(ty));
+                }
+            }
+            Statement::Adt(name, vars, variants) => {
+                self.write("type ");
+                self.write(&type_header(name, vars));
+
+                for (index, (variant_span, variant, params)) in variants.iter().enumerate() {
+                    self.line_break(4, variant_span.0);
+                    self.write(if index == 0 { "= " } else { "| " });
+                    self.write(variant);
+
+                    for param in params {
+                        self.write(" ");
+                        self.write(&type_arg_to_string(param));
+                    }
+                }
+            }
+            Statement::Port(_, name, ty) => {
+                self.write("port ");
+                self.write(name);
+                self.write(" : ");
+                self.write(&type_to_string(ty));
+            }
+            Statement::Def(def) => {
+                self.definition(def, 0);
+            }
+            Statement::Infix(direction, level, op, func) => {
+                self.write(&format!("infix {} {} ({}) = {}", direction, level, op, func));
+            }
+        }
+    }
+
+    /// Records of type aliases use one line per field if they did in the original code
+    fn breaks_record_type(&self, span: Span) -> bool {
+        let text = &self.source()[span.0 as usize..(span.1 as usize).min(self.source().len())];
+
+        match text.find('=').and_then(|eq| text[eq..].find('{').map(|brace| eq + brace)) {
+            Some(brace) => text[..brace].trim_end().ends_with('=') && text[brace..].contains('\n'),
+            None => false,
+        }
+    }
+
+    fn record_type(&mut self, ty: &Type) {
+        match ty {
+            Type::Record(fields) if !fields.is_empty() => {
+                for (index, (name, ty)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        self.newline(4);
+                    }
+                    self.write(if index == 0 { "{ " } else { ", " });
+                    self.write(&format!("{} : {}", name, type_to_string(ty)));
+                }
+                self.newline(4);
+                self.write("}");
+            }
+            Type::RecExt(base, fields) if !fields.is_empty() => {
+                self.write("{ ");
+                self.write(base);
+
+                for (index, (name, ty)) in fields.iter().enumerate() {
+                    self.newline(8);
+                    self.write(if index == 0 { "| " } else { ", " });
+                    self.write(&format!("{} : {}", name, type_to_string(ty)));
+                }
+                self.newline(4);
+                self.write("}");
+            }
+            _ => self.write(&type_to_string(ty)),
+        }
+    }
+
+    fn definition(&mut self, def: &Definition, indent: usize) {
+        if let Some(ty) = &def.header {
+            self.write(&format!("{} : {}", def.name, type_to_string(ty)));
+            self.newline(indent);
+        }
+
+        self.write(&def.name);
+        for pattern in &def.patterns {
+            self.write(" ");
+            self.write(&pattern_arg_to_string(pattern));
+        }
+        self.write(" =");
+
+        self.line_break(indent + 4, def.expr.get_span().0);
+        self.expr(&def.expr, indent + 4);
+    }
+
+    // Expressions
+
+    /// Writes an expression, `indent` is the indentation of the line where the expression starts,
+    /// the lines of lists, records and tuples are aligned with their first line instead
+    fn expr(&mut self, expr: &Expr, indent: usize) {
+        match expr {
+            Expr::Unit(_) => self.write("()"),
+            Expr::Literal(span, literal) => {
+                let text = self.literal(*span, literal);
+                self.write(&text);
+            }
+            Expr::Ref(_, name) => {
+                if is_operator(name) {
+                    self.write(&format!("({})", name));
+                } else {
+                    self.write(name);
+                }
+            }
+            Expr::QualifiedRef(_, path, name) => {
+                self.write(&path.join("."));
+                self.write(".");
+                self.write(name);
+            }
+            Expr::RecordAccess(_, name) => {
+                self.write(".");
+                self.write(name);
+            }
+            Expr::RecordField(_, record, name) => {
+                if is_atom(record) {
+                    self.expr(record, indent);
+                } else {
+                    self.parens(record, indent);
+                }
+                self.write(".");
+                self.write(name);
+            }
+            Expr::Tuple(span, items) => {
+                self.sequence("(", ")", items, *span, self.is_multiline(expr));
+            }
+            Expr::List(span, items) => {
+                if items.is_empty() {
+                    self.write("[]");
+                } else {
+                    self.sequence("[", "]", items, *span, self.is_multiline(expr));
+                }
+            }
+            Expr::Record(span, fields) => {
+                if fields.is_empty() {
+                    self.write("{}");
+                } else {
+                    self.record(None, fields, *span, self.is_multiline(expr));
+                }
+            }
+            Expr::RecordUpdate(span, name, fields) => {
+                self.record(Some(name), fields, *span, self.is_multiline(expr));
+            }
+            Expr::If(_, cond, then, otherwise) => {
+                let column = self.column();
+                self.if_expr(cond, then, otherwise, indent, column);
+            }
+            Expr::Case(_, subject, branches) => {
+                self.write("case ");
+                self.expr(subject, indent);
+                self.write(" of");
+
+                for (index, (pattern, branch)) in branches.iter().enumerate() {
+                    if index > 0 {
+                        self.newline(indent + 4);
+                    }
+                    self.line_break(indent + 4, pattern.get_span().0);
+                    self.write(&pattern_to_string(pattern));
+                    self.write(" ->");
+                    self.line_break(indent + 8, branch.get_span().0);
+                    self.expr(branch, indent + 8);
+                }
+            }
+            Expr::Lambda(_, patterns, body) => {
+                self.write("\\");
+                self.write(&patterns.iter().map(pattern_arg_to_string).collect::<Vec<_>>().join(" "));
+                self.write(" ->");
+
+                if self.is_multiline(expr) {
+                    self.line_break(indent + 4, body.get_span().0);
+                    self.expr(body, indent + 4);
+                } else {
+                    self.write(" ");
+                    self.expr(body, indent);
+                }
+            }
+            Expr::Application(..) => {
+                self.application(expr, indent);
+            }
+            Expr::Let(_, declarations, body) => {
+                self.write("let");
+
+                for (index, declaration) in declarations.iter().enumerate() {
+                    if index > 0 {
+                        self.newline(indent + 4);
+                    }
+
+                    match declaration {
+                        LetDeclaration::Def(def) => {
+                            let start = def.patterns.first().map(|it| it.get_span().0).unwrap_or(def.expr.get_span().0);
+                            self.line_break(indent + 4, start);
+                            self.definition(def, indent + 4);
+                        }
+                        LetDeclaration::Pattern(pattern, value) => {
+                            self.line_break(indent + 4, pattern.get_span().0);
+                            self.write(&pattern_arg_to_string(pattern));
+                            self.write(" =");
+                            self.line_break(indent + 8, value.get_span().0);
+                            self.expr(value, indent + 8);
+                        }
+                    }
+                }
+
+                self.line_break(indent, body.get_span().0);
+                self.write("in");
+                self.line_break(indent, body.get_span().0);
+                self.expr(body, indent);
+            }
+            Expr::OpChain(_, exprs, ops) => {
+                let multiline = self.is_multiline(expr);
+                self.chain_item(&exprs[0], indent, false);
+
+                for (index, (op, item)) in ops.iter().zip(exprs.iter().skip(1)).enumerate() {
+                    let last = index == ops.len() - 1;
+
+                    if multiline && op == "<|" {
+                        // `f <|` keeps the operator at the end of the line
+                        self.write(" <|");
+                        self.line_break(indent + 4, item.get_span().0);
+                        self.chain_item(item, indent + 4, last);
+                    } else if multiline {
+                        self.line_break(indent + 4, item.get_span().0);
+                        self.write(op);
+                        self.write(" ");
+                        self.chain_item(item, indent + 4, last);
+                    } else {
+                        self.write(&format!(" {} ", op));
+                        self.chain_item(item, indent, last);
+                    }
+                }
+            }
+        }
+    }
+
+    fn literal(&self, span: Span, literal: &Literal) -> String {
+        // Keep the original text to avoid changes in hex numbers, floats and escaped characters
+        if self.tree.is_some() {
+            let text = &self.source()[span.0 as usize..(span.1 as usize).min(self.source().len())];
+            if !text.is_empty() && !text.starts_with("\"\"\"") {
+                return text.to_string();
+            }
+        }
+
+        literal_to_string(literal)
+    }
+
+    /// Tuples and lists
+    fn sequence(&mut self, open: &str, close: &str, items: &[Expr], span: Span, multiline: bool) {
+        let column = self.column();
+
+        for (index, item) in items.iter().enumerate() {
+            if index == 0 {
+                self.write(open);
+                self.write(" ");
+            } else if multiline {
+                self.line_break(column, item.get_span().0);
+                self.write(", ");
+            } else {
+                self.write(", ");
+            }
+            self.expr(item, column);
+        }
+
+        if multiline {
+            let end = self.real_end(span.1);
+            self.line_break(column, end);
+        } else {
+            self.write(" ");
+        }
+        self.write(close);
+    }
+
+    /// Records and record updates
+    fn record(&mut self, base: Option<&String>, fields: &[(String, Expr)], span: Span, multiline: bool) {
+        let column = self.column();
+        let field_column = if base.is_some() { column + 4 } else { column };
+
+        self.write("{ ");
+        if let Some(base) = base {
+            self.write(base);
+        }
+
+        for (index, (name, value)) in fields.iter().enumerate() {
+            let separator = if index == 0 { "| " } else { ", " };
+
+            if index == 0 && base.is_none() {
+                // Placed after `{ `
+            } else if multiline {
+                self.line_break(field_column, value.get_span().0);
+                self.write(separator);
+            } else {
+                self.write(if index == 0 { " | " } else { ", " });
+            }
+
+            self.write(name);
+            self.write(" =");
+
+            if self.is_multiline(value) {
+                self.line_break(field_column + 4, value.get_span().0);
+                self.expr(value, field_column + 4);
+            } else {
+                self.write(" ");
+                self.expr(value, field_column);
+            }
+        }
+
+        if multiline {
+            let end = self.real_end(span.1);
+            self.line_break(column, end);
+        } else {
+            self.write(" ");
+        }
+        self.write("}");
+    }
+
+    /// The `else` is aligned with the `if`, which is not the start of the line inside parenthesis
+    fn if_expr(&mut self, cond: &Expr, then: &Expr, otherwise: &Expr, indent: usize, column: usize) {
+        self.write("if ");
+        self.expr(cond, indent);
+        self.write(" then");
+        self.line_break(indent + 4, then.get_span().0);
+        self.expr(then, indent + 4);
+        self.newline(column);
+        self.line_break(column, otherwise.get_span().0);
+        self.write("else");
+
+        if let Expr::If(_, cond, then, otherwise) = otherwise {
+            self.write(" ");
+            self.if_expr(cond, then, otherwise, indent, column);
+        } else {
+            self.line_break(indent + 4, otherwise.get_span().0);
+            self.expr(otherwise, indent + 4);
+        }
+    }
+
+    fn application(&mut self, expr: &Expr, indent: usize) {
+        // Prefix minus: -x
+        if let Expr::Application(_, _, arg) = expr {
+            if is_minus(expr) {
+                self.write("-");
+                self.arg(arg, indent);
+                return;
+            }
+        }
+
+        let mut args = vec![];
+        let mut func = expr;
+
+        while let Expr::Application(_, next, arg) = func {
+            if is_minus(func) {
+                break;
+            }
+            args.push(&**arg);
+            func = next;
+        }
+        args.reverse();
+
+        self.arg(func, indent);
+
+        if !self.is_multiline(expr) {
+            for arg in args {
+                self.write(" ");
+                self.arg(arg, indent);
+            }
+            return;
+        }
+
+        // The first argument can stay in the line of the function, like `div []`
+        let join_first = !self.is_multiline(args[0])
+            && !self.breaks(self.end_of(func), args[0].get_span().0);
+
+        for (index, arg) in args.into_iter().enumerate() {
+            if index == 0 && join_first {
+                self.write(" ");
+                self.arg(arg, indent);
+            } else {
+                self.line_break(indent + 4, arg.get_span().0);
+                self.arg(arg, indent + 4);
+            }
+        }
+    }
+
+    /// Function or argument of a function application
+    fn arg(&mut self, expr: &Expr, indent: usize) {
+        if is_atom(expr) || is_minus(expr) {
+            self.expr(expr, indent);
+        } else {
+            self.parens(expr, indent);
+        }
+    }
+
+    /// Operand of a binary operator, the last one can be a lambda or an `if` without parenthesis
+    /// because it extends until the end of the expression
+    fn chain_item(&mut self, expr: &Expr, indent: usize, last: bool) {
+        match expr {
+            Expr::If(..) | Expr::Case(..) | Expr::Let(..) | Expr::Lambda(..) if last => {
+                self.expr(expr, indent)
+            }
+            Expr::OpChain(..) | Expr::If(..) | Expr::Case(..) | Expr::Let(..) | Expr::Lambda(..) => {
+                self.parens(expr, indent)
+            }
+            _ => self.expr(expr, indent),
+        }
+    }
+
+    fn parens(&mut self, expr: &Expr, indent: usize) {
+        self.write("(");
+        self.expr(expr, indent);
+
+        if self.is_multiline(expr) {
+            let end = self.end_of(expr);
+            self.line_break(indent, end);
+        }
+        self.write(")");
+    }
+
+    /// Decides if an expression needs more than one line, this happens when the original code
+    /// had line breaks or comments inside it, or if it contains `case`, `let` or `if` expressions
+    fn is_multiline(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Case(..) | Expr::Let(..) | Expr::If(..) => return true,
+            _ => {}
+        }
+
+        if self.breaks(expr.get_span().0, self.end_of(expr)) {
+            return true;
+        }
+
+        match expr {
+            Expr::Tuple(_, items) | Expr::List(_, items) | Expr::OpChain(_, items, _) => {
+                items.iter().any(|it| self.is_multiline(it))
+            }
+            Expr::Record(_, fields) | Expr::RecordUpdate(_, _, fields) => {
+                fields.iter().any(|(_, it)| self.is_multiline(it))
+            }
+            Expr::RecordField(_, a, _) | Expr::Lambda(_, _, a) => self.is_multiline(a),
+            Expr::If(_, a, b, c) => self.is_multiline(a) || self.is_multiline(b) || self.is_multiline(c),
+            Expr::Application(_, a, b) => self.is_multiline(a) || self.is_multiline(b),
+            _ => false,
+        }
+    }
+}
+
+fn is_atom(expr: &Expr) -> bool {
+    match expr {
+        Expr::Unit(_) | Expr::Tuple(..) | Expr::List(..) | Expr::Record(..) | Expr::RecordUpdate(..)
+        | Expr::QualifiedRef(..) | Expr::RecordAccess(..) | Expr::RecordField(..) | Expr::Literal(..)
+        | Expr::Ref(..) => true,
+        _ => false,
+    }
+}
+
+fn is_minus(expr: &Expr) -> bool {
+    match expr {
+        Expr::Application(_, func, _) => match &**func {
+            Expr::Ref(_, name) => name == "__internal__minus",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_operator(name: &str) -> bool {
+    name.chars().next().map(|c| !c.is_alphanumeric() && c != '_').unwrap_or(false)
+}
+
+fn literal_to_string(literal: &Literal) -> String {
+    match literal {
+        Literal::Int(value) => value.to_string(),
+        Literal::Float(value) => {
+            let text = value.to_string();
+            if text.contains('.') { text } else { format!("{}.0", text) }
+        }
+        Literal::String(value) => string_to_string(value),
+        Literal::Char(value) => format!("'{}'", value),
+    }
+}
+
+fn string_to_string(value: &str) -> String {
+    let mut text = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '"' => text.push_str("\\\""),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            _ => text.push(c),
+        }
+    }
+
+    text.push('"');
+    text
+}
+
+/// Exposing lists are sorted: operators, types and then definitions
+fn exposing(exposing: &ModuleExposing) -> String {
+    let list = match exposing {
+        ModuleExposing::All => return "(..)".to_string(),
+        ModuleExposing::Just(list) => list,
+    };
+
+    let mut items = list.iter()
+        .map(|item| match item {
+            Exposing::BinaryOperator(op) => (0, format!("({})", op)),
+            Exposing::Type(name) => (1, name.clone()),
+            Exposing::Adt(name, AdtExposing::All) => (1, format!("{}(..)", name)),
+            Exposing::Adt(name, AdtExposing::Variants(variants)) => (1, format!("{}({})", name, variants.join(", "))),
+            Exposing::Definition(name) => (2, name.clone()),
+        })
+        .collect::<Vec<_>>();
+
+    items.sort();
+    items.dedup();
+
+    let names = items.into_iter().map(|(_, name)| name).collect::<Vec<_>>();
+    format!("({})", names.join(", "))
+}
+
+fn type_header(name: &str, vars: &[String]) -> String {
+    let mut text = name.to_string();
+    for var in vars {
+        text.push(' ');
+        text.push_str(var);
+    }
+    text
+}
+
+fn type_to_string(ty: &Type) -> String {
+    match ty {
+        Type::Unit => "()".to_string(),
+        Type::Var(name) => name.clone(),
+        Type::Tag(name, args) => {
+            let mut text = name.clone();
+            for arg in args {
+                text.push(' ');
+                text.push_str(&type_arg_to_string(arg));
+            }
+            text
+        }
+        Type::Fun(input, output) => {
+            let input = match **input {
+                Type::Fun(..) => format!("({})", type_to_string(input)),
+                _ => type_to_string(input),
+            };
+            format!("{} -> {}", input, type_to_string(output))
+        }
+        Type::Tuple(items) => {
+            format!("( {} )", items.iter().map(type_to_string).collect::<Vec<_>>().join(", "))
+        }
+        Type::Record(fields) => {
+            if fields.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", fields_to_string(fields))
+            }
+        }
+        Type::RecExt(base, fields) => {
+            format!("{{ {} | {} }}", base, fields_to_string(fields))
+        }
+    }
+}
+
+fn fields_to_string(fields: &[(String, Type)]) -> String {
+    fields.iter()
+        .map(|(name, ty)| format!("{} : {}", name, type_to_string(ty)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Argument of a type constructor
+fn type_arg_to_string(ty: &Type) -> String {
+    match ty {
+        Type::Fun(..) => format!("({})", type_to_string(ty)),
+        Type::Tag(_, args) if !args.is_empty() => format!("({})", type_to_string(ty)),
+        _ => type_to_string(ty),
+    }
+}
+
+fn pattern_to_string(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Var(_, name) => name.clone(),
+        Pattern::Adt(_, name, args) => {
+            let mut text = name.clone();
+            for arg in args {
+                text.push(' ');
+                text.push_str(&pattern_arg_to_string(arg));
+            }
+            text
+        }
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Unit(_) => "()".to_string(),
+        Pattern::Tuple(_, items) => {
+            format!("( {} )", items.iter().map(pattern_to_string).collect::<Vec<_>>().join(", "))
+        }
+        Pattern::List(_, items) => {
+            if items.is_empty() {
+                "[]".to_string()
+            } else {
+                format!("[ {} ]", items.iter().map(pattern_to_string).collect::<Vec<_>>().join(", "))
+            }
+        }
+        Pattern::BinaryOp(_, op, left, right) => {
+            let left = match **left {
+                Pattern::BinaryOp(..) | Pattern::Alias(..) => format!("({})", pattern_to_string(left)),
+                _ => pattern_to_string(left),
+            };
+            let right = match **right {
+                Pattern::Alias(..) => format!("({})", pattern_to_string(right)),
+                _ => pattern_to_string(right),
+            };
+            format!("{} {} {}", left, op, right)
+        }
+        Pattern::Record(_, fields) => {
+            if fields.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", fields.join(", "))
+            }
+        }
+        Pattern::LitInt(_, value) => value.to_string(),
+        Pattern::LitString(_, value) => string_to_string(value),
+        Pattern::LitChar(_, value) => format!("'{}'", value),
+        Pattern::Alias(_, pattern, name) => {
+            match **pattern {
+                Pattern::BinaryOp(..) | Pattern::Alias(..) => format!("({}) as {}", pattern_to_string(pattern), name),
+                _ => format!("{} as {}", pattern_to_string(pattern), name),
+            }
+        }
+    }
+}
+
+/// Argument of a function, lambda or constructor
+fn pattern_arg_to_string(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Adt(_, _, args) if !args.is_empty() => format!("({})", pattern_to_string(pattern)),
+        Pattern::BinaryOp(..) | Pattern::Alias(..) => format!("({})", pattern_to_string(pattern)),
+        _ => pattern_to_string(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json;
+
+    use parsers::Parser;
+    use tokenizer::Tokenizer;
+    use util::resource_path;
+
+    use super::*;
+
+    const UNFORMATTED: &str = r#"module Main exposing (view, Msg(..), Model, update)
+import Html exposing (text, div)
+import Dict
+type alias Model = { count : Int, names : List String }
+type Msg = Increment | Decrement Int
+update msg model =
+  case msg of
+    Increment -> { model | count = model.count + 1 }
+    Decrement amount -> if amount > model.count then model else { model | count = model.count - amount }
+view : Model -> Html.Html msg
+view model =
+  let
+    label = String.fromInt model.count
+    (first, rest) = (List.head model.names, List.drop 1 model.names)
+  in
+  div [] [ text label
+    , text (if model.count == 0 then "none" else "some") ]
+"#;
+
+    const FORMATTED: &str = r#"module Main exposing (Model, Msg(..), update, view)
+
+import Dict
+import Html exposing (div, text)
+
+
+type alias Model =
+    { count : Int, names : List String }
+
+
+type Msg
+    = Increment
+    | Decrement Int
+
+
+update msg model =
+    case msg of
+        Increment ->
+            { model | count = model.count + 1 }
+
+        Decrement amount ->
+            if amount > model.count then
+                model
+
+            else
+                { model | count = model.count - amount }
+
+
+view : Model -> Html.Html msg
+view model =
+    let
+        label =
+            String.fromInt model.count
+
+        ( first, rest ) =
+            ( List.head model.names, List.drop 1 model.names )
+    in
+    div []
+        [ text label
+        , text
+            (if model.count == 0 then
+                "none"
+
+             else
+                "some"
+            )
+        ]
+"#;
+
+    const COMMENTED: &str = r#"module Main exposing (..)
+
+-- MODEL
+type alias Model = Int
+
+{-| Initial model -}
+init : Model
+init = {- zero -} 0 -- trailing
+
+
+-- UPDATE
+
+update msg model = model
+"#;
+
+    const COMMENTED_FORMATTED: &str = r#"module Main exposing (..)
+
+
+-- MODEL
+type alias Model =
+    Int
+
+
+{-| Initial model -}
+init : Model
+init =
+    {- zero -}
+    0
+
+
+
+-- trailing
+
+
+
+-- UPDATE
+
+
+update msg model =
+    model
+"#;
+
+    fn parse(code: &str) -> Module {
+        Parser::new(Tokenizer::new(&SourceCode::from_str(code))).parse_module().unwrap()
+    }
+
+    fn comments(code: &str) -> Vec<String> {
+        let (tree, _) = SyntaxTree::parse(&SourceCode::from_str(code));
+        let mut comments = tree.comments().iter()
+            .map(|it| it.text.lines().next().unwrap_or("").trim().to_string())
+            .collect::<Vec<_>>();
+
+        comments.sort();
+        comments
+    }
+
+    /// The result must not change if it's formatted again, must have the same AST and comments
+    fn check_formatted(code: &str) {
+        let formatted = format_code(code).unwrap();
+
+        assert_eq!(format_code(&formatted).unwrap(), formatted);
+        // Without source code the result only depends on the AST, spans and order of imports are ignored
+        assert_eq!(format_module(&parse(&formatted)), format_module(&parse(code)));
+        assert_eq!(comments(&formatted), comments(code));
+    }
+
+    #[test]
+    fn check_layout() {
+        assert_eq!(format_code(UNFORMATTED).unwrap(), FORMATTED);
+        check_formatted(UNFORMATTED);
+    }
+
+    #[test]
+    fn check_comments() {
+        assert_eq!(format_code(COMMENTED).unwrap(), COMMENTED_FORMATTED);
+        check_formatted(COMMENTED);
+        check_formatted(r#"port module Main exposing (main, (|>))
+
+{-| Module docs
+    indented line
+-}
+
+import Json.Decode as D
+-- about html
+import Html exposing (text)
+
+type alias Model = { count : Int -- the count
+                   , name : String }
+
+type Msg = Increment -- plus one
+    | Decrement
+
+update msg model = -- body
+    case msg of
+        -- first
+        Increment -> { model | count = model.count + 1 }
+        Decrement ->
+            {- block -} { model | count = model.count - 1, name = "x" }
+
+view model =
+    let
+        -- local
+        x = [ 1, 2 -- two
+            , 3 ]
+    in
+    text <| if x == [] then "a" else if model.count > 2 then "b" else "c"
+
+pipe list = list
+    |> List.map (\x -> -x + 1)
+    |> List.filter (\x ->
+        x > 2)
+-- end
+"#);
+    }
+
+    #[test]
+    fn check_errors() {
+        assert!(format_code("x = [").is_err());
+        assert!(format_code("x = ¤").is_err());
+    }
+
+    #[test]
+    fn check_idempotence_project() {
+        let mut folders = vec![resource_path("test"), resource_path("benches")];
+
+        while let Some(folder) = folders.pop() {
+            for entry in fs::read_dir(&folder).unwrap() {
+                let path = entry.unwrap().path();
+
+                if path.is_dir() {
+                    folders.push(path.to_string_lossy().to_string());
+                } else if path.extension().map(|ext| ext == "elm").unwrap_or(false) {
+                    check_formatted(&fs::read_to_string(&path).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_idempotence_packed_modules() {
+        for folder in &["packed_modules/core", "packed_modules/json"] {
+            for entry in fs::read_dir(resource_path(folder)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+                    continue;
+                }
+
+                let module: Module = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+                let code = format_module(&module);
+                let parsed = parse(&code);
+
+                assert_eq!(format_module(&parsed), code, "{}", path.display());
+                assert_eq!(format_code(&code).unwrap(), code, "{}", path.display());
+
+                let definitions = |module: &Module| module.statements.iter()
+                    .filter_map(|stm| if let Statement::Def(def) = stm { Some(def.clone()) } else { None })
+                    .collect::<Vec<_>>();
+
+                assert_eq!(definitions(&parsed), definitions(&module), "{}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,649 @@
+use std::sync::Arc;
+
+use ast::Int;
+use ast::Span;
+use ast::Type;
+use builtin::record_access;
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use interpreter::closure_helper::is_pending_constant;
+use interpreter::closure_helper::let_components;
+use interpreter::runtime_stack::RuntimeStack;
+use interpreter::runtime_stack::GlobalId;
+use interpreter::runtime_stack::Globals;
+use interpreter::stack_trace::CodeOrigin;
+use typed_ast::{LetEntry, TypedDefinition, TypedExpr, TypedPattern};
+use types::Value;
+
+/// Bytecode instruction of the virtual machine, operands are indices in the tables of the `Proto`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// Pushes a value from the constant table
+    Const(usize),
+    /// Pushes the value of a local slot
+    LoadLocal(usize),
+    /// Pops a value into a local slot
+    StoreLocal(usize),
+    /// Pushes a value captured by the closure
+    LoadCapture(usize),
+    /// Pushes a function of the group of this closure, used for recursive calls
+    LoadSibling(usize),
+    /// Pushes a definition that was not available when the code was compiled, the name is
+    /// interned during the compilation
+    LoadGlobal(GlobalId),
+    /// Pops N values and pushes a tuple
+    Tuple(usize),
+    /// Pops N values and pushes a list
+    List(usize),
+    /// Pops the values of the fields of the record, in the order of the field table
+    Record(usize),
+    /// Pops the new values of the fields of the field table and then the record to update
+    RecordUpdate(usize),
+    /// Pops a record and pushes the value of the field with the name from the name table
+    RecordField(usize),
+    /// Pops the captured values of a closure group and pushes all the functions of the group
+    MakeClosures(usize, usize),
+    /// Pops N arguments and a function and pushes the result of the call
+    Call(usize),
+    /// Same as `Call`, but the result is returned from the current function, reusing its frame
+    TailCall(usize),
+    /// Pops a value and returns it from the current function
+    Return,
+    Jump(usize),
+    /// Pops a boolean and jumps if it is false
+    JumpIfFalse(usize),
+    /// Pops a value, if it matches the pattern its variables are stored, otherwise jumps
+    Match(usize, usize),
+    /// Pops a value that must match the pattern, used in let declarations
+    Destructure(usize),
+    /// Pops the value of a case expression that didn't match any branch
+    CaseFail(usize),
+}
+
+/// Pattern with the variables already resolved to local slots
+#[derive(Clone, Debug, PartialEq)]
+pub enum SlotPattern {
+    Var(usize),
+    Wildcard,
+    Unit,
+    Alias(Box<SlotPattern>, usize),
+    Adt(String, Vec<SlotPattern>),
+    Tuple(Vec<SlotPattern>),
+    List(Vec<SlotPattern>),
+    Cons(Box<SlotPattern>, Box<SlotPattern>),
+    Record(Vec<(String, usize)>),
+    LitInt(Int),
+    LitString(String),
+    LitChar(char),
+}
+
+/// Compiled function, the arguments are stored in the first slots of the frame
+#[derive(Debug)]
+pub struct Proto {
+    pub name: Option<String>,
+    /// Expression evaluated outside any function, in stack traces it is the top level frame
+    pub top_level: bool,
+    pub arity: usize,
+    pub slots: usize,
+    pub function_type: Type,
+    pub code: Vec<Op>,
+    /// Span of the expression that generated each instruction
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub fields: Vec<Vec<String>>,
+    pub patterns: Vec<SlotPattern>,
+    /// Original patterns of each case expression, used for error messages
+    pub cases: Vec<Vec<TypedPattern>>,
+    pub groups: Vec<Arc<ProtoGroup>>,
+}
+
+/// Functions that are created together and can call each other, like the functions
+/// in a let expression. All of them share the same captured values.
+#[derive(Debug)]
+pub struct ProtoGroup {
+    pub members: Vec<Arc<Proto>>,
+}
+
+/// Values captured by a closure group when it was created
+#[derive(Debug)]
+pub struct ClosureEnv {
+    pub group: Arc<ProtoGroup>,
+    pub captures: Vec<Value>,
+    pub origin: Arc<CodeOrigin>,
+    pub globals: Arc<Globals>,
+}
+
+/// Location of a variable from the point of view of a function
+#[derive(Clone, Debug, PartialEq)]
+enum VarRef {
+    Local(usize),
+    Capture(usize),
+    Sibling(usize),
+    Global(GlobalId),
+}
+
+/// Place the value of a captured variable is loaded from when the closure is created
+#[derive(Clone, Debug)]
+enum CaptureSource {
+    Ref(VarRef),
+    /// Captured by the outermost function from the runtime stack
+    Value(Value),
+}
+
+/// Compilation state of a single function
+struct FnScope {
+    proto: Proto,
+    locals: Vec<(String, usize)>,
+    next_slot: usize,
+    siblings: Vec<String>,
+    captures: Vec<(String, CaptureSource)>,
+}
+
+/// Compiles typed expressions into closures, the names that are not defined in the expression
+/// are taken from the runtime stack, like the tree-walker does when it creates a closure
+pub struct Compiler<'a> {
+    env: &'a RuntimeStack,
+    scopes: Vec<FnScope>,
+}
+
+/// Result of the compilation, the captured values must be passed to the closures when they are created
+pub struct CompiledGroup {
+    pub group: Arc<ProtoGroup>,
+    pub captures: Vec<Value>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(env: &'a RuntimeStack) -> Self {
+        Compiler { env, scopes: vec![] }
+    }
+
+    /// Compiles an expression as a function without arguments
+    pub fn compile_expr(mut self, expr: &TypedExpr) -> Result<CompiledGroup, ElmError> {
+        let mut scope = FnScope::new(None, expr.get_type(), 0, vec![], vec![]);
+        scope.proto.top_level = true;
+
+        // Without tail calls, so the frame of the top level is kept in the stack traces
+        self.scopes.push(scope);
+        self.expr(expr, false)?;
+        self.emit(Op::Return, expr.get_span());
+        let scope = self.scopes.pop().unwrap();
+
+        Ok(Self::root_group(vec![scope.proto], scope.captures))
+    }
+
+    /// Compiles a top level definition, the function can refer to itself
+    pub fn compile_definition(mut self, def: &TypedDefinition) -> Result<CompiledGroup, ElmError> {
+        let (protos, captures) = self.definition_group(&[def])?;
+        Ok(Self::root_group(protos, captures))
+    }
+
+    fn root_group(protos: Vec<Proto>, captures: Vec<(String, CaptureSource)>) -> CompiledGroup {
+        let captures = captures.into_iter()
+            .map(|(_, source)| match source {
+                CaptureSource::Value(value) => value,
+                CaptureSource::Ref(_) => unreachable!("The outermost function can only capture values"),
+            })
+            .collect();
+
+        CompiledGroup {
+            group: Arc::new(ProtoGroup { members: protos.into_iter().map(Arc::new).collect() }),
+            captures,
+        }
+    }
+
+    /// Compiles a list of functions that can see each other, the captures of every member are
+    /// added to the same list
+    fn definition_group(&mut self, defs: &[&TypedDefinition]) -> Result<(Vec<Proto>, Vec<(String, CaptureSource)>), ElmError> {
+        let siblings = defs.iter().map(|def| def.name.clone()).collect::<Vec<_>>();
+        let mut captures = vec![];
+        let mut protos = vec![];
+
+        for def in defs {
+            let scope = FnScope::new(Some(def.name.clone()), def.header.clone(), def.patterns.len(), siblings.clone(), captures);
+            let (proto, group_captures) = self.function(scope, &def.patterns, &def.expr)?;
+
+            captures = group_captures;
+            protos.push(proto);
+        }
+
+        Ok((protos, captures))
+    }
+
+    fn function(&mut self, scope: FnScope, patterns: &[TypedPattern], body: &TypedExpr) -> Result<(Proto, Vec<(String, CaptureSource)>), ElmError> {
+        let span = body.get_span();
+        self.scopes.push(scope);
+
+        // Arguments are stored in the first slots, patterns are destructured in the prologue
+        for (index, patt) in patterns.iter().enumerate() {
+            if let TypedPattern::Var(_, _, name) = patt {
+                self.scope().locals.push((name.clone(), index));
+            } else {
+                let patt = self.pattern(patt)?;
+                let patt = self.add_pattern(patt);
+                self.emit(Op::LoadLocal(index), span);
+                self.emit(Op::Destructure(patt), span);
+            }
+        }
+
+        self.expr(body, true)?;
+        self.emit(Op::Return, span);
+
+        let scope = self.scopes.pop().unwrap();
+        Ok((scope.proto, scope.captures))
+    }
+
+    fn expr(&mut self, expr: &TypedExpr, tail: bool) -> Result<(), ElmError> {
+        let span = expr.get_span();
+
+        match expr {
+            TypedExpr::Const(_, _, value) => {
+                let index = self.add_constant(value.clone());
+                self.emit(Op::Const(index), span);
+            }
+            TypedExpr::Ref(_, _, name) => {
+                let var = self.resolve(name, self.scopes.len() - 1);
+                self.emit_load(var, span);
+            }
+            TypedExpr::Tuple(_, _, items) => {
+                for item in items {
+                    self.expr(item, false)?;
+                }
+                self.emit(Op::Tuple(items.len()), span);
+            }
+            TypedExpr::List(_, _, items) => {
+                for item in items {
+                    self.expr(item, false)?;
+                }
+                self.emit(Op::List(items.len()), span);
+            }
+            TypedExpr::Record(_, _, entries) => {
+                for (_, item) in entries {
+                    self.expr(item, false)?;
+                }
+                let fields = self.add_fields(entries);
+                self.emit(Op::Record(fields), span);
+            }
+            TypedExpr::RecordUpdate(_, _, record, entries) => {
+                self.expr(record, false)?;
+                for (_, item) in entries {
+                    self.expr(item, false)?;
+                }
+                let fields = self.add_fields(entries);
+                self.emit(Op::RecordUpdate(fields), span);
+            }
+            TypedExpr::RecordField(_, _, record, field) => {
+                self.expr(record, false)?;
+                let name = self.add_name(field);
+                self.emit(Op::RecordField(name), span);
+            }
+            TypedExpr::RecordAccess(_, ty, field) => {
+                let index = self.add_constant(record_access(ty, field));
+                self.emit(Op::Const(index), span);
+            }
+            TypedExpr::If(_, _, cond, a, b) => {
+                self.expr(cond, false)?;
+                let jump_else = self.emit(Op::JumpIfFalse(0), span);
+
+                self.expr(a, tail)?;
+                let jump_end = self.emit_branch_end(tail, span);
+                self.patch_jump(jump_else);
+
+                self.expr(b, tail)?;
+                if let Some(jump_end) = jump_end {
+                    self.patch_jump(jump_end);
+                }
+            }
+            TypedExpr::Case(_, _, cond, branches) => {
+                let outer = self.enter_scope();
+                self.expr(cond, false)?;
+                let value = self.alloc_slot();
+                self.emit(Op::StoreLocal(value), span);
+                let mut jumps_end = vec![];
+
+                for (patt, branch) in branches {
+                    let scope = self.enter_scope();
+                    let patt = self.pattern(patt)?;
+                    let patt = self.add_pattern(patt);
+
+                    self.emit(Op::LoadLocal(value), span);
+                    let jump_next = self.emit(Op::Match(patt, 0), span);
+                    self.expr(branch, tail)?;
+                    jumps_end.extend(self.emit_branch_end(tail, span));
+                    self.patch_jump(jump_next);
+                    self.exit_scope(scope);
+                }
+
+                let case = self.scope().proto.cases.len();
+                self.scope().proto.cases.push(branches.iter().map(|(p, _)| p.clone()).collect());
+                self.emit(Op::LoadLocal(value), span);
+                self.emit(Op::CaseFail(case), span);
+
+                for jump in jumps_end {
+                    self.patch_jump(jump);
+                }
+                self.exit_scope(outer);
+            }
+            TypedExpr::Lambda(_, ty, patterns, body) => {
+                let scope = FnScope::new(None, ty.clone(), patterns.len(), vec![], vec![]);
+                let (proto, captures) = self.function(scope, patterns, body)?;
+                self.emit_closures(vec![proto], captures, span);
+            }
+            TypedExpr::Resolved(..) | TypedExpr::Closure(..) => {
+                unreachable!("Resolved expressions are only evaluated by the tree walker")
+            }
+            TypedExpr::Let(_, _, entries, body) => {
+                let scope = self.enter_scope();
+                self.let_entries(entries, span)?;
+                self.expr(body, tail)?;
+                self.exit_scope(scope);
+            }
+            TypedExpr::Application(_, _, _, _) => {
+                let mut inputs = vec![];
+                let function = collect_arguments(expr, &mut inputs);
+
+                self.expr(function, false)?;
+                for input in &inputs {
+                    self.expr(input, false)?;
+                }
+
+                if tail {
+                    self.emit(Op::TailCall(inputs.len()), span);
+                } else {
+                    self.emit(Op::Call(inputs.len()), span);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn let_entries(&mut self, entries: &[LetEntry], span: Span) -> Result<(), ElmError> {
+        let mut functions = vec![];
+        let mut values = vec![];
+
+        // All the names of the let are visible from the start, local functions can call each other
+        for entry in entries {
+            match entry {
+                LetEntry::Definition(def) if !def.patterns.is_empty() => {
+                    let slot = self.bind_local(&def.name);
+                    functions.push(Some((def, slot)));
+                    values.push(None);
+                }
+                LetEntry::Definition(def) => {
+                    let slot = self.bind_local(&def.name);
+                    functions.push(None);
+                    values.push(Some((&def.expr, Op::StoreLocal(slot))));
+                }
+                LetEntry::Pattern(patt, expr) => {
+                    let patt = self.pattern(patt)?;
+                    let patt = self.add_pattern(patt);
+                    functions.push(None);
+                    values.push(Some((expr, Op::Destructure(patt))));
+                }
+                LetEntry::Functions(..) => {
+                    unreachable!("Resolved expressions are only evaluated by the tree walker")
+                }
+            }
+        }
+
+        // The entries are created in dependency order, so the slots of the values a function
+        // captures are filled before the function is created
+        for component in let_components(entries) {
+            let group = component.iter()
+                .filter_map(|index| functions[*index])
+                .collect::<Vec<_>>();
+
+            if !group.is_empty() {
+                let defs = group.iter().map(|(def, _)| *def).collect::<Vec<_>>();
+                let closures = self.definition_group(&defs)?;
+                self.emit_let_functions(closures, &group, span);
+            }
+
+            for index in component {
+                if let Some((expr, store)) = values[index].take() {
+                    self.expr(expr, false)?;
+                    self.emit(store, span);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit_let_functions(&mut self, (protos, captures): (Vec<Proto>, Vec<(String, CaptureSource)>), functions: &[(&TypedDefinition, usize)], span: Span) {
+        if protos.is_empty() {
+            return;
+        }
+
+        self.emit_closures(protos, captures, span);
+
+        for (_, slot) in functions.iter().rev() {
+            self.emit(Op::StoreLocal(*slot), span);
+        }
+    }
+
+    fn emit_closures(&mut self, protos: Vec<Proto>, captures: Vec<(String, CaptureSource)>, span: Span) {
+        for (_, source) in &captures {
+            match source {
+                CaptureSource::Ref(var) => self.emit_load(var.clone(), span),
+                CaptureSource::Value(_) => unreachable!("Only the outermost function captures values"),
+            };
+        }
+
+        let group = Arc::new(ProtoGroup { members: protos.into_iter().map(Arc::new).collect() });
+        let index = self.scope().proto.groups.len();
+        self.scope().proto.groups.push(group);
+        self.emit(Op::MakeClosures(index, captures.len()), span);
+    }
+
+    fn pattern(&mut self, pattern: &TypedPattern) -> Result<SlotPattern, ElmError> {
+        let patt = match pattern {
+            TypedPattern::Var(_, _, name) => SlotPattern::Var(self.bind_local(name)),
+            TypedPattern::Wildcard(_) => SlotPattern::Wildcard,
+            TypedPattern::Unit(_) => SlotPattern::Unit,
+            TypedPattern::Alias(_, _, patt, name) => {
+                let slot = self.bind_local(name);
+                SlotPattern::Alias(Box::new(self.pattern(patt)?), slot)
+            }
+            TypedPattern::Adt(_, _, _, name, items) => {
+                // The pattern can use the qualified name of the variant, like `Maybe.Just`
+                let name = name.rsplit('.').next().unwrap_or(name);
+                SlotPattern::Adt(name.to_string(), self.patterns(items)?)
+            }
+            TypedPattern::Tuple(_, _, items) => SlotPattern::Tuple(self.patterns(items)?),
+            TypedPattern::List(_, _, items) => SlotPattern::List(self.patterns(items)?),
+            TypedPattern::BinaryOp(_, _, op, a, b) => {
+                if op != "::" {
+                    return Err(InterpreterError::UnknownOperatorPattern(op.clone()).wrap());
+                }
+                SlotPattern::Cons(Box::new(self.pattern(a)?), Box::new(self.pattern(b)?))
+            }
+            TypedPattern::Record(_, _, fields) => {
+                SlotPattern::Record(fields.iter().map(|name| (name.clone(), self.bind_local(name))).collect())
+            }
+            TypedPattern::LitInt(_, value) => SlotPattern::LitInt(*value),
+            TypedPattern::LitString(_, value) => SlotPattern::LitString(value.clone()),
+            TypedPattern::LitChar(_, value) => SlotPattern::LitChar(*value),
+        };
+
+        Ok(patt)
+    }
+
+    fn patterns(&mut self, patterns: &[TypedPattern]) -> Result<Vec<SlotPattern>, ElmError> {
+        patterns.iter().map(|p| self.pattern(p)).collect()
+    }
+
+    /// Finds a variable from the point of view of the function at `depth`
+    fn resolve(&mut self, name: &str, depth: usize) -> VarRef {
+        {
+            let scope = &self.scopes[depth];
+
+            if let Some((_, slot)) = scope.locals.iter().rev().find(|(n, _)| n == name) {
+                return VarRef::Local(*slot);
+            }
+
+            if let Some(index) = scope.siblings.iter().position(|n| n == name) {
+                return VarRef::Sibling(index);
+            }
+
+            if let Some(index) = scope.captures.iter().position(|(n, _)| n == name) {
+                return VarRef::Capture(index);
+            }
+        }
+
+        let source = if depth == 0 {
+            match self.env.find(name) {
+                Some(ref value) if !is_pending_constant(value) => CaptureSource::Value(value.clone()),
+                _ => return VarRef::Global(self.env.intern(name)),
+            }
+        } else {
+            match self.resolve(name, depth - 1) {
+                VarRef::Global(id) => return VarRef::Global(id),
+                var => CaptureSource::Ref(var),
+            }
+        };
+
+        let captures = &mut self.scopes[depth].captures;
+        captures.push((name.to_string(), source));
+        VarRef::Capture(captures.len() - 1)
+    }
+
+    fn emit_load(&mut self, var: VarRef, span: Span) {
+        let op = match var {
+            VarRef::Local(slot) => Op::LoadLocal(slot),
+            VarRef::Capture(index) => Op::LoadCapture(index),
+            VarRef::Sibling(index) => Op::LoadSibling(index),
+            VarRef::Global(id) => Op::LoadGlobal(id),
+        };
+
+        self.emit(op, span);
+    }
+
+    /// Jump to the end of the if/case expression, in tail position the value is returned instead
+    fn emit_branch_end(&mut self, tail: bool, span: Span) -> Option<usize> {
+        if tail {
+            self.emit(Op::Return, span);
+            None
+        } else {
+            Some(self.emit(Op::Jump(0), span))
+        }
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        let proto = &mut self.scope().proto;
+        proto.code.push(op);
+        proto.spans.push(span);
+        proto.code.len() - 1
+    }
+
+    /// Sets the destination of a jump to the next instruction
+    fn patch_jump(&mut self, index: usize) {
+        let proto = &mut self.scope().proto;
+        let target = proto.code.len();
+
+        match &mut proto.code[index] {
+            Op::Jump(dst) | Op::JumpIfFalse(dst) | Op::Match(_, dst) => *dst = target,
+            op => panic!("Not a jump instruction: {:?}", op),
+        }
+    }
+
+    fn scope(&mut self) -> &mut FnScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn enter_scope(&mut self) -> (usize, usize) {
+        let scope = self.scope();
+        (scope.locals.len(), scope.next_slot)
+    }
+
+    /// Removes the names defined since `enter_scope`, the slots can be reused
+    fn exit_scope(&mut self, (locals, next_slot): (usize, usize)) {
+        let scope = self.scope();
+        scope.locals.truncate(locals);
+        scope.next_slot = next_slot;
+    }
+
+    fn alloc_slot(&mut self) -> usize {
+        let scope = self.scope();
+        let slot = scope.next_slot;
+        scope.next_slot += 1;
+        scope.proto.slots = scope.proto.slots.max(scope.next_slot);
+        slot
+    }
+
+    fn bind_local(&mut self, name: &str) -> usize {
+        let slot = self.alloc_slot();
+        self.scope().locals.push((name.to_string(), slot));
+        slot
+    }
+
+    fn add_constant(&mut self, value: Value) -> usize {
+        let constants = &mut self.scope().proto.constants;
+        constants.push(value);
+        constants.len() - 1
+    }
+
+    fn add_name(&mut self, name: &str) -> usize {
+        let names = &mut self.scope().proto.names;
+
+        match names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        }
+    }
+
+    fn add_fields(&mut self, entries: &[(String, TypedExpr)]) -> usize {
+        let fields = &mut self.scope().proto.fields;
+        fields.push(entries.iter().map(|(name, _)| name.clone()).collect());
+        fields.len() - 1
+    }
+
+    fn add_pattern(&mut self, pattern: SlotPattern) -> usize {
+        let patterns = &mut self.scope().proto.patterns;
+        patterns.push(pattern);
+        patterns.len() - 1
+    }
+}
+
+impl FnScope {
+    fn new(name: Option<String>, function_type: Type, arity: usize, siblings: Vec<String>, captures: Vec<(String, CaptureSource)>) -> Self {
+        FnScope {
+            proto: Proto {
+                name,
+                top_level: false,
+                arity,
+                slots: arity,
+                function_type,
+                code: vec![],
+                spans: vec![],
+                constants: vec![],
+                names: vec![],
+                fields: vec![],
+                patterns: vec![],
+                cases: vec![],
+                groups: vec![],
+            },
+            locals: vec![],
+            next_slot: arity,
+            siblings,
+            captures,
+        }
+    }
+}
+
+/// Splits a chain of applications `f a b c` into the function and the list of arguments
+fn collect_arguments<'e>(expr: &'e TypedExpr, inputs: &mut Vec<&'e TypedExpr>) -> &'e TypedExpr {
+    match expr {
+        TypedExpr::Application(_, _, fun, input) => {
+            let function = collect_arguments(fun, inputs);
+            inputs.push(input);
+            function
+        }
+        _ => expr,
+    }
+}
@@ -1,113 +1,382 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
-use ast::Type;
+use errors::*;
 use interpreter::Interpreter;
-use interpreter::runtime_stack::RuntimeStack;
+use interpreter::runtime_stack::GlobalId;
+use interpreter::runtime_stack::Globals;
 use typed_ast::{LetEntry, TypedPattern};
-use typed_ast::TypedDefinition;
+use typed_ast::FunctionGroup;
 use typed_ast::TypedExpr;
+use typed_ast::VarSlot;
 use types::Function;
 use types::next_fun_id;
 use types::Value;
+use util::sort::strongly_connected_components;
 
 impl Interpreter {
-    pub fn create_lambda_closure(env: &mut RuntimeStack, ty: &Type, patterns: &Vec<TypedPattern>, expr: &TypedExpr) -> Value {
-        let function = Arc::new(Function::Definition {
-            id: next_fun_id(),
-            patterns: patterns.clone(),
-            expression: expr.clone(),
-            captures: Self::extract_captures(env, &expr),
-            function_type: ty.clone(),
-        });
-
-        Value::Fun {
-            arg_count: patterns.len() as u32,
-            args: vec![],
-            fun: function,
+    /// Creates the functions of a group, all of them share the values read from `captures`
+    pub fn create_closures(&mut self, group: &Arc<FunctionGroup>, captures: &[VarSlot]) -> Result<Vec<Value>, ElmError> {
+        let values = group.captures.iter().zip(captures)
+            .map(|(name, slot)| self.load_var(name, *slot))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let origin = self.current_origin();
+        let globals = self.current_globals();
+        let values = Arc::new(values);
+
+        let closures = (0..group.functions.len())
+            .map(|index| {
+                let function = Arc::new(Function::Definition {
+                    id: next_fun_id(),
+                    origin: origin.clone(),
+                    group: group.clone(),
+                    index,
+                    captures: values.clone(),
+                    globals: globals.clone(),
+                });
+
+                function_value(function)
+            })
+            .collect();
+
+        Ok(closures)
+    }
+
+    /// Reads the value of a resolved variable
+    pub fn load_var(&mut self, name: &str, slot: VarSlot) -> Result<Value, ElmError> {
+        let opt = match slot {
+            VarSlot::Local(slot) => self.stack.get(slot).cloned(),
+            VarSlot::Global(id) => {
+                let globals = self.current_globals();
+                self.load_global(&globals, id)?
+            }
+            VarSlot::Capture(index) => {
+                match self.current_function().map(|fun| fun.as_ref()) {
+                    Some(Function::Definition { captures, .. }) => captures.get(index).cloned(),
+                    _ => None,
+                }
+            }
+            VarSlot::Sibling(index) => self.sibling_value(index),
+        };
+
+        opt.ok_or_else(|| {
+            let name = match slot {
+                VarSlot::Global(id) => self.stack.global_name(id),
+                _ => name.to_string(),
+            };
+            InterpreterError::MissingDefinition(name).wrap()
+        })
+    }
+
+    /// Reads a global value, module constants are evaluated the first time they are read
+    pub(crate) fn load_global(&mut self, globals: &Arc<Globals>, id: GlobalId) -> Result<Option<Value>, ElmError> {
+        match globals.get(id) {
+            Some(Value::Fun { arg_count: 0, fun, .. }) => {
+                let value = self.exec_fun(&fun, vec![])?;
+                globals.set(id, value.clone());
+                Ok(Some(value))
+            }
+            opt => Ok(opt),
         }
     }
 
-    pub fn create_function_closure(env: &mut RuntimeStack, def: &TypedDefinition) -> Value {
-        let function = Arc::new(Function::Definition {
-            id: next_fun_id(),
-            patterns: def.patterns.clone(),
-            expression: def.expr.clone(),
-            captures: Self::extract_captures(env, &def.expr),
-            function_type: def.header.clone(),
-        });
+    /// A function of the same group as the function in execution, the function itself is
+    /// reused so recursive calls keep the same function id
+    fn sibling_value(&self, index: usize) -> Option<Value> {
+        let fun = self.current_function()?;
 
-        Value::Fun {
-            arg_count: def.patterns.len() as u32,
-            args: vec![],
-            fun: function,
+        match fun.as_ref() {
+            Function::Definition { index: current, .. } if *current == index => {
+                Some(function_value(fun.clone()))
+            }
+            Function::Definition { origin, group, captures, globals, .. } => {
+                let function = Arc::new(Function::Definition {
+                    id: next_fun_id(),
+                    origin: origin.clone(),
+                    group: group.clone(),
+                    index,
+                    captures: captures.clone(),
+                    globals: globals.clone(),
+                });
+
+                Some(function_value(function))
+            }
+            _ => None,
         }
     }
+}
 
-    pub fn extract_captures(env: &mut RuntimeStack, expr: &TypedExpr) -> HashMap<String, Value> {
-        let mut map = HashMap::new();
-        Self::traverse_expr(&mut map, env, expr);
-        map
+/// Module constants are stored as functions without arguments until they are evaluated
+pub fn is_pending_constant(value: &Value) -> bool {
+    match value {
+        Value::Fun { arg_count: 0, .. } => true,
+        _ => false,
     }
+}
 
-    fn traverse_expr(result: &mut HashMap<String, Value>, env: &mut RuntimeStack, expr: &TypedExpr) {
-        // TODO avoid capturing internal definitions
-        match expr {
-            TypedExpr::Ref(_, _, name) => {
-                if let Some(value) = env.find(name) {
-                    result.insert(name.to_string(), value);
-                }
+fn function_value(fun: Arc<Function>) -> Value {
+    let arg_count = match fun.as_ref() {
+        Function::Definition { group, index, .. } => group.functions[*index].patterns.len() as u32,
+        _ => 0,
+    };
+
+    Value::Fun { arg_count, args: vec![], fun }
+}
+
+/// Returns the names used in the body of a function that are not bound by the function patterns
+/// or by any binder (lambda, case branch or let entry) inside the body
+pub fn free_variables(patterns: &[TypedPattern], expr: &TypedExpr) -> Vec<String> {
+    let mut bound = vec![];
+    let mut free = vec![];
+
+    for patt in patterns {
+        pattern_bindings(&mut bound, patt);
+    }
+
+    traverse_expr(&mut free, &mut bound, expr);
+    free
+}
+
+/// Sorts the entries of a let in groups, every group comes after the groups it uses, so values
+/// and functions are created once the values they capture exist. Functions that call each
+/// other end in the same group. Returns the indices of the entries of each group
+pub fn let_components(entries: &[LetEntry]) -> Vec<Vec<usize>> {
+    let bindings = entries.iter()
+        .map(|entry| {
+            let mut names = vec![];
+            match entry {
+                LetEntry::Definition(def) => names.push(def.name.clone()),
+                LetEntry::Pattern(patt, _) => pattern_bindings(&mut names, patt),
+                LetEntry::Functions(..) => {}
             }
-            TypedExpr::Tuple(_, _, list)
-            | TypedExpr::List(_, _, list) => {
-                for expr in list {
-                    Self::traverse_expr(result, env, expr);
-                }
+            names
+        })
+        .collect::<Vec<_>>();
+
+    let edges = entries.iter()
+        .map(|entry| {
+            let free = match entry {
+                LetEntry::Definition(def) => free_variables(&def.patterns, &def.expr),
+                LetEntry::Pattern(_, expr) => free_variables(&[], expr),
+                LetEntry::Functions(..) => vec![],
+            };
+
+            (0..entries.len())
+                .filter(|index| bindings[*index].iter().any(|name| free.contains(name)))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+
+    strongly_connected_components(&edges)
+}
+
+/// Adds the names introduced by a pattern to the list
+pub fn pattern_bindings(result: &mut Vec<String>, pattern: &TypedPattern) {
+    match pattern {
+        TypedPattern::Var(_, _, name) => {
+            result.push(name.clone());
+        }
+        TypedPattern::Alias(_, _, patt, name) => {
+            result.push(name.clone());
+            pattern_bindings(result, patt);
+        }
+        TypedPattern::Record(_, _, fields) => {
+            result.extend(fields.iter().cloned());
+        }
+        TypedPattern::Adt(_, _, _, _, items)
+        | TypedPattern::Tuple(_, _, items)
+        | TypedPattern::List(_, _, items) => {
+            for patt in items {
+                pattern_bindings(result, patt);
             }
-            TypedExpr::Record(_, _, records)
-            | TypedExpr::RecordUpdate(_, _, _, records) => {
-                for (_, expr) in records {
-                    Self::traverse_expr(result, env, expr);
-                }
+        }
+        TypedPattern::BinaryOp(_, _, _, a, b) => {
+            pattern_bindings(result, a);
+            pattern_bindings(result, b);
+        }
+        TypedPattern::Wildcard(_)
+        | TypedPattern::Unit(_)
+        | TypedPattern::LitInt(_, _)
+        | TypedPattern::LitString(_, _)
+        | TypedPattern::LitChar(_, _) => {}
+    }
+}
+
+fn traverse_expr(free: &mut Vec<String>, bound: &mut Vec<String>, expr: &TypedExpr) {
+    match expr {
+        TypedExpr::Ref(_, _, name) => {
+            if !bound.contains(name) && !free.contains(name) {
+                free.push(name.clone());
             }
-            TypedExpr::RecordField(_, _, box_expr, _) => {
-                Self::traverse_expr(result, env, box_expr.as_ref());
+        }
+        TypedExpr::Tuple(_, _, list)
+        | TypedExpr::List(_, _, list) => {
+            for expr in list {
+                traverse_expr(free, bound, expr);
             }
-            TypedExpr::If(_, _, a, b, c) => {
-                Self::traverse_expr(result, env, a.as_ref());
-                Self::traverse_expr(result, env, b.as_ref());
-                Self::traverse_expr(result, env, c.as_ref());
+        }
+        TypedExpr::Record(_, _, records) => {
+            for (_, expr) in records {
+                traverse_expr(free, bound, expr);
             }
-            TypedExpr::Application(_, _, a, b) => {
-                Self::traverse_expr(result, env, a.as_ref());
-                Self::traverse_expr(result, env, b.as_ref());
+        }
+        TypedExpr::RecordUpdate(_, _, record, records) => {
+            traverse_expr(free, bound, record.as_ref());
+            for (_, expr) in records {
+                traverse_expr(free, bound, expr);
             }
-            // TODO removed defined variables from captures, case, lambda and let
-            TypedExpr::Case(_, _, a, entries) => {
-                Self::traverse_expr(result, env, a.as_ref());
-                for (_, expr) in entries {
-                    Self::traverse_expr(result, env, expr);
-                }
+        }
+        TypedExpr::RecordField(_, _, box_expr, _) => {
+            traverse_expr(free, bound, box_expr.as_ref());
+        }
+        TypedExpr::If(_, _, a, b, c) => {
+            traverse_expr(free, bound, a.as_ref());
+            traverse_expr(free, bound, b.as_ref());
+            traverse_expr(free, bound, c.as_ref());
+        }
+        TypedExpr::Application(_, _, a, b) => {
+            traverse_expr(free, bound, a.as_ref());
+            traverse_expr(free, bound, b.as_ref());
+        }
+        TypedExpr::Case(_, _, a, branches) => {
+            traverse_expr(free, bound, a.as_ref());
+
+            for (patt, expr) in branches {
+                let scope = bound.len();
+                pattern_bindings(bound, patt);
+                traverse_expr(free, bound, expr);
+                bound.truncate(scope);
             }
-            TypedExpr::Lambda(_, _, _, box_expr) => {
-                Self::traverse_expr(result, env, box_expr.as_ref());
+        }
+        TypedExpr::Lambda(_, _, patterns, box_expr) => {
+            let scope = bound.len();
+            for patt in patterns {
+                pattern_bindings(bound, patt);
             }
-            TypedExpr::Let(_, _, decls, box_expr) => {
-                Self::traverse_expr(result, env, box_expr.as_ref());
-                for decl in decls {
-                    match decl {
-                        LetEntry::Definition(def) => {
-                            Self::traverse_expr(result, env, &def.expr);
-                        }
-                        LetEntry::Pattern(_, expr) => {
-                            Self::traverse_expr(result, env, expr);
+            traverse_expr(free, bound, box_expr.as_ref());
+            bound.truncate(scope);
+        }
+        TypedExpr::Let(_, _, entries, box_expr) => {
+            let scope = bound.len();
+
+            // All the let entries are visible inside each other and in the final expression
+            for entry in entries {
+                match entry {
+                    LetEntry::Definition(def) => bound.push(def.name.clone()),
+                    LetEntry::Pattern(patt, _) => pattern_bindings(bound, patt),
+                    LetEntry::Functions(..) => {}
+                }
+            }
+
+            for entry in entries {
+                match entry {
+                    LetEntry::Definition(def) => {
+                        let def_scope = bound.len();
+                        for patt in &def.patterns {
+                            pattern_bindings(bound, patt);
                         }
+                        traverse_expr(free, bound, &def.expr);
+                        bound.truncate(def_scope);
+                    }
+                    LetEntry::Pattern(_, expr) => {
+                        traverse_expr(free, bound, expr);
                     }
+                    LetEntry::Functions(..) => {}
                 }
             }
-            _ => {
-                // ignored
+
+            traverse_expr(free, bound, box_expr.as_ref());
+            bound.truncate(scope);
+        }
+        TypedExpr::Const(..)
+        | TypedExpr::RecordAccess(..)
+        | TypedExpr::Resolved(..)
+        | TypedExpr::Closure(..) => {
+            // ignored, resolved expressions don't refer to variables by name
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::Test;
+
+    use super::*;
+
+    fn lambda_free_variables(code: &str) -> Vec<String> {
+        match Test::typed_expr(code) {
+            TypedExpr::Let(_, _, _, lambda) => {
+                if let TypedExpr::Lambda(_, _, patterns, expr) = *lambda {
+                    free_variables(&patterns, &expr)
+                } else {
+                    panic!("Expected lambda: {:?}", lambda)
+                }
+            }
+            other => panic!("Expected let: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn check_free_variables() {
+        assert_eq!(lambda_free_variables("let w = 1 in \\x -> (x, w)"), vec!["w".to_string()]);
+        assert_eq!(lambda_free_variables("let w = 1 in \\x -> \\y -> (x, y)"), Vec::<String>::new());
+        assert_eq!(lambda_free_variables("let w = 1 in \\x -> case x of\n (a, b) -> (a, w)"), vec!["w".to_string()]);
+        assert_eq!(lambda_free_variables("let w = 1 in \\x -> let (a, b) = x in (a, w)"), vec!["w".to_string()]);
+        assert_eq!(lambda_free_variables("let w = 1 in \\x -> let f y = (y, w) in f x"), vec!["w".to_string()]);
+    }
+
+    #[test]
+    fn check_let_components() {
+        let code = "let\n n = 1\n addN x = (x, n)\n m = addN 1\n addM x = (x, m)\n in\n addM 1";
+
+        match Test::typed_expr(code) {
+            TypedExpr::Let(_, _, entries, _) => {
+                assert_eq!(let_components(&entries), vec![vec![0], vec![1], vec![2], vec![3]]);
             }
+            other => panic!("Expected let: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn check_nested_lambdas() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("curry3 a = \\b -> \\c -> a * 100 + b * 10 + c").unwrap();
+
+            assert_eq!(Value::Number(123), i.eval_expr("((curry3 1) 2) 3").unwrap());
+        }
+    }
+
+    #[test]
+    fn check_case_bound_variables() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("choose x = \\pair -> case pair of\n (a, b) -> a + x").unwrap();
+            // The argument `a` is in the stack when the lambda is created, it must not be captured
+            i.eval_statement("wrapper a = (choose 10) (1, 2)").unwrap();
+
+            assert_eq!(Value::Number(11), i.eval_expr("wrapper 100").unwrap());
+        }
+    }
+
+    #[test]
+    fn check_case_custom_types() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("unwrap m = case m of\n Just v -> v\n Nothing -> 0").unwrap();
+
+            assert_eq!(Value::Number(4), i.eval_expr("unwrap (Just 4)").unwrap());
+            assert_eq!(Value::Number(0), i.eval_expr("unwrap Nothing").unwrap());
+        }
+    }
+
+    #[test]
+    fn check_partial_application() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("add3 a b c = a * 100 + b * 10 + c").unwrap();
+            i.eval_statement("addOne = add3 1").unwrap();
+            i.eval_statement("addOneTwo = addOne 2").unwrap();
+            i.eval_statement("apply f b = f b 9").unwrap();
+
+            assert_eq!(Value::Number(123), i.eval_expr("addOneTwo 3").unwrap());
+            assert_eq!(Value::Number(129), i.eval_expr("apply addOne 2").unwrap());
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use interpreter::Interpreter;
+use types::Value;
+
+/// Resource limits of the interpreter, `None` means unlimited
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionLimits {
+    /// Max number of expressions evaluated until the budget is reset
+    pub max_steps: Option<u64>,
+    /// Max number of nested function calls, tail calls don't increase the depth.
+    /// Every call uses native stack, so this is the limit that prevents stack overflows
+    pub max_call_depth: Option<u32>,
+    /// Max number of elements in a list
+    pub max_list_size: Option<usize>,
+    /// Max number of bytes in a string
+    pub max_string_size: Option<usize>,
+}
+
+/// The limit that stopped the execution, with its configured value
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecutionLimit {
+    Steps(u64),
+    CallDepth(u32),
+    ListSize(usize),
+    StringSize(usize),
+}
+
+/// Number of steps between checks of the interrupt flag, must be a power of two
+const INTERRUPT_CHECK_INTERVAL: u64 = 1024;
+
+/// Thread-safe token to stop an evaluation from outside the interpreter,
+/// all the clones of the handle share the same flag
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the interruption of the current evaluation, if there is no evaluation in progress
+    /// the next one will be interrupted
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Cancels a pending interruption
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Clears the flag, returning true if an interruption was requested
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl ExecutionLimits {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: u32) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    pub fn with_max_list_size(mut self, size: usize) -> Self {
+        self.max_list_size = Some(size);
+        self
+    }
+
+    pub fn with_max_string_size(mut self, size: usize) -> Self {
+        self.max_string_size = Some(size);
+        self
+    }
+}
+
+impl Interpreter {
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    /// Number of evaluation steps consumed since the last reset
+    pub fn steps_used(&self) -> u64 {
+        self.steps
+    }
+
+    /// Restores the full step budget, the consumed steps are kept between evaluations
+    /// until this method is called
+    pub fn reset_budget(&mut self) {
+        self.steps = 0;
+    }
+
+    /// Returns a handle to interrupt the evaluations of this interpreter from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    pub(crate) fn consume_step(&mut self) -> Result<(), ElmError> {
+        if self.steps & (INTERRUPT_CHECK_INTERVAL - 1) == 0 && self.interrupt.take() {
+            return Err(InterpreterError::Interrupted.wrap());
+        }
+
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Err(InterpreterError::ExecutionLimitExceeded(ExecutionLimit::Steps(max)).wrap());
+            }
+        }
+
+        self.steps += 1;
+        Ok(())
+    }
+
+    pub(crate) fn enter_call(&mut self) -> Result<(), ElmError> {
+        if let Some(max) = self.limits.max_call_depth {
+            if self.call_depth >= max {
+                return Err(InterpreterError::ExecutionLimitExceeded(ExecutionLimit::CallDepth(max)).wrap());
+            }
+        }
+
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    /// Checks the number of elements of a list or array, builtin functions call it before
+    /// allocating the elements
+    pub(crate) fn check_list_size(&self, len: usize) -> Result<(), ElmError> {
+        if let Some(max) = self.limits.max_list_size {
+            if len > max {
+                return Err(InterpreterError::ExecutionLimitExceeded(ExecutionLimit::ListSize(max)).wrap());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a value created by the interpreter or by a builtin function fits in the limits
+    pub(crate) fn check_value_size(&self, value: &Value) -> Result<(), ElmError> {
+        match value {
+            Value::List(items) => self.check_list_size(items.len())?,
+            Value::Array(items) => self.check_list_size(items.len())?,
+            Value::String(string) => {
+                if let Some(max) = self.limits.max_string_size {
+                    if string.len() > max {
+                        return Err(InterpreterError::ExecutionLimitExceeded(ExecutionLimit::StringSize(max)).wrap());
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use errors::ElmError;
+    use errors::InterpreterError;
+    use test_utils::Test;
+    use Runtime;
+
+    use super::*;
+
+    fn limit_error<T>(result: Result<T, ElmError>) -> Option<ExecutionLimit> {
+        match result {
+            Err(ElmError::Interpreter(_, InterpreterError::ExecutionLimitExceeded(limit))) => Some(limit),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn check_step_limit() {
+        let mut i = Runtime::new();
+        i.eval_statement("loop n = loop (n + 1)").unwrap();
+        i.set_limits(ExecutionLimits::unlimited().with_max_steps(1000));
+
+        assert_eq!(limit_error(i.eval_expr("loop 0")), Some(ExecutionLimit::Steps(1000)));
+        assert_eq!(i.steps_used(), 1000);
+    }
+
+    #[test]
+    fn check_step_limit_in_record_update() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("loop n = loop (n + 1)").unwrap();
+            i.eval_statement("r = { x = 1 }").unwrap();
+            i.set_limits(ExecutionLimits::unlimited().with_max_steps(1000));
+
+            assert_eq!(limit_error(i.eval_expr("{ r | x = loop 0 }")), Some(ExecutionLimit::Steps(1000)));
+        }
+    }
+
+    #[test]
+    fn check_budget_reset() {
+        let mut i = Runtime::new();
+        i.set_limits(ExecutionLimits::unlimited().with_max_steps(10));
+
+        assert_eq!(i.eval_expr("(1, 2, 3)"), Ok(Value::Tuple(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+        assert_eq!(i.eval_expr("(1, 2, 3)"), Ok(Value::Tuple(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+        assert_eq!(limit_error(i.eval_expr("(1, 2, 3)")), Some(ExecutionLimit::Steps(10)));
+
+        i.reset_budget();
+        assert_eq!(i.eval_expr("(1, 2, 3)"), Ok(Value::Tuple(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+    }
+
+    #[test]
+    fn check_call_depth_limit() {
+        let mut i = Runtime::new();
+        i.eval_statement("sum n = if n == 0 then 0 else n + sum (n - 1)").unwrap();
+        i.eval_statement("countdown n = if n == 0 then 0 else countdown (n - 1)").unwrap();
+        i.set_limits(ExecutionLimits::unlimited().with_max_call_depth(20));
+
+        assert_eq!(i.eval_expr("sum 10"), Ok(Value::Number(55)));
+        assert_eq!(limit_error(i.eval_expr("sum 100")), Some(ExecutionLimit::CallDepth(20)));
+        // Tail calls run in a loop, so they don't increase the depth
+        assert_eq!(i.eval_expr("countdown 1000"), Ok(Value::Number(0)));
+        // The depth is restored after an error
+        assert_eq!(i.eval_expr("sum 10"), Ok(Value::Number(55)));
+    }
+
+    #[test]
+    fn check_interrupt() {
+        let mut i = Runtime::new();
+        i.eval_statement("loop n = loop (n + 1)").unwrap();
+        let stack = i.debug();
+        let handle = i.interrupt_handle();
+
+        let thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+
+        match i.eval_expr("loop 0") {
+            Err(ElmError::Interpreter(_, InterpreterError::Interrupted)) => {}
+            other => panic!("Expected interruption: {:?}", other)
+        }
+        thread.join().unwrap();
+
+        // The runtime can be used after the interruption
+        assert_eq!(i.debug(), stack);
+        assert_eq!(i.eval_expr("1 + 2"), Ok(Value::Number(3)));
+    }
+
+    #[test]
+    fn check_interrupt_in_record_update() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("loop n = loop (n + 1)").unwrap();
+            i.eval_statement("r = { x = 1 }").unwrap();
+            let handle = i.interrupt_handle();
+
+            let thread = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                handle.interrupt();
+            });
+
+            // The interruption is consumed while evaluating the field, it must not be lost
+            match i.eval_expr("{ r | x = loop 0 }") {
+                Err(ElmError::Interpreter(_, InterpreterError::Interrupted)) => {}
+                other => panic!("Expected interruption: {:?}", other)
+            }
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn check_size_limits() {
+        let mut i = Runtime::new();
+        i.set_limits(ExecutionLimits::unlimited().with_max_list_size(3).with_max_string_size(5));
+
+        assert_eq!(limit_error(i.eval_expr("[1, 2, 3, 4]")), Some(ExecutionLimit::ListSize(3)));
+        assert_eq!(limit_error(i.eval_expr("0 :: [1, 2, 3]")), Some(ExecutionLimit::ListSize(3)));
+        assert_eq!(limit_error(i.eval_expr("\"abc\" ++ \"def\"")), Some(ExecutionLimit::StringSize(5)));
+        assert_eq!(i.eval_expr("\"ab\" ++ \"c\""), Ok(Value::String("abc".to_string())));
+        // The length is checked before the elements are created
+        i.import_module("Array").unwrap();
+        assert_eq!(limit_error(i.eval_expr("Array.repeat 2000000000 0")), Some(ExecutionLimit::ListSize(3)));
+        assert_eq!(limit_error(i.eval_expr("Array.initialize 4 identity")), Some(ExecutionLimit::ListSize(3)));
+        assert_eq!(i.eval_expr("Array.length (Array.repeat (negate 1) 0)"), Ok(Value::Int(0)));
+    }
+}
@@ -1,40 +1,102 @@
-use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use ast::Span;
 use builtin::adt_constructor;
+use builtin::platform::port_value;
 use builtin::record_access;
 use constructors::type_bool;
 use errors::*;
+use interpreter::limits::ExecutionLimits;
+use interpreter::limits::InterruptHandle;
+use interpreter::resolver::Resolver;
+use interpreter::closure_helper::is_pending_constant;
+use interpreter::runtime_stack::Interner;
 use interpreter::runtime_stack::RuntimeStack;
+use interpreter::stack_trace::ActiveCall;
+use interpreter::stack_trace::CodeOrigin;
+use interpreter::vm::bool_value;
 use loader::AnalyzedModule;
 use loader::Declaration;
 use loader::RuntimeModule;
-use rust_interop::call_function;
-use typed_ast::{TypedDefinition, TypedPattern};
+use rust_interop::host_context::HostContext;
+use typed_ast::{LetEntry, TypedDefinition, TypedPattern};
 use typed_ast::TypedExpr;
+use typed_ast::VarSlot;
 use types::Adt;
 use types::AdtVariant;
 use types::Function;
 use types::Value;
-use util::VecExt;
 
 pub mod runtime_stack;
+pub mod limits;
+pub mod stack_trace;
+pub mod bytecode;
+pub mod resolver;
 //mod builtins;
 mod closure_helper;
+mod vm;
+
+/// Strategy used to execute the code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// Evaluates the typed expression tree directly
+    TreeWalker,
+    /// Compiles the expressions and definitions to bytecode and runs them in a virtual machine
+    Bytecode,
+}
 
 #[derive(Clone, Debug)]
 pub struct Interpreter {
     pub stack: RuntimeStack,
+    backend: Backend,
+    limits: ExecutionLimits,
+    steps: u64,
+    call_depth: u32,
+    interrupt: InterruptHandle,
+    origin: Arc<CodeOrigin>,
+    calls: Vec<ActiveCall>,
+    top_level_span: Span,
+    context: HostContext,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_interner(Arc::new(Interner::default()))
+    }
+
+    /// Creates an interpreter that shares the global names with other interpreters, so they
+    /// can call the functions created by each other
+    pub fn with_interner(interner: Arc<Interner>) -> Self {
         Interpreter {
-            stack: RuntimeStack::new()
+            stack: RuntimeStack::with_interner(interner),
+            backend: Backend::default(),
+            limits: ExecutionLimits::default(),
+            steps: 0,
+            call_depth: 0,
+            interrupt: InterruptHandle::new(),
+            origin: Arc::new(CodeOrigin::empty()),
+            calls: vec![],
+            top_level_span: (0, 0),
+            context: HostContext::default(),
         }
     }
 
+    /// Values of the host available to the registered closures
+    pub fn context(&self) -> &HostContext {
+        &self.context
+    }
+
+    /// Selects the backend used to evaluate the following expressions and definitions,
+    /// functions created before keep running in the backend they were created with
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
     pub fn adt_value(&mut self, adt_name: &str, arguments: &[Value]) -> Result<Value, ElmError> {
         let ctor = self.find_value(adt_name)
             .ok_or_else(|| InterpreterError::MissingDefinition(adt_name.to_string()).wrap())?;
@@ -47,19 +109,26 @@ impl Interpreter {
     }
 
     pub fn true_value(&mut self) -> Value {
-        self.eval_expr(&TypedExpr::Ref((0, 0), type_bool(), "True".to_string())).unwrap()
+        self.walk_expr(&TypedExpr::Ref((0, 0), type_bool(), "True".to_string())).unwrap()
     }
 
     pub fn false_value(&mut self) -> Value {
-        self.eval_expr(&TypedExpr::Ref((0, 0), type_bool(), "False".to_string())).unwrap()
+        self.walk_expr(&TypedExpr::Ref((0, 0), type_bool(), "False".to_string())).unwrap()
     }
 
     pub fn eval_constants(&mut self, module: RuntimeModule) -> Result<RuntimeModule, ElmError> {
         let RuntimeModule { name, definitions: old_definitions, imports } = module;
         let mut definitions = HashMap::new();
 
+        let globals = self.stack.globals().clone();
+
         for (name, value) in old_definitions.into_iter() {
-            let new_value = self.eval_const(value)?;
+            // Constants already read by other definitions are not evaluated again
+            let new_value = if is_pending_constant(&value) {
+                self.load_global(&globals, self.stack.intern(&name))?.unwrap_or(value)
+            } else {
+                value
+            };
 
             definitions.insert(name, new_value);
         }
@@ -70,7 +139,7 @@ impl Interpreter {
     fn eval_const(&mut self, value: Value) -> Result<Value, ElmError> {
         let opt = if let Value::Fun { arg_count, fun, .. } = &value {
             if *arg_count == 0 {
-                Some(self.exec_fun(fun.borrow(), vec![])?)
+                Some(self.exec_fun(fun, vec![])?)
             } else {
                 None
             }
@@ -99,9 +168,14 @@ impl Interpreter {
 
         for decl in &module.all_declarations {
             match decl {
-                Declaration::Port(_, _) => {}
+                Declaration::Port(name, ty) => {
+                    if let Some(value) = port_value(&module.name, name, ty) {
+                        self.stack.add(name, value.clone());
+                        definitions.insert(name.clone(), value);
+                    }
+                }
                 Declaration::Definition(_, def) => {
-                    let (name, value) = self.eval_definition(def);
+                    let (name, value) = self.eval_definition(def)?;
                     definitions.insert(name, value);
                 }
                 Declaration::Alias(_) => {}
@@ -123,24 +197,37 @@ impl Interpreter {
     }
 
     pub fn eval_declaration(&mut self, decl: &Declaration) -> Result<Option<Value>, ElmError> {
-        if let Declaration::Definition(_, def) = decl {
-            let (name, value) = self.eval_definition(def);
-            let value = self.eval_const(value)?;
+        match decl {
+            Declaration::Definition(_, def) => {
+                let (name, value) = self.eval_definition(def)?;
+                let value = self.eval_const(value)?;
 
-            self.stack.add(&name, value.clone());
-            Ok(Some(value))
-        } else {
-            Ok(None)
+                self.stack.add(&name, value.clone());
+                Ok(Some(value))
+            }
+            Declaration::Adt(_, adt) => {
+                for variant in &adt.variants {
+                    self.eval_adt_variant(adt.clone(), variant);
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
         }
     }
 
-    fn eval_definition(&mut self, def: &TypedDefinition) -> (String, Value) {
+    fn eval_definition(&mut self, def: &TypedDefinition) -> Result<(String, Value), ElmError> {
         let name = def.name.clone();
-        let value = Self::create_function_closure(&mut self.stack, def);
+        let value = match self.backend {
+            Backend::TreeWalker => {
+                let (group, captures) = Resolver::new(&self.stack).resolve_definition(def);
+                self.create_closures(&group, &captures)?.remove(0)
+            }
+            Backend::Bytecode => self.create_compiled_closure(def)?,
+        };
 
         self.stack.add(&name, value.clone());
 
-        (name, value)
+        Ok((name, value))
     }
 
     fn eval_adt_variant(&mut self, adt: Arc<Adt>, variant: &AdtVariant) -> (String, Value) {
@@ -152,8 +239,31 @@ impl Interpreter {
         (name, value)
     }
 
+    /// Evaluates an expression with the selected backend
     pub fn eval_expr(&mut self, expr: &TypedExpr) -> Result<Value, ElmError> {
+        match self.backend {
+            Backend::TreeWalker => {
+                let expr = Resolver::new(&self.stack).resolve_expr(expr);
+
+                self.stack.enter_frame(vec![]);
+                let res = self.walk_expr(&expr);
+                self.stack.exit_frame();
+                res
+            }
+            Backend::Bytecode => self.run_expr(expr),
+        }
+    }
+
+    fn walk_expr(&mut self, expr: &TypedExpr) -> Result<Value, ElmError> {
+        self.walk_expr_inner(expr)
+            .map_err(|e| self.add_stack_trace(e, expr.get_span()))
+    }
+
+    fn walk_expr_inner(&mut self, expr: &TypedExpr) -> Result<Value, ElmError> {
+        self.consume_step()?;
+
         match expr {
+            TypedExpr::Resolved(_, _, name, slot) => self.load_var(name, *slot),
             TypedExpr::Ref(_, _, name) => {
                 let opt = self.stack.find(name);
                 match opt {
@@ -166,68 +276,60 @@ impl Interpreter {
             TypedExpr::Const(_, _, value) => Ok(value.clone()),
             TypedExpr::Tuple(_, _, items) => {
                 let values = items.iter()
-                    .map(|e| self.eval_expr(e))
+                    .map(|e| self.walk_expr(e))
                     .collect::<Result<Vec<_>, _>>()?;
 
                 Ok(Value::Tuple(values))
             }
             TypedExpr::List(_, _, items) => {
                 let values = items.iter()
-                    .map(|e| self.eval_expr(e))
+                    .map(|e| self.walk_expr(e))
                     .collect::<Result<Vec<_>, _>>()?;
 
-                Ok(Value::List(values))
+                let list = Value::List(values.into());
+                self.check_value_size(&list)?;
+                Ok(list)
             }
             TypedExpr::Record(_, _, items) => {
                 let values = items.iter()
                     .map(|(s, e)| {
-                        self.eval_expr(e).map(|e| (s.clone(), e))
+                        self.walk_expr(e).map(|e| (s.clone(), e))
                     })
                     .collect::<Result<Vec<_>, _>>()?;
 
-                Ok(Value::Record(values))
+                Ok(Value::Record(Arc::new(values)))
             }
             TypedExpr::RecordUpdate(_, _, name, items) => {
-                let val = self.eval_expr(name.as_ref())?;
+                let val = self.walk_expr(name.as_ref())?;
 
                 if let Value::Record(values) = &val {
                     let entries = values.iter().map(|(name, value)| {
-                        items.iter()
-                            .find(|(_name, _)| name == _name)
-                            .and_then(|(nam, expr)| {
-                                self.eval_expr(expr).map(|val| (nam.clone(), val)).ok()
-                            })
-                            .unwrap_or((name.clone(), value.clone()))
-                    }).collect();
-
-                    Ok(Value::Record(entries))
+                        match items.iter().find(|(_name, _)| name == _name) {
+                            Some((nam, expr)) => self.walk_expr(expr).map(|val| (nam.clone(), val)),
+                            None => Ok((name.clone(), value.clone())),
+                        }
+                    }).collect::<Result<Vec<_>, _>>()?;
+
+                    Ok(Value::Record(Arc::new(entries)))
                 } else {
                     Err(InterpreterError::RecordUpdateOnNonRecord(name.as_ref().clone(), val.clone()).wrap())
                 }
             }
             TypedExpr::If(_, _, cond, a, b) => {
-                let cond = self.eval_expr(cond)?;
-
-                match &cond {
-                    Value::Adt(ref name, ref vals, _) => {
-                        if name == "True" && vals.is_empty() {
-                            self.eval_expr(a)
-                        } else if name == "False" && vals.is_empty() {
-                            self.eval_expr(b)
-                        } else {
-                            Err(InterpreterError::InvalidIfCondition(cond.clone()).wrap())
-                        }
-                    }
-                    _ => {
-                        Err(InterpreterError::InvalidIfCondition(cond.clone()).wrap())
-                    }
+                if self.eval_if_condition(cond)? {
+                    self.walk_expr(a)
+                } else {
+                    self.walk_expr(b)
                 }
             }
-            TypedExpr::Lambda(_, ty, patt, expr) => {
-                Ok(Self::create_lambda_closure(&mut self.stack, ty, patt, expr))
+            TypedExpr::Closure(_, _, group, captures) => {
+                Ok(self.create_closures(group, captures)?.remove(0))
+            }
+            TypedExpr::Lambda(..) => {
+                unreachable!("Lambdas are replaced by closures in the resolution pass")
             }
             TypedExpr::RecordField(_, _, record, field) => {
-                let rec = self.eval_expr(record)?;
+                let rec = self.walk_expr(record)?;
 
                 if let Value::Record(entries) = &rec {
                     let (_, value) = entries.iter()
@@ -243,24 +345,119 @@ impl Interpreter {
                 Ok(record_access(ty, field))
             }
             TypedExpr::Case(_, _, cond, branches) => {
-                let cond_val = self.eval_expr(cond)?;
-                for (patt, expr) in branches {
-                    if matches_pattern(patt, &cond_val) {
-                        return self.eval_expr(expr);
-                    }
-                }
+                let cond_val = self.walk_expr(cond)?;
 
-                return Err(InterpreterError::CaseExpressionNonExhaustive(cond_val, branches.map(|(p, _)| p.clone())).wrap());
+                let level = self.stack.level();
+                let res = self.select_case_branch(cond_val, branches)
+                    .and_then(|expr| self.walk_expr(expr));
+                self.stack.truncate(level);
+                res
             }
-            TypedExpr::Let(..) => Ok(Value::Unit), // TODO
-            TypedExpr::Application(_, _, fun, input) => {
-                let function = self.eval_expr(fun)?;
-                let input = self.eval_expr(input)?;
+            TypedExpr::Let(_, _, entries, expr) => {
+                let level = self.stack.level();
+                let res = self.eval_let_entries(entries)
+                    .and_then(|_| self.walk_expr(expr));
+                self.stack.truncate(level);
+                res
+            }
+            TypedExpr::Application(span, _, fun, input) => {
+                let function = self.walk_expr(fun)?;
+                let input = self.walk_expr(input)?;
+                self.set_call_site(*span);
                 self.application(function, input)
             }
         }
     }
 
+    /// Evaluates an expression that is the result of the function `index` of the current group,
+    /// if the expression is a call to the same function, the arguments are returned instead so
+    /// the call can be executed as a loop without growing the stack
+    fn eval_tail_expr(&mut self, index: usize, arity: usize, expr: &TypedExpr) -> Result<TailCall, ElmError> {
+        self.eval_tail_expr_inner(index, arity, expr)
+            .map_err(|e| self.add_stack_trace(e, expr.get_span()))
+    }
+
+    fn eval_tail_expr_inner(&mut self, index: usize, arity: usize, expr: &TypedExpr) -> Result<TailCall, ElmError> {
+        self.consume_step()?;
+
+        match expr {
+            TypedExpr::If(_, _, cond, a, b) => {
+                let branch = if self.eval_if_condition(cond)? { a } else { b };
+                self.eval_tail_expr(index, arity, branch)
+            }
+            TypedExpr::Case(_, _, cond, branches) => {
+                let cond_val = self.walk_expr(cond)?;
+
+                let level = self.stack.level();
+                let res = self.select_case_branch(cond_val, branches)
+                    .and_then(|expr| self.eval_tail_expr(index, arity, expr));
+                self.stack.truncate(level);
+                res
+            }
+            TypedExpr::Let(_, _, entries, expr) => {
+                let level = self.stack.level();
+                let res = self.eval_let_entries(entries)
+                    .and_then(|_| self.eval_tail_expr(index, arity, expr));
+                self.stack.truncate(level);
+                res
+            }
+            TypedExpr::Application(..) => {
+                let mut inputs = vec![];
+
+                if collect_self_call(index, expr, &mut inputs) && inputs.len() == arity {
+                    let args = inputs.into_iter()
+                        .map(|e| self.walk_expr(e))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok(TailCall::Recurse(args))
+                } else {
+                    self.walk_expr(expr).map(TailCall::Return)
+                }
+            }
+            _ => self.walk_expr(expr).map(TailCall::Return)
+        }
+    }
+
+    fn eval_if_condition(&mut self, cond: &TypedExpr) -> Result<bool, ElmError> {
+        let cond = self.walk_expr(cond)?;
+        bool_value(cond)
+    }
+
+    /// Finds the first branch that matches the value and adds its variables to the current frame
+    fn select_case_branch<'a>(&mut self, value: Value, branches: &'a [(TypedPattern, TypedExpr)]) -> Result<&'a TypedExpr, ElmError> {
+        for (patt, expr) in branches {
+            if matches_pattern(patt, &value) {
+                add_pattern_values(self, patt, value).map_err(|e| e.wrap())?;
+                return Ok(expr);
+            }
+        }
+
+        Err(InterpreterError::CaseExpressionNonExhaustive(value, branches.iter().map(|(p, _)| p.clone()).collect()).wrap())
+    }
+
+    fn eval_let_entries(&mut self, entries: &[LetEntry]) -> Result<(), ElmError> {
+        for entry in entries {
+            match entry {
+                LetEntry::Definition(def) => {
+                    debug_assert!(def.patterns.is_empty(), "Local functions are grouped in the resolution pass");
+                    let value = self.walk_expr(&def.expr)?;
+                    self.stack.push(value);
+                }
+                LetEntry::Pattern(patt, expr) => {
+                    let value = self.walk_expr(expr)?;
+                    add_pattern_values(self, patt, value).map_err(|e| e.wrap())?;
+                }
+                LetEntry::Functions(group, captures) => {
+                    for value in self.create_closures(group, captures)? {
+                        self.stack.push(value);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn apply_function(&mut self, function: Value, arguments: &[Value]) -> Result<Value, ElmError> {
         let mut value = function;
 
@@ -303,32 +500,48 @@ impl Interpreter {
         }
     }
 
-    fn exec_fun(&mut self, fun: &Function, args: Vec<Value>) -> Result<Value, ElmError> {
-        self.stack.enter_block();
-        let res = match fun {
+    fn exec_fun(&mut self, fun: &Arc<Function>, args: Vec<Value>) -> Result<Value, ElmError> {
+        self.enter_call()?;
+        let res = match fun.as_ref() {
+            // Errors are propagated as they are, builtins can fail because of the execution limits
             Function::External(_, func, _) => {
                 (func.fun)(self, &args)
-                    .map_err(|_| InterpreterError::BuiltinFunctionError.wrap())
+                    .and_then(|value| self.check_value_size(&value).map(|_| value))
             }
-            Function::Wrapper(_, func, _) => {
-                call_function(func, self, &args)
-                    .map_err(|_| InterpreterError::BuiltinFunctionError.wrap())
+            Function::Closure(_, func, _) => {
+                (func.fun)(self, &args)
+                    .and_then(|value| self.check_value_size(&value).map(|_| value))
             }
-            Function::Definition { patterns, expression, captures, .. } => {
-                assert_eq!(patterns.len(), args.len());
-
-                for (name, val) in captures {
-                    self.stack.add(name, val.clone())
-                }
+            Function::Definition { group, index, .. } => {
+                let function = &group.functions[*index];
+                assert_eq!(function.patterns.len(), args.len());
+                let mut args = args;
+                self.enter_frame(fun, function.expr.get_span());
+                self.stack.enter_frame(Vec::with_capacity(args.len()));
+
+                let res = loop {
+                    for (patt, val) in function.patterns.iter().zip(args) {
+                        add_pattern_values(self, patt, val).unwrap();
+                    }
 
-                for (patt, val) in patterns.iter().zip(args) {
-                    add_pattern_values(self, patt, val).unwrap();
-                }
+                    match self.eval_tail_expr(*index, function.patterns.len(), &function.expr) {
+                        Ok(TailCall::Recurse(next_args)) => {
+                            // Start the next iteration with a clean frame
+                            self.stack.truncate(0);
+                            args = next_args;
+                        }
+                        Ok(TailCall::Return(value)) => break Ok(value),
+                        Err(e) => break Err(e),
+                    }
+                };
 
-                self.eval_expr(expression)
+                self.stack.exit_frame();
+                self.exit_frame();
+                res
             }
+            Function::Compiled { .. } => self.run_compiled(fun, args),
         };
-        self.stack.exit_block();
+        self.exit_call();
         Ok(res?)
     }
 
@@ -337,15 +550,46 @@ impl Interpreter {
     }
 }
 
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::TreeWalker
+    }
+}
+
+/// Result of the evaluation of an expression in tail position
+enum TailCall {
+    /// The final value of the function
+    Return(Value),
+    /// The function calls itself with new arguments
+    Recurse(Vec<Value>),
+}
+
+/// Checks if the expression is a call to the function `index` of the current group, and collects
+/// the argument expressions
+fn collect_self_call<'a>(index: usize, expr: &'a TypedExpr, inputs: &mut Vec<&'a TypedExpr>) -> bool {
+    match expr {
+        TypedExpr::Application(_, _, fun, input) => {
+            let is_self_call = collect_self_call(index, fun, inputs);
+            inputs.push(input);
+            is_self_call
+        }
+        TypedExpr::Resolved(_, _, _, VarSlot::Sibling(sibling)) => *sibling == index,
+        _ => false,
+    }
+}
+
 fn matches_pattern(pattern: &TypedPattern, value: &Value) -> bool {
     match pattern {
         TypedPattern::Var(_, _, _) => true,
         TypedPattern::Wildcard(_) => true,
         TypedPattern::Alias(_, _, pat, _) => matches_pattern(pat, value),
-        TypedPattern::Adt(_, _, p_ty, p_sub) => {
-            let val_ty = value.get_type();
-            if let Value::Adt(_, v_sub, _) = value {
-                p_ty == &val_ty && p_sub.iter().zip(v_sub).all(|(a, b)| matches_pattern(a, b))
+        TypedPattern::Adt(_, _, _, p_name, p_sub) => {
+            if let Value::Adt(v_name, v_sub, _) = value {
+                // The pattern can use the qualified name of the variant, like `Maybe.Just`
+                let p_name = p_name.rsplit('.').next().unwrap_or(p_name);
+
+                p_name == v_name && p_sub.len() == v_sub.len() &&
+                    p_sub.iter().zip(v_sub).all(|(a, b)| matches_pattern(a, b))
             } else {
                 false
             }
@@ -360,7 +604,7 @@ fn matches_pattern(pattern: &TypedPattern, value: &Value) -> bool {
         }
         TypedPattern::List(_, _, p_sub) => {
             if let Value::List(v_sub) = value {
-                p_sub.iter().zip(v_sub).all(|(a, b)| matches_pattern(a, b))
+                p_sub.len() == v_sub.len() && p_sub.iter().zip(v_sub).all(|(a, b)| matches_pattern(a, b))
             } else {
                 false
             }
@@ -369,11 +613,11 @@ fn matches_pattern(pattern: &TypedPattern, value: &Value) -> bool {
             assert_eq!(op.as_str(), "::");
 
             if let Value::List(v_sub) = value {
-                if !v_sub.is_empty() {
-                    matches_pattern(first, &v_sub[0]) &&
-                        matches_pattern(rest, &Value::List(v_sub[1..].to_vec()))
-                } else {
-                    false
+                match v_sub.split_first() {
+                    Some((head, tail)) => {
+                        matches_pattern(first, head) && matches_pattern(rest, &Value::List(tail))
+                    }
+                    None => false,
                 }
             } else {
                 false
@@ -412,27 +656,27 @@ fn matches_pattern(pattern: &TypedPattern, value: &Value) -> bool {
 
 pub fn add_pattern_values(env: &mut Interpreter, pattern: &TypedPattern, value: Value) -> Result<(), InterpreterError> {
     match pattern {
-        TypedPattern::Var(_, _, n) => {
-            env.stack.add(&n, value);
+        TypedPattern::Var(_, _, _) => {
+            env.stack.push(value);
         }
-        TypedPattern::Alias(_, _, pat, name) => {
-            env.stack.add(name, value.clone());
+        TypedPattern::Alias(_, _, pat, _) => {
+            env.stack.push(value.clone());
             add_pattern_values(env, pat, value)?;
         }
         TypedPattern::Record(_, _, items) => {
             if let Value::Record(vars) = &value {
                 for patt in items {
-                    let (name, val) = vars.iter()
+                    let (_, val) = vars.iter()
                         .find(|(name, _)| name == patt)
                         .ok_or(InterpreterError::RecordFieldNotFound(patt.clone(), value.clone()))?;
 
-                    env.stack.add(name, val.clone());
+                    env.stack.push(val.clone());
                 }
             } else {
                 return Err(InterpreterError::ExpectedRecord(value.clone()));
             }
         }
-        TypedPattern::Adt(_, _, _, items) => {
+        TypedPattern::Adt(_, _, _, _, items) => {
             if let Value::Adt(_, vars, _) = &value {
                 for (patt, val) in items.iter().zip(vars) {
                     add_pattern_values(env, patt, val.clone())?;
@@ -467,17 +711,10 @@ pub fn add_pattern_values(env: &mut Interpreter, pattern: &TypedPattern, value:
         TypedPattern::BinaryOp(_, _, op, a, b) => {
             if op == "::" {
                 if let Value::List(vars) = &value {
-                    if vars.len() == 0 {
-                        return Err(InterpreterError::ExpectedNonEmptyList(value.clone()));
-                    }
+                    let (first, rest) = vars.split_first()
+                        .ok_or_else(|| InterpreterError::ExpectedNonEmptyList(value.clone()))?;
 
-                    let first = vars[0].clone();
-                    let mut rest: Vec<Value> = Vec::new();
-                    for i in 1..vars.len() {
-                        rest.push(vars[i].clone());
-                    }
-
-                    add_pattern_values(env, a, first)?;
+                    add_pattern_values(env, a, first.clone())?;
                     add_pattern_values(env, b, Value::List(rest))?;
                 } else {
                     return Err(InterpreterError::ExpectedList(value.clone()));
@@ -514,7 +751,7 @@ mod tests {
             Value::Number(1),
             Value::Number(2),
             Value::Number(3),
-        ])));
+        ].into())));
     }
 
 //    #[test]
@@ -540,6 +777,38 @@ mod tests {
         assert_eq!(env.eval_expr(&expr), Ok(Value::Number(0)));
     }
 
+    #[test]
+    fn check_let() {
+        let expr = Test::typed_expr("let x = 1 in x");
+        let mut env = Interpreter::new();
+
+        assert_eq!(env.eval_expr(&expr), Ok(Value::Number(1)));
+    }
+
+    #[test]
+    fn check_let_function() {
+        let expr = Test::typed_expr("let first a b = a in first 1 2");
+        let mut env = Interpreter::new();
+
+        assert_eq!(env.eval_expr(&expr), Ok(Value::Number(1)));
+    }
+
+    #[test]
+    fn check_let_pattern() {
+        let expr = Test::typed_expr("let (a, b) = (1, 2) in b");
+        let mut env = Interpreter::new();
+
+        assert_eq!(env.eval_expr(&expr), Ok(Value::Number(2)));
+    }
+
+    #[test]
+    fn check_let_capture() {
+        let expr = Test::typed_expr("(\\x -> let f y = x in f ()) 3");
+        let mut env = Interpreter::new();
+
+        assert_eq!(env.eval_expr(&expr), Ok(Value::Number(3)));
+    }
+
 //    #[test]
 //    fn check_number() {
 //        let expr = Test::typed_expr("1 / 3");
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use interpreter::closure_helper::let_components;
+use interpreter::closure_helper::pattern_bindings;
+use interpreter::closure_helper::is_pending_constant;
+use interpreter::runtime_stack::GlobalId;
+use interpreter::runtime_stack::RuntimeStack;
+use typed_ast::FunctionGroup;
+use typed_ast::LetEntry;
+use typed_ast::ResolvedFunction;
+use typed_ast::TypedDefinition;
+use typed_ast::TypedExpr;
+use typed_ast::VarSlot;
+
+/// Rewrites the references of an expression tree into the slots where the interpreter will find
+/// the values, so no lookup by name is needed at runtime.
+///
+/// The local variables of a scope mirror the order in which the interpreter pushes the values
+/// into the call frame, so the position of a name in `locals` is its slot. Functions don't see
+/// the frame of the enclosing scope, the values they need are captured when they are created.
+pub struct Resolver<'a> {
+    env: &'a RuntimeStack,
+    scopes: Vec<Scope>,
+}
+
+/// Variables visible from the body of a function, or from the top level expression
+#[derive(Default)]
+struct Scope {
+    locals: Vec<String>,
+    siblings: Vec<String>,
+    /// Captured names and the slot of the value in the enclosing scope
+    captures: Vec<(String, VarSlot)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(env: &'a RuntimeStack) -> Self {
+        Resolver { env, scopes: vec![Scope::default()] }
+    }
+
+    /// Resolves an expression evaluated at the top level, in a new call frame
+    pub fn resolve_expr(&mut self, expr: &TypedExpr) -> TypedExpr {
+        self.expr(expr)
+    }
+
+    /// Resolves a module level definition, the returned slots point to global values
+    pub fn resolve_definition(&mut self, def: &TypedDefinition) -> (Arc<FunctionGroup>, Vec<VarSlot>) {
+        self.group(&[def])
+    }
+
+    fn expr(&mut self, expr: &TypedExpr) -> TypedExpr {
+        match expr {
+            TypedExpr::Ref(span, ty, name) => {
+                let slot = self.resolve_name(self.scopes.len() - 1, name);
+                TypedExpr::Resolved(*span, ty.clone(), name.clone(), slot)
+            }
+            TypedExpr::Tuple(span, ty, items) => {
+                TypedExpr::Tuple(*span, ty.clone(), items.iter().map(|e| self.expr(e)).collect())
+            }
+            TypedExpr::List(span, ty, items) => {
+                TypedExpr::List(*span, ty.clone(), items.iter().map(|e| self.expr(e)).collect())
+            }
+            TypedExpr::Record(span, ty, entries) => {
+                TypedExpr::Record(*span, ty.clone(), self.entries(entries))
+            }
+            TypedExpr::RecordUpdate(span, ty, record, entries) => {
+                let record = self.expr(record);
+                TypedExpr::RecordUpdate(*span, ty.clone(), Box::new(record), self.entries(entries))
+            }
+            TypedExpr::RecordField(span, ty, record, field) => {
+                TypedExpr::RecordField(*span, ty.clone(), Box::new(self.expr(record)), field.clone())
+            }
+            TypedExpr::If(span, ty, cond, a, b) => {
+                TypedExpr::If(
+                    *span,
+                    ty.clone(),
+                    Box::new(self.expr(cond)),
+                    Box::new(self.expr(a)),
+                    Box::new(self.expr(b)),
+                )
+            }
+            TypedExpr::Application(span, ty, fun, input) => {
+                TypedExpr::Application(*span, ty.clone(), Box::new(self.expr(fun)), Box::new(self.expr(input)))
+            }
+            TypedExpr::Case(span, ty, cond, branches) => {
+                let cond = self.expr(cond);
+                let branches = branches.iter()
+                    .map(|(patt, expr)| {
+                        let level = self.scope().locals.len();
+                        pattern_bindings(&mut self.scope_mut().locals, patt);
+                        let expr = self.expr(expr);
+                        self.scope_mut().locals.truncate(level);
+                        (patt.clone(), expr)
+                    })
+                    .collect();
+
+                TypedExpr::Case(*span, ty.clone(), Box::new(cond), branches)
+            }
+            TypedExpr::Lambda(span, ty, patterns, body) => {
+                let function = ResolvedFunction {
+                    name: None,
+                    header: ty.clone(),
+                    patterns: patterns.clone(),
+                    expr: body.as_ref().clone(),
+                };
+                let (group, captures) = self.function_group(vec![function]);
+
+                TypedExpr::Closure(*span, ty.clone(), group, captures)
+            }
+            TypedExpr::Let(span, ty, entries, body) => {
+                let level = self.scope().locals.len();
+                let entries = self.let_entries(entries);
+                let body = self.expr(body);
+                self.scope_mut().locals.truncate(level);
+
+                TypedExpr::Let(*span, ty.clone(), entries, Box::new(body))
+            }
+            TypedExpr::Const(..)
+            | TypedExpr::RecordAccess(..)
+            | TypedExpr::Resolved(..)
+            | TypedExpr::Closure(..) => expr.clone(),
+        }
+    }
+
+    fn entries(&mut self, entries: &[(String, TypedExpr)]) -> Vec<(String, TypedExpr)> {
+        entries.iter().map(|(name, e)| (name.clone(), self.expr(e))).collect()
+    }
+
+    /// The entries are created in dependency order, functions that call each other are created
+    /// together as a group, at the position of their component
+    fn let_entries(&mut self, entries: &[LetEntry]) -> Vec<LetEntry> {
+        let mut resolved = vec![];
+
+        for component in let_components(entries) {
+            let functions = component.iter()
+                .filter_map(|index| match &entries[*index] {
+                    LetEntry::Definition(def) if !def.patterns.is_empty() => Some(def),
+                    _ => None
+                })
+                .collect::<Vec<_>>();
+
+            if !functions.is_empty() {
+                resolved.push(self.let_functions(&functions));
+            }
+
+            for index in component {
+                match &entries[index] {
+                    LetEntry::Definition(def) if def.patterns.is_empty() => {
+                        let expr = self.expr(&def.expr);
+                        self.scope_mut().locals.push(def.name.clone());
+                        resolved.push(LetEntry::Definition(TypedDefinition {
+                            header: def.header.clone(),
+                            name: def.name.clone(),
+                            patterns: vec![],
+                            expr,
+                        }));
+                    }
+                    LetEntry::Pattern(patt, expr) => {
+                        let expr = self.expr(expr);
+                        pattern_bindings(&mut self.scope_mut().locals, patt);
+                        resolved.push(LetEntry::Pattern(patt.clone(), expr));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        resolved
+    }
+
+    fn let_functions(&mut self, functions: &[&TypedDefinition]) -> LetEntry {
+        let (group, captures) = self.group(functions);
+
+        for def in functions {
+            self.scope_mut().locals.push(def.name.clone());
+        }
+
+        LetEntry::Functions(group, captures)
+    }
+
+    fn group(&mut self, defs: &[&TypedDefinition]) -> (Arc<FunctionGroup>, Vec<VarSlot>) {
+        let functions = defs.iter()
+            .map(|def| ResolvedFunction {
+                name: Some(def.name.clone()),
+                header: def.header.clone(),
+                patterns: def.patterns.clone(),
+                expr: def.expr.clone(),
+            })
+            .collect();
+
+        self.function_group(functions)
+    }
+
+    /// Resolves the bodies of a group of functions, the result contains the slots of the
+    /// captured values in the current scope
+    fn function_group(&mut self, mut functions: Vec<ResolvedFunction>) -> (Arc<FunctionGroup>, Vec<VarSlot>) {
+        self.scopes.push(Scope {
+            locals: vec![],
+            siblings: functions.iter().filter_map(|f| f.name.clone()).collect(),
+            captures: vec![],
+        });
+
+        for function in &mut functions {
+            let mut locals = vec![];
+            for patt in &function.patterns {
+                pattern_bindings(&mut locals, patt);
+            }
+
+            self.scope_mut().locals = locals;
+            function.expr = self.expr(&function.expr);
+        }
+
+        let scope = self.scopes.pop().unwrap();
+        let (names, slots) = scope.captures.into_iter().unzip();
+
+        (Arc::new(FunctionGroup { functions, captures: names }), slots)
+    }
+
+    /// Finds a name in the scope at `depth`, names from enclosing scopes are captured.
+    /// Global values that already exist are captured too, like the rest of the values, so a
+    /// function keeps using the definitions of the module where it was created. Missing globals
+    /// are looked up when the function runs.
+    fn resolve_name(&mut self, depth: usize, name: &str) -> VarSlot {
+        let scope = &self.scopes[depth];
+
+        if let Some(slot) = scope.locals.iter().rposition(|local| local == name) {
+            return VarSlot::Local(slot);
+        }
+
+        if let Some(index) = scope.siblings.iter().position(|sibling| sibling == name) {
+            return VarSlot::Sibling(index);
+        }
+
+        if let Some(index) = scope.captures.iter().position(|(capture, _)| capture == name) {
+            return VarSlot::Capture(index);
+        }
+
+        if depth == 0 {
+            return VarSlot::Global(self.env.intern(name));
+        }
+
+        match self.resolve_name(depth - 1, name) {
+            VarSlot::Global(id) if !self.is_captured_global(id) => VarSlot::Global(id),
+            slot => {
+                let captures = &mut self.scopes[depth].captures;
+                captures.push((name.to_string(), slot));
+                VarSlot::Capture(captures.len() - 1)
+            }
+        }
+    }
+
+    /// Constants of a module that are not evaluated yet are read when the function runs
+    fn is_captured_global(&self, id: GlobalId) -> bool {
+        match self.env.get_global(id) {
+            Some(value) => !is_pending_constant(&value),
+            None => false,
+        }
+    }
+
+    fn scope(&self) -> &Scope {
+        self.scopes.last().unwrap()
+    }
+
+    fn scope_mut(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Type;
+    use test_utils::Test;
+
+    use super::*;
+
+    fn resolved_slots(expr: &TypedExpr, slots: &mut Vec<(String, VarSlot)>) {
+        match expr {
+            TypedExpr::Resolved(_, _, name, slot) => slots.push((name.clone(), *slot)),
+            TypedExpr::Application(_, _, a, b) => {
+                resolved_slots(a, slots);
+                resolved_slots(b, slots);
+            }
+            TypedExpr::Tuple(_, _, items) => {
+                items.iter().for_each(|e| resolved_slots(e, slots));
+            }
+            TypedExpr::If(_, _, cond, a, b) => {
+                resolved_slots(cond, slots);
+                resolved_slots(a, slots);
+                resolved_slots(b, slots);
+            }
+            TypedExpr::Case(_, _, cond, branches) => {
+                resolved_slots(cond, slots);
+                branches.iter().for_each(|(_, e)| resolved_slots(e, slots));
+            }
+            TypedExpr::Let(_, _, entries, body) => {
+                for entry in entries {
+                    match entry {
+                        LetEntry::Definition(def) => resolved_slots(&def.expr, slots),
+                        LetEntry::Pattern(_, e) => resolved_slots(e, slots),
+                        LetEntry::Functions(group, _) => {
+                            group.functions.iter().for_each(|f| resolved_slots(&f.expr, slots));
+                        }
+                    }
+                }
+                resolved_slots(body, slots);
+            }
+            TypedExpr::Closure(_, _, group, _) => {
+                group.functions.iter().for_each(|f| resolved_slots(&f.expr, slots));
+            }
+            _ => {}
+        }
+    }
+
+    fn slots_of(code: &str) -> Vec<(String, VarSlot)> {
+        let env = RuntimeStack::new();
+        let expr = Resolver::new(&env).resolve_expr(&Test::typed_expr(code));
+        let mut slots = vec![];
+        resolved_slots(&expr, &mut slots);
+        slots
+    }
+
+    fn local(name: &str, slot: usize) -> (String, VarSlot) {
+        (name.to_string(), VarSlot::Local(slot))
+    }
+
+    #[test]
+    fn check_let_slots() {
+        assert_eq!(slots_of("let\n (a, b) = (1, 2)\n c = 3\n in\n (c, b, a)"), vec![
+            local("c", 2),
+            local("b", 1),
+            local("a", 0),
+        ]);
+    }
+
+    #[test]
+    fn check_case_slots() {
+        assert_eq!(slots_of("let x = 1 in case (x, 2) of\n (a, b) -> b"), vec![
+            local("x", 0),
+            local("b", 2),
+        ]);
+    }
+
+    #[test]
+    fn check_lambda_captures() {
+        let expr = Test::typed_expr("let w = 1 in \\x -> (x, w)");
+        let env = RuntimeStack::new();
+
+        match Resolver::new(&env).resolve_expr(&expr) {
+            TypedExpr::Let(_, _, _, lambda) => {
+                if let TypedExpr::Closure(_, _, group, captures) = *lambda {
+                    assert_eq!(group.captures, vec!["w".to_string()]);
+                    assert_eq!(captures, vec![VarSlot::Local(0)]);
+                } else {
+                    panic!("Expected closure: {:?}", lambda)
+                }
+            }
+            other => panic!("Expected let: {:?}", other)
+        }
+
+        assert_eq!(slots_of("let w = 1 in \\x -> (x, w)"), vec![
+            local("x", 0),
+            ("w".to_string(), VarSlot::Capture(0)),
+        ]);
+    }
+
+    #[test]
+    fn check_local_function_slots() {
+        let slots = slots_of("let\n k = 1\n loop n = loop k\n in\n loop 4");
+
+        assert_eq!(slots, vec![
+            ("loop".to_string(), VarSlot::Sibling(0)),
+            ("k".to_string(), VarSlot::Capture(0)),
+            local("loop", 1),
+        ]);
+    }
+
+    #[test]
+    fn check_global_slots() {
+        let env = RuntimeStack::new();
+        let expr = TypedExpr::Ref((0, 0), Type::Unit, "later".to_string());
+
+        match Resolver::new(&env).resolve_expr(&expr) {
+            TypedExpr::Resolved(_, _, _, VarSlot::Global(id)) => {
+                assert_eq!(env.interner().id("later"), Some(id));
+                assert_eq!(env.global_name(id), "later");
+            }
+            other => panic!("Expected global: {:?}", other)
+        }
+
+        // Each runtime has its own table of names
+        assert_eq!(RuntimeStack::new().interner().id("later"), None);
+    }
+}
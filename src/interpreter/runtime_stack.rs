@@ -1,57 +1,200 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::RwLock;
 
 use types::Value;
 
+/// Index of an interned global name, the same name has the same id in all the interpreters
+/// that share the `Interner`
+pub type GlobalId = usize;
+
+/// Values of the interpreter, global definitions are indexed by the interned name and local
+/// variables by the slot assigned in the resolution pass
 #[derive(Clone, Debug)]
 pub struct RuntimeStack {
-    frames: Vec<StackFrame>
+    interner: Arc<Interner>,
+    globals: Arc<Globals>,
+    frames: Vec<Vec<Value>>,
 }
 
-#[derive(Clone, Debug)]
-struct StackFrame {
-    values: HashMap<String, Value>
+/// Global definitions of a module or of the top level, functions keep a reference to the
+/// globals of the place where they were defined to find the definitions added after them
+#[derive(Default)]
+pub struct Globals {
+    values: RwLock<Vec<Option<Value>>>,
+}
+
+/// Table of global names, shared by all the interpreters of a runtime so resolved references
+/// stay valid when a function created in one interpreter is called from another.
+/// Names are interned when the code is resolved or compiled, running the code only uses the ids.
+#[derive(Default)]
+pub struct Interner {
+    table: RwLock<InternTable>,
+}
+
+#[derive(Default)]
+struct InternTable {
+    ids: HashMap<String, GlobalId>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    /// Returns the id of a global name, adding it to the table if needed
+    pub fn intern(&self, name: &str) -> GlobalId {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+
+        let mut table = self.table.write().unwrap();
+
+        if let Some(id) = table.ids.get(name) {
+            return *id;
+        }
+
+        let id = table.names.len();
+        table.names.push(name.to_string());
+        table.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Id of a name, if it was interned
+    pub fn id(&self, name: &str) -> Option<GlobalId> {
+        self.table.read().unwrap().ids.get(name).cloned()
+    }
+
+    /// Name of an interned global
+    pub fn name(&self, id: GlobalId) -> String {
+        self.table.read().unwrap().names[id].clone()
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Interner")
+    }
+}
+
+impl Globals {
+    pub fn get(&self, id: GlobalId) -> Option<Value> {
+        self.values.read().unwrap().get(id).and_then(|opt| opt.clone())
+    }
+
+    pub fn set(&self, id: GlobalId, val: Value) {
+        let mut values = self.values.write().unwrap();
+
+        if values.len() <= id {
+            values.resize(id + 1, None);
+        }
+        values[id] = Some(val);
+    }
+}
+
+// The values contain functions that point back to the globals
+impl fmt::Debug for Globals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Globals")
+    }
 }
 
 impl RuntimeStack {
     pub fn new() -> Self {
+        Self::with_interner(Arc::new(Interner::default()))
+    }
+
+    pub fn with_interner(interner: Arc<Interner>) -> Self {
         RuntimeStack {
-            frames: vec![StackFrame { values: HashMap::new() }]
+            interner,
+            globals: Arc::new(Globals::default()),
+            // Frame for the local variables of the top level expressions
+            frames: vec![vec![]],
         }
     }
 
+    /// Adds or replaces a global definition
     pub fn add(&mut self, name: &str, val: Value) {
-        self.frames.last_mut().unwrap().values.insert(name.to_owned(), val);
+        let id = self.interner.intern(name);
+        self.set_global(id, val);
     }
 
+    /// Finds a global definition by name
     pub fn find(&self, name: &str) -> Option<Value> {
-        for frame in self.frames.iter().rev() {
-            let opt = frame.values.get(name);
-            if opt.is_some() {
-                return opt.cloned();
-            }
-        }
-        None
+        self.interner.id(name).and_then(|id| self.get_global(id))
+    }
+
+    /// Returns the id of a global name, used to resolve the references before running the code
+    pub fn intern(&self, name: &str) -> GlobalId {
+        self.interner.intern(name)
+    }
+
+    /// Name of an interned global, for error messages
+    pub fn global_name(&self, id: GlobalId) -> String {
+        self.interner.name(id)
+    }
+
+    pub fn interner(&self) -> &Arc<Interner> {
+        &self.interner
+    }
+
+    pub fn set_global(&mut self, id: GlobalId, val: Value) {
+        self.globals.set(id, val);
+    }
+
+    pub fn get_global(&self, id: GlobalId) -> Option<Value> {
+        self.globals.get(id)
     }
 
-    pub fn enter_block(&mut self) {
-        self.frames.push(StackFrame { values: HashMap::new() });
+    /// Globals shared with the functions defined in this stack
+    pub fn globals(&self) -> &Arc<Globals> {
+        &self.globals
     }
 
-    pub fn exit_block(&mut self) {
+    /// Starts the frame of a function call
+    pub fn enter_frame(&mut self, values: Vec<Value>) {
+        self.frames.push(values);
+    }
+
+    pub fn exit_frame(&mut self) {
         self.frames.pop().expect("Tried to pop all the stack frames!");
     }
 
+    /// Adds a local variable to the current frame, its slot is the number of previous variables
+    pub fn push(&mut self, val: Value) {
+        self.frames.last_mut().unwrap().push(val);
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Value> {
+        self.frames.last().unwrap().get(slot)
+    }
+
+    /// Number of local variables in the current frame
+    pub fn level(&self) -> usize {
+        self.frames.last().unwrap().len()
+    }
+
+    /// Removes the local variables added after the frame had `level` variables
+    pub fn truncate(&mut self, level: usize) {
+        self.frames.last_mut().unwrap().truncate(level);
+    }
+
     pub fn debug(&self) -> String {
         let mut msg = String::new();
 
+        writeln!(&mut msg, "Globals").unwrap();
+        for (id, value) in self.globals.values.read().unwrap().iter().enumerate() {
+            if let Some(value) = value {
+                writeln!(&mut msg, "  {} = {}", self.interner.name(id), value).unwrap();
+            }
+        }
+
         for (i, frame) in self.frames.iter().enumerate() {
             writeln!(&mut msg, "Frame #{}", i).unwrap();
-            for (name, value) in &frame.values {
-                writeln!(&mut msg, "  {} = {}", name, value).unwrap();
+            for (slot, value) in frame.iter().enumerate() {
+                writeln!(&mut msg, "  #{} = {}", slot, value).unwrap();
             }
         }
 
         msg
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use ast::Span;
+use errors::ElmError;
+use interpreter::Interpreter;
+use interpreter::runtime_stack::Globals;
+use source::SourceCode;
+use source::SourceSpan;
+use types::Function;
+
+/// Module and source code where a function was defined
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeOrigin {
+    pub module: String,
+    pub source: SourceCode,
+}
+
+/// A function that was running when an error happened, the span points to the expression
+/// that was being evaluated inside the function
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallFrame {
+    pub function: String,
+    pub module: String,
+    pub source: SourceCode,
+    pub span: Span,
+}
+
+impl CallFrame {
+    /// File, line and column of the expression that was being evaluated
+    pub fn location(&self) -> SourceSpan {
+        self.source.locate(self.span)
+    }
+}
+
+/// List of call frames, the innermost call goes first and the top level code goes last
+pub type StackTrace = Vec<CallFrame>;
+
+/// Function in execution, `span` is updated before each call it makes
+#[derive(Clone, Debug)]
+pub(crate) struct ActiveCall {
+    function: Arc<Function>,
+    span: Span,
+}
+
+impl CodeOrigin {
+    pub fn new(module: &str, source: SourceCode) -> Self {
+        CodeOrigin { module: module.to_string(), source }
+    }
+
+    pub fn empty() -> Self {
+        CodeOrigin::new("", SourceCode::from_str(""))
+    }
+}
+
+impl Interpreter {
+    /// Sets the module and the source code of the top level expressions evaluated next
+    pub fn set_origin(&mut self, module: &str, source: SourceCode) {
+        self.origin = Arc::new(CodeOrigin::new(module, source));
+    }
+
+    /// Origin of the code in execution, closures created now are defined in the same module
+    pub(crate) fn current_origin(&self) -> Arc<CodeOrigin> {
+        match self.current_function().map(|fun| fun.as_ref()) {
+            Some(Function::Definition { origin, .. }) => origin.clone(),
+            Some(Function::Compiled { env, .. }) => env.origin.clone(),
+            _ => self.origin.clone(),
+        }
+    }
+
+    /// Globals of the code in execution, functions look up there the definitions that didn't
+    /// exist when they were created
+    pub(crate) fn current_globals(&self) -> Arc<Globals> {
+        match self.current_function().map(|fun| fun.as_ref()) {
+            Some(Function::Definition { globals, .. }) => globals.clone(),
+            Some(Function::Compiled { env, .. }) => env.globals.clone(),
+            _ => self.stack.globals().clone(),
+        }
+    }
+
+    /// Innermost function in execution
+    pub(crate) fn current_function(&self) -> Option<&Arc<Function>> {
+        self.calls.last().map(|call| &call.function)
+    }
+
+    pub(crate) fn enter_frame(&mut self, function: &Arc<Function>, span: Span) {
+        self.calls.push(ActiveCall { function: function.clone(), span });
+    }
+
+    pub(crate) fn exit_frame(&mut self) {
+        self.calls.pop();
+    }
+
+    /// Records the location of the function call about to be executed
+    pub(crate) fn set_call_site(&mut self, span: Span) {
+        match self.calls.last_mut() {
+            Some(call) => call.span = span,
+            None => self.top_level_span = span,
+        }
+    }
+
+    /// Attaches the current call frames to an interpreter error, the innermost frame points
+    /// to `span`. Errors that already have a stack trace are returned unchanged.
+    pub(crate) fn add_stack_trace(&self, error: ElmError, span: Span) -> ElmError {
+        match error {
+            ElmError::Interpreter(ref trace, _) if !trace.is_empty() => error,
+            ElmError::Interpreter(_, info) => {
+                let mut trace = vec![];
+                let mut top_level_span = self.top_level_span;
+
+                for call in self.calls.iter().rev() {
+                    let (name, origin) = match call.function.as_ref() {
+                        Function::Definition { group, index, origin, .. } => {
+                            (group.functions[*index].name.clone(), origin)
+                        }
+                        Function::Compiled { env, index, .. } => {
+                            let proto = &env.group.members[*index];
+
+                            if proto.top_level {
+                                top_level_span = call.span;
+                                continue;
+                            }
+
+                            (proto.name.clone(), &env.origin)
+                        }
+                        _ => continue,
+                    };
+
+                    trace.push(CallFrame {
+                        function: name.unwrap_or_else(|| "<lambda>".to_string()),
+                        module: origin.module.clone(),
+                        source: origin.source.clone(),
+                        span: call.span,
+                    });
+                }
+
+                trace.push(CallFrame {
+                    function: "<top level>".to_string(),
+                    module: self.origin.module.clone(),
+                    source: self.origin.source.clone(),
+                    span: top_level_span,
+                });
+
+                trace[0].span = span;
+                ElmError::Interpreter(trace, info)
+            }
+            _ => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::InterpreterError;
+    use Runtime;
+
+    use super::*;
+
+    fn trace_of(error: ElmError) -> StackTrace {
+        match error {
+            ElmError::Interpreter(trace, _) => trace,
+            _ => panic!("Expected interpreter error: {:?}", error)
+        }
+    }
+
+    fn frame_code(frame: &CallFrame) -> &str {
+        &frame.source.as_str()[frame.span.0 as usize..frame.span.1 as usize]
+    }
+
+    #[test]
+    fn check_stack_trace() {
+        let mut i = Runtime::new();
+        i.eval_statement("unwrap m = case m of\n Just v -> v").unwrap();
+        i.eval_statement("twice m = unwrap m + unwrap m").unwrap();
+
+        let trace = trace_of(i.eval_expr("1 + twice Nothing").unwrap_err());
+        let names = trace.iter().map(|f| f.function.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["unwrap", "twice", "<top level>"]);
+        assert_eq!(frame_code(&trace[0]), "case m of\n Just v -> v");
+        assert_eq!(frame_code(&trace[1]), "unwrap m");
+        assert_eq!(frame_code(&trace[2]), "twice Nothing");
+    }
+
+    #[test]
+    fn check_stack_trace_lambda() {
+        let mut i = Runtime::new();
+        i.eval_statement("apply f x = f x").unwrap();
+
+        let error = i.eval_expr("apply (\\x -> case x of\n 0 -> 1) 5").unwrap_err();
+        let names = trace_of(error.clone()).iter().map(|f| f.function.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["<lambda>", "apply", "<top level>"]);
+
+        match error {
+            ElmError::Interpreter(_, InterpreterError::CaseExpressionNonExhaustive(..)) => {}
+            _ => panic!("Unexpected error: {:?}", error)
+        }
+    }
+
+    #[test]
+    fn check_stack_trace_format() {
+        let mut i = Runtime::new();
+        i.eval_statement("unwrap m = case m of\n Just v -> v").unwrap();
+
+        let msg = format!("{}", i.eval_expr("unwrap Nothing").unwrap_err());
+
+        assert!(msg.contains("in `unwrap`"), "{}", msg);
+        assert!(msg.contains("1 │ unwrap m = case m of"), "{}", msg);
+        assert!(msg.contains("1 │ unwrap Nothing"), "{}", msg);
+    }
+
+    #[test]
+    fn check_stack_trace_locations() {
+        let mut i = Runtime::new();
+        i.eval_module("module Util exposing (..)\n\nunwrap m =\n    case m of\n        Just v -> v\n", "Util").unwrap();
+
+        let error = i.eval_expr("Util.unwrap Nothing").unwrap_err();
+        let trace = trace_of(error.clone());
+
+        assert_eq!(trace[0].location().to_string(), "Util:4:5");
+        assert_eq!(trace[1].location().path, "inline");
+        assert_ne!(trace[0].location().file, trace[1].location().file);
+        assert_eq!(error.location(), Some(trace[0].location()));
+    }
+}
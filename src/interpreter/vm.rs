@@ -0,0 +1,521 @@
+use std::sync::Arc;
+
+use errors::*;
+use interpreter::bytecode::ClosureEnv;
+use interpreter::bytecode::CompiledGroup;
+use interpreter::bytecode::Compiler;
+use interpreter::bytecode::Op;
+use interpreter::bytecode::Proto;
+use interpreter::bytecode::SlotPattern;
+use interpreter::Interpreter;
+use typed_ast::TypedDefinition;
+use typed_ast::TypedExpr;
+use types::Function;
+use types::next_fun_id;
+use types::Value;
+
+/// Function in execution inside the virtual machine
+struct Frame {
+    proto: Arc<Proto>,
+    env: Arc<ClosureEnv>,
+    locals: Vec<Value>,
+    pc: usize,
+    /// Size of the operand stack when the function was called
+    base: usize,
+}
+
+impl Interpreter {
+    /// Compiles an expression to bytecode and runs it
+    pub(crate) fn run_expr(&mut self, expr: &TypedExpr) -> Result<Value, ElmError> {
+        let compiled = Compiler::new(&self.stack).compile_expr(expr)
+            .map_err(|e| self.add_stack_trace(e, expr.get_span()))?;
+
+        match self.create_compiled_closures(compiled).remove(0) {
+            Value::Fun { fun, .. } => self.exec_fun(&fun, vec![]),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Compiles a definition to bytecode and returns the closure that runs it
+    pub(crate) fn create_compiled_closure(&mut self, def: &TypedDefinition) -> Result<Value, ElmError> {
+        let compiled = Compiler::new(&self.stack).compile_definition(def)?;
+        Ok(self.create_compiled_closures(compiled).remove(0))
+    }
+
+    fn create_compiled_closures(&self, compiled: CompiledGroup) -> Vec<Value> {
+        let env = Arc::new(ClosureEnv {
+            group: compiled.group,
+            captures: compiled.captures,
+            origin: self.current_origin(),
+            globals: self.current_globals(),
+        });
+
+        (0..env.group.members.len())
+            .map(|index| closure_value(&env, index))
+            .collect()
+    }
+
+    /// Runs a compiled function, the calls to other compiled functions reuse the same loop
+    /// so they don't consume native stack
+    pub(crate) fn run_compiled(&mut self, function: &Arc<Function>, args: Vec<Value>) -> Result<Value, ElmError> {
+        let first = Frame::new(function, args, 0);
+        self.enter_frame(function, first.body_span());
+
+        let mut frames = vec![first];
+        let mut stack = vec![];
+
+        match self.execute(&mut frames, &mut stack) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                let error = {
+                    // The step check can fail before the first instruction of the frame
+                    let frame = frames.last().unwrap();
+                    self.add_stack_trace(error, frame.proto.spans[frame.pc.saturating_sub(1)])
+                };
+
+                // The depth of the first frame is managed by the caller
+                for index in 0..frames.len() {
+                    self.exit_frame();
+                    if index > 0 {
+                        self.exit_call();
+                    }
+                }
+
+                Err(error)
+            }
+        }
+    }
+
+    fn execute(&mut self, frames: &mut Vec<Frame>, stack: &mut Vec<Value>) -> Result<Value, ElmError> {
+        loop {
+            self.consume_step()?;
+
+            let frame = frames.last_mut().unwrap();
+            let op = frame.proto.code[frame.pc].clone();
+            frame.pc += 1;
+
+            match op {
+                Op::Const(index) => {
+                    stack.push(frame.proto.constants[index].clone());
+                }
+                Op::LoadLocal(slot) => {
+                    stack.push(frame.locals[slot].clone());
+                }
+                Op::StoreLocal(slot) => {
+                    frame.locals[slot] = stack.pop().unwrap();
+                }
+                Op::LoadCapture(index) => {
+                    stack.push(frame.env.captures[index].clone());
+                }
+                Op::LoadSibling(index) => {
+                    stack.push(closure_value(&frame.env, index));
+                }
+                Op::LoadGlobal(id) => {
+                    let value = self.load_global(&frame.env.globals, id)?
+                        .ok_or_else(|| InterpreterError::MissingDefinition(self.stack.global_name(id)).wrap())?;
+
+                    stack.push(value);
+                }
+                Op::Tuple(size) => {
+                    let items = pop_values(stack, size);
+                    stack.push(Value::Tuple(items));
+                }
+                Op::List(size) => {
+                    let list = Value::List(pop_values(stack, size).into());
+                    self.check_value_size(&list)?;
+                    stack.push(list);
+                }
+                Op::Record(index) => {
+                    let fields = &frame.proto.fields[index];
+                    let values = pop_values(stack, fields.len());
+
+                    stack.push(Value::Record(Arc::new(fields.iter().cloned().zip(values).collect())));
+                }
+                Op::RecordUpdate(index) => {
+                    let fields = &frame.proto.fields[index];
+                    let values = pop_values(stack, fields.len());
+                    let record = stack.pop().unwrap();
+
+                    if let Value::Record(entries) = record {
+                        let entries = entries.iter()
+                            .map(|(name, value)| {
+                                match fields.iter().position(|field| field == name) {
+                                    Some(index) => (name.clone(), values[index].clone()),
+                                    None => (name.clone(), value.clone()),
+                                }
+                            })
+                            .collect();
+
+                        stack.push(Value::Record(Arc::new(entries)));
+                    } else {
+                        return Err(InterpreterError::ExpectedRecord(record).wrap());
+                    }
+                }
+                Op::RecordField(index) => {
+                    let field = &frame.proto.names[index];
+                    let record = stack.pop().unwrap();
+
+                    let value = if let Value::Record(entries) = &record {
+                        entries.iter()
+                            .find(|(name, _)| name == field)
+                            .map(|(_, value)| value.clone())
+                            .ok_or_else(|| InterpreterError::RecordFieldNotFound(field.clone(), record.clone()).wrap())?
+                    } else {
+                        return Err(InterpreterError::ExpectedRecord(record).wrap());
+                    };
+
+                    stack.push(value);
+                }
+                Op::MakeClosures(index, size) => {
+                    let env = Arc::new(ClosureEnv {
+                        group: frame.proto.groups[index].clone(),
+                        captures: pop_values(stack, size),
+                        origin: frame.env.origin.clone(),
+                        globals: frame.env.globals.clone(),
+                    });
+
+                    for index in 0..env.group.members.len() {
+                        stack.push(closure_value(&env, index));
+                    }
+                }
+                Op::Jump(target) => {
+                    frame.pc = target;
+                }
+                Op::JumpIfFalse(target) => {
+                    if !bool_value(stack.pop().unwrap())? {
+                        frame.pc = target;
+                    }
+                }
+                Op::Match(index, target) => {
+                    let value = stack.pop().unwrap();
+
+                    if !match_pattern(&frame.proto.patterns[index], &value, &mut frame.locals) {
+                        frame.pc = target;
+                    }
+                }
+                Op::Destructure(index) => {
+                    let value = stack.pop().unwrap();
+
+                    if !match_pattern(&frame.proto.patterns[index], &value, &mut frame.locals) {
+                        return Err(InterpreterError::CaseExpressionNonExhaustive(value, vec![]).wrap());
+                    }
+                }
+                Op::CaseFail(index) => {
+                    let value = stack.pop().unwrap();
+                    let patterns = frame.proto.cases[index].clone();
+
+                    return Err(InterpreterError::CaseExpressionNonExhaustive(value, patterns).wrap());
+                }
+                Op::Return => {
+                    let value = stack.pop().unwrap();
+
+                    if let Some(value) = self.return_value(frames, stack, value) {
+                        return Ok(value);
+                    }
+                }
+                Op::Call(size) | Op::TailCall(size) => {
+                    let is_tail = op == Op::TailCall(size);
+                    let span = frame.proto.spans[frame.pc - 1];
+                    let args = pop_values(stack, size);
+                    let function = stack.pop().unwrap();
+
+                    match saturated_call(function, args) {
+                        Ok((function, args)) => {
+                            if is_tail {
+                                // The new frame replaces the current one, the depth doesn't change
+                                let frame = frames.pop().unwrap();
+                                stack.truncate(frame.base);
+                                self.exit_frame();
+                            } else {
+                                self.set_call_site(span);
+                                self.enter_call()?;
+                            }
+
+                            let frame = Frame::new(&function, args, stack.len());
+                            self.enter_frame(&function, frame.body_span());
+                            frames.push(frame);
+                        }
+                        Err((function, args)) => {
+                            self.set_call_site(span);
+                            let value = self.apply_function(function, &args)?;
+
+                            if !is_tail {
+                                stack.push(value);
+                            } else if let Some(value) = self.return_value(frames, stack, value) {
+                                return Ok(value);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes the current frame, returns the value if it was the last one
+    fn return_value(&mut self, frames: &mut Vec<Frame>, stack: &mut Vec<Value>, value: Value) -> Option<Value> {
+        let frame = frames.pop().unwrap();
+        stack.truncate(frame.base);
+        self.exit_frame();
+
+        if frames.is_empty() {
+            return Some(value);
+        }
+
+        self.exit_call();
+        stack.push(value);
+        None
+    }
+}
+
+impl Frame {
+    fn new(function: &Arc<Function>, args: Vec<Value>, base: usize) -> Self {
+        let (env, index) = match function.as_ref() {
+            Function::Compiled { env, index, .. } => (env.clone(), *index),
+            _ => unreachable!("Only compiled functions run in the virtual machine"),
+        };
+
+        let proto = env.group.members[index].clone();
+        let mut locals = args;
+        locals.resize(proto.slots, Value::Unit);
+
+        Frame { proto, env, locals, pc: 0, base }
+    }
+
+    fn body_span(&self) -> (u32, u32) {
+        self.proto.spans.last().cloned().unwrap_or((0, 0))
+    }
+}
+
+fn closure_value(env: &Arc<ClosureEnv>, index: usize) -> Value {
+    Value::Fun {
+        arg_count: env.group.members[index].arity as u32,
+        args: vec![],
+        fun: Arc::new(Function::Compiled { id: next_fun_id(), env: env.clone(), index }),
+    }
+}
+
+/// Checks if the call can run in a new frame of the virtual machine, otherwise the function and
+/// the arguments are returned to be applied by the interpreter
+fn saturated_call(function: Value, args: Vec<Value>) -> Result<(Arc<Function>, Vec<Value>), (Value, Vec<Value>)> {
+    if let Value::Fun { arg_count, args: applied, fun } = &function {
+        if let Function::Compiled { .. } = fun.as_ref() {
+            if applied.len() + args.len() == *arg_count as usize {
+                let mut all_args = applied.clone();
+                all_args.extend(args);
+                return Ok((fun.clone(), all_args));
+            }
+        }
+    }
+
+    Err((function, args))
+}
+
+fn pop_values(stack: &mut Vec<Value>, size: usize) -> Vec<Value> {
+    let start = stack.len() - size;
+    stack.split_off(start)
+}
+
+pub(crate) fn bool_value(value: Value) -> Result<bool, ElmError> {
+    if let Value::Adt(name, values, _) = &value {
+        if values.is_empty() {
+            if name == "True" {
+                return Ok(true);
+            } else if name == "False" {
+                return Ok(false);
+            }
+        }
+    }
+
+    Err(InterpreterError::InvalidIfCondition(value).wrap())
+}
+
+/// Checks if the value matches the pattern, storing the values of the variables in the slots
+fn match_pattern(pattern: &SlotPattern, value: &Value, locals: &mut [Value]) -> bool {
+    match pattern {
+        SlotPattern::Var(slot) => {
+            locals[*slot] = value.clone();
+            true
+        }
+        SlotPattern::Wildcard => true,
+        SlotPattern::Unit => value == &Value::Unit,
+        SlotPattern::Alias(patt, slot) => {
+            locals[*slot] = value.clone();
+            match_pattern(patt, value, locals)
+        }
+        SlotPattern::Adt(name, items) => {
+            if let Value::Adt(v_name, values, _) = value {
+                name == v_name && match_all(items, values, locals)
+            } else {
+                false
+            }
+        }
+        SlotPattern::Tuple(items) => {
+            if let Value::Tuple(values) = value { match_all(items, values, locals) } else { false }
+        }
+        SlotPattern::List(items) => {
+            if let Value::List(values) = value {
+                items.len() == values.len() &&
+                    items.iter().zip(values).all(|(patt, value)| match_pattern(patt, value, locals))
+            } else {
+                false
+            }
+        }
+        SlotPattern::Cons(first, rest) => {
+            if let Value::List(values) = value {
+                match values.split_first() {
+                    Some((head, tail)) => {
+                        match_pattern(first, head, locals) &&
+                            match_pattern(rest, &Value::List(tail), locals)
+                    }
+                    None => false,
+                }
+            } else {
+                false
+            }
+        }
+        SlotPattern::Record(fields) => {
+            if let Value::Record(entries) = value {
+                fields.iter().all(|(field, slot)| {
+                    match entries.iter().find(|(name, _)| name == field) {
+                        Some((_, value)) => {
+                            locals[*slot] = value.clone();
+                            true
+                        }
+                        None => false,
+                    }
+                })
+            } else {
+                false
+            }
+        }
+        SlotPattern::LitInt(p) => {
+            match value {
+                Value::Int(v) | Value::Number(v) => p == v,
+                _ => false,
+            }
+        }
+        SlotPattern::LitString(p) => {
+            if let Value::String(v) = value { p == v } else { false }
+        }
+        SlotPattern::LitChar(p) => {
+            if let Value::Char(v) = value { p == v } else { false }
+        }
+    }
+}
+
+fn match_all(patterns: &[SlotPattern], values: &[Value], locals: &mut [Value]) -> bool {
+    patterns.len() == values.len() &&
+        patterns.iter().zip(values).all(|(patt, value)| match_pattern(patt, value, locals))
+}
+
+#[cfg(test)]
+mod tests {
+    use interpreter::Backend;
+    use Runtime;
+
+    use super::*;
+
+    /// Runs the same code with both backends, the results must be equal
+    fn compare(statements: &[&str], exprs: &[&str]) {
+        let mut tree_walker = Runtime::new();
+        let mut bytecode = Runtime::with_backend(Backend::Bytecode);
+
+        for stm in statements {
+            tree_walker.eval_statement(stm).expect("Tree-walker failed to evaluate the statement");
+            bytecode.eval_statement(stm).expect("Bytecode failed to evaluate the statement");
+        }
+
+        for expr in exprs {
+            // Functions are compared by id, so the values are compared by their text representation
+            let expected = tree_walker.eval_expr(expr).map(|value| value.to_string());
+            let found = bytecode.eval_expr(expr).map(|value| value.to_string());
+
+            assert_eq!(expected, found, "Different results for: {}", expr);
+        }
+    }
+
+    #[test]
+    fn check_vm_programs() {
+        compare(&[
+            "fib num = case num of \n 0 -> 0 \n 1 -> 1 \n _ -> fib (num - 1) + fib (num - 2)",
+            "countdown n acc = if n == 0 then acc else countdown (n - 1) (acc + 2)",
+            "build n acc = if n == 0 then acc else build (n - 1) (n :: acc)",
+            "len list acc = case list of\n [] -> acc\n _ :: rest -> len rest (acc + 1)",
+            "total n = let go x acc = if x == 0 then acc else go (x - 1) (acc + 1) in go n 0",
+            "adder n = let add x = x + n in add",
+            "sumPair x = let (a, b) = (x, x * 2) in a + b",
+            "curry3 a = \\b -> \\c -> a * 100 + b * 10 + c",
+            "unwrap m = case m of\n Just v -> v\n Nothing -> 0",
+            "add3 a b c = a * 100 + b * 10 + c",
+            "addOne = add3 1",
+            "apply f b = f b 9",
+            "rec = { x = 1, y = \"a\" }",
+        ], &[
+            "fib 15",
+            "countdown 10000 0",
+            "len (build 3000 []) 0",
+            "total 10000",
+            "(adder 2) 5",
+            "sumPair 3",
+            "((curry3 1) 2) 3",
+            "unwrap (Just 4)",
+            "unwrap Nothing",
+            "addOne 2 3",
+            "apply addOne 2",
+            "{ rec | x = 5 }",
+            "(rec.y, .x rec)",
+            "List.map (\\x -> x * 2) [1, 2, 3]",
+            "List.foldl (+) 0 (List.range 1 100)",
+            "String.join \", \" [\"a\", \"b\"]",
+            "Dict.toList (Dict.fromList [(3, \"c\"), (1, \"a\")])",
+            "Set.toList (Set.fromList [3, 1, 2, 1])",
+            "let (a, b) = (1, 2) in b",
+            "let first a b = a in first 1 2",
+            "(\\x -> let f y = x in f ()) 3",
+            "unwrap",
+            "Maybe.withDefault 0 Nothing",
+        ]);
+    }
+
+    #[test]
+    fn check_vm_deep_recursion() {
+        let mut i = Runtime::with_backend(Backend::Bytecode);
+        i.eval_statement("depth n = if n == 0 then 0 else 1 + depth (n - 1)").unwrap();
+
+        // Calls between compiled functions don't use native stack
+        assert_eq!(i.eval_expr("depth 50000"), Ok(Value::Number(50000)));
+    }
+
+    #[test]
+    fn check_vm_step_limits() {
+        use interpreter::limits::ExecutionLimits;
+
+        let mut i = Runtime::with_backend(Backend::Bytecode);
+        i.eval_statement("f x = x + 1").unwrap();
+
+        assert_eq!(i.eval_expr("f 1"), Ok(Value::Number(2)));
+        let used = i.steps_used();
+
+        // Every limit lower than the steps of the evaluation stops it at a different instruction
+        for steps in 0..used {
+            i.reset_budget();
+            i.set_limits(ExecutionLimits::unlimited().with_max_steps(steps));
+            assert!(i.eval_expr("f 1").is_err(), "Expected error with {} steps", steps);
+        }
+    }
+
+    #[test]
+    fn check_vm_stack_trace() {
+        let mut i = Runtime::with_backend(Backend::Bytecode);
+        i.eval_statement("unwrap m = case m of\n Just v -> v").unwrap();
+        i.eval_statement("twice m = unwrap m + unwrap m").unwrap();
+
+        match i.eval_expr("1 + twice Nothing") {
+            Err(ElmError::Interpreter(trace, InterpreterError::CaseExpressionNonExhaustive(..))) => {
+                let names = trace.iter().map(|f| f.function.as_str()).collect::<Vec<_>>();
+                assert_eq!(names, vec!["unwrap", "twice", "<top level>"]);
+            }
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+}
@@ -5,7 +5,11 @@
 // }
 // TODO port to Rust 2018
 
+// The code generated by `#[derive(ElmValue)]` refers to this crate by name
+extern crate self as elm_interpreter;
+extern crate elm_interpreter_derive;
 extern crate hashbrown;
+extern crate im;
 extern crate nom;
 #[cfg(test)]
 #[macro_use]
@@ -19,12 +23,18 @@ use std::sync::Arc;
 use analyzer::Analyzer;
 use ast::Type;
 use builtin::ELM_CORE_MODULES;
+use builtin::ELM_JSON_MODULES;
 use builtin::get_core_kernel_modules;
 use errors::ElmError;
 use errors::LoaderError;
 use errors::Wrappable;
+use interpreter::Backend;
 use interpreter::Interpreter;
+use interpreter::runtime_stack::Interner;
+use interpreter::limits::ExecutionLimits;
+use interpreter::limits::InterruptHandle;
 use loader::AnalyzedModule;
+use loader::Declaration;
 use loader::declaration_name;
 use loader::declaration_type;
 use loader::LoadedModule;
@@ -32,6 +42,9 @@ use loader::ModuleLoader;
 use loader::RuntimeModule;
 use loader::SourceFile;
 use parsers::Parser;
+use platform::ports::Ports;
+use platform::scheduler::Scheduler;
+use rust_interop::elm_value::TypeRegistry;
 use source::SourceCode;
 use tokenizer::Tokenizer;
 use types::ExternalFunc;
@@ -41,6 +54,9 @@ use types::Value;
 use util::{build_fun_type, create_vec_inv, resource_path};
 
 pub mod ast;
+pub mod cst;
+pub mod formatter;
+pub mod lsp;
 pub mod typed_ast;
 pub mod types;
 #[macro_use]
@@ -55,53 +71,35 @@ pub mod errors;
 pub mod rust_interop;
 pub mod loader;
 pub mod source;
+pub mod platform;
 #[cfg(test)]
 pub mod test_utils;
 
 #[derive(Debug)]
 pub struct Runtime {
+    interner: Arc<Interner>,
     interpreter: Interpreter,
     analyzer: Analyzer,
     loaded_modules: HashMap<String, LoadedModule>,
     analyzed_modules: HashMap<String, AnalyzedModule>,
     runtime_modules: HashMap<String, RuntimeModule>,
+    ports: Ports,
+    scheduler: Scheduler,
+    types: TypeRegistry,
 }
 
 impl Runtime {
     /// Creates a new Runtime instance
     pub fn new() -> Runtime {
-        let mut run = Self::empty_runtime();
-
-        // Add kernel modules, in the standard compiler those modules are written in JS,
-        // but here they are in Rust
-        for (name, analyzed, runtime) in get_core_kernel_modules() {
-            run.analyzed_modules.insert(name.to_string(), analyzed);
-            run.runtime_modules.insert(name.to_string(), runtime);
-        }
-
-        // Load Elm-core modules, except Array, Process, Task and Platform as they doesn't make sense
-        // outside JS and a browser sandbox
-
-        // Load from packed modules
-        for name in ELM_CORE_MODULES.iter() {
-            let path = format!("{}/{}.json", resource_path("packed_modules/core"), name);
-            run.include_packed_module(&path).unwrap();
-        }
-
-        /* DEBUG ONLY
-        // Load from elm-core source files.
-        // (To get nice error messages when debugging, download the git repo and update the path)
-        for name in ELM_CORE_MODULES.iter() {
-            let path = format!("/Data/Dev/Elm/core/src/{}.elm", name);
-            run.include_file(&path).unwrap();
-        }
-        */
+        Self::with_backend(Backend::default())
+    }
 
-        // Analyze and evaluate all core modules
-        for name in ELM_CORE_MODULES.iter() {
-            run.load_analyzed_module(name).expect("Unable to load analyzed module");
-            run.load_runtime_module(name).expect("Unable to load runtime module");
-        }
+    /// Creates a new Runtime instance that executes all the code, including the core modules,
+    /// with the selected backend
+    pub fn with_backend(backend: Backend) -> Runtime {
+        let mut run = Self::empty_runtime();
+        run.set_backend(backend);
+        run.load_core_modules(true);
 
         // Import the default modules into the current environment
         let imports = run.analyzer.get_default_imports(&run.analyzed_modules)
@@ -120,13 +118,27 @@ impl Runtime {
         run
     }
 
+    /// Creates a Runtime instance with the core modules analyzed but not evaluated, enough to
+    /// type check code
+    pub(crate) fn analysis_runtime() -> Runtime {
+        let mut run = Self::empty_runtime();
+        run.load_core_modules(false);
+        run
+    }
+
     pub fn empty_runtime() -> Runtime {
+        let interner = Arc::new(Interner::default());
+
         Runtime {
-            interpreter: Interpreter::new(),
+            interpreter: Interpreter::with_interner(interner.clone()),
+            interner,
             analyzer: Analyzer::new(SourceCode::from_str("")),
             loaded_modules: HashMap::new(),
             analyzed_modules: HashMap::new(),
             runtime_modules: HashMap::new(),
+            ports: Ports::default(),
+            scheduler: Scheduler::default(),
+            types: TypeRegistry::default(),
         }
     }
 
@@ -136,7 +148,8 @@ impl Runtime {
         let tokenizer = Tokenizer::new(&code);
         let mut parser = Parser::new(tokenizer);
         let expr = parser.parse_expression()?;
-        let typed_expr = self.analyzer.with(code).analyze_expression(&expr)?;
+        let typed_expr = self.analyzer.with(code.clone()).analyze_expression(&expr)?;
+        self.interpreter.set_origin("", code);
         let value = self.interpreter.eval_expr(&typed_expr)?;
 
         Ok(value)
@@ -153,6 +166,7 @@ impl Runtime {
         let mut parser = Parser::new(tokenizer);
         let stm = parser.parse_statement()?;
         let declarations = self.analyzer.with(code.clone()).analyze_statement(&stm)?;
+        self.interpreter.set_origin("", code);
 
         let mut opt_value = None;
 
@@ -162,6 +176,13 @@ impl Runtime {
             if let Some(ty) = declaration_type(decl) {
                 self.analyzer.add_port(declaration_name(decl), ty.clone());
             }
+
+            // Types declared in the repl can be used by the next statements
+            match decl {
+                Declaration::Alias(alias) => self.analyzer.add_type_alias(alias.clone()),
+                Declaration::Adt(name, adt) => self.analyzer.add_canonical_type_name(name, &adt.name),
+                _ => {}
+            }
         }
 
         Ok(opt_value)
@@ -177,7 +198,7 @@ impl Runtime {
     /// toRecord (a, b) = { x = a, y = b }
     /// ```
     pub fn eval_module(&mut self, module: &str, name: &str) -> Result<(), ElmError> {
-        let code = SourceCode::from_str(module);
+        let code = SourceCode::from_string(module.to_string(), name);
         let tokenizer = Tokenizer::new(&code);
         let mut parser = Parser::new(tokenizer);
         let module = parser.parse_module()?;
@@ -254,6 +275,34 @@ impl Runtime {
         Ok(())
     }
 
+    /// Selects the backend used to execute the code evaluated or imported from now on,
+    /// the modules already loaded keep their current backend
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.interpreter.set_backend(backend);
+    }
+
+    /// Sets the limits for the following evaluations, going over a limit returns an
+    /// `InterpreterError::ExecutionLimitExceeded` error
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    /// Number of evaluation steps consumed since the last budget reset
+    pub fn steps_used(&self) -> u64 {
+        self.interpreter.steps_used()
+    }
+
+    /// Restores the step budget, otherwise the consumed steps accumulate between evaluations
+    pub fn reset_budget(&mut self) {
+        self.interpreter.reset_budget();
+    }
+
+    /// Returns a handle that can be used from another thread to interrupt the running evaluation,
+    /// the interrupted evaluation returns `InterpreterError::Interrupted`
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interpreter.interrupt_handle()
+    }
+
     /// Print all the values in the stack
     pub fn debug(&self) -> String {
         self.interpreter.debug()
@@ -276,6 +325,52 @@ impl Runtime {
         Ok(())
     }
 
+    /// Loads the kernel, elm-core and elm-json modules, `evaluate` also creates the runtime
+    /// modules needed to execute code
+    fn load_core_modules(&mut self, evaluate: bool) {
+        // Add kernel modules, in the standard compiler those modules are written in JS,
+        // but here they are in Rust
+        for (name, analyzed, runtime) in get_core_kernel_modules() {
+            self.analyzed_modules.insert(name.to_string(), analyzed);
+
+            if evaluate {
+                self.runtime_modules.insert(name.to_string(), runtime);
+            }
+        }
+
+        // Load Elm-core modules, Platform only supports headless workers and the tasks run
+        // in the scheduler of the runtime
+
+        // Load from packed modules
+        for name in ELM_CORE_MODULES.iter() {
+            let path = format!("{}/{}.json", resource_path("packed_modules/core"), name);
+            self.include_packed_module(&path).unwrap();
+        }
+
+        for name in ELM_JSON_MODULES.iter() {
+            let path = format!("{}/{}.json", resource_path("packed_modules/json"), name);
+            self.include_packed_module(&path).unwrap();
+        }
+
+        /* DEBUG ONLY
+        // Load from elm-core source files.
+        // (To get nice error messages when debugging, download the git repo and update the path)
+        for name in ELM_CORE_MODULES.iter() {
+            let path = format!("/Data/Dev/Elm/core/src/{}.elm", name);
+            self.include_file(&path).unwrap();
+        }
+        */
+
+        // Analyze all core modules, and evaluate them if requested
+        for name in ELM_CORE_MODULES.iter().chain(ELM_JSON_MODULES.iter()) {
+            self.load_analyzed_module(name).expect("Unable to load analyzed module");
+
+            if evaluate {
+                self.load_runtime_module(name).expect("Unable to load runtime module");
+            }
+        }
+    }
+
     fn load_analyzed_module(&mut self, module_name: &str) -> Result<(), ElmError> {
         let dependencies = self.loaded_modules.get(module_name)
             .ok_or_else(|| LoaderError::MissingModule { module: module_name.to_string() }.wrap())?
@@ -314,7 +409,14 @@ impl Runtime {
         }
 
 //        eprintln!("Evaluating {}", module_name);
-        let mut interpreter = Interpreter::new();
+        let mut interpreter = Interpreter::with_interner(self.interner.clone());
+        interpreter.set_backend(self.interpreter.backend());
+
+        // Kernel modules don't have source code
+        if let Some(loaded) = self.loaded_modules.get(module_name) {
+            interpreter.set_origin(module_name, loaded.src.source.clone());
+        }
+
         let runtime_module = {
             let module = self.analyzed_modules.get(module_name)
                 .ok_or_else(|| LoaderError::MissingModule { module: module_name.to_string() }.wrap())?;
@@ -331,33 +433,37 @@ impl Runtime {
 #[cfg(test)]
 mod test {
     use ast::Int;
+    use test_utils::Test;
     use util::test_resource;
 
     use super::*;
 
     #[test]
     fn run_hello_world_project() {
-        let mut runtime = Runtime::new();
-        runtime.include_files(&test_resource("sample_project")).unwrap();
-        runtime.import_module("Main").unwrap();
+        for mut runtime in Test::runtimes() {
+            runtime.include_files(&test_resource("sample_project")).unwrap();
+            runtime.import_module("Main").unwrap();
 
-        let value = runtime.eval_expr("Main.sayHello")
-            .expect("Expected correct execution, but failed");
+            let value = runtime.eval_expr("Main.sayHello")
+                .expect("Expected correct execution, but failed");
 
-        assert_eq!(Value::String("hello world".to_string()), value);
+            assert_eq!(Value::String("hello world".to_string()), value);
+        }
     }
 
     #[test]
     fn test_eval_expr() {
-        let mut i = Runtime::new();
-        i.eval_expr("1 + 2 / 3").expect("Expect expression to execute correctly");
+        for mut i in Test::runtimes() {
+            i.eval_expr("1 + 2 / 3").expect("Expect expression to execute correctly");
+        }
     }
 
     #[test]
     fn test_eval_stm() {
-        let mut i = Runtime::new();
-        i.eval_statement("x = 2").expect("Expect x to be defined as 2");
-        i.eval_expr("1 + x / 3").expect("Expect expression to execute correctly");
+        for mut i in Test::runtimes() {
+            i.eval_statement("x = 2").expect("Expect x to be defined as 2");
+            i.eval_expr("1 + x / 3").expect("Expect expression to execute correctly");
+        }
     }
 
     #[test]
@@ -375,28 +481,207 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn test_register_fn() {
         use rust_interop::function_register::RegisterFn;
 
+        fn sum(x: Int, y: Int) -> Int { x + y }
+
+        for mut i in Test::runtimes() {
+            i.register_fn("sum", sum).expect("Expect sum to be defined");
+
+            i.eval_expr("sum 1 3").expect("Expect expression to execute correctly");
+        }
+    }
+
+    #[test]
+    fn test_let_mutual_recursion() {
+        for mut i in Test::runtimes() {
+            let code = r#"
+parity n =
+    let
+        even : Int -> String
+        even x = case x of
+            0 -> "even"
+            _ -> odd (x - 1)
+
+        odd : Int -> String
+        odd x = case x of
+            0 -> "odd"
+            _ -> even (x - 1)
+    in
+    even n
+"#;
+            i.eval_statement(code.trim()).expect("Expect parity to be defined");
+
+            let result = i.eval_expr("parity 7").expect("Expect expression to execute correctly");
+            assert_eq!(Value::String("odd".to_string()), result);
+        }
+    }
+
+    #[test]
+    fn test_array() {
+        for mut i in Test::runtimes() {
+            i.import_module("Array").unwrap();
+            i.eval_statement("squares = Array.initialize 5 (\\n -> n * n)").unwrap();
+
+            assert_eq!(i.eval_expr("Array.get 3 squares").unwrap().to_string(), "Just 9");
+            assert_eq!(i.eval_expr("Array.get 5 squares").unwrap().to_string(), "Nothing");
+            assert_eq!(i.eval_expr("Array.set 0 7 squares |> Array.push 25 |> Array.toList").unwrap().to_string(), "[7, 1, 4, 9, 16, 25]");
+            assert_eq!(i.eval_expr("Array.toList (Array.slice 1 (negate 1) squares)").unwrap().to_string(), "[1, 4, 9]");
+            assert_eq!(i.eval_expr("Array.toIndexedList (Array.fromList [\"a\", \"b\"])").unwrap().to_string(), "[(0, \"a\"), (1, \"b\")]");
+            assert_eq!(i.eval_expr("Array.foldl (+) 0 squares").unwrap(), Value::Int(30));
+            assert_eq!(i.eval_expr("Array.length (Array.append squares Array.empty)").unwrap(), Value::Int(5));
+        }
+    }
+
+    #[test]
+    fn test_json_decode() {
+        for mut i in Test::runtimes() {
+            i.import_module_as("Json.Decode", "D").unwrap();
+            i.import_module_as("Json.Encode", "E").unwrap();
+            i.eval_statement("point = D.map2 (\\x y -> (x, y)) (D.field \"x\" D.int) (D.at [\"pos\", \"y\"] D.float)").unwrap();
+            i.eval_statement("errorMessage result = case result of\n Err error -> D.errorToString error\n Ok _ -> \"\"").unwrap();
+
+            assert_eq!(i.eval_expr("D.decodeString point \"{ \\\"x\\\": 1, \\\"pos\\\": { \\\"y\\\": 2.5 } }\"").unwrap().to_string(), "Ok (1, 2.5)");
+            assert_eq!(i.eval_expr("D.decodeString (D.list (D.oneOf [D.int, D.succeed 0])) \"[1, true, 3]\"").unwrap().to_string(), "Ok [1, 0, 3]");
+            assert_eq!(
+                i.eval_expr("D.decodeString (D.field \"a\" (D.list D.string)) \"{ \\\"a\\\": [\\\"b\\\", 1] }\" |> errorMessage").unwrap(),
+                Value::String("Problem with the value at json.a[1]:\n\n    1\n\nExpecting a STRING".to_string())
+            );
+            assert_eq!(
+                i.eval_expr("D.decodeValue D.int (E.bool True) |> errorMessage").unwrap(),
+                Value::String("Problem with the given value:\n\ntrue\n\nExpecting an INT".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_json_encode() {
+        for mut i in Test::runtimes() {
+            i.import_module_as("Json.Encode", "E").unwrap();
+
+            let code = "E.encode 0 (E.object [(\"b\", E.list E.int [1, 2]), (\"a\", E.float 1.5), (\"c\", E.null)])";
+            assert_eq!(i.eval_expr(code).unwrap(), Value::String("{\"b\":[1,2],\"a\":1.5,\"c\":null}".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_string_split_join() {
+        for mut i in Test::runtimes() {
+
+            assert_eq!(i.eval_expr("String.split \",\" \"a,b,c\"").unwrap().to_string(), "[\"a\", \"b\", \"c\"]");
+            assert_eq!(i.eval_expr("String.join \", \" [\"a\", \"b\", \"c\"]").unwrap(), Value::String("a, b, c".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_comparisons() {
         let mut i = Runtime::new();
 
-        fn sum(x: Int, y: Int) -> Int { x + y }
+        assert_eq!(i.eval_expr("compare 1 2").unwrap().to_string(), "LT");
+        assert_eq!(i.eval_expr("compare \"b\" \"a\"").unwrap().to_string(), "GT");
+        assert_eq!(i.eval_expr("(1 < 2, 2 > 1, 2 <= 1, 1 >= 2)").unwrap().to_string(), "(True, True, False, False)");
+    }
+
+    #[test]
+    fn test_module_functions_defined_later() {
+        let mut i = Runtime::new();
+        i.import_module("Dict").unwrap();
+
+        // Dict.insert calls insertHelp, defined after it in the module
+        assert_eq!(i.eval_expr("Dict.toList (Dict.fromList [(2, \"b\"), (1, \"a\")])").unwrap().to_string(), "[(1, \"a\"), (2, \"b\")]");
+    }
+
+    #[test]
+    fn test_let_closure() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("adder n = let add x = x + n in add").expect("Expect adder to be defined");
+            i.eval_statement("sumPair x = let (a, b) = (x, x * 2) in a + b").expect("Expect sumPair to be defined");
 
-        i.register_fn("sum", sum).expect("Expect sum to be defined");
+            assert_eq!(Value::Number(7), i.eval_expr("(adder 2) 5").unwrap());
+            assert_eq!(Value::Number(9), i.eval_expr("sumPair 3").unwrap());
+        }
+    }
 
-        i.eval_expr("sum 1 3").expect("Expect expression to execute correctly");
+    #[test]
+    fn test_let_dependency_order() {
+        for mut i in Test::runtimes() {
+            let code = "let\n n = 1\n addN x = x + n\n m = addN 1\n addM x = x + m\n in\n addM 1";
+
+            assert_eq!(Value::Number(3), i.eval_expr(code).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tail_call_if() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("countdown n acc = if n == 0 then acc else countdown (n - 1) (acc + 2)").unwrap();
+
+            assert_eq!(Value::Number(20000), i.eval_expr("countdown 10000 0").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tail_call_case() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("build n acc = if n == 0 then acc else build (n - 1) (n :: acc)").unwrap();
+            i.eval_statement("len list acc = case list of\n [] -> acc\n _ :: rest -> len rest (acc + 1)").unwrap();
+
+            assert_eq!(Value::Number(3000), i.eval_expr("len (build 3000 []) 0").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tail_call_let() {
+        for mut i in Test::runtimes() {
+            i.eval_statement("total n = let go x acc = if x == 0 then acc else go (x - 1) (acc + 1) in go n 0").unwrap();
+
+            assert_eq!(Value::Number(10000), i.eval_expr("total 10000").unwrap());
+        }
     }
 
     #[test]
     fn test_closure() {
-        let mut i = Runtime::new();
-        i.eval_statement("genClosure x = \\y -> x + y").expect("1\n");
-        eprintln!("genClosure : {}", i.eval_expr("genClosure").unwrap().get_type());
-        i.eval_statement("addFive = genClosure 5").expect("2\n");
-        eprintln!("addFive : {}", i.eval_expr("addFive").unwrap().get_type());
-        i.eval_statement("result = addFive 3").expect("3\n");
-        let result = i.eval_expr("result").expect("Expect expression to execute correctly");
-        assert_eq!(Value::Number(8), result);
-    }
-}
\ No newline at end of file
+        for mut i in Test::runtimes() {
+            i.eval_statement("genClosure x = \\y -> x + y").expect("1\n");
+            eprintln!("genClosure : {}", i.eval_expr("genClosure").unwrap().get_type());
+            i.eval_statement("addFive = genClosure 5").expect("2\n");
+            eprintln!("addFive : {}", i.eval_expr("addFive").unwrap().get_type());
+            i.eval_statement("result = addFive 3").expect("3\n");
+            let result = i.eval_expr("result").expect("Expect expression to execute correctly");
+            assert_eq!(Value::Number(8), result);
+        }
+    }
+
+    #[test]
+    fn test_module_forward_reference() {
+        for mut i in Test::runtimes() {
+            // `Maybe(..)` in the default imports exposes the constructors, but not `Maybe.andThen`
+            let module = "module Chain exposing (..)\n\n\
+                          run x = andThen (\\a -> Just (a + 1)) x\n\n\
+                          andThen : (a -> Maybe b) -> Maybe a -> Maybe b\n\
+                          andThen f m = case m of\n  Just v -> f v\n  Nothing -> Nothing\n";
+
+            i.eval_module(module, "Chain").unwrap();
+            assert_eq!(i.eval_expr("Chain.run (Just 1)").unwrap(), i.eval_expr("Just 2").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_type_alias_usage() {
+        for mut i in Test::runtimes() {
+            let module = "module Shapes exposing (..)\n\n\
+                          type Shape = Dot Point | Empty\n\n\
+                          type alias Point = { x : Int }\n\n\
+                          getX : Point -> Int\n\
+                          getX p = p.x\n";
+
+            i.eval_module(module, "Shapes").unwrap();
+            assert_eq!(i.eval_expr("Shapes.getX { x = 2 }").unwrap(), Value::Int(2));
+
+            i.eval_statement("type alias Size = { width : Int }").unwrap();
+            i.eval_statement("type Box = Box Size").unwrap();
+            assert_eq!(i.eval_expr("case Box { width = 3 } of\n  Box size -> size.width").unwrap(), Value::Int(3));
+        }
+    }
+}
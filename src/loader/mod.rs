@@ -77,31 +77,54 @@ pub enum Declaration {
 impl ModuleLoader {
     pub fn include_folder(run: &mut Runtime, path: &str) -> Result<(), ElmError> {
         let mut sources = vec![];
-        let mut graph: HashMap<String, Vec<String>> = HashMap::new();
-        let mut data: HashMap<String, (SourceFile, Module)> = HashMap::new();
+        let mut modules = vec![];
 
         get_all_source_files(&mut sources, "", path)?;
 
         for src in sources {
             let ast = load_source_file(&src)?;
-            graph.insert(src.name.to_string(), get_module_dependencies(&ast));
-            data.insert(src.name.to_string(), (src, ast));
+            modules.push((src, ast));
         }
 
-        let keys = graph.keys().cloned().collect::<HashSet<_>>();
-
-        for (_, value) in graph.iter_mut() {
-            *value = value.iter().filter(|dep| keys.contains(*dep)).cloned().collect();
+        for (source, ast) in sort_modules(modules)? {
+            Self::include_module(run, source, ast)?;
         }
+        Ok(())
+    }
+
+    /// Finds all the source files in a folder and its sub-folders, the module names come from
+    /// the paths of the files
+    pub fn find_source_files(path: &str) -> Result<Vec<SourceFile>, ElmError> {
+        let mut sources = vec![];
+        get_all_source_files(&mut sources, "", path)?;
+        Ok(sources)
+    }
 
-        let sorted = sort_dependencies(graph)
-            .map_err(|e| LoaderError::CyclicDependency { cycle: e }.wrap())?;
+    /// Includes the source files that are valid in the order of their dependencies, instead of
+    /// stopping at the first error like `include_folder`, returns the errors of the rest
+    pub fn include_sources(run: &mut Runtime, sources: Vec<SourceFile>) -> Vec<ElmError> {
+        let mut errors = vec![];
+        let mut modules = vec![];
 
-        for name in sorted {
-            let (source, ast) = data.remove(&name).unwrap();
-            Self::include_module(run, source, ast)?;
+        for src in sources {
+            match load_source_file(&src) {
+                Ok(ast) => modules.push((src, ast)),
+                Err(error) => errors.push(error),
+            }
         }
-        Ok(())
+
+        match sort_modules(modules) {
+            Ok(sorted) => {
+                for (source, ast) in sorted {
+                    if let Err(error) = Self::include_module(run, source, ast) {
+                        errors.push(error);
+                    }
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+
+        errors
     }
 
     pub fn include_file(run: &mut Runtime, inner_path: &str, path: &str) -> Result<(), ElmError> {
@@ -171,6 +194,28 @@ fn get_module_dependencies(module: &Module) -> Vec<String> {
     module.imports.iter().map(|import| import.path.join(".")).collect::<Vec<String>>()
 }
 
+/// Sorts the modules so every module comes after the modules it imports
+fn sort_modules(modules: Vec<(SourceFile, Module)>) -> Result<Vec<(SourceFile, Module)>, ElmError> {
+    let mut graph: HashMap<String, Vec<String>> = HashMap::new();
+    let mut data: HashMap<String, (SourceFile, Module)> = HashMap::new();
+
+    for (src, ast) in modules {
+        graph.insert(src.name.to_string(), get_module_dependencies(&ast));
+        data.insert(src.name.to_string(), (src, ast));
+    }
+
+    let keys = graph.keys().cloned().collect::<HashSet<_>>();
+
+    for (_, value) in graph.iter_mut() {
+        *value = value.iter().filter(|dep| keys.contains(*dep)).cloned().collect();
+    }
+
+    let sorted = sort_dependencies(graph)
+        .map_err(|e| LoaderError::CyclicDependency { cycle: e }.wrap())?;
+
+    Ok(sorted.into_iter().map(|name| data.remove(&name).unwrap()).collect())
+}
+
 fn load_source_file(file: &SourceFile) -> Result<Module, ElmError> {
     Parser::new(Tokenizer::new(&file.source)).parse_module()
 }
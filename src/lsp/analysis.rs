@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+
+use ast::AdtExposing;
+use ast::Exposing;
+use ast::Expr;
+use ast::LetDeclaration;
+use ast::Module;
+use ast::ModuleExposing;
+use ast::Pattern;
+use ast::Span;
+use ast::Statement;
+use ast::Type;
+use cst::CstItemKind;
+use cst::SyntaxTree;
+use errors::ElmError;
+use loader::AnalyzedModule;
+use loader::Declaration;
+use loader::declaration_name;
+use loader::declaration_type;
+use loader::ModuleLoader;
+use loader::SourceFile;
+use parsers::Parser;
+use Runtime;
+use source::Location;
+use source::SourceCode;
+use tokenizer::Token;
+use tokenizer::Tokenizer;
+use typed_ast::LetEntry;
+use typed_ast::TypedDefinition;
+use typed_ast::TypedExpr;
+use typed_ast::TypedPattern;
+
+// Symbol kinds of the protocol
+pub const SYMBOL_ENUM: u32 = 10;
+pub const SYMBOL_FUNCTION: u32 = 12;
+pub const SYMBOL_CONSTANT: u32 = 14;
+pub const SYMBOL_ENUM_MEMBER: u32 = 22;
+pub const SYMBOL_STRUCT: u32 = 23;
+pub const SYMBOL_OPERATOR: u32 = 25;
+
+/// An open document checked together with the rest of the project
+pub struct Analysis {
+    pub name: String,
+    pub source: SourceCode,
+    pub tree: SyntaxTree,
+    /// Valid parts of the module, the declarations with syntax errors are missing
+    pub module: Module,
+    /// Runtime with the core modules and all the project modules that could be loaded
+    pub runtime: Runtime,
+    /// Types of the declarations, only available if the whole module is correct
+    pub analyzed: Option<AnalyzedModule>,
+    /// Errors that affect this module, some of them can come from its dependencies
+    pub errors: Vec<ElmError>,
+}
+
+/// A top level declaration of a module
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: u32,
+    /// Full declaration, including the type annotation
+    pub span: Span,
+    /// Name of the declaration
+    pub selection: Span,
+    /// Variants of a custom type
+    pub children: Vec<Symbol>,
+}
+
+/// A place in the code of a module
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub path: String,
+    pub source: SourceCode,
+    pub span: Span,
+}
+
+/// Parses, loads and analyzes a document, `core` provides the core modules and `sources` the
+/// rest of the modules of the project
+pub fn analyze(core: &Runtime, sources: Vec<SourceFile>, path: &str, text: &str) -> Analysis {
+    let source = SourceCode::from_string(text.to_string(), path);
+    let (tree, _) = SyntaxTree::parse(&source);
+    let (module, mut errors) = Parser::new(Tokenizer::new(&source)).parse_module_partial();
+
+    let name = match &module.header {
+        Some(header) => header.name.clone(),
+        None => sources.iter()
+            .find(|src| src.path == path)
+            .map(|src| src.name.clone())
+            .unwrap_or_else(|| "Main".to_string()),
+    };
+
+    let mut runtime = Runtime {
+        loaded_modules: core.loaded_modules.clone(),
+        analyzed_modules: core.analyzed_modules.clone(),
+        ..Runtime::empty_runtime()
+    };
+
+    // The errors of other files are reported when they are open
+    let sources = sources.into_iter()
+        .filter(|src| src.path != path && src.name != name && !runtime.loaded_modules.contains_key(&src.name))
+        .collect();
+
+    ModuleLoader::include_sources(&mut runtime, sources);
+
+    let file = SourceFile { name: name.clone(), path: path.to_string(), source: source.clone() };
+    let mut analyzed = None;
+
+    match ModuleLoader::include_module(&mut runtime, file, module.clone()) {
+        Ok(()) => match runtime.load_analyzed_module(&name) {
+            Ok(()) => analyzed = runtime.analyzed_modules.get(&name).cloned(),
+            Err(error) => errors.push(error),
+        },
+        Err(error) => errors.push(error),
+    }
+
+    Analysis { name, source, tree, module, runtime, analyzed, errors }
+}
+
+/// Finds the top level declarations, using the syntax tree, so declarations with errors are
+/// also included
+pub fn symbols(tree: &SyntaxTree) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = vec![];
+    let mut annotation: Option<(String, Location)> = None;
+
+    for item in tree.items.iter().filter(|item| item.kind == CstItemKind::Declaration) {
+        let tokens = &tree.tokens[item.tokens.clone()];
+        let span = (tokens[0].span.0, tokens[tokens.len() - 1].span.1);
+        let next = tokens.get(1).map(|it| &it.token);
+
+        let symbol = match (&tokens[0].token, next) {
+            (Token::TypeTk, Some(Token::Alias)) => match tokens.get(2) {
+                Some(token) if is_upper_id(&token.token) => {
+                    Some(Symbol { name: token.text.clone(), kind: SYMBOL_STRUCT, span, selection: token.span, children: vec![] })
+                }
+                _ => None,
+            },
+            (Token::TypeTk, Some(Token::UpperId(name))) => {
+                let mut children: Vec<Symbol> = vec![];
+
+                for (index, token) in tokens.iter().enumerate() {
+                    if token.token == Token::Equals || token.token == Token::Pipe {
+                        if let Some(last) = children.last_mut() {
+                            last.span.1 = tokens[index - 1].span.1;
+                        }
+
+                        if let Some(variant) = tokens.get(index + 1).filter(|it| is_upper_id(&it.token)) {
+                            children.push(Symbol {
+                                name: variant.text.clone(),
+                                kind: SYMBOL_ENUM_MEMBER,
+                                span: (variant.span.0, span.1),
+                                selection: variant.span,
+                                children: vec![],
+                            });
+                        }
+                    }
+                }
+
+                Some(Symbol { name: name.clone(), kind: SYMBOL_ENUM, span, selection: tokens[1].span, children })
+            }
+            (Token::Port, Some(Token::Id(name))) => {
+                Some(Symbol { name: name.clone(), kind: SYMBOL_FUNCTION, span, selection: tokens[1].span, children: vec![] })
+            }
+            (Token::InfixTk, _) => {
+                tokens.iter().find(|it| if let Token::BinaryOperator(_) = it.token { true } else { false })
+                    .map(|op| Symbol { name: op.text.clone(), kind: SYMBOL_OPERATOR, span, selection: op.span, children: vec![] })
+            }
+            (Token::Id(name), Some(Token::Colon)) => {
+                annotation = Some((name.clone(), span.0));
+                continue;
+            }
+            (Token::Id(name), _) => {
+                let is_function = next != Some(&Token::Equals) || annotation.as_ref()
+                    .filter(|(annotated, _)| annotated == name)
+                    .map(|_| tree.tokens[..item.tokens.start].iter().rev()
+                        .take_while(|it| it.token != Token::Colon)
+                        .any(|it| it.token == Token::RightArrow))
+                    .unwrap_or(false);
+
+                let start = match &annotation {
+                    Some((annotated, start)) if annotated == name => *start,
+                    _ => span.0,
+                };
+
+                Some(Symbol {
+                    name: name.clone(),
+                    kind: if is_function { SYMBOL_FUNCTION } else { SYMBOL_CONSTANT },
+                    span: (start, span.1),
+                    selection: tokens[0].span,
+                    children: vec![],
+                })
+            }
+            _ => None,
+        };
+
+        annotation = None;
+        symbols.extend(symbol);
+    }
+
+    symbols
+}
+
+/// Finds where the name under the cursor is defined, local variables first, then the
+/// declarations of this module and the modules it imports
+pub fn find_definition(analysis: &Analysis, offset: Location) -> Option<Target> {
+    let (qualifier, name, _) = reference_at(&analysis.tree, offset)?;
+
+    if qualifier.is_empty() {
+        let local = LocalScope { tree: &analysis.tree, offset, scope: vec![] }.find(&analysis.module, &name);
+
+        if let Some(span) = local {
+            return Some(Target { path: analysis.source.path().to_string(), source: analysis.source.clone(), span });
+        }
+    }
+
+    for module in candidate_modules(analysis, &qualifier, &name) {
+        if module == analysis.name {
+            if let Some(span) = find_symbol(&symbols(&analysis.tree), &name) {
+                return Some(Target { path: analysis.source.path().to_string(), source: analysis.source.clone(), span });
+            }
+            continue;
+        }
+
+        // Packed modules don't keep their source code
+        let loaded = match analysis.runtime.loaded_modules.get(&module) {
+            Some(loaded) if loaded.src.source.len() > 0 => loaded,
+            _ => continue,
+        };
+
+        let (tree, _) = SyntaxTree::parse(&loaded.src.source);
+
+        if let Some(span) = find_symbol(&symbols(&tree), &name) {
+            return Some(Target { path: loaded.src.path.clone(), source: loaded.src.source.clone(), span });
+        }
+    }
+
+    None
+}
+
+/// Returns the name and type of the value under the cursor, with the span of the name
+pub fn find_type(analysis: &Analysis, offset: Location) -> Option<(String, Type, Span)> {
+    let analyzed = analysis.analyzed.as_ref()?;
+    let (qualifier, name, span) = reference_at(&analysis.tree, offset)?;
+    let mut search = TypeSearch { offset, name: &name, span, found: None };
+
+    for decl in &analyzed.all_declarations {
+        if let Declaration::Definition(_, def) = decl {
+            search.definition(def, None);
+        }
+    }
+
+    if let Some((name, ty, span)) = search.found {
+        return Some((name, ty, span));
+    }
+
+    // Names of the top level declarations
+    let is_declaration = symbols(&analysis.tree).iter()
+        .any(|symbol| symbol.name == name && (symbol.selection == span || symbol.span.0 == span.0));
+
+    if qualifier.is_empty() && is_declaration {
+        let ty = analyzed.all_declarations.iter()
+            .find(|decl| declaration_name(decl) == name)
+            .and_then(|decl| declaration_type(decl))?;
+
+        return Some((name, ty.clone(), span));
+    }
+
+    None
+}
+
+fn find_symbol(symbols: &[Symbol], name: &str) -> Option<Span> {
+    for symbol in symbols {
+        if symbol.name == name {
+            return Some(symbol.selection);
+        }
+        if let Some(span) = find_symbol(&symbol.children, name) {
+            return Some(span);
+        }
+    }
+    None
+}
+
+/// Modules that can contain the definition of a name, in order of priority
+fn candidate_modules(analysis: &Analysis, qualifier: &str, name: &str) -> Vec<String> {
+    let imports = &analysis.module.imports;
+
+    if !qualifier.is_empty() {
+        let mut modules = imports.iter()
+            .filter(|import| import.alias.as_ref().map(|alias| alias == qualifier).unwrap_or(false) || import.path.join(".") == qualifier)
+            .map(|import| import.path.join("."))
+            .collect::<Vec<_>>();
+
+        if qualifier == analysis.name {
+            modules.push(analysis.name.clone());
+        }
+        return modules;
+    }
+
+    let mut modules = vec![analysis.name.clone()];
+
+    for import in imports {
+        let exposed = match &import.exposing {
+            Some(ModuleExposing::All) => true,
+            Some(ModuleExposing::Just(list)) => list.iter().any(|exposing| match exposing {
+                // The variants are unknown until the module is checked
+                Exposing::Adt(_, AdtExposing::All) => true,
+                Exposing::Adt(adt, AdtExposing::Variants(variants)) => adt == name || variants.iter().any(|it| it == name),
+                Exposing::Type(ty) => ty == name,
+                Exposing::Definition(def) => def == name,
+                Exposing::BinaryOperator(op) => op == name,
+            }),
+            None => false,
+        };
+
+        if exposed {
+            modules.push(import.path.join("."));
+        }
+    }
+
+    modules
+}
+
+/// Finds the reference under the cursor, like `List.map`, returns the qualifier, the name and
+/// the span of the name
+fn reference_at(tree: &SyntaxTree, offset: Location) -> Option<(String, String, Span)> {
+    let tokens = &tree.tokens;
+    let index = tokens.iter()
+        .position(|it| is_name(&it.token) && it.span.0 <= offset && offset <= it.span.1)?;
+
+    let joined = |a: usize, b: usize| tokens[a].span.1 == tokens[b].span.0;
+
+    // Move from the qualifier to the name, `Li|st.map` refers to `map`
+    let mut last = index;
+    while is_upper_id(&tokens[last].token) && last + 2 < tokens.len()
+        && tokens[last + 1].token == Token::Dot && joined(last, last + 1) && joined(last + 1, last + 2)
+        && is_name(&tokens[last + 2].token) {
+        last += 2;
+    }
+
+    let mut first = last;
+    while first >= 2 && tokens[first - 1].token == Token::Dot && joined(first - 1, first) && joined(first - 2, first - 1) {
+        if !is_upper_id(&tokens[first - 2].token) {
+            // Record fields are not definitions
+            return None;
+        }
+        first -= 2;
+    }
+
+    let qualifier = (first..last).step_by(2).map(|it| tokens[it].text.as_str()).collect::<Vec<_>>().join(".");
+
+    Some((qualifier, tokens[last].text.clone(), tokens[last].span))
+}
+
+fn is_name(token: &Token) -> bool {
+    match token {
+        Token::Id(_) | Token::UpperId(_) => true,
+        _ => false,
+    }
+}
+
+fn is_upper_id(token: &Token) -> bool {
+    if let Token::UpperId(_) = token { true } else { false }
+}
+
+fn contains(span: Span, offset: Location) -> bool {
+    span.0 <= offset && offset <= span.1
+}
+
+/// Collects the variables visible at an offset while moving down the expression tree
+struct LocalScope<'a> {
+    tree: &'a SyntaxTree,
+    offset: Location,
+    scope: Vec<(String, Span)>,
+}
+
+impl<'a> LocalScope<'a> {
+    fn find(mut self, module: &Module, name: &str) -> Option<Span> {
+        for stm in &module.statements {
+            if let Statement::Def(def) = stm {
+                self.definition(&def.patterns, &def.expr);
+            }
+        }
+
+        self.scope.iter().rev()
+            .find(|(var, _)| var == name)
+            .map(|(_, span)| *span)
+    }
+
+    fn definition(&mut self, patterns: &[Pattern], expr: &Expr) {
+        let start = patterns.first().map(|it| it.get_span().0).unwrap_or(expr.get_span().0);
+
+        if contains((start, expr.get_span().1), self.offset) {
+            for pattern in patterns {
+                self.pattern(pattern);
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Var(span, name) => self.scope.push((name.clone(), *span)),
+            Pattern::Alias(span, inner, name) => {
+                self.pattern(inner);
+                self.scope.push((name.clone(), *span));
+            }
+            Pattern::Record(span, fields) => {
+                for field in fields {
+                    self.scope.push((field.clone(), *span));
+                }
+            }
+            Pattern::Adt(_, _, items) | Pattern::Tuple(_, items) | Pattern::List(_, items) => {
+                for item in items {
+                    self.pattern(item);
+                }
+            }
+            Pattern::BinaryOp(_, _, a, b) => {
+                self.pattern(a);
+                self.pattern(b);
+            }
+            Pattern::Wildcard(_) | Pattern::Unit(_) | Pattern::LitInt(..) | Pattern::LitString(..) | Pattern::LitChar(..) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        if !contains(expr.get_span(), self.offset) {
+            return;
+        }
+
+        match expr {
+            Expr::Tuple(_, items) | Expr::List(_, items) | Expr::OpChain(_, items, _) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Record(_, entries) | Expr::RecordUpdate(_, _, entries) => {
+                for (_, value) in entries {
+                    self.expr(value);
+                }
+            }
+            Expr::RecordField(_, record, _) => self.expr(record),
+            Expr::If(_, a, b, c) => {
+                self.expr(a);
+                self.expr(b);
+                self.expr(c);
+            }
+            Expr::Case(_, value, branches) => {
+                self.expr(value);
+
+                for (pattern, branch) in branches {
+                    if contains((pattern.get_span().0, branch.get_span().1), self.offset) {
+                        self.pattern(pattern);
+                        self.expr(branch);
+                    }
+                }
+            }
+            Expr::Lambda(_, patterns, body) => {
+                for pattern in patterns {
+                    self.pattern(pattern);
+                }
+                self.expr(body);
+            }
+            Expr::Application(_, a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Expr::Let(span, decls, body) => {
+                for decl in decls {
+                    match decl {
+                        LetDeclaration::Def(def) => {
+                            let start = def.patterns.first().map(|it| it.get_span().0).unwrap_or(def.expr.get_span().0);
+                            let name = self.tree.tokens.iter().rev()
+                                .filter(|it| span.0 <= it.span.0 && it.span.1 <= start)
+                                .find(|it| it.token == Token::Id(def.name.clone()));
+
+                            if let Some(token) = name {
+                                self.scope.push((def.name.clone(), token.span));
+                            }
+                        }
+                        LetDeclaration::Pattern(pattern, _) => self.pattern(pattern),
+                    }
+                }
+
+                for decl in decls {
+                    match decl {
+                        LetDeclaration::Def(def) => self.definition(&def.patterns, &def.expr),
+                        LetDeclaration::Pattern(_, value) => self.expr(value),
+                    }
+                }
+                self.expr(body);
+            }
+            Expr::Unit(_) | Expr::QualifiedRef(..) | Expr::RecordAccess(..) | Expr::Literal(..) | Expr::Ref(..) => {}
+        }
+    }
+}
+
+/// Looks for the innermost reference or variable at an offset in the typed expressions
+struct TypeSearch<'a> {
+    offset: Location,
+    /// Name and span of the token under the cursor
+    name: &'a str,
+    span: Span,
+    found: Option<(String, Type, Span)>,
+}
+
+impl<'a> TypeSearch<'a> {
+    fn add(&mut self, name: &str, ty: &Type, span: Span) {
+        // The spans of the operators cover both operands
+        if !contains(span, self.offset) || name.rsplit('.').next() != Some(self.name) {
+            return;
+        }
+
+        let smaller = match &self.found {
+            Some((_, _, found)) => span.1 - span.0 < found.1 - found.0,
+            None => true,
+        };
+
+        if smaller {
+            self.found = Some((name.to_string(), ty.clone(), span));
+        }
+    }
+
+    fn definition(&mut self, def: &TypedDefinition, let_span: Option<Span>) {
+        // The name of a local function, the top level names are found with the symbols
+        if let Some(let_span) = let_span {
+            if let_span.0 <= self.span.0 && self.span.1 <= def.expr.get_span().0 {
+                let span = self.span;
+                self.add(&def.name, &def.header, span);
+            }
+        }
+
+        for pattern in &def.patterns {
+            self.pattern(pattern);
+        }
+        self.expr(&def.expr);
+    }
+
+    fn pattern(&mut self, pattern: &TypedPattern) {
+        match pattern {
+            TypedPattern::Var(span, ty, name) => self.add(name, ty, *span),
+            TypedPattern::Alias(span, ty, inner, name) => {
+                self.pattern(inner);
+                self.add(name, ty, *span);
+            }
+            TypedPattern::Adt(_, _, _, _, items) | TypedPattern::Tuple(_, _, items) | TypedPattern::List(_, _, items) => {
+                for item in items {
+                    self.pattern(item);
+                }
+            }
+            TypedPattern::BinaryOp(_, _, _, a, b) => {
+                self.pattern(a);
+                self.pattern(b);
+            }
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &TypedExpr) {
+        match expr {
+            TypedExpr::Ref(span, ty, name) | TypedExpr::Resolved(span, ty, name, _) => self.add(name, ty, *span),
+            TypedExpr::Tuple(_, _, items) | TypedExpr::List(_, _, items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            TypedExpr::Record(_, _, entries) => {
+                for (_, value) in entries {
+                    self.expr(value);
+                }
+            }
+            TypedExpr::RecordUpdate(_, _, record, entries) => {
+                self.expr(record);
+                for (_, value) in entries {
+                    self.expr(value);
+                }
+            }
+            TypedExpr::RecordField(_, _, record, _) => self.expr(record),
+            TypedExpr::If(_, _, a, b, c) => {
+                self.expr(a);
+                self.expr(b);
+                self.expr(c);
+            }
+            TypedExpr::Case(_, _, value, branches) => {
+                self.expr(value);
+                for (pattern, branch) in branches {
+                    self.pattern(pattern);
+                    self.expr(branch);
+                }
+            }
+            TypedExpr::Lambda(_, _, patterns, body) => {
+                for pattern in patterns {
+                    self.pattern(pattern);
+                }
+                self.expr(body);
+            }
+            TypedExpr::Application(_, _, a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            TypedExpr::Let(span, _, entries, body) => {
+                for entry in entries {
+                    match entry {
+                        LetEntry::Definition(def) => self.definition(def, Some(*span)),
+                        LetEntry::Pattern(pattern, value) => {
+                            self.pattern(pattern);
+                            self.expr(value);
+                        }
+                        LetEntry::Functions(..) => {}
+                    }
+                }
+                self.expr(body);
+            }
+            TypedExpr::Const(..) | TypedExpr::RecordAccess(..) | TypedExpr::Closure(..) => {}
+        }
+    }
+}
+
+/// Creates the source files of the project, the open documents replace the files on disk
+pub fn project_sources(folders: &[String], documents: &HashMap<String, String>) -> Vec<SourceFile> {
+    let mut sources = vec![];
+
+    for folder in folders {
+        if let Ok(files) = ModuleLoader::find_source_files(folder) {
+            sources.extend(files);
+        }
+    }
+
+    for src in sources.iter_mut() {
+        if let Some(text) = documents.get(&src.path) {
+            src.source = SourceCode::from_string(text.clone(), &src.path);
+        }
+    }
+
+    sources
+}
@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::json;
+use serde_json::Value as JsonValue;
+
+use errors::report::diagnostics;
+use errors::report::MessagePart;
+use errors::report::Severity;
+use lsp::analysis::Analysis;
+use lsp::analysis::Symbol;
+use lsp::protocol::*;
+use Runtime;
+use source::SourceCode;
+
+pub mod protocol;
+pub mod analysis;
+
+/// Server of the Language Server Protocol, the `elm-ls` binary runs it over stdio. Open documents
+/// are checked together with the rest of the project every time they change
+pub struct LanguageServer {
+    /// Core modules, analyzed once and copied into every analysis
+    core: Runtime,
+    /// Folders with the source code of the project
+    source_folders: Vec<String>,
+    /// Open documents by URI
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+    exit_code: Option<i32>,
+}
+
+struct Document {
+    path: String,
+    text: String,
+    analysis: Analysis,
+}
+
+/// Reads messages from `input` and writes the responses to `output` until the client sends
+/// `exit`, returns the exit code of the process
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<i32> {
+    let mut server = LanguageServer::new();
+
+    while let Some(message) = read_message(&mut input)? {
+        for response in server.handle(&message) {
+            write_message(&mut output, &response)?;
+        }
+
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+
+    // The client disappeared without asking the server to exit
+    Ok(1)
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        LanguageServer {
+            core: Runtime::analysis_runtime(),
+            source_folders: vec![],
+            documents: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Exit code requested by the client with the `exit` notification
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles the content of a message, returns the responses and notifications to send
+    pub fn handle(&mut self, message: &str) -> Vec<JsonValue> {
+        let message: JsonValue = match serde_json::from_str(message) {
+            Ok(value) => value,
+            Err(err) => return vec![error_response(&JsonValue::Null, PARSE_ERROR, &format!("Invalid JSON: {}", err))],
+        };
+
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // Responses to requests of the server are ignored
+            None if message.get("result").is_some() || message.get("error").is_some() => return vec![],
+            None => return vec![error_response(&id.unwrap_or(JsonValue::Null), INVALID_REQUEST, "Missing method")],
+        };
+
+        let id = match id {
+            Some(id) => id,
+            None => return self.notification(method, params),
+        };
+
+        if method == "initialize" {
+            return vec![response(&id, self.initialize(params))];
+        }
+
+        if !self.initialized {
+            return vec![error_response(&id, SERVER_NOT_INITIALIZED, "The server is not initialized")];
+        }
+
+        let result = match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(JsonValue::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => return vec![error_response(&id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", method))],
+        };
+
+        match result {
+            Ok(result) => vec![response(&id, result)],
+            Err(msg) => vec![error_response(&id, INVALID_PARAMS, &msg)],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        if method == "exit" {
+            self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+            return vec![];
+        }
+
+        if !self.initialized {
+            return vec![];
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update(&uri, text)
+            }
+            "textDocument/didChange" => {
+                // Only full synchronization is supported, so the last change has all the text
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|list| list.last()).and_then(|change| change["text"].as_str()) {
+                    Some(text) => self.update(&uri, text),
+                    None => vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }))]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn initialize(&mut self, params: &JsonValue) -> JsonValue {
+        let root = params["workspaceFolders"][0]["uri"].as_str()
+            .or_else(|| params["rootUri"].as_str())
+            .and_then(uri_to_path)
+            .or_else(|| params["rootPath"].as_str().map(|path| path.to_string()));
+
+        if let Some(root) = root {
+            self.source_folders = source_folders(&root);
+        }
+
+        self.initialized = true;
+
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "documentSymbolProvider": true,
+            },
+            "serverInfo": {
+                "name": "elm-ls",
+                "version": env!("CARGO_PKG_VERSION"),
+            }
+        })
+    }
+
+    /// Checks the new version of a document and publishes its diagnostics
+    fn update(&mut self, uri: &str, text: &str) -> Vec<JsonValue> {
+        let path = uri_to_path(uri).unwrap_or_else(|| uri.to_string());
+
+        let open = self.documents.values()
+            .filter(|doc| doc.path != path)
+            .map(|doc| (doc.path.clone(), doc.text.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let sources = analysis::project_sources(&self.source_folders, &open);
+        let analysis = analysis::analyze(&self.core, sources, &path, text);
+        let diagnostics = publish_diagnostics(uri, &analysis);
+
+        self.documents.insert(uri.to_string(), Document { path, text: text.to_string(), analysis });
+        vec![diagnostics]
+    }
+
+    fn hover(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let (document, offset) = self.document_position(params)?;
+
+        let (name, ty, span) = match analysis::find_type(&document.analysis, offset) {
+            Some(found) => found,
+            None => return Ok(JsonValue::Null),
+        };
+
+        Ok(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```elm\n{} : {}\n```", name, ty),
+            },
+            "range": range(&document.analysis.source, span),
+        }))
+    }
+
+    fn definition(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let (document, offset) = self.document_position(params)?;
+
+        Ok(match analysis::find_definition(&document.analysis, offset) {
+            Some(target) => json!({
+                "uri": path_to_uri(&target.path),
+                "range": range(&target.source, target.span),
+            }),
+            None => JsonValue::Null,
+        })
+    }
+
+    fn document_symbols(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let document = self.document(params)?;
+        let code = &document.analysis.source;
+
+        let symbols = analysis::symbols(&document.analysis.tree).iter()
+            .map(|symbol| symbol_to_json(code, symbol))
+            .collect::<Vec<_>>();
+
+        Ok(JsonValue::Array(symbols))
+    }
+
+    fn document(&self, params: &JsonValue) -> Result<&Document, String> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| "Missing textDocument.uri".to_string())?;
+
+        self.documents.get(uri)
+            .ok_or_else(|| format!("The document '{}' is not open", uri))
+    }
+
+    fn document_position(&self, params: &JsonValue) -> Result<(&Document, u32), String> {
+        let document = self.document(params)?;
+        let offset = offset(&document.analysis.source, &params["position"])
+            .ok_or_else(|| "Missing position".to_string())?;
+
+        Ok((document, offset))
+    }
+}
+
+/// Reads the source folders from the `elm.json` of the project, without it the root is used
+fn source_folders(root: &str) -> Vec<String> {
+    let root = root.trim_end_matches('/');
+    let project = fs::read(Path::new(root).join("elm.json")).ok()
+        .and_then(|bytes| serde_json::from_slice::<JsonValue>(&bytes).ok());
+
+    let project = match project {
+        Some(project) => project,
+        None => return vec![root.to_string()],
+    };
+
+    match project["source-directories"].as_array() {
+        Some(folders) => folders.iter()
+            .filter_map(|folder| folder.as_str())
+            .map(|folder| format!("{}/{}", root, folder.trim_start_matches("./").trim_end_matches('/')))
+            .collect(),
+        // Packages always have the code in `src`
+        None => vec![format!("{}/src", root)],
+    }
+}
+
+fn publish_diagnostics(uri: &str, analysis: &Analysis) -> JsonValue {
+    let path = analysis.source.path();
+    let mut list = vec![];
+
+    for error in &analysis.errors {
+        for diagnostic in diagnostics(error) {
+            // Errors in other files are shown at the start of the document
+            let range = match diagnostic.region {
+                Some(region) if diagnostic.path.as_ref().map(|it| it == path).unwrap_or(false) => json!({
+                    "start": { "line": region.start.line - 1, "character": region.start.column - 1 },
+                    "end": { "line": region.end.line - 1, "character": region.end.column - 1 },
+                }),
+                _ => json!({
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 0, "character": 0 },
+                }),
+            };
+
+            let text = diagnostic.message.iter()
+                .map(|part| match part {
+                    MessagePart::Text(text) => text.as_str(),
+                    MessagePart::Styled { string, .. } => string.as_str(),
+                })
+                .collect::<String>();
+
+            list.push(json!({
+                "range": range,
+                "severity": match diagnostic.severity { Severity::Error => 1, Severity::Warning => 2 },
+                "source": "elm",
+                "code": diagnostic.title,
+                "message": if text.is_empty() { diagnostic.title.clone() } else { text },
+            }));
+        }
+    }
+
+    notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": list }))
+}
+
+fn symbol_to_json(code: &SourceCode, symbol: &Symbol) -> JsonValue {
+    json!({
+        "name": symbol.name,
+        "kind": symbol.kind,
+        "range": range(code, symbol.span),
+        "selectionRange": range(code, symbol.selection),
+        "children": symbol.children.iter().map(|child| symbol_to_json(code, child)).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use util::test_resource;
+
+    use super::*;
+
+    const SHAPES: &str = r#"module Shapes exposing (..)
+
+type Shape
+    = Circle Float
+    | Square Float
+
+area : Shape -> Float
+area shape =
+    case shape of
+        Circle radius ->
+            let
+                squared = radius * radius
+            in
+            pi * squared
+
+        Square side ->
+            (\s -> s * s) side
+"#;
+
+    /// Sends the messages to a new server, returns the messages sent back and the exit code
+    fn session(messages: &[JsonValue]) -> (Vec<JsonValue>, i32) {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = vec![];
+        let code = run(Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut sent = vec![];
+        while let Some(content) = read_message(&mut output).unwrap() {
+            sent.push(serde_json::from_str(&content).unwrap());
+        }
+
+        (sent, code)
+    }
+
+    fn request(id: u32, method: &str, params: JsonValue) -> JsonValue {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn position(id: u32, method: &str, uri: &str, line: u32, character: u32) -> JsonValue {
+        request(id, method, json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } }))
+    }
+
+    fn open(uri: &str, text: &str) -> JsonValue {
+        notification("textDocument/didOpen", json!({
+            "textDocument": { "uri": uri, "languageId": "elm", "version": 1, "text": text }
+        }))
+    }
+
+    fn result(sent: &[JsonValue], id: u32) -> JsonValue {
+        let message = sent.iter().find(|it| it["id"] == id).expect("Missing response");
+        assert_eq!(message.get("error"), None);
+        message["result"].clone()
+    }
+
+    fn published(sent: &[JsonValue]) -> Vec<JsonValue> {
+        sent.iter()
+            .filter(|it| it["method"] == "textDocument/publishDiagnostics")
+            .map(|it| it["params"]["diagnostics"].clone())
+            .collect()
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> JsonValue {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn check_project_session() {
+        let root = test_resource("sample_project");
+        let main = path_to_uri(&format!("{}/Main.elm", root));
+        let text = fs::read_to_string(format!("{}/Main.elm", root)).unwrap();
+
+        let (sent, code) = session(&[
+            request(1, "initialize", json!({ "rootUri": path_to_uri(&root), "capabilities": {} })),
+            notification("initialized", json!({})),
+            open(&main, &text),
+            position(2, "textDocument/hover", &main, 6, 13),
+            position(3, "textDocument/definition", &main, 6, 29),
+            position(4, "textDocument/definition", &main, 6, 11),
+            request(5, "textDocument/documentSymbol", json!({ "textDocument": { "uri": main } })),
+            request(6, "shutdown", JsonValue::Null),
+            notification("exit", JsonValue::Null),
+        ]);
+
+        assert_eq!(code, 0);
+        assert_eq!(result(&sent, 1)["capabilities"]["hoverProvider"], true);
+        assert_eq!(published(&sent), vec![json!([])]);
+
+        assert_eq!(result(&sent, 2), json!({
+            "contents": { "kind": "markdown", "value": "```elm\nhello : String\n```" },
+            "range": range((6, 11), (6, 16)),
+        }));
+
+        assert_eq!(result(&sent, 3), json!({
+            "uri": path_to_uri(&format!("{}/Mod/SubModule2.elm", root)),
+            "range": range((2, 0), (2, 5)),
+        }));
+
+        assert_eq!(result(&sent, 4)["uri"], path_to_uri(&format!("{}/SubModule1.elm", root)));
+
+        assert_eq!(result(&sent, 5), json!([{
+            "name": "sayHello",
+            "kind": analysis::SYMBOL_CONSTANT,
+            "range": range((6, 0), (6, 32)),
+            "selectionRange": range((6, 0), (6, 8)),
+            "children": [],
+        }]));
+
+        assert_eq!(result(&sent, 6), JsonValue::Null);
+    }
+
+    #[test]
+    fn check_diagnostics() {
+        let uri = "file:///project/src/Main.elm";
+
+        let (sent, _) = session(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            open(uri, "module Main exposing (..)\n\nx : Int\nx = \"a\"\n"),
+            notification("textDocument/didChange", json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "module Main exposing (..)\n\nx = if\n\ny = 1\n" }],
+            })),
+            notification("textDocument/didChange", json!({
+                "textDocument": { "uri": uri, "version": 3 },
+                "contentChanges": [{ "text": "module Main exposing (..)\n\nx = 1\n" }],
+            })),
+            notification("textDocument/didClose", json!({ "textDocument": { "uri": uri } })),
+        ]);
+
+        let published = published(&sent);
+        assert_eq!(published.len(), 4);
+
+        assert_eq!(published[0].as_array().unwrap().len(), 1);
+        assert_eq!(published[0][0]["code"], "TYPE ERROR");
+        assert_eq!(published[0][0]["severity"], 1);
+        assert_eq!(published[0][0]["range"]["start"]["line"], 3);
+
+        assert_eq!(published[1].as_array().unwrap().len(), 1);
+        assert_eq!(published[1][0]["code"], "PARSE ERROR");
+        assert_eq!(published[1][0]["range"]["start"]["line"], 2);
+
+        assert_eq!(published[2], json!([]));
+        assert_eq!(published[3], json!([]));
+    }
+
+    #[test]
+    fn check_local_names() {
+        let uri = "file:///project/src/Shapes.elm";
+
+        let (sent, _) = session(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            open(uri, SHAPES),
+            position(2, "textDocument/hover", uri, 9, 17),
+            position(3, "textDocument/hover", uri, 13, 19),
+            position(4, "textDocument/hover", uri, 7, 2),
+            position(5, "textDocument/definition", uri, 13, 19),
+            position(6, "textDocument/definition", uri, 16, 20),
+            position(7, "textDocument/definition", uri, 9, 10),
+            position(8, "textDocument/definition", uri, 13, 12),
+            request(9, "textDocument/documentSymbol", json!({ "textDocument": { "uri": uri } })),
+        ]);
+
+        assert_eq!(published(&sent), vec![json!([])]);
+
+        let hover = |id| result(&sent, id)["contents"]["value"].as_str().unwrap().to_string();
+        assert_eq!(hover(2), "```elm\nradius : Float\n```");
+        assert_eq!(hover(3), "```elm\nsquared : Float\n```");
+        assert_eq!(hover(4), "```elm\narea : Shape -> Float\n```");
+
+        assert_eq!(result(&sent, 5), json!({ "uri": uri, "range": range((11, 16), (11, 23)) }));
+        assert_eq!(result(&sent, 6), json!({ "uri": uri, "range": range((16, 14), (16, 15)) }));
+        assert_eq!(result(&sent, 7), json!({ "uri": uri, "range": range((3, 6), (3, 12)) }));
+
+        // Core modules are packed without their source code
+        assert_eq!(result(&sent, 8), JsonValue::Null);
+
+        let symbols = result(&sent, 9);
+        assert_eq!(symbols[0]["name"], "Shape");
+        assert_eq!(symbols[0]["kind"], analysis::SYMBOL_ENUM);
+        assert_eq!(symbols[0]["children"][1]["name"], "Square");
+        assert_eq!(symbols[0]["children"][1]["range"], range((4, 6), (4, 18)));
+        assert_eq!(symbols[1]["name"], "area");
+        assert_eq!(symbols[1]["kind"], analysis::SYMBOL_FUNCTION);
+        assert_eq!(symbols[1]["range"], range((6, 0), (16, 30)));
+        assert_eq!(symbols[1]["selectionRange"], range((7, 0), (7, 4)));
+    }
+
+    #[test]
+    fn check_protocol_errors() {
+        let (sent, code) = session(&[
+            request(1, "textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///Main.elm" } })),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "initialize", "params": {} }),
+            request(3, "workspace/symbol", json!({ "query": "" })),
+            request(4, "textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///Main.elm" } })),
+            notification("exit", JsonValue::Null),
+        ]);
+
+        assert_eq!(code, 1);
+        assert_eq!(sent[0]["error"]["code"], SERVER_NOT_INITIALIZED);
+        assert_eq!(sent[1]["id"], 2);
+        assert_eq!(sent[2]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(sent[3]["error"]["code"], INVALID_PARAMS);
+
+        let mut server = LanguageServer::new();
+        assert_eq!(server.handle("{ not json")[0]["error"]["code"], PARSE_ERROR);
+    }
+}
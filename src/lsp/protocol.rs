@@ -0,0 +1,209 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+
+use serde_json::json;
+use serde_json::Value as JsonValue;
+
+use ast::Span;
+use source::LineColumn;
+use source::Location;
+use source::SourceCode;
+
+// Error codes of JSON-RPC and the language server protocol
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/// Largest message accepted, bigger `Content-Length` values are rejected before reading the content
+pub const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Reads the content of the next message, a `Content-Length` header followed by an empty line
+/// and the JSON content, returns `None` at the end of the input
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length: Option<usize> = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let value = value.parse::<usize>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid Content-Length '{}'", value)))?;
+
+            if value > MAX_MESSAGE_LENGTH {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Content-Length {} is too big", value)));
+            }
+
+            length = Some(value);
+        }
+    }
+
+    let length = length.unwrap();
+    let mut content = Vec::new();
+    input.take(length as u64).read_to_end(&mut content)?;
+
+    if content.len() < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete message content"));
+    }
+
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes a message with the header required by the protocol
+pub fn write_message<W: Write>(output: &mut W, message: &JsonValue) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()
+}
+
+pub fn response(id: &JsonValue, result: JsonValue) -> JsonValue {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_response(id: &JsonValue, code: i64, message: &str) -> JsonValue {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn notification(method: &str, params: JsonValue) -> JsonValue {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Converts an offset to a protocol position, lines and characters start at 0 and characters
+/// are measured in UTF-16 code units
+pub fn position(code: &SourceCode, offset: Location) -> JsonValue {
+    let position = code.position_utf16(offset);
+    json!({ "line": position.line - 1, "character": position.column - 1 })
+}
+
+pub fn range(code: &SourceCode, span: Span) -> JsonValue {
+    json!({ "start": position(code, span.0), "end": position(code, span.1) })
+}
+
+/// Converts a protocol position to an offset in the code
+pub fn offset(code: &SourceCode, position: &JsonValue) -> Option<Location> {
+    let line = position["line"].as_u64()? as u32;
+    let character = position["character"].as_u64()? as u32;
+
+    Some(code.offset_utf16(LineColumn { line: line + 1, column: character + 1 }))
+}
+
+/// Returns the path of a `file://` URI
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+
+    let encoded = &uri.as_bytes()["file://".len()..];
+    let mut bytes = vec![];
+    let mut index = 0;
+
+    while index < encoded.len() {
+        let byte = encoded[index];
+        let escaped = if byte == b'%' && index + 2 < encoded.len() {
+            ::std::str::from_utf8(&encoded[index + 1..index + 3]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(value) => {
+                bytes.push(value);
+                index += 3;
+            }
+            None => {
+                bytes.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    let path = String::from_utf8(bytes).ok()?;
+
+    // Windows paths look like `/C:/folder`
+    if path.len() > 2 && path.as_bytes()[2] == b':' {
+        Some(path[1..].to_string())
+    } else {
+        Some(path)
+    }
+}
+
+/// Creates a `file://` URI from a path
+pub fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => uri.push(byte as char),
+            b'\\' => uri.push('/'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn check_message_framing() {
+        let mut output = vec![];
+        write_message(&mut output, &json!({ "id": 1 })).unwrap();
+        write_message(&mut output, &json!({ "text": "á" })).unwrap();
+
+        assert_eq!(&output[..29], b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut input = Cursor::new(output);
+        assert_eq!(read_message(&mut input).unwrap(), Some("{\"id\":1}".to_string()));
+        assert_eq!(read_message(&mut input).unwrap(), Some("{\"text\":\"á\"}".to_string()));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn check_invalid_header() {
+        let mut input = Cursor::new(b"Content-Length: abc\r\n\r\n{}".to_vec());
+        assert!(read_message(&mut input).is_err());
+
+        let mut input = Cursor::new(b"Content-Length: 18446744073709551615\r\n\r\n{}".to_vec());
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut input = Cursor::new(b"Content-Length: 10\r\n\r\n{}".to_vec());
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn check_uris() {
+        assert_eq!(uri_to_path("file:///home/user/My%20Project/Main.elm"), Some("/home/user/My Project/Main.elm".to_string()));
+        assert_eq!(uri_to_path("file:///C:/Project/Main.elm"), Some("C:/Project/Main.elm".to_string()));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+        assert_eq!(path_to_uri("/home/user/My Project/Main.elm"), "file:///home/user/My%20Project/Main.elm");
+    }
+}
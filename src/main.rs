@@ -1,12 +1,20 @@
 extern crate elm_interpreter;
 
+use std::env;
+use std::fs;
 use std::io::BufRead;
+use std::io::Read;
 use std::io::stdin;
 use std::io::stdout;
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::exit;
 
 use elm_interpreter::errors::ElmError;
+use elm_interpreter::formatter::format_code;
+use elm_interpreter::formatter::format_source;
 use elm_interpreter::Runtime;
+use elm_interpreter::source::SourceCode;
 
 /*
 fib num = case num of \
@@ -20,7 +28,116 @@ map list fun = case list of \
 */
 
 fn main() {
-    repl();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    match args.first().map(|arg| arg.as_str()) {
+        Some("fmt") => exit(fmt(&args[1..])),
+        _ => repl(),
+    }
+}
+
+/// Formats the files and folders in place, `--check` only prints the files that need changes
+/// and `--stdin` formats the standard input into the standard output
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let from_stdin = args.iter().any(|arg| arg == "--stdin");
+
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with("--") && *arg != "--check" && *arg != "--stdin") {
+        eprintln!("Unknown option '{}'\nUsage: elm-interpreter fmt [--check] [--stdin] [files or folders...]", arg);
+        return 2;
+    }
+
+    if from_stdin {
+        let mut code = String::new();
+        if let Err(error) = stdin().read_to_string(&mut code) {
+            eprintln!("Unable to read the standard input: {}", error);
+            return 1;
+        }
+
+        return match format_code(&code) {
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                1
+            }
+        };
+    }
+
+    let mut pending = args.iter().filter(|arg| !arg.starts_with("--")).map(PathBuf::from).collect::<Vec<_>>();
+    let mut status = 0;
+
+    while let Some(path) = pending.pop() {
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(error) => {
+                eprintln!("Unable to read '{}': {}", path.display(), error);
+                status = 1;
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    eprintln!("Unable to read '{}': {}", path.display(), error);
+                    status = 1;
+                    continue;
+                }
+            };
+
+            for entry in entries {
+                match entry {
+                    Ok(entry) => pending.push(entry.path()),
+                    Err(error) => {
+                        eprintln!("Unable to read '{}': {}", path.display(), error);
+                        status = 1;
+                    }
+                }
+            }
+            continue;
+        }
+
+        if !metadata.is_file() || path.extension().map(|ext| ext != "elm").unwrap_or(true) {
+            continue;
+        }
+
+        let name = path.to_string_lossy().to_string();
+        let code = match fs::read_to_string(&path) {
+            Ok(code) => code,
+            Err(error) => {
+                eprintln!("Unable to read '{}': {}", name, error);
+                status = 1;
+                continue;
+            }
+        };
+
+        let formatted = match format_source(&SourceCode::from_string(code.clone(), &name)) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("{}", error);
+                status = 1;
+                continue;
+            }
+        };
+
+        if formatted == code {
+            continue;
+        }
+
+        if check {
+            println!("{}", name);
+            status = 1;
+        } else if let Err(error) = fs::write(&path, formatted) {
+            eprintln!("Unable to write '{}': {}", name, error);
+            status = 1;
+        }
+    }
+
+    status
 }
 
 fn repl() {
@@ -50,12 +50,25 @@ fn parse_expr_base(input: Input) -> Result<(Expr, Input), ParseError> {
             match i.read() {
                 Token::Dot => {
                     // Parsed: Upper.
-                    let (rest, i) = many0(&parse_dot_name, i)?;
+                    let (mut rest, i) = many0(&parse_dot_name, i)?;
                     // Parsed: Upper.A.B.C
-                    let i = expect(Token::Dot, i)?;
-                    let (name, i) = expect_id(i)?;
-                    // Parsed: Upper.A.B.C.func
-                    (Expr::QualifiedRef((input.pos(), i.pos()), create_vec(first, rest), name), i)
+                    match i.read() {
+                        Token::Dot => {
+                            let (name, i) = expect_id(i.next())?;
+                            // Parsed: Upper.A.B.C.func
+                            (Expr::QualifiedRef((input.pos(), i.pos()), create_vec(first, rest), name), i)
+                        }
+                        _ if !rest.is_empty() => {
+                            // Parsed: Upper.A.B.Constructor
+                            let name = rest.pop().unwrap();
+                            (Expr::QualifiedRef((input.pos(), i.pos()), create_vec(first, rest), name), i)
+                        }
+                        _ => {
+                            let i = expect(Token::Dot, i)?;
+                            let (name, i) = expect_id(i)?;
+                            (Expr::QualifiedRef((input.pos(), i.pos()), create_vec(first, rest), name), i)
+                        }
+                    }
                 }
                 _ => {
                     (Expr::Ref((input.pos(), i.pos()), first), input.next())
@@ -524,6 +537,15 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn check_qualified_constructor() {
+        test_parser_result(parse_expr, "Maybe.Just", Expr::QualifiedRef(
+            (0, 0),
+            vec!["Maybe".s()],
+            "Just".s(),
+        ));
+    }
+
     #[test]
     fn check_function_application() {
         test_parser_result(parse_expr, "my_fun 1", Expr::Application(
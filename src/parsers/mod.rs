@@ -3,6 +3,7 @@ use errors::*;
 use parsers::input::Input;
 use parsers::util::complete;
 use source::SourceCode;
+use tokenizer::TokenInfo;
 use tokenizer::Tokenizer;
 
 mod input;
@@ -39,12 +40,36 @@ impl Parser {
             .map_err(|e| ElmError::Parser(self.code.clone(), e))
     }
 
-    /// Generates an abstract syntax tree from an elm module
+    /// Generates an abstract syntax tree from an elm module, all the syntax errors are reported,
+    /// not just the first one
     pub fn parse_module(&mut self) -> Result<Module, ElmError> {
-        let input = Input::new(self.code.clone(), self.tokenizer.tokenize()?);
+        let tokens = self.tokenizer.tokenize()?;
+        Self::parse_module_tokens(self.code.clone(), tokens)
+    }
 
-        complete(&module::parse_module, input)
-            .map_err(|e| ElmError::Parser(self.code.clone(), e))
+    /// Same as `parse_module` but with tokens that come from another source, like a syntax tree
+    pub fn parse_module_tokens(code: SourceCode, tokens: Vec<TokenInfo>) -> Result<Module, ElmError> {
+        let (module, errors, _) = module::parse_module_recovering(Input::new(code.clone(), tokens));
+
+        let mut errors = errors.into_iter()
+            .map(|e| ElmError::Parser(code.clone(), e))
+            .collect::<Vec<_>>();
+
+        match errors.len() {
+            0 => Ok(module),
+            1 => Err(errors.remove(0)),
+            _ => Err(ElmError::List(errors)),
+        }
+    }
+
+    /// Generates an abstract syntax tree with the parts of an elm module that are valid, and
+    /// returns the lexical and syntax errors found, useful for tools that work with incomplete code
+    pub fn parse_module_partial(&mut self) -> (Module, Vec<ElmError>) {
+        let (tokens, mut errors) = self.tokenizer.read_all();
+        let (module, parse_errors, _) = module::parse_module_recovering(Input::new(self.code.clone(), tokens));
+
+        errors.extend(parse_errors.into_iter().map(|e| ElmError::Parser(self.code.clone(), e)));
+        (module, errors)
     }
 
     /// Generates an abstract syntax tree from an elm type definition
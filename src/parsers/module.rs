@@ -12,12 +12,30 @@ use tokenizer::Token;
 use util::create_vec;
 
 pub fn parse_module(input: Input) -> Result<(Module, Input), ParseError> {
-    let i = skip_empty_lines(input)?;
+    let (module, errors, i) = parse_module_recovering(input);
+
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok((module, i)),
+    }
+}
+
+/// Parses a module without stopping at the first error, when the header, an import or a
+/// statement is invalid the parser skips to the next line that starts at column 0. The module
+/// contains all the parts that could be parsed
+pub fn parse_module_recovering(input: Input) -> (Module, Vec<ParseError>, Input) {
+    let mut errors = vec![];
+    let i = skip_empty_lines(input);
 
     let (header, i) = match i.read() {
-        Token::ModuleTk | Token::EffectTk => {
-            let (header, i) = parse_module_header(i)?;
-            (Some(header), i)
+        Token::ModuleTk | Token::EffectTk | Token::Port => {
+            match parse_module_header(i.clone()) {
+                Ok((header, i)) => (Some(header), i),
+                Err(e) => {
+                    errors.push(e);
+                    (None, skip_to_the_next_block(i))
+                }
+            }
         }
         _ => {
             (None, i)
@@ -28,12 +46,19 @@ pub fn parse_module(input: Input) -> Result<(Module, Input), ParseError> {
     let mut i = i;
 
     loop {
-        i = skip_empty_lines(i)?;
+        i = skip_empty_lines(i);
         match i.read() {
             Token::ImportTk => {
-                let pair = parse_import(i)?;
-                imports.push(pair.0);
-                i = pair.1;
+                match parse_import(i.clone()) {
+                    Ok((import, input)) => {
+                        imports.push(import);
+                        i = input;
+                    }
+                    Err(e) => {
+                        errors.push(e);
+                        i = skip_to_the_next_block(i);
+                    }
+                }
             }
             _ => {
                 break;
@@ -45,7 +70,7 @@ pub fn parse_module(input: Input) -> Result<(Module, Input), ParseError> {
     let mut i = i;
 
     loop {
-        i = skip_empty_lines(i)?;
+        i = skip_empty_lines(i);
 
         if let Token::Eof = i.read() {
             break;
@@ -57,21 +82,21 @@ pub fn parse_module(input: Input) -> Result<(Module, Input), ParseError> {
                 i = input;
             }
             Err(e) => {
-                // TODO collect all errors
-                return Err(e);
-//                i = skip_to_the_next_block(i);
+                errors.push(e);
+                i = skip_to_the_next_block(i);
             }
         }
     }
 
-    Ok((Module { header, imports, statements }, i))
+    (Module { header, imports, statements }, errors, i)
 }
 
+/// Skips the current token and the rest of the block, until a line that starts at column 0
 fn skip_to_the_next_block(input: Input) -> Input {
-    let mut i = input;
+    let mut i = input.next();
     loop {
         match i.read() {
-            Token::Indent(0) => {
+            Token::Indent(0) | Token::Eof => {
                 break;
             }
             _ => {
@@ -83,7 +108,7 @@ fn skip_to_the_next_block(input: Input) -> Input {
     i
 }
 
-fn skip_empty_lines(input: Input) -> Result<Input, ParseError> {
+fn skip_empty_lines(input: Input) -> Input {
     let mut i = input;
     loop {
         match expect_indent(0, i.clone()) {
@@ -95,7 +120,7 @@ fn skip_empty_lines(input: Input) -> Result<Input, ParseError> {
             }
         }
     }
-    Ok(i)
+    i
 }
 
 
@@ -106,6 +131,11 @@ fn parse_module_header(input: Input) -> Result<(ModuleHeader, Input), ParseError
             let i = input.next();
             expect_upper_chain(i)?
         }
+        Token::Port => {
+            // port module Main exposing (..)
+            let i = expect(Token::ModuleTk, input.next())?;
+            expect_upper_chain(i)?
+        }
         Token::EffectTk => {
             // effect module Task where { command = MyCmd } exposing (..)
             let i = expect(Token::ModuleTk, input.next())?;
@@ -121,7 +151,7 @@ fn parse_module_header(input: Input) -> Result<(ModuleHeader, Input), ParseError
         }
         _ => {
             let found = input.read();
-            return Err(ParseError::UnmatchedToken { span: input.span(), found, options: vec![Token::ModuleTk, Token::EffectTk] });
+            return Err(ParseError::UnmatchedToken { span: input.span(), found, options: vec![Token::ModuleTk, Token::EffectTk, Token::Port] });
         }
     };
 
@@ -233,7 +263,11 @@ mod tests {
     use ast::Literal;
     use ast::Pattern;
     use ast::Statement;
+    use errors::ElmError;
+    use parsers::Parser;
     use parsers::util::test_utils::*;
+    use source::SourceCode;
+    use tokenizer::Tokenizer;
     use util::StringConversion;
 
     use super::*;
@@ -268,6 +302,7 @@ mod tests {
         test_parser(parse_module_header, "module Util exposing (A(..))");
         test_parser(parse_module_header, "module Util exposing (A(B))");
         test_parser(parse_module_header, "module Util exposing (A(B, C))");
+        test_parser(parse_module_header, "port module Main exposing (..)");
     }
 
     #[test]
@@ -404,4 +439,55 @@ import Elm.JsArray as JsArray exposing (JsArray)\n\
             statements: vec![],
         });
     }
+
+    fn definition_names(module: &Module) -> Vec<&str> {
+        module.statements.iter()
+            .filter_map(|stm| match stm {
+                Statement::Def(def) => Some(def.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_error_recovery() {
+        let code = "\
+module Main exposing (..)\n\
+\n\
+import Util exposing ()\n\
+import List\n\
+\n\
+x = = 1\n\
+\n\
+y = 2\n\
+\n\
+z = (3\n\
+\n\
+w =\n    case y of\n        _ -> 4\n\
+";
+        let source = SourceCode::from_str(code);
+        let tokens = Tokenizer::new(&source).tokenize().unwrap();
+        let (module, errors, _) = parse_module_recovering(Input::new(source.clone(), tokens));
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(module.header.as_ref().unwrap().name, "Main");
+        assert_eq!(module.imports, vec![Import { path: vec!["List".s()], exposing: None, alias: None }]);
+        assert_eq!(definition_names(&module), vec!["y", "w"]);
+
+        match Parser::new(Tokenizer::new(&source)).parse_module() {
+            Err(ElmError::List(list)) => assert_eq!(list.len(), 3),
+            other => panic!("Expected a list of errors: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_partial_module() {
+        let source = SourceCode::from_str("x = 1\n\ny = 2 ` 3\n\nz = )\n");
+        let (module, errors) = Parser::new(Tokenizer::new(&source)).parse_module_partial();
+
+        assert_eq!(definition_names(&module), vec!["x", "y"]);
+        assert_eq!(errors.len(), 2);
+        assert!(match &errors[0] { ElmError::Tokenizer(..) => true, _ => false });
+        assert!(match &errors[1] { ElmError::Parser(..) => true, _ => false });
+    }
 }
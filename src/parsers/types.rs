@@ -52,7 +52,7 @@ pub fn parse_type_base(input: Input, adt: bool) -> Result<(Type, Input), ParseEr
             };
 
             if adt {
-                let (params, i) = many0(&parse_type_with_adt, i)?;
+                let (params, i) = many0(&parse_type_without_adt, i)?;
                 (Type::Tag(name, params), i)
             } else {
                 (Type::Tag(name, vec![]), i)
@@ -223,6 +223,14 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn check_tag_arguments() {
+        test_parser_result(parse_type, "Dict String (List a)", Type::Tag(
+            "Dict".s(),
+            vec![Type::Tag("String".s(), vec![]), Type::Tag("List".s(), vec![Type::Var("a".s())])],
+        ));
+    }
+
     #[test]
     fn check_tuple2() {
         test_parser_result(parse_type, "(a,b)", Type::Tuple(vec![
@@ -0,0 +1,445 @@
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use rust_interop::conversions::list_of;
+use rust_interop::conversions::str_of;
+use platform::scheduler::ProcessId;
+use types::Value;
+use Runtime;
+
+pub mod ports;
+pub mod scheduler;
+
+/// A headless program created with `Platform.worker`, the host sends the messages and
+/// collects the commands returned by `init` and `update`
+#[derive(Debug)]
+pub struct Worker {
+    runtime: Runtime,
+    update: Value,
+    subscriptions: Value,
+    model: Value,
+    active_subs: Value,
+    commands: Vec<Value>,
+    /// Processes of the `Task.perform` commands and the commands, to apply their `Cmd.map`
+    tasks: Vec<(ProcessId, Value)>,
+}
+
+impl Worker {
+    /// Starts the program defined as `main` in the module, the module must be already
+    /// included in the runtime
+    pub fn start(mut runtime: Runtime, module_name: &str, flags: Value) -> Result<Worker, ElmError> {
+        runtime.import_module(module_name)?;
+
+        let main = runtime.runtime_modules.get(module_name)
+            .and_then(|module| module.definitions.get("main"))
+            .cloned()
+            .ok_or_else(|| InterpreterError::MissingDefinition(format!("{}.main", module_name)).wrap())?;
+
+        let init = program_field(&main, "init")?;
+        let update = program_field(&main, "update")?;
+        let subscriptions = program_field(&main, "subscriptions")?;
+
+        let result = runtime.interpreter.apply_function(init, &[flags])?;
+        let (model, cmd) = model_and_cmd(result)?;
+        let active_subs = runtime.interpreter.apply_function(subscriptions.clone(), &[model.clone()])?;
+
+        let mut worker = Worker {
+            runtime,
+            update,
+            subscriptions,
+            model,
+            active_subs,
+            commands: vec![],
+            tasks: vec![],
+        };
+
+        worker.add_commands(&cmd)?;
+        Ok(worker)
+    }
+
+    /// Runs `update` with the message, the commands are stored until they are collected
+    /// with `take_commands`
+    pub fn send(&mut self, msg: Value) -> Result<(), ElmError> {
+        let result = self.runtime.interpreter.apply_function(self.update.clone(), &[msg, self.model.clone()])?;
+        let (model, cmd) = model_and_cmd(result)?;
+
+        self.active_subs = self.runtime.interpreter.apply_function(self.subscriptions.clone(), &[model.clone()])?;
+        self.model = model;
+        self.add_commands(&cmd)
+    }
+
+    /// Evaluates the expression and sends the result as a message, the definitions of the
+    /// program module are available with the module name as prefix
+    pub fn send_expr(&mut self, msg: &str) -> Result<(), ElmError> {
+        let msg = self.runtime.eval_expr(msg)?;
+        self.send(msg)
+    }
+
+    /// Current model of the program
+    pub fn model(&self) -> &Value {
+        &self.model
+    }
+
+    /// Subscriptions returned by `subscriptions` for the current model
+    pub fn subscriptions(&self) -> &Value {
+        &self.active_subs
+    }
+
+    /// Returns the commands emitted since the last call, batches are flattened so every
+    /// command is an effect, wrapped in the `Cmd.map` calls applied to it
+    pub fn take_commands(&mut self) -> Vec<Value> {
+        self.commands.split_off(0)
+    }
+
+    pub fn runtime(&mut self) -> &mut Runtime {
+        &mut self.runtime
+    }
+
+    /// Stops the program and returns the runtime
+    pub fn into_runtime(self) -> Runtime {
+        self.runtime
+    }
+
+    /// Delivers the values pushed by the host into incoming ports, each value is sent as a
+    /// message to every active subscription of the port
+    pub fn process_ports(&mut self) -> Result<(), ElmError> {
+        let values = self.runtime.ports.receive(&mut self.runtime.interpreter)?;
+
+        for (port, value) in values {
+            let mut effects = vec![];
+            flatten_effects(&self.active_subs, &mut effects)?;
+
+            for effect in &effects {
+                let (leaf, taggers) = unwrap_taggers(effect);
+
+                match leaf {
+                    Value::Adt(name, values, _) if name == "Port" && values[0] == Value::String(port.clone()) => {
+                        let mut msg = self.runtime.interpreter.apply_function(values[1].clone(), &[value.clone()])?;
+
+                        for tagger in taggers.iter().rev() {
+                            msg = self.runtime.interpreter.apply_function((*tagger).clone(), &[msg])?;
+                        }
+
+                        self.send(msg)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the tasks of `Task.perform` and `Task.attempt` until all of them finish or sleep,
+    /// the results are sent as messages. It doesn't wait for sleeping tasks
+    pub fn process_tasks(&mut self) -> Result<(), ElmError> {
+        loop {
+            self.runtime.scheduler.run_ready(&mut self.runtime.interpreter)?;
+
+            let mut finished = vec![];
+            let scheduler = &mut self.runtime.scheduler;
+
+            self.tasks.retain(|(id, cmd)| {
+                match scheduler.take_result(*id) {
+                    Some(result) => {
+                        finished.push((cmd.clone(), result));
+                        false
+                    }
+                    None => scheduler.is_alive(*id),
+                }
+            });
+
+            if finished.is_empty() {
+                return Ok(());
+            }
+
+            for (cmd, result) in finished {
+                // The tasks of commands cannot fail, their type is `Task Never msg`
+                let mut msg = result.map_err(|error| InterpreterError::ExpectedTask(error).wrap())?;
+
+                for tagger in unwrap_taggers(&cmd).1.iter().rev() {
+                    msg = self.runtime.interpreter.apply_function((*tagger).clone(), &[msg])?;
+                }
+
+                self.send(msg)?;
+            }
+        }
+    }
+
+    /// Runs the tasks until all of them finish, the clock of the runtime is used to wait
+    /// for the sleeping tasks
+    pub fn run_tasks(&mut self) -> Result<(), ElmError> {
+        loop {
+            self.process_tasks()?;
+
+            if self.tasks.is_empty() || !self.runtime.scheduler.wait() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// True if there are tasks of commands that have not finished
+    pub fn has_pending_tasks(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    /// Commands for ports with receivers are sent to them and the tasks are given to the
+    /// scheduler, the rest are kept for `take_commands`
+    fn add_commands(&mut self, cmd: &Value) -> Result<(), ElmError> {
+        let mut effects = vec![];
+        flatten_effects(cmd, &mut effects)?;
+
+        for effect in effects {
+            let sent = match unwrap_taggers(&effect).0 {
+                Value::Adt(name, values, _) if name == "Port" => {
+                    let port = str_of(&values[0])?;
+                    self.runtime.ports.send(port, &values[1])?
+                }
+                Value::Adt(name, values, _) if name == "Perform" => {
+                    let id = self.runtime.scheduler.spawn(values[0].clone());
+                    self.tasks.push((id, effect.clone()));
+                    true
+                }
+                _ => false,
+            };
+
+            if !sent {
+                self.commands.push(effect);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the leaves of a command or subscription tree, `Map` is moved down to the leaves
+pub fn flatten_effects(value: &Value, effects: &mut Vec<Value>) -> Result<(), ElmError> {
+    match value {
+        Value::Adt(name, values, _) if name == "Batch" => {
+            for value in list_of(&values[0])?.iter() {
+                flatten_effects(value, effects)?;
+            }
+        }
+        Value::Adt(name, values, adt) if name == "Map" => {
+            let mut inner = vec![];
+            flatten_effects(&values[1], &mut inner)?;
+
+            for effect in inner {
+                effects.push(Value::Adt(name.clone(), vec![values[0].clone(), effect], adt.clone()));
+            }
+        }
+        _ => effects.push(value.clone()),
+    }
+
+    Ok(())
+}
+
+/// Returns the effect inside the `Map` nodes and the functions of the nodes, outermost first
+fn unwrap_taggers(effect: &Value) -> (&Value, Vec<&Value>) {
+    let mut taggers = vec![];
+    let mut current = effect;
+
+    while let Value::Adt(name, values, _) = current {
+        if name != "Map" {
+            break;
+        }
+
+        taggers.push(&values[0]);
+        current = &values[1];
+    }
+
+    (current, taggers)
+}
+
+fn program_field(program: &Value, field: &str) -> Result<Value, ElmError> {
+    let record = match program {
+        Value::Adt(name, values, _) if name == "Program" && values.len() == 1 => &values[0],
+        _ => return Err(InterpreterError::ExpectedProgram(program.clone()).wrap()),
+    };
+
+    match record {
+        Value::Record(entries) => {
+            entries.iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| InterpreterError::RecordFieldNotFound(field.to_string(), record.clone()).wrap())
+        }
+        _ => Err(InterpreterError::ExpectedRecord(record.clone()).wrap())
+    }
+}
+
+fn model_and_cmd(value: Value) -> Result<(Value, Value), ElmError> {
+    match value {
+        Value::Tuple(mut items) if items.len() == 2 => {
+            let cmd = items.pop().unwrap();
+            let model = items.pop().unwrap();
+            Ok((model, cmd))
+        }
+        _ => Err(InterpreterError::ExpectedTuple(value).wrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use builtin::platform::cmd_value;
+    use platform::scheduler::Clock;
+    use platform::scheduler::VirtualClock;
+
+    use super::*;
+
+    const COUNTER: &str = r#"
+module Counter exposing (..)
+
+type Msg = Increment Int | Reset
+
+init : Int -> ( Int, Cmd Msg )
+init start = ( start, Cmd.none )
+
+update : Msg -> Int -> ( Int, Cmd Msg )
+update msg model =
+    case msg of
+        Increment amount -> ( model + amount, Cmd.batch [ Cmd.none, Cmd.none ] )
+        Reset -> ( 0, Cmd.none )
+
+subscriptions : Int -> Sub Msg
+subscriptions model = Sub.none
+
+main : Program Int Int Msg
+main = Platform.worker { init = init, update = update, subscriptions = subscriptions }
+"#;
+
+    // `main` has no type annotation, the literals are typed by `init` and `Msg`
+    const LATEST: &str = r#"
+module Latest exposing (..)
+
+type Msg = Set (Maybe Int)
+
+init : () -> ( Maybe Int, Cmd Msg )
+init flags = ( Just 0, Cmd.none )
+
+update msg model =
+    case msg of
+        Set value -> ( value, Cmd.none )
+
+main = Platform.worker { init = init, update = update, subscriptions = \_ -> Sub.none }
+"#;
+
+    #[test]
+    fn check_worker_updates() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module(COUNTER, "Counter").unwrap();
+
+        let mut worker = Worker::start(runtime, "Counter", Value::Int(5)).unwrap();
+        assert_eq!(worker.model(), &Value::Int(5));
+
+        worker.send_expr("Counter.Increment 3").unwrap();
+        worker.send_expr("Counter.Increment 2").unwrap();
+        assert_eq!(worker.model(), &Value::Int(10));
+        assert!(worker.take_commands().is_empty());
+
+        // The analyzer types the literal 0 in `Reset -> ( 0, Cmd.none )` as an Int
+        worker.send_expr("Counter.Reset").unwrap();
+        assert_eq!(worker.model(), &Value::Int(0));
+    }
+
+    #[test]
+    fn check_worker_number_literals() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module(LATEST, "Latest").unwrap();
+
+        let mut worker = Worker::start(runtime, "Latest", Value::Unit).unwrap();
+        assert_eq!(just_value(worker.model()), &Value::Int(0));
+
+        worker.send_expr("Latest.Set (Just 7)").unwrap();
+        assert_eq!(just_value(worker.model()), &Value::Int(7));
+    }
+
+    fn just_value(value: &Value) -> &Value {
+        match value {
+            Value::Adt(name, items, _) if name == "Just" => &items[0],
+            _ => panic!("Expected Just, found: {}", value),
+        }
+    }
+
+    #[test]
+    fn check_flatten_commands() {
+        let leaf = Value::String("effect".to_string());
+        let tagger = Value::Unit;
+        let cmd = cmd_value("Batch", vec![Value::List(vec![
+            cmd_value("Batch", vec![Value::List(vec![].into())]),
+            leaf.clone(),
+            cmd_value("Map", vec![tagger.clone(), cmd_value("Batch", vec![Value::List(vec![leaf.clone(), leaf.clone()].into())])]),
+        ].into())]);
+
+        let mut effects = vec![];
+        flatten_effects(&cmd, &mut effects).unwrap();
+
+        assert_eq!(effects, vec![
+            leaf.clone(),
+            cmd_value("Map", vec![tagger.clone(), leaf.clone()]),
+            cmd_value("Map", vec![tagger.clone(), leaf.clone()]),
+        ]);
+    }
+
+    const TIMER: &str = r#"
+module Timer exposing (..)
+
+import Process
+import Task
+
+type Msg = Tick Int | Failed String
+
+init : () -> ( List Int, Cmd Msg )
+init flags = ( [], Cmd.batch [ after 200 2, after 100 1, Task.attempt result (Task.fail "oops") ] )
+
+after : Float -> Int -> Cmd Msg
+after ms n = Task.perform Tick (Process.sleep ms |> Task.map (\_ -> n))
+
+result : Result String Int -> Msg
+result res =
+    case res of
+        Ok n -> Tick n
+        Err error -> Failed error
+
+update : Msg -> List Int -> ( List Int, Cmd Msg )
+update msg model =
+    case msg of
+        Tick n -> ( n :: model, Cmd.none )
+        Failed error -> ( 0 :: model, Cmd.none )
+
+subscriptions : List Int -> Sub Msg
+subscriptions model = Sub.none
+
+main : Program () (List Int) Msg
+main = Platform.worker { init = init, update = update, subscriptions = subscriptions }
+"#;
+
+    #[test]
+    fn check_worker_tasks() {
+        let clock = VirtualClock::new();
+        let mut runtime = Runtime::new();
+        runtime.set_clock(clock.clone());
+        runtime.eval_module(TIMER, "Timer").unwrap();
+
+        let mut worker = Worker::start(runtime, "Timer", Value::Unit).unwrap();
+        worker.process_tasks().unwrap();
+        assert_eq!(worker.model(), &Value::List(vec![Value::Int(0)].into()));
+
+        clock.advance(150.0);
+        worker.process_tasks().unwrap();
+        assert_eq!(worker.model(), &Value::List(vec![Value::Int(1), Value::Int(0)].into()));
+
+        worker.run_tasks().unwrap();
+        assert_eq!(worker.model(), &Value::List(vec![Value::Int(2), Value::Int(1), Value::Int(0)].into()));
+        assert!(!worker.has_pending_tasks());
+        assert_eq!(clock.now(), 200.0);
+    }
+
+    #[test]
+    fn check_main_must_be_a_program() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module("module NotAProgram exposing (..)\n\nmain : Int\nmain = 1\n", "NotAProgram").unwrap();
+
+        assert!(Worker::start(runtime, "NotAProgram", Value::Unit).is_err());
+    }
+}
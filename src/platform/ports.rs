@@ -0,0 +1,216 @@
+use std::fmt;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+
+use ast::Type;
+use builtin::platform::port_kind;
+use builtin::platform::PortKind;
+use errors::ElmError;
+use errors::InteropError;
+use errors::Wrappable;
+use interpreter::Interpreter;
+use loader::Declaration;
+use rust_interop::elm_value::ElmValue;
+use types::Value;
+use Runtime;
+
+type OutgoingPort = Box<Fn(&Value) -> Result<(), ElmError> + Send>;
+type IncomingPort = Box<Fn(&mut Interpreter) -> Result<Vec<Value>, ElmError> + Send>;
+
+/// Channels connected to the ports of the programs
+#[derive(Default)]
+pub struct Ports {
+    outgoing: Vec<(String, OutgoingPort)>,
+    incoming: Vec<(String, IncomingPort)>,
+}
+
+impl Ports {
+    /// Sends the value to all the receivers of the port, returns false if there are none
+    pub fn send(&self, port: &str, value: &Value) -> Result<bool, ElmError> {
+        let mut sent = false;
+
+        for (name, sender) in &self.outgoing {
+            if name == port {
+                sender(value)?;
+                sent = true;
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Takes the values pushed by the host into incoming ports, in the order of the ports
+    pub fn receive(&self, i: &mut Interpreter) -> Result<Vec<(String, Value)>, ElmError> {
+        let mut values = vec![];
+
+        for (name, receiver) in &self.incoming {
+            for value in receiver(i)? {
+                values.push((name.clone(), value));
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+// The channels don't have useful debug information
+impl fmt::Debug for Ports {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outgoing = self.outgoing.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let incoming = self.incoming.iter().map(|(name, _)| name).collect::<Vec<_>>();
+
+        write!(f, "Ports {{ outgoing: {:?}, incoming: {:?} }}", outgoing, incoming)
+    }
+}
+
+impl Runtime {
+    /// Returns a channel with the values the program sends through an outgoing port,
+    /// like `port send : String -> Cmd msg`, named with its module as `Echo.send`. The module
+    /// that declares the port must be already loaded and the Elm type of `T` must match the
+    /// type of the port
+    pub fn subscribe_port<T: ElmValue + Send + 'static>(&mut self, name: &str) -> Result<Receiver<T>, ElmError> {
+        match self.find_port(name)? {
+            PortKind::Outgoing(ty) => self.check_port_type::<T>(name, &ty)?,
+            PortKind::Incoming(ty) => return Err(InteropError::PortTypeMismatch(name.to_string(), ty).wrap()),
+        }
+
+        let (sender, receiver) = channel();
+        let outgoing: OutgoingPort = Box::new(move |value| {
+            // Nobody is listening if the receiver was dropped
+            let _ = sender.send(T::from_elm(value)?);
+            Ok(())
+        });
+
+        self.ports.outgoing.push((name.to_string(), outgoing));
+        Ok(receiver)
+    }
+
+    /// Returns a channel to push values into an incoming port, like
+    /// `port receive : (Int -> msg) -> Sub msg` named `Echo.receive`, the values are delivered
+    /// to the program by `Worker::process_ports`. The Elm type of `T` must match the type of
+    /// the port
+    pub fn port_sender<T: ElmValue + Send + 'static>(&mut self, name: &str) -> Result<Sender<T>, ElmError> {
+        match self.find_port(name)? {
+            PortKind::Incoming(ty) => self.check_port_type::<T>(name, &ty)?,
+            PortKind::Outgoing(ty) => return Err(InteropError::PortTypeMismatch(name.to_string(), ty).wrap()),
+        }
+
+        let (sender, receiver) = channel::<T>();
+        let incoming: IncomingPort = Box::new(move |_| {
+            Ok(receiver.try_iter().map(|value| value.to_elm()).collect())
+        });
+
+        self.ports.incoming.push((name.to_string(), incoming));
+        Ok(sender)
+    }
+
+    /// Finds a port by its qualified name, `Module.name`
+    fn find_port(&self, name: &str) -> Result<PortKind, ElmError> {
+        let not_found = || InteropError::PortNotFound(name.to_string()).wrap();
+        let dot = name.rfind('.').ok_or_else(not_found)?;
+        let (module_name, port_name) = (&name[..dot], &name[dot + 1..]);
+        let module = self.analyzed_modules.get(module_name).ok_or_else(not_found)?;
+
+        module.all_declarations.iter()
+            .filter_map(|decl| {
+                match decl {
+                    Declaration::Port(port, ty) if port == port_name => port_kind(port, ty),
+                    _ => None,
+                }
+            })
+            .next()
+            .ok_or_else(not_found)
+    }
+
+    fn check_port_type<T: ElmValue>(&self, name: &str, ty: &Type) -> Result<(), ElmError> {
+        match self.analyzer.check_type((0, 0), T::elm_type()) {
+            Ok(ref rust_type) if rust_type == ty => Ok(()),
+            _ => Err(InteropError::PortTypeMismatch(name.to_string(), ty.clone()).wrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Int;
+    use platform::Worker;
+
+    use super::*;
+
+    const ECHO: &str = r#"
+port module Echo exposing (..)
+
+port send : String -> Cmd msg
+
+port receive : (Int -> msg) -> Sub msg
+
+type Msg = Received Int
+
+init : () -> ( Int, Cmd Msg )
+init flags = ( 0, send "ready" )
+
+update : Msg -> Int -> ( Int, Cmd Msg )
+update msg model =
+    case msg of
+        Received amount -> ( model + amount, send (String.fromInt (model + amount)) )
+
+subscriptions : Int -> Sub Msg
+subscriptions model = Sub.map identity (receive Received)
+
+main : Program () Int Msg
+main = Platform.worker { init = init, update = update, subscriptions = subscriptions }
+"#;
+
+    #[test]
+    fn check_ports() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module(ECHO, "Echo").unwrap();
+
+        let sent = runtime.subscribe_port::<String>("Echo.send").unwrap();
+        let receive = runtime.port_sender::<Int>("Echo.receive").unwrap();
+
+        let mut worker = Worker::start(runtime, "Echo", Value::Unit).unwrap();
+        assert_eq!(sent.try_recv(), Ok("ready".to_string()));
+
+        receive.send(3).unwrap();
+        receive.send(4).unwrap();
+        worker.process_ports().unwrap();
+
+        assert_eq!(worker.model(), &Value::Int(7));
+        assert_eq!(sent.try_iter().collect::<Vec<_>>(), vec!["3".to_string(), "7".to_string()]);
+        assert!(worker.take_commands().is_empty());
+    }
+
+    #[test]
+    fn check_ports_with_same_name() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module("port module Log exposing (..)\n\nport send : Int -> Cmd msg\n", "Log").unwrap();
+        runtime.eval_module(ECHO, "Echo").unwrap();
+
+        let log = runtime.subscribe_port::<Int>("Log.send").unwrap();
+        let sent = runtime.subscribe_port::<String>("Echo.send").unwrap();
+
+        Worker::start(runtime, "Echo", Value::Unit).unwrap();
+        assert_eq!(sent.try_recv(), Ok("ready".to_string()));
+        assert!(log.try_recv().is_err());
+    }
+
+    #[test]
+    fn check_port_types() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module(ECHO, "Echo").unwrap();
+
+        assert!(runtime.subscribe_port::<Int>("Echo.send").is_err());
+        assert!(runtime.port_sender::<String>("Echo.receive").is_err());
+        assert!(runtime.port_sender::<Int>("Echo.send").is_err());
+        assert_eq!(
+            runtime.subscribe_port::<String>("missing").err(),
+            Some(InteropError::PortNotFound("missing".to_string()).wrap())
+        );
+        assert_eq!(
+            runtime.subscribe_port::<String>("send").err(),
+            Some(InteropError::PortNotFound("send".to_string()).wrap())
+        );
+    }
+}
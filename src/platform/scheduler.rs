@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use ast::Int;
+use builtin::scheduler::process_id_of;
+use builtin::scheduler::process_id_value;
+use builtin::scheduler::task_value;
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use interpreter::Interpreter;
+use rust_interop::conversions::float_of;
+use types::Value;
+use Runtime;
+
+/// Source of time for `Process.sleep`, times are milliseconds since an arbitrary origin
+pub trait Clock: Send {
+    /// Current time
+    fn now(&self) -> f64;
+
+    /// Blocks until the time is reached, it's only called with times in the future
+    fn wait_until(&mut self, time: f64);
+}
+
+/// Wall clock time, waiting blocks the thread
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+
+    fn wait_until(&mut self, time: f64) {
+        let remaining = time - self.now();
+
+        if remaining > 0.0 {
+            thread::sleep(Duration::from_secs_f64(remaining / 1000.0));
+        }
+    }
+}
+
+/// Time that only moves when the scheduler waits or when it's advanced by hand, clones share
+/// the same time so tests can keep a handle after giving the clock to the runtime
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    time: Arc<Mutex<f64>>,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock::default()
+    }
+
+    /// Moves the time forward, sleeping processes are woken up the next time the scheduler runs
+    pub fn advance(&self, millis: f64) {
+        *self.time.lock().unwrap() += millis;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> f64 {
+        *self.time.lock().unwrap()
+    }
+
+    fn wait_until(&mut self, time: f64) {
+        let mut current = self.time.lock().unwrap();
+
+        if *current < time {
+            *current = time;
+        }
+    }
+}
+
+/// Identifier of a process, the same number is inside the `Process.Id` values
+pub type ProcessId = Int;
+
+/// Callbacks waiting for the result of the current task of a process
+enum Handler {
+    AndThen(Value),
+    OnError(Value),
+}
+
+struct Process {
+    id: ProcessId,
+    root: Value,
+    stack: Vec<Handler>,
+    /// Processes spawned by the host keep the result until it's collected
+    reported: bool,
+}
+
+/// Cooperative scheduler of tasks, a process runs until its task finishes or it calls
+/// `Process.sleep`, processes are resumed in the order they were spawned or woken up
+pub struct Scheduler {
+    clock: Box<Clock>,
+    next_id: ProcessId,
+    ready: VecDeque<Process>,
+    sleeping: Vec<(f64, Process)>,
+    completed: Vec<(ProcessId, Result<Value, Value>)>,
+}
+
+enum Step {
+    Done(Result<Value, Value>),
+    Sleep(f64),
+}
+
+impl Scheduler {
+    pub fn new(clock: Box<Clock>) -> Self {
+        Scheduler {
+            clock,
+            next_id: 0,
+            ready: VecDeque::new(),
+            sleeping: vec![],
+            completed: vec![],
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.clock = clock;
+    }
+
+    /// Current time of the clock
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    /// Adds a process that runs the task, the result can be collected with `take_result`
+    pub fn spawn(&mut self, task: Value) -> ProcessId {
+        self.add_process(task, true)
+    }
+
+    /// Stops a process, it does nothing if the process is already finished
+    pub fn kill(&mut self, id: ProcessId) {
+        self.ready.retain(|process| process.id != id);
+        self.sleeping.retain(|(_, process)| process.id != id);
+    }
+
+    /// True if the process has not finished and it was not killed
+    pub fn is_alive(&self, id: ProcessId) -> bool {
+        self.ready.iter().any(|process| process.id == id)
+            || self.sleeping.iter().any(|(_, process)| process.id == id)
+    }
+
+    /// True if there are processes that have not finished
+    pub fn has_processes(&self) -> bool {
+        !self.ready.is_empty() || !self.sleeping.is_empty()
+    }
+
+    /// Returns the result of a finished process spawned by the host
+    pub fn take_result(&mut self, id: ProcessId) -> Option<Result<Value, Value>> {
+        let index = self.completed.iter().position(|(process, _)| *process == id)?;
+        Some(self.completed.remove(index).1)
+    }
+
+    /// Runs the processes that are ready and the ones whose sleep has finished, it returns when
+    /// all the remaining processes are sleeping
+    pub fn run_ready(&mut self, i: &mut Interpreter) -> Result<(), ElmError> {
+        loop {
+            self.wake_up();
+
+            let mut process = match self.ready.pop_front() {
+                Some(process) => process,
+                None => return Ok(()),
+            };
+
+            match self.step(i, &mut process)? {
+                Step::Done(result) => {
+                    if process.reported {
+                        self.completed.push((process.id, result));
+                    }
+                }
+                Step::Sleep(millis) => {
+                    let time = self.clock.now() + millis;
+                    let index = self.sleeping.iter().position(|(wake, _)| *wake > time).unwrap_or(self.sleeping.len());
+                    self.sleeping.insert(index, (time, process));
+                }
+            }
+        }
+    }
+
+    /// Waits until the next sleeping process can continue, returns false if there are none
+    pub fn wait(&mut self) -> bool {
+        match self.sleeping.first() {
+            Some((time, _)) => {
+                let time = *time;
+                if time > self.clock.now() {
+                    self.clock.wait_until(time);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs all the processes until they finish, waiting for the sleeping ones
+    pub fn run(&mut self, i: &mut Interpreter) -> Result<(), ElmError> {
+        loop {
+            self.run_ready(i)?;
+
+            if !self.wait() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn add_process(&mut self, task: Value, reported: bool) -> ProcessId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ready.push_back(Process { id, root: task, stack: vec![], reported });
+        id
+    }
+
+    fn wake_up(&mut self) {
+        let now = self.clock.now();
+
+        while self.sleeping.first().map(|(time, _)| *time <= now).unwrap_or(false) {
+            let (_, process) = self.sleeping.remove(0);
+            self.ready.push_back(process);
+        }
+    }
+
+    /// Runs a process until its task finishes or it goes to sleep
+    fn step(&mut self, i: &mut Interpreter, process: &mut Process) -> Result<Step, ElmError> {
+        loop {
+            let (name, values) = match &process.root {
+                Value::Adt(name, values, _) => (name.clone(), values.clone()),
+                _ => return Err(InterpreterError::ExpectedTask(process.root.clone()).wrap()),
+            };
+
+            match name.as_str() {
+                "Succeed" | "Fail" => {
+                    let success = name == "Succeed";
+                    let value = values[0].clone();
+
+                    // Handlers of the other kind are skipped, like in elm/core
+                    let callback = loop {
+                        match process.stack.pop() {
+                            Some(Handler::AndThen(callback)) if success => break Some(callback),
+                            Some(Handler::OnError(callback)) if !success => break Some(callback),
+                            Some(_) => {}
+                            None => break None,
+                        }
+                    };
+
+                    match callback {
+                        Some(callback) => process.root = i.apply_function(callback, &[value])?,
+                        None if success => return Ok(Step::Done(Ok(value))),
+                        None => return Ok(Step::Done(Err(value))),
+                    }
+                }
+                "AndThen" => {
+                    process.stack.push(Handler::AndThen(values[0].clone()));
+                    process.root = values[1].clone();
+                }
+                "OnError" => {
+                    process.stack.push(Handler::OnError(values[0].clone()));
+                    process.root = values[1].clone();
+                }
+                "Spawn" => {
+                    let id = self.add_process(values[0].clone(), false);
+                    process.root = succeed(process_id_value(id));
+                }
+                "Sleep" => {
+                    let millis = float_of(&values[0])? as f64;
+                    process.root = succeed(Value::Unit);
+                    return Ok(Step::Sleep(millis));
+                }
+                "Kill" => {
+                    let id = process_id_of(&values[0])?;
+                    self.kill(id);
+                    process.root = succeed(Value::Unit);
+                }
+                _ => return Err(InterpreterError::ExpectedTask(process.root.clone()).wrap()),
+            }
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new(Box::new(SystemClock::new()))
+    }
+}
+
+// The clock and the tasks don't have useful debug information
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scheduler {{ ready: {}, sleeping: {} }}", self.ready.len(), self.sleeping.len())
+    }
+}
+
+fn succeed(value: Value) -> Value {
+    task_value("Succeed", vec![value])
+}
+
+impl Runtime {
+    /// Replaces the clock used by `Process.sleep`, the default is the system clock
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.scheduler.set_clock(Box::new(clock));
+    }
+
+    /// Runs a task until it finishes, other processes run alongside it. The result is
+    /// `Ok` with the value of the task or `Err` with its error
+    pub fn run_task(&mut self, task: Value) -> Result<Result<Value, Value>, ElmError> {
+        let id = self.scheduler.spawn(task);
+
+        loop {
+            self.scheduler.run_ready(&mut self.interpreter)?;
+
+            if let Some(result) = self.scheduler.take_result(id) {
+                return Ok(result);
+            }
+
+            if !self.scheduler.is_alive(id) || !self.scheduler.wait() {
+                return Err(InterpreterError::ProcessKilled(id).wrap());
+            }
+        }
+    }
+
+    /// Evaluates an expression that returns a task and runs it, see `run_task`
+    pub fn run_task_expr(&mut self, expr: &str) -> Result<Result<Value, Value>, ElmError> {
+        let task = self.eval_expr(expr)?;
+        self.run_task(task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime_with_clock(clock: &VirtualClock) -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.set_clock(clock.clone());
+        runtime.import_module("Task").unwrap();
+        runtime.import_module("Process").unwrap();
+        runtime
+    }
+
+    #[test]
+    fn check_task_sequencing() {
+        let clock = VirtualClock::new();
+        let mut runtime = runtime_with_clock(&clock);
+
+        assert_eq!(
+            runtime.run_task_expr("Task.map2 (+) (Task.succeed 1) (Task.succeed 2)").unwrap(),
+            Ok(Value::Number(3))
+        );
+        assert_eq!(
+            runtime.run_task_expr("Task.sequence [Task.succeed 1, Task.fail \"oops\", Task.succeed 3]").unwrap(),
+            Err(Value::String("oops".to_string()))
+        );
+        assert_eq!(
+            runtime.run_task_expr("Task.fail 1 |> Task.map (\\a -> a + 1) |> Task.mapError (\\a -> a * 10)").unwrap(),
+            Err(Value::Number(10))
+        );
+        assert_eq!(
+            runtime.run_task_expr("Task.fail 1 |> Task.onError (\\a -> Task.succeed (a + 1)) |> Task.andThen (\\a -> Task.succeed (a * 10))").unwrap(),
+            Ok(Value::Number(20))
+        );
+    }
+
+    #[test]
+    fn check_sleep_uses_the_clock() {
+        let clock = VirtualClock::new();
+        let mut runtime = runtime_with_clock(&clock);
+
+        let result = runtime.run_task_expr("Process.sleep 1500 |> Task.andThen (\\_ -> Task.succeed \"awake\")").unwrap();
+
+        assert_eq!(result, Ok(Value::String("awake".to_string())));
+        assert_eq!(clock.now(), 1500.0);
+    }
+
+    #[test]
+    fn check_spawn_and_kill() {
+        let clock = VirtualClock::new();
+        let mut runtime = runtime_with_clock(&clock);
+        runtime.eval_statement("after ms value = Process.sleep ms |> Task.andThen (\\_ -> Task.succeed value)").unwrap();
+
+        // The spawned process is killed before it wakes up, the main task is not affected
+        let result = runtime.run_task_expr(
+            "Process.spawn (after 100 1) |> Task.andThen (\\id -> after 50 2 |> Task.andThen (\\v -> Process.kill id |> Task.map (\\_ -> v)))"
+        ).unwrap();
+
+        assert_eq!(result, Ok(Value::Number(2)));
+        assert_eq!(clock.now(), 50.0);
+
+        let task = runtime.eval_expr("after 10 3").unwrap();
+        let id = runtime.scheduler.spawn(task);
+        runtime.scheduler.kill(id);
+        assert!(!runtime.scheduler.has_processes());
+    }
+}
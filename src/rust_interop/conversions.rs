@@ -1,5 +1,9 @@
 use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use im::Vector;
+use serde_json::Value as JsonValue;
 
 use ast::Float;
 use ast::Int;
@@ -7,6 +11,7 @@ use errors::ElmError;
 use errors::InterpreterError;
 use errors::Wrappable;
 use types::Value;
+use util::persistent_list::List;
 
 // TODO convert to postfix function calls
 pub fn float_of(value: &Value) -> Result<f32, ElmError> {
@@ -56,15 +61,33 @@ pub fn str_of(value: &Value) -> Result<&str, ElmError> {
     }
 }
 
-pub fn list_of(value: &Value) -> Result<&[Value], ElmError> {
+pub fn list_of(value: &Value) -> Result<&List<Value>, ElmError> {
     match value {
-        Value::List(vec) => Ok(vec.as_slice()),
+        Value::List(list) => Ok(list),
         _ => {
             Err(InterpreterError::ExpectedList(value.clone()).wrap())
         }
     }
 }
 
+pub fn array_of(value: &Value) -> Result<&Vector<Value>, ElmError> {
+    match value {
+        Value::Array(array) => Ok(array),
+        _ => {
+            Err(InterpreterError::ExpectedArray(value.clone()).wrap())
+        }
+    }
+}
+
+pub fn json_of(value: &Value) -> Result<&JsonValue, ElmError> {
+    match value {
+        Value::Json(json) => Ok(json),
+        _ => {
+            Err(InterpreterError::ExpectedJson(value.clone()).wrap())
+        }
+    }
+}
+
 pub fn bool_of(value: &Value) -> Result<bool, ElmError> {
     match value {
         Value::Adt(name, _, _) => Ok(name == "True"),
@@ -95,13 +118,19 @@ pub fn convert_to_rust(value: &Value) -> Option<Box<Any>> {
             return Some(Box::new(val.clone()));
         }
         Value::List(items) => {
-            return Some(Box::new(items.clone()));
+            return Some(Box::new(items.to_vec()));
+        }
+        Value::Array(items) => {
+            return Some(Box::new(items.iter().cloned().collect::<Vec<_>>()));
+        }
+        Value::Json(json) => {
+            return Some(Box::new(json.as_ref().clone()));
         }
         Value::Tuple(items) => {
             return Some(Box::new(items.clone()));
         }
         Value::Record(entries) => {
-            return Some(Box::new(entries.clone()));
+            return Some(Box::new(entries.as_ref().clone()));
         }
         Value::Adt(_, _, _) => {
             return None;
@@ -109,6 +138,9 @@ pub fn convert_to_rust(value: &Value) -> Option<Box<Any>> {
         Value::Fun { .. } => {
             return None;
         }
+        Value::Opaque(_) => {
+            return None;
+        }
     }
 }
 
@@ -128,13 +160,16 @@ pub fn convert_from_rust(val: &Any) -> Option<Value> {
     if let Some(unwrapped) = val.downcast_ref::<char>() {
         return Some(Value::Char(*unwrapped));
     }
+    if let Some(unwrapped) = val.downcast_ref::<JsonValue>() {
+        return Some(Value::Json(Arc::new(unwrapped.clone())));
+    }
 
     if let Some(unwrapped) = val.downcast_ref::<Vec<Box<Any>>>() {
         let values = unwrapped.iter()
             .map(|t| convert_from_rust(t))
             .collect::<Option<Vec<Value>>>()?;
 
-        return Some(Value::List(values));
+        return Some(Value::List(values.into()));
     }
 
     if let Some(unwrapped) = val.downcast_ref::<HashMap<String, Box<Any>>>() {
@@ -144,7 +179,7 @@ pub fn convert_from_rust(val: &Any) -> Option<Value> {
             values.push((key.clone(), convert_from_rust(value)?));
         }
 
-        return Some(Value::Record(values));
+        return Some(Value::Record(Arc::new(values)));
     }
 
     if let Some(unwrapped) = val.downcast_ref::<(Box<Any>, Box<Any>)>() {
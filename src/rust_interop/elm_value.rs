@@ -0,0 +1,616 @@
+use std::any::Any;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use ast::Float;
+use ast::Int;
+use ast::Type;
+use errors::ElmError;
+use errors::InterpreterError;
+use errors::Wrappable;
+use rust_interop::conversions::bool_of;
+use rust_interop::conversions::char_of;
+use rust_interop::conversions::float_of;
+use rust_interop::conversions::int_of;
+use rust_interop::conversions::list_of;
+use rust_interop::conversions::string_of;
+use types::Adt;
+use types::AdtVariant;
+use types::Opaque;
+use types::Value;
+
+pub use elm_interpreter_derive::ElmValue;
+
+/// Rust types with an equivalent Elm type. It's implemented for the primitive types and it can
+/// be derived with `#[derive(ElmValue)]`, structs become records and enums become custom types
+pub trait ElmValue: Sized {
+    /// Elm type of the values
+    fn elm_type() -> Type;
+
+    /// Source of the Elm declarations needed to use the type, dependencies go first
+    fn elm_declarations() -> Vec<String> {
+        vec![]
+    }
+
+    fn to_elm(&self) -> Value;
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError>;
+}
+
+impl ElmValue for () {
+    fn elm_type() -> Type {
+        Type::Unit
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Unit
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match value {
+            Value::Unit => Ok(()),
+            _ => Err(InterpreterError::ImpossibleConversion.wrap()),
+        }
+    }
+}
+
+impl ElmValue for Int {
+    fn elm_type() -> Type {
+        Type::Tag("Int".to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Int(*self)
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        int_of(value)
+    }
+}
+
+impl ElmValue for Float {
+    fn elm_type() -> Type {
+        Type::Tag("Float".to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Float(*self)
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        float_of(value)
+    }
+}
+
+impl ElmValue for String {
+    fn elm_type() -> Type {
+        Type::Tag("String".to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        string_of(value)
+    }
+}
+
+impl ElmValue for char {
+    fn elm_type() -> Type {
+        Type::Tag("Char".to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Char(*self)
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        char_of(value)
+    }
+}
+
+impl ElmValue for bool {
+    fn elm_type() -> Type {
+        Type::Tag("Bool".to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        static BOOL_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+        let adt = BOOL_ADT.get_or_init(|| custom_type("Bool", &[], vec![("True", vec![]), ("False", vec![])]));
+
+        adt_value(adt, if *self { "True" } else { "False" }, vec![])
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        bool_of(value)
+    }
+}
+
+impl<T: ElmValue> ElmValue for Vec<T> {
+    fn elm_type() -> Type {
+        Type::Tag("List".to_string(), vec![T::elm_type()])
+    }
+
+    fn elm_declarations() -> Vec<String> {
+        T::elm_declarations()
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::List(self.iter().map(|item| item.to_elm()).collect::<Vec<_>>().into())
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        list_of(value)?.iter().map(|item| T::from_elm(item)).collect()
+    }
+}
+
+impl<T: ElmValue> ElmValue for Option<T> {
+    fn elm_type() -> Type {
+        Type::Tag("Maybe".to_string(), vec![T::elm_type()])
+    }
+
+    fn elm_declarations() -> Vec<String> {
+        T::elm_declarations()
+    }
+
+    fn to_elm(&self) -> Value {
+        maybe_value(self.as_ref().map(|value| value.to_elm()))
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match adt_variant(value, "Maybe")? {
+            ("Just", values) if values.len() == 1 => Ok(Some(T::from_elm(&values[0])?)),
+            ("Nothing", _) => Ok(None),
+            _ => Err(InterpreterError::ImpossibleConversion.wrap()),
+        }
+    }
+}
+
+impl<T: ElmValue, E: ElmValue> ElmValue for Result<T, E> {
+    fn elm_type() -> Type {
+        Type::Tag("Result".to_string(), vec![E::elm_type(), T::elm_type()])
+    }
+
+    fn elm_declarations() -> Vec<String> {
+        merge_declarations(vec![E::elm_declarations(), T::elm_declarations()])
+    }
+
+    fn to_elm(&self) -> Value {
+        static RESULT_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+        let adt = RESULT_ADT.get_or_init(|| {
+            custom_type("Result", &["error", "value"], vec![
+                ("Ok", vec![Type::Var("value".to_string())]),
+                ("Err", vec![Type::Var("error".to_string())]),
+            ])
+        });
+
+        match self {
+            Ok(value) => adt_value(adt, "Ok", vec![value.to_elm()]),
+            Err(error) => adt_value(adt, "Err", vec![error.to_elm()]),
+        }
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match adt_variant(value, "Result")? {
+            ("Ok", values) if values.len() == 1 => Ok(Ok(T::from_elm(&values[0])?)),
+            ("Err", values) if values.len() == 1 => Ok(Err(E::from_elm(&values[0])?)),
+            _ => Err(InterpreterError::ImpossibleConversion.wrap()),
+        }
+    }
+}
+
+impl<A: ElmValue, B: ElmValue> ElmValue for (A, B) {
+    fn elm_type() -> Type {
+        Type::Tuple(vec![A::elm_type(), B::elm_type()])
+    }
+
+    fn elm_declarations() -> Vec<String> {
+        merge_declarations(vec![A::elm_declarations(), B::elm_declarations()])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Tuple(vec![self.0.to_elm(), self.1.to_elm()])
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match value {
+            Value::Tuple(items) if items.len() == 2 => Ok((A::from_elm(&items[0])?, B::from_elm(&items[1])?)),
+            _ => Err(InterpreterError::ExpectedTuple(value.clone()).wrap()),
+        }
+    }
+}
+
+impl<A: ElmValue, B: ElmValue, C: ElmValue> ElmValue for (A, B, C) {
+    fn elm_type() -> Type {
+        Type::Tuple(vec![A::elm_type(), B::elm_type(), C::elm_type()])
+    }
+
+    fn elm_declarations() -> Vec<String> {
+        merge_declarations(vec![A::elm_declarations(), B::elm_declarations(), C::elm_declarations()])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Tuple(vec![self.0.to_elm(), self.1.to_elm(), self.2.to_elm()])
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match value {
+            Value::Tuple(items) if items.len() == 3 => {
+                Ok((A::from_elm(&items[0])?, B::from_elm(&items[1])?, C::from_elm(&items[2])?))
+            }
+            _ => Err(InterpreterError::ExpectedTuple(value.clone()).wrap()),
+        }
+    }
+}
+
+/// Host types that Elm code can store, compare and pass back but not inspect, like an entity or
+/// a texture handle. The type must be declared with `Runtime::register_opaque_type`
+pub trait OpaqueValue: Any + Send + Sync + Clone + PartialEq + Hash {
+    /// Name of the Elm type
+    fn elm_name() -> &'static str;
+}
+
+impl<T: OpaqueValue> ElmValue for T {
+    fn elm_type() -> Type {
+        Type::Tag(T::elm_name().to_string(), vec![])
+    }
+
+    fn to_elm(&self) -> Value {
+        Value::Opaque(Opaque::new(T::elm_name(), self.clone()))
+    }
+
+    fn from_elm(value: &Value) -> Result<Self, ElmError> {
+        match value {
+            Value::Opaque(opaque) if opaque.type_name == T::elm_name() => {
+                opaque.downcast_ref::<T>()
+                    .cloned()
+                    .ok_or_else(|| InterpreterError::ExpectedOpaque(T::elm_name().to_string(), value.clone()).wrap())
+            }
+            _ => Err(InterpreterError::ExpectedOpaque(T::elm_name().to_string(), value.clone()).wrap()),
+        }
+    }
+}
+
+type ToElm = Box<Fn(&Any) -> Option<Value> + Send + Sync>;
+
+/// Rust types registered with `Runtime::register_type` or `Runtime::register_serde_type`,
+/// each runtime has its own registry
+#[derive(Default)]
+pub struct TypeRegistry {
+    types: HashMap<TypeId, (Type, ToElm)>,
+}
+
+impl TypeRegistry {
+    /// Makes the type available to the conversions of the arguments of `call_fn`,
+    /// `ty` is the Elm type with the aliases expanded
+    pub fn register_elm_value<T: ElmValue + Any>(&mut self, ty: Type) {
+        self.register::<T>(ty, Box::new(|any| any.downcast_ref::<T>().map(|value| value.to_elm())));
+    }
+
+    /// Same as `register_elm_value` but with a custom conversion
+    pub fn register<T: Any>(&mut self, ty: Type, to_elm: ToElm) {
+        self.types.insert(TypeId::of::<T>(), (ty, to_elm));
+    }
+
+    /// Elm type of a registered Rust type
+    pub fn elm_type(&self, id: TypeId) -> Option<Type> {
+        self.types.get(&id).map(|(ty, _)| ty.clone())
+    }
+
+    /// Converts a value of a registered Rust type into an Elm value
+    pub fn to_elm(&self, value: &Any) -> Option<Value> {
+        let (_, to_elm) = self.types.get(&value.type_id())?;
+        to_elm(value)
+    }
+}
+
+// The conversions are closures without useful debug information
+impl fmt::Debug for TypeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let types = self.types.values().map(|(ty, _)| ty).collect::<Vec<_>>();
+        write!(f, "TypeRegistry {{ types: {:?} }}", types)
+    }
+}
+
+/// Creates a `Maybe` value, `Just value` or `Nothing`
+pub fn maybe_value(value: Option<Value>) -> Value {
+    static MAYBE_ADT: OnceLock<Arc<Adt>> = OnceLock::new();
+    let adt = MAYBE_ADT.get_or_init(|| {
+        custom_type("Maybe", &["a"], vec![("Just", vec![Type::Var("a".to_string())]), ("Nothing", vec![])])
+    });
+
+    match value {
+        Some(value) => adt_value(adt, "Just", vec![value]),
+        None => adt_value(adt, "Nothing", vec![]),
+    }
+}
+
+/// Creates the description of a custom type, used by the derived implementations
+pub fn custom_type(name: &str, vars: &[&str], variants: Vec<(&str, Vec<Type>)>) -> Arc<Adt> {
+    Arc::new(Adt {
+        name: name.to_string(),
+        types: vars.iter().map(|var| var.to_string()).collect(),
+        variants: variants.into_iter()
+            .map(|(name, types)| AdtVariant { name: name.to_string(), types })
+            .collect(),
+    })
+}
+
+pub fn adt_value(adt: &Arc<Adt>, variant: &str, values: Vec<Value>) -> Value {
+    Value::Adt(variant.to_string(), values, adt.clone())
+}
+
+/// Returns the variant name and the values of a custom type value
+pub fn adt_variant<'a>(value: &'a Value, type_name: &str) -> Result<(&'a str, &'a [Value]), ElmError> {
+    match value {
+        Value::Adt(variant, values, adt) if adt.name == type_name => Ok((variant.as_str(), values.as_slice())),
+        _ => Err(InterpreterError::ExpectedAdt(value.clone()).wrap()),
+    }
+}
+
+pub fn record_value(entries: Vec<(&str, Value)>) -> Value {
+    Value::Record(Arc::new(entries.into_iter().map(|(name, value)| (name.to_string(), value)).collect()))
+}
+
+pub fn record_field<'a>(value: &'a Value, field: &str) -> Result<&'a Value, ElmError> {
+    match value {
+        Value::Record(entries) => {
+            entries.iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, value)| value)
+                .ok_or_else(|| InterpreterError::RecordFieldNotFound(field.to_string(), value.clone()).wrap())
+        }
+        _ => Err(InterpreterError::ExpectedRecord(value.clone()).wrap()),
+    }
+}
+
+thread_local! {
+    /// Types whose declarations are being generated in this thread
+    static DECLARING: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Generates the declarations of a type, recursive types return nothing when they reach
+/// themselves again, their declaration is added by the outermost call
+pub fn declarations_of<F: FnOnce() -> Vec<String>>(name: &str, declarations: F) -> Vec<String> {
+    let first = DECLARING.with(|set| set.borrow_mut().insert(name.to_string()));
+    if !first {
+        return vec![];
+    }
+
+    let result = declarations();
+    DECLARING.with(|set| set.borrow_mut().remove(name));
+    result
+}
+
+/// Joins the declarations of several types, removing duplicates
+pub fn merge_declarations(lists: Vec<Vec<String>>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    for decl in lists.into_iter().flatten() {
+        if !result.contains(&decl) {
+            result.push(decl);
+        }
+    }
+
+    result
+}
+
+/// Elm source of a type used as argument of a variant
+pub fn argument_source(ty: &Type) -> String {
+    match ty {
+        Type::Tag(_, args) if !args.is_empty() => format!("({})", type_source(ty)),
+        Type::Fun(..) => format!("({})", type_source(ty)),
+        _ => type_source(ty),
+    }
+}
+
+/// Elm source of a type, with parentheses around the arguments that need them
+pub fn type_source(ty: &Type) -> String {
+    match ty {
+        Type::Tag(name, args) => {
+            let mut source = name.clone();
+
+            for arg in args {
+                source.push(' ');
+                source.push_str(&argument_source(arg));
+            }
+
+            source
+        }
+        Type::Fun(a, b) => {
+            match a.as_ref() {
+                Type::Fun(..) => format!("({}) -> {}", type_source(a), type_source(b)),
+                _ => format!("{} -> {}", type_source(a), type_source(b)),
+            }
+        }
+        Type::Tuple(items) => {
+            format!("( {} )", items.iter().map(type_source).collect::<Vec<_>>().join(", "))
+        }
+        Type::Record(entries) => {
+            let entries = entries.iter()
+                .map(|(name, ty)| format!("{} : {}", name, type_source(ty)))
+                .collect::<Vec<_>>();
+
+            format!("{{ {} }}", entries.join(", "))
+        }
+        _ => ty.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::InteropError;
+    use rust_interop::function_register::RegisterFn;
+    use Runtime;
+
+    use super::*;
+
+    #[derive(ElmValue, Clone, Debug, PartialEq)]
+    struct Point {
+        x: Int,
+        y: Int,
+    }
+
+    #[derive(ElmValue, Clone, Debug, PartialEq)]
+    enum Shape {
+        Circle(Point, Float),
+        Polygon { points: Vec<Point>, closed: bool },
+        Empty,
+    }
+
+    #[derive(ElmValue, Clone, Debug, PartialEq)]
+    struct Scene {
+        shapes: Vec<Shape>,
+        selected_shape: Option<Int>,
+    }
+
+    fn scene() -> Scene {
+        Scene {
+            shapes: vec![
+                Shape::Circle(Point { x: 1, y: 2 }, 3.0),
+                Shape::Polygon { points: vec![Point { x: 0, y: 0 }, Point { x: 4, y: 0 }], closed: true },
+                Shape::Empty,
+            ],
+            selected_shape: Some(1),
+        }
+    }
+
+    #[test]
+    fn check_derived_declarations() {
+        assert_eq!(Scene::elm_declarations(), vec![
+            "type alias Point = { x : Int, y : Int }".to_string(),
+            "type Shape = Circle Point Float | Polygon { points : List Point, closed : Bool } | Empty".to_string(),
+            "type alias Scene = { shapes : List Shape, selectedShape : Maybe Int }".to_string(),
+        ]);
+    }
+
+    #[derive(ElmValue, Clone, Debug, PartialEq)]
+    enum Tree {
+        Leaf,
+        Node(Vec<Tree>),
+    }
+
+    #[test]
+    fn check_recursive_declarations() {
+        assert_eq!(Tree::elm_declarations(), vec![
+            "type Tree = Leaf | Node (List Tree)".to_string(),
+        ]);
+
+        let tree = Tree::Node(vec![Tree::Leaf, Tree::Node(vec![])]);
+        assert_eq!(Tree::from_elm(&tree.to_elm()).unwrap(), tree);
+
+        let mut runtime = Runtime::new();
+        runtime.register_type::<Tree>().unwrap();
+        let value = runtime.eval_expr("Node [ Leaf, Node [] ]").unwrap();
+        assert_eq!(Tree::from_elm(&value).unwrap(), tree);
+    }
+
+    #[test]
+    fn check_derived_round_trip() {
+        let scene = scene();
+        assert_eq!(Scene::from_elm(&scene.to_elm()).unwrap(), scene);
+        assert!(Point::from_elm(&Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn check_register_domain_types() {
+        let mut runtime = Runtime::new();
+        runtime.register_type::<Point>().unwrap();
+        runtime.register_type::<Scene>().unwrap();
+
+        runtime.register_fn("shapeCount", |scene: Scene| scene.shapes.len() as Int).unwrap();
+        runtime.register_fn("origin", |_: ()| Point { x: 0, y: 0 }).unwrap();
+
+        runtime.eval_statement("scene = { shapes = [ Circle (origin ()) 1.5, Empty ], selectedShape = Nothing }").unwrap();
+        assert_eq!(runtime.eval_expr("shapeCount scene").unwrap(), Value::Int(2));
+
+        let value = runtime.eval_expr("scene").unwrap();
+        assert_eq!(Scene::from_elm(&value).unwrap(), Scene {
+            shapes: vec![Shape::Circle(Point { x: 0, y: 0 }, 1.5), Shape::Empty],
+            selected_shape: None,
+        });
+    }
+
+    #[test]
+    fn check_registry_per_runtime() {
+        use rust_interop::function_call::CallFn;
+
+        let mut runtime = Runtime::new();
+        runtime.register_type::<Point>().unwrap();
+        runtime.eval_statement("getX : Point -> Int\ngetX p = p.x").unwrap();
+
+        let x = runtime.call_fn("getX", (Point { x: 3, y: 4 },)).unwrap();
+        assert_eq!(x.downcast_ref::<Int>(), Some(&3));
+
+        // Other runtimes don't know the type
+        let mut other = Runtime::new();
+        other.eval_statement("getX : { x : Int, y : Int } -> Int\ngetX p = p.x").unwrap();
+        assert_eq!(other.call_fn("getX", (Point { x: 3, y: 4 },)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+    }
+
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    struct Entity(u32);
+
+    impl OpaqueValue for Entity {
+        fn elm_name() -> &'static str {
+            "Entity"
+        }
+    }
+
+    #[test]
+    fn check_opaque_values() {
+        use rust_interop::function_register::RegisterClosure;
+
+        let mut runtime = Runtime::new();
+        runtime.register_opaque_type::<Entity>().unwrap();
+
+        let mut next_id = 0;
+        runtime.register_closure("spawn", move |_: ()| {
+            next_id += 1;
+            Entity(next_id)
+        }).unwrap();
+        runtime.register_fn("entityId", |entity: Entity| entity.0 as Int).unwrap();
+
+        runtime.eval_statement("player : Entity\nplayer = spawn ()").unwrap();
+        runtime.eval_statement("enemy = spawn ()").unwrap();
+
+        assert_eq!(runtime.eval_expr("player").unwrap(), Entity(1).to_elm());
+        assert_eq!(runtime.eval_expr("entityId enemy").unwrap(), Value::Int(2));
+        assert_eq!(runtime.eval_expr("player == enemy").unwrap(), false.to_elm());
+        assert_eq!(runtime.eval_expr("[ player, enemy ] == [ player, enemy ]").unwrap(), true.to_elm());
+        assert_eq!(runtime.eval_expr("Debug.toString player").unwrap(), Value::String("<Entity>".to_string()));
+        assert!(runtime.eval_expr("entityId 1").is_err());
+        assert!(Entity::from_elm(&Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn check_compare_opaque_values() {
+        use rust_interop::function_register::RegisterClosure;
+
+        let mut runtime = Runtime::new();
+        runtime.register_opaque_type::<Entity>().unwrap();
+
+        let mut next_id = 0;
+        runtime.register_closure("spawn", move |_: ()| {
+            next_id += 1;
+            Entity(next_id)
+        }).unwrap();
+
+        runtime.eval_statement("player = spawn ()").unwrap();
+        runtime.eval_statement("enemy = spawn ()").unwrap();
+
+        let order = runtime.eval_expr("compare player enemy").unwrap();
+        let reverse = runtime.eval_expr("compare enemy player").unwrap();
+
+        assert_ne!(order, reverse);
+        assert_eq!(runtime.eval_expr("compare player player").unwrap(), runtime.eval_expr("EQ").unwrap());
+        assert_eq!(runtime.eval_expr("player < enemy").unwrap(), runtime.eval_expr("enemy > player").unwrap());
+    }
+}
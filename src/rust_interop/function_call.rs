@@ -1,30 +1,30 @@
 use std::any::Any;
 use std::any::TypeId;
 
-use rust_interop::InteropError;
+use errors::ElmError;
 
 pub trait FunctionCall {
-    fn call_fn_raw(&mut self, name: &str, args: Vec<&mut Any>, arg_types: Vec<TypeId>) -> Result<Box<Any>, InteropError>;
+    fn call_fn_raw(&mut self, name: &str, args: Vec<&mut Any>, arg_types: Vec<TypeId>) -> Result<Box<Any>, ElmError>;
 }
 
 pub trait CallFn<ARGS> {
-    fn call_fn(&mut self, name: &str, args: ARGS) -> Result<Box<Any>, InteropError>;
+    fn call_fn(&mut self, name: &str, args: ARGS) -> Result<Box<Any>, ElmError>;
 }
 
 macro_rules! impl_args {
     ($($p:ident),*) => {
-        impl<'a, T, $($p),*> CallFn<($(&'a mut $p,)*)> for T
+        impl<T, $($p),*> CallFn<($($p,)*)> for T
         where
             T: FunctionCall,
-            $($p: Any + Clone),*
+            $($p: Any),*
         {
 
             #[allow(non_snake_case, dead_code, unused_mut, unused)]
-            fn call_fn(&mut self, name: &str, args: ($(&'a mut $p,)*)) -> Result<Box<Any>, InteropError> {
-                let ($($p,)*) = args;
+            fn call_fn(&mut self, name: &str, args: ($($p,)*)) -> Result<Box<Any>, ElmError> {
+                let ($(mut $p,)*) = args;
 
                 let mut values = Vec::new();
-                $(values.push($p as &mut Any);)*
+                $(values.push(&mut $p as &mut Any);)*
 
                 let mut types = Vec::new();
                 $(types.push(TypeId::of::<$p>());)*
@@ -53,7 +53,7 @@ mod tests {
     struct Test {}
 
     impl FunctionCall for Test {
-        fn call_fn_raw(&mut self, name: &str, args: Vec<&mut Any>, arg_types: Vec<TypeId>) -> Result<Box<Any>, InteropError> {
+        fn call_fn_raw(&mut self, name: &str, args: Vec<&mut Any>, arg_types: Vec<TypeId>) -> Result<Box<Any>, ElmError> {
             assert_eq!(name, "test_function");
             println!("{:?}", args);
             println!("{:?}", arg_types);
@@ -65,8 +65,8 @@ mod tests {
     fn call_function() {
         let mut e = Test{};
 
-        e.call_fn("test_function", (&mut 1,)).unwrap();
-        e.call_fn("test_function", (&mut 1, &mut 2)).unwrap();
-        e.call_fn("test_function", (&mut 1, &mut 2, &mut 3)).unwrap();
+        e.call_fn("test_function", (1,)).unwrap();
+        e.call_fn("test_function", (1, 2)).unwrap();
+        e.call_fn("test_function", (1, 2, 3)).unwrap();
     }
 }
@@ -4,74 +4,80 @@
  */
 
 use std::any::Any;
-use std::any::TypeId;
+use std::sync::Mutex;
 
+use ast::Type;
 use errors::ElmError;
-use errors::InteropError;
-use errors::Wrappable;
 use interpreter::Interpreter;
-use rust_interop::FnAny;
+use rust_interop::elm_value::ElmValue;
+use types::ElmClosure;
+use types::Value;
 
 pub trait FunctionRegister {
-    fn register_fn_raw(&mut self, name: String, args: Vec<TypeId>, ret: TypeId, boxed: Box<FnAny>) -> Result<(), ElmError>;
+    fn register_closure_raw(&mut self, name: String, args: Vec<Type>, ret: Type, boxed: Box<ElmClosure>) -> Result<(), ElmError>;
 }
 
+/// Registers Rust functions, the Elm types of the arguments and the return value are taken from
+/// their `ElmValue` implementation
 pub trait RegisterFn<FN, ARGS, RET> {
     fn register_fn(&mut self, name: &str, f: FN) -> Result<(), ElmError>;
 }
 
+/// Registers closures that can keep state between calls, the Elm types of the arguments and
+/// the return value are taken from their `ElmValue` implementation, so they can be `Option`,
+/// `Vec`, tuples, `Result` or derived types
+pub trait RegisterClosure<FN, ARGS, RET> {
+    fn register_closure(&mut self, name: &str, f: FN) -> Result<(), ElmError>;
+}
+
+/// Same as `RegisterClosure`, but the first argument of the closure is a mutable reference to
+/// the value of type `CTX` in the host context, see `Runtime::set_context`
+pub trait RegisterContextFn<FN, CTX, ARGS, RET> {
+    fn register_context_fn(&mut self, name: &str, f: FN) -> Result<(), ElmError>;
+}
+
 pub struct Ref<A>(A);
 
 pub struct Mut<A>(A);
 
-macro_rules! count_args {
-    () => {0usize};
-    ($head:ident $($tail:ident)*) => {1usize + count_args!($($tail)*)};
+// Expression used to pass a converted argument to the function, by value or by reference
+macro_rules! pass_arg {
+    (val, $arg:ident) => {$arg};
+    (ref, $arg:ident) => {&$arg};
+    (mut, $arg:ident) => {&mut $arg};
 }
 
 macro_rules! def_register {
     () => {
         def_register!(imp);
     };
-    (imp $($par:ident => $mark:ty => $param:ty => $clone:expr),*) => {
+    (imp $($par:ident => $mark:ty => $param:ty => $pass:ident),*) => {
         impl<$($par,)* FN, RET, T> RegisterFn<FN, ($($mark,)*), RET> for T
         where
-            $($par: Any + Clone,)*
+            $($par: ElmValue,)*
             FN: Fn($($param),*) -> RET + 'static + Sync + Send,
-            RET: Any,
+            RET: ElmValue,
             T: FunctionRegister
         {
-            #[allow(non_snake_case, dead_code, unused_mut, unused)]
+            #[allow(non_snake_case, unused_mut, unused_variables)]
             fn register_fn(&mut self, name: &str, f: FN) -> Result<(), ElmError> {
-                let fun = move |_: &mut Interpreter, mut args: Vec<&mut Any>| {
-                    // Check for length at the beginning to avoid
-                    // per-element bound checks.
-                    let argc = count_args!($($par)*);
-
-                    if args.len() != argc {
-                        return Err(InteropError::FunctionArgMismatch.wrap());
-                    }
-
-                    let mut drain = args.drain(..);
-                    $(
-                    // Downcast every element, return in case of a type mismatch
-                    let $par = ((*drain.next().unwrap()).downcast_mut() as Option<&mut $par>)
-                        .ok_or(InteropError::FunctionArgMismatch.wrap())?;
-                    )*
-
-                    // Call the user-supplied function using ($clone) to
-                    // potentially clone the value, otherwise pass the reference.
-                    Ok(Box::new(f($(($clone)($par)),*)) as Box<Any>)
+                let fun = move |_: &mut Interpreter, args: &[Value]| {
+                    let mut args = args.iter();
+                    $(let mut $par = <$par as ElmValue>::from_elm(args.next().unwrap())?;)*
+
+                    Ok(f($(pass_arg!($pass, $par)),*).to_elm())
                 };
-                self.register_fn_raw(name.to_owned(), vec![$(TypeId::of::<$par>()),*], TypeId::of::<RET>(), Box::new(fun))
+
+                let args = vec![$(<$par as ElmValue>::elm_type()),*];
+                self.register_closure_raw(name.to_owned(), args, RET::elm_type(), Box::new(fun))
             }
         }
 
     };
     ($p0:ident $(, $p:ident)*) => {
-        def_register!(imp $p0 => $p0 => $p0 => Clone::clone $(, $p => $p => $p => Clone::clone)*);
-        def_register!(imp $p0 => Ref<$p0> => &$p0 => |x| { x } $(, $p => $p => $p => Clone::clone)*);
-        def_register!(imp $p0 => Mut<$p0> => &mut $p0 => |x| { x } $(, $p => $p => $p => Clone::clone)*);
+        def_register!(imp $p0 => $p0 => $p0 => val $(, $p => $p => $p => val)*);
+        def_register!(imp $p0 => Ref<$p0> => &$p0 => ref $(, $p => $p => $p => val)*);
+        def_register!(imp $p0 => Mut<$p0> => &mut $p0 => mut $(, $p => $p => $p => val)*);
 
         def_register!($($p),*);
     };
@@ -80,31 +86,135 @@ macro_rules! def_register {
 #[cfg_attr(rustfmt, rustfmt_skip)]
 def_register!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S);
 
+macro_rules! def_register_closure {
+    () => {};
+    ($p0:ident $(, $p:ident)*) => {
+        impl<$p0, $($p,)* FN, RET, T> RegisterClosure<FN, ($p0, $($p,)*), RET> for T
+        where
+            $p0: ElmValue, $($p: ElmValue,)*
+            FN: FnMut($p0, $($p),*) -> RET + 'static + Send,
+            RET: ElmValue,
+            T: FunctionRegister
+        {
+            #[allow(non_snake_case)]
+            fn register_closure(&mut self, name: &str, f: FN) -> Result<(), ElmError> {
+                // The closure can mutate its state, calls are serialized by the lock
+                let f = Mutex::new(f);
+                let fun = move |_: &mut Interpreter, args: &[Value]| {
+                    let mut args = args.iter();
+                    let $p0 = <$p0 as ElmValue>::from_elm(args.next().unwrap())?;
+                    $(let $p = <$p as ElmValue>::from_elm(args.next().unwrap())?;)*
+
+                    let mut f = f.lock().unwrap();
+                    Ok((&mut *f)($p0, $($p),*).to_elm())
+                };
+
+                let args = vec![<$p0 as ElmValue>::elm_type(), $(<$p as ElmValue>::elm_type()),*];
+                self.register_closure_raw(name.to_owned(), args, RET::elm_type(), Box::new(fun))
+            }
+        }
+
+        impl<$p0, $($p,)* FN, CTX, RET, T> RegisterContextFn<FN, CTX, ($p0, $($p,)*), RET> for T
+        where
+            $p0: ElmValue, $($p: ElmValue,)*
+            FN: FnMut(&mut CTX, $p0, $($p),*) -> RET + 'static + Send,
+            CTX: Any + Send,
+            RET: ElmValue,
+            T: FunctionRegister
+        {
+            #[allow(non_snake_case)]
+            fn register_context_fn(&mut self, name: &str, f: FN) -> Result<(), ElmError> {
+                let f = Mutex::new(f);
+                let fun = move |i: &mut Interpreter, args: &[Value]| {
+                    let mut args = args.iter();
+                    let $p0 = <$p0 as ElmValue>::from_elm(args.next().unwrap())?;
+                    $(let $p = <$p as ElmValue>::from_elm(args.next().unwrap())?;)*
+
+                    let mut f = f.lock().unwrap();
+                    let result = i.context().with(|context: &mut CTX| (&mut *f)(context, $p0, $($p),*))?;
+                    Ok(result.to_elm())
+                };
+
+                let args = vec![<$p0 as ElmValue>::elm_type(), $(<$p as ElmValue>::elm_type()),*];
+                self.register_closure_raw(name.to_owned(), args, RET::elm_type(), Box::new(fun))
+            }
+        }
+
+        def_register_closure!($($p),*);
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+def_register_closure!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S);
 
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use ast::Int;
+    use rust_interop::elm_value::maybe_value;
+
     use super::*;
 
-    struct Test {}
+    #[derive(Default)]
+    struct Test {
+        registered: Vec<(String, Vec<Type>, Type, Box<ElmClosure>)>,
+    }
 
     impl FunctionRegister for Test {
-        fn register_fn_raw(&mut self, name: String, args: Vec<TypeId>, ret: TypeId, _boxed: Box<FnAny>) -> Result<(), ElmError> {
-            assert_eq!(name, "test_function");
-            assert_eq!(args, vec![TypeId::of::<i32>()]);
-            assert_eq!(ret, TypeId::of::<i32>());
+        fn register_closure_raw(&mut self, name: String, args: Vec<Type>, ret: Type, boxed: Box<ElmClosure>) -> Result<(), ElmError> {
+            self.registered.push((name, args, ret, boxed));
             Ok(())
         }
     }
 
+    fn int_type() -> Type {
+        Type::Tag("Int".to_string(), vec![])
+    }
+
     #[test]
     fn register_function() {
-        let mut e = Test{};
-
+        let mut e = Test::default();
         e.register_fn("test_function", test_function).unwrap();
+        e.register_fn("length", |text: &String| text.len() as Int).unwrap();
+
+        let (name, args, ret, fun) = &e.registered[0];
+        assert_eq!(name, "test_function");
+        assert_eq!(args, &vec![int_type()]);
+        assert_eq!(ret, &int_type());
+        assert_eq!(fun(&mut Interpreter::new(), &[Value::Int(4)]), Ok(Value::Int(4)));
+
+        let (_, args, _, fun) = &e.registered[1];
+        assert_eq!(args, &vec![Type::Tag("String".to_string(), vec![])]);
+        assert_eq!(fun(&mut Interpreter::new(), &[Value::String("abc".to_string())]), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn register_closure() {
+        let mut e = Test::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        e.register_closure("test_closure", move |list: Vec<i32>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            list.first().cloned()
+        }).unwrap();
+
+        let (name, args, ret, fun) = &e.registered[0];
+        assert_eq!(name, "test_closure");
+        assert_eq!(args, &vec![Type::Tag("List".to_string(), vec![int_type()])]);
+        assert_eq!(ret, &Type::Tag("Maybe".to_string(), vec![int_type()]));
+
+        let list = Value::List(vec![Value::Int(7), Value::Int(8)].into());
+        assert_eq!(fun(&mut Interpreter::new(), &[list.clone()]), Ok(maybe_value(Some(Value::Int(7)))));
+        assert_eq!(fun(&mut Interpreter::new(), &[list]), Ok(maybe_value(Some(Value::Int(7)))));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
     }
 
     fn test_function(a: i32) -> i32 {
         a
     }
-}
\ No newline at end of file
+}
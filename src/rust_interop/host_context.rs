@@ -0,0 +1,143 @@
+use std::any::Any;
+use std::any::type_name;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use errors::ElmError;
+use errors::InteropError;
+use errors::Wrappable;
+use Runtime;
+
+/// Values owned by the host, like a database handle or a game world, that registered closures
+/// can borrow mutably. There is at most one value of each type, copies of the interpreter
+/// share the same values
+#[derive(Clone, Debug, Default)]
+pub struct HostContext {
+    values: Arc<Mutex<HashMap<TypeId, Box<Any + Send>>>>,
+}
+
+impl HostContext {
+    /// Adds a value, returns the previous value of the same type
+    pub fn insert<C: Any + Send>(&self, value: C) -> Option<C> {
+        self.values.lock().unwrap()
+            .insert(TypeId::of::<C>(), Box::new(value))
+            .and_then(|old| downcast(old))
+    }
+
+    /// Removes the value of type `C`
+    pub fn remove<C: Any + Send>(&self) -> Option<C> {
+        self.values.lock().unwrap()
+            .remove(&TypeId::of::<C>())
+            .and_then(|old| downcast(old))
+    }
+
+    /// Runs `f` with the value of type `C`, the context is locked until `f` returns
+    pub fn with<C: Any + Send, R, F: FnOnce(&mut C) -> R>(&self, f: F) -> Result<R, ElmError> {
+        let mut values = self.values.lock().unwrap();
+
+        values.get_mut(&TypeId::of::<C>())
+            .and_then(|value| value.downcast_mut::<C>())
+            .map(f)
+            .ok_or_else(|| InteropError::ContextNotFound(type_name::<C>().to_string()).wrap())
+    }
+}
+
+fn downcast<C: Any>(value: Box<Any + Send>) -> Option<C> {
+    let value: Box<Any> = value;
+    value.downcast::<C>().ok().map(|value| *value)
+}
+
+impl Runtime {
+    /// Makes a value available to the closures registered with `register_context_fn`,
+    /// returns the previous value of the same type
+    pub fn set_context<C: Any + Send>(&mut self, value: C) -> Option<C> {
+        self.interpreter.context().insert(value)
+    }
+
+    /// Removes the value of type `C` from the context
+    pub fn take_context<C: Any + Send>(&mut self) -> Option<C> {
+        self.interpreter.context().remove()
+    }
+
+    /// Runs `f` with the value of type `C` in the context
+    pub fn with_context<C: Any + Send, R, F: FnOnce(&mut C) -> R>(&mut self, f: F) -> Result<R, ElmError> {
+        self.interpreter.context().with(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Int;
+    use rust_interop::elm_value::ElmValue;
+    use rust_interop::function_register::RegisterClosure;
+    use rust_interop::function_register::RegisterContextFn;
+    use types::Value;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct World {
+        players: Vec<(String, Int)>,
+    }
+
+    #[test]
+    fn check_context_fn() {
+        let mut runtime = Runtime::new();
+        runtime.set_context(World::default());
+
+        runtime.register_context_fn("addPlayer", |world: &mut World, name: String, score: Int| {
+            world.players.push((name, score));
+            world.players.len() as Int
+        }).unwrap();
+
+        runtime.register_context_fn("score", |world: &mut World, name: String| -> Result<Int, String> {
+            world.players.iter()
+                .find(|(player, _)| player == &name)
+                .map(|(_, score)| *score)
+                .ok_or_else(|| format!("{} is not playing", name))
+        }).unwrap();
+
+        assert_eq!(runtime.eval_expr("addPlayer \"ana\" 3").unwrap(), Value::Int(1));
+        assert_eq!(runtime.eval_expr("addPlayer \"bob\" 5").unwrap(), Value::Int(2));
+        assert_eq!(runtime.eval_expr("score \"bob\"").unwrap(), Ok::<Int, String>(5).to_elm());
+        assert_eq!(
+            runtime.eval_expr("Result.withDefault 0 (score \"eve\")").unwrap(),
+            Value::Int(0)
+        );
+        assert_eq!(
+            runtime.eval_expr("case score \"eve\" of\n  Err msg -> msg\n  Ok _ -> \"\"").unwrap(),
+            Value::String("eve is not playing".to_string())
+        );
+
+        let world = runtime.take_context::<World>().unwrap();
+        assert_eq!(world.players, vec![("ana".to_string(), 3), ("bob".to_string(), 5)]);
+
+        assert_eq!(
+            runtime.eval_expr("addPlayer \"eve\" 1").err(),
+            Some(InteropError::ContextNotFound(type_name::<World>().to_string()).wrap())
+        );
+    }
+
+    #[test]
+    fn check_closure_state() {
+        let mut runtime = Runtime::new();
+        let mut total = 0;
+
+        runtime.register_closure("accumulate", move |values: Vec<Int>, bonus: Option<Int>| {
+            total += values.iter().sum::<Int>() + bonus.unwrap_or(0);
+            (total, values.len() as Int)
+        }).unwrap();
+
+        assert_eq!(
+            runtime.eval_expr("accumulate [ 1, 2 ] Nothing").unwrap(),
+            Value::Tuple(vec![Value::Int(3), Value::Int(2)])
+        );
+        assert_eq!(
+            runtime.eval_expr("Tuple.first (accumulate [ 4 ] (Just 10))").unwrap(),
+            Value::Int(17)
+        );
+        assert!(runtime.eval_expr("accumulate [ \"a\" ] Nothing").is_err());
+    }
+}
@@ -2,68 +2,72 @@ use std::any::Any;
 use std::any::TypeId;
 use std::sync::Arc;
 
+use serde_json::Value as JsonValue;
+
+use analyzer::is_assignable;
 use ast::Float;
 use ast::Int;
 use ast::Type;
 use errors::*;
 use errors::ElmError;
-use interpreter::Interpreter;
 use Runtime;
+use rust_interop::conversions::bool_of;
+use rust_interop::conversions::char_of;
 use rust_interop::conversions::convert_from_rust;
-use rust_interop::conversions::convert_to_rust;
+use rust_interop::conversions::float_of;
+use rust_interop::conversions::int_of;
+use rust_interop::conversions::json_of;
+use rust_interop::conversions::string_of;
+use rust_interop::elm_value::ElmValue;
+use rust_interop::elm_value::OpaqueValue;
+use rust_interop::function_call::FunctionCall;
 use rust_interop::function_register::FunctionRegister;
+use types::ClosureFunc;
+use types::ElmClosure;
 use types::Function;
 use types::next_fun_id;
 use types::Value;
-use types::WrapperFunc;
 use util::build_fun_type;
+use util::create_vec_inv;
 
 pub mod conversions;
 pub mod function_register;
 pub mod function_call;
+pub mod elm_value;
+pub mod serde_value;
+pub mod host_context;
+
+
+impl Runtime {
+    /// Adds the Elm declarations of a type that implements `ElmValue`, like the ones created
+    /// with `#[derive(ElmValue)]`, so the type can be used in the Elm code and in the arguments
+    /// and return values of `register_fn`, `call_fn` and ports
+    pub fn register_type<T: ElmValue + Any>(&mut self) -> Result<(), ElmError> {
+        for declaration in T::elm_declarations() {
+            self.eval_statement(&declaration)?;
+        }
 
-
-pub type FnAny = Fn(&mut Interpreter, Vec<&mut Any>) -> Result<Box<Any>, ElmError> + Sync + Send;
-
-struct FnWrapper {
-    fun: Box<FnAny>
-}
-
-pub fn call_function(this: &WrapperFunc, i: &mut Interpreter, args: &Vec<Value>) -> Result<Value, ElmError> {
-    let mut rust_values = vec![];
-
-    for arg in args {
-        let value = convert_to_rust(arg)
-            .ok_or_else(|| InterpreterError::ImpossibleConversion.wrap())?;
-
-        rust_values.push(value);
-    }
-
-    let mut arguments: Vec<&mut Any> = vec![];
-
-    for val in rust_values.iter_mut() {
-        arguments.push(val);
+        let ty = self.analyzer.check_type((0, 0), T::elm_type())?;
+        self.types.register_elm_value::<T>(ty);
+        Ok(())
     }
 
-    let result: Result<Box<Any>, ElmError> = (this.fun)(i, arguments);
-    match result {
-        Ok(boxed) => {
-            convert_from_rust(&*boxed)
-                .ok_or_else(|| InterpreterError::ImpossibleConversion.wrap())
-        }
-        Err(e) => Err(e)
+    /// Declares the Elm type of an `OpaqueValue`, so it can be used in type annotations and in
+    /// the arguments and return values of registered functions
+    pub fn register_opaque_type<T: OpaqueValue>(&mut self) -> Result<(), ElmError> {
+        self.analyzer.add_opaque_type(T::elm_name());
+        self.register_type::<T>()
     }
 }
 
 impl FunctionRegister for Runtime {
-    fn register_fn_raw(&mut self, name: String, args: Vec<TypeId>, ret: TypeId, boxed: Box<FnAny>) -> Result<(), ElmError> {
+    fn register_closure_raw(&mut self, name: String, args: Vec<Type>, ret: Type, boxed: Box<ElmClosure>) -> Result<(), ElmError> {
         let len = args.len() as u32;
-        let ty = type_from_ids(args, ret)
-            .map_err(|e| e.wrap())?;
+        let ty = self.analyzer.check_type((0, 0), build_fun_type(&create_vec_inv(&args, ret)))?;
 
-        let function = Arc::new(Function::Wrapper(
+        let function = Arc::new(Function::Closure(
             next_fun_id(),
-            WrapperFunc { name: name.to_string(), fun: boxed },
+            ClosureFunc { name: name.to_string(), fun: boxed },
             ty.clone(),
         ));
 
@@ -79,33 +83,102 @@ impl FunctionRegister for Runtime {
     }
 }
 
-fn type_from_ids(args: Vec<TypeId>, ret: TypeId) -> Result<Type, InteropError> {
-    let mut types = vec![];
+impl FunctionCall for Runtime {
+    /// Calls a function by name, like `Main.update`, all the arguments must be provided.
+    /// Elm values can be passed as `Value`, other arguments are checked against the type of the
+    /// function. The result is converted to a Rust type if the return type is a primitive type,
+    /// otherwise the `Value` is returned
+    fn call_fn_raw(&mut self, name: &str, args: Vec<&mut Any>, arg_types: Vec<TypeId>) -> Result<Box<Any>, ElmError> {
+        let function = self.find_function(name)
+            .ok_or_else(|| InteropError::FunctionNotFound(name.to_string()).wrap())?;
+
+        let arity = match &function {
+            Value::Fun { arg_count, args, .. } => *arg_count as usize - args.len(),
+            _ => 0,
+        };
 
-    for (arg_index, arg) in args.into_iter().enumerate() {
-        match type_from_id(arg) {
-            Some(ty) => {
-                types.push(ty);
-            }
-            None => {
-                return Err(InteropError::FunRegistrationUnknownTypeArg(arg_index));
+        if arity != args.len() {
+            return Err(InteropError::FunctionArgMismatch.wrap());
+        }
+
+        // The type of a partially applied function only has the missing arguments
+        let mut ty = function.get_type();
+        let mut values = vec![];
+
+        for (arg, id) in args.into_iter().zip(arg_types) {
+            let (arg_type, rest) = match ty {
+                Type::Fun(arg_type, rest) => (*arg_type, *rest),
+                _ => return Err(InteropError::FunctionArgMismatch.wrap()),
+            };
+
+            values.push(self.argument_value(arg, id, &arg_type)?);
+            ty = rest;
+        }
+
+        let result = self.interpreter.apply_function(function, &values)?;
+
+        result_to_rust(&result, &ty)
+            .map_err(|_| InteropError::MismatchOutputType.wrap())
+    }
+}
+
+impl Runtime {
+    fn find_function(&mut self, name: &str) -> Option<Value> {
+        match name.rfind('.') {
+            Some(index) => {
+                self.runtime_modules.get(&name[..index])
+                    .and_then(|module| module.definitions.get(&name[index + 1..]))
+                    .cloned()
             }
+            None => self.interpreter.find_value(name),
         }
     }
 
-    match type_from_id(ret) {
-        Some(ty) => {
-            types.push(ty);
+    fn argument_value(&mut self, arg: &mut Any, id: TypeId, expected: &Type) -> Result<Value, ElmError> {
+        if let Some(value) = arg.downcast_ref::<Value>() {
+            return if is_assignable(expected, &value.get_type()) {
+                Ok(value.clone())
+            } else {
+                Err(InteropError::FunctionArgMismatch.wrap())
+            };
         }
-        None => {
-            return Err(InteropError::FunRegistrationUnknownTypeRet);
+
+        match type_from_id(id).or_else(|| self.types.elm_type(id)) {
+            Some(ref ty) if is_assignable(expected, ty) => {}
+            _ => return Err(InteropError::FunctionArgMismatch.wrap()),
+        }
+
+        // Booleans are custom types in Elm
+        match arg.downcast_ref::<bool>() {
+            Some(true) => Ok(self.interpreter.true_value()),
+            Some(false) => Ok(self.interpreter.false_value()),
+            None => self.types.to_elm(arg)
+                .or_else(|| convert_from_rust(arg))
+                .ok_or_else(|| InteropError::FunctionArgMismatch.wrap()),
         }
     }
+}
 
-    Ok(build_fun_type(&types))
+fn result_to_rust(value: &Value, ty: &Type) -> Result<Box<Any>, ElmError> {
+    let name = match ty {
+        Type::Unit => return Ok(Box::new(())),
+        Type::Tag(name, args) if args.is_empty() => name.as_str(),
+        _ => return Ok(Box::new(value.clone())),
+    };
+
+    match name {
+        "Bool" => Ok(Box::new(bool_of(value)?)),
+        "Int" => Ok(Box::new(int_of(value)?)),
+        "Float" => Ok(Box::new(float_of(value)?)),
+        "String" => Ok(Box::new(string_of(value)?)),
+        "Char" => Ok(Box::new(char_of(value)?)),
+        "Value" => Ok(Box::new(json_of(value)?.clone())),
+        _ => Ok(Box::new(value.clone())),
+    }
 }
 
-fn type_from_id(id: TypeId) -> Option<Type> {
+/// Elm type of the primitive Rust types, the other types are found in the `TypeRegistry`
+pub fn type_from_id(id: TypeId) -> Option<Type> {
     if id == TypeId::of::<()>() {
         return Some(Type::Unit);
     }
@@ -130,11 +203,16 @@ fn type_from_id(id: TypeId) -> Option<Type> {
         return Some(Type::Tag("Bool".to_owned(), vec![]));
     }
 
+    if id == TypeId::of::<JsonValue>() {
+        return Some(Type::Tag("Value".to_owned(), vec![]));
+    }
+
     None
 }
 
 #[cfg(test)]
 mod tests {
+    use rust_interop::function_call::CallFn;
     use rust_interop::function_register::RegisterFn;
 
     use super::*;
@@ -146,13 +224,66 @@ mod tests {
     }
 
     #[test]
-    fn test_register_invalid_function() {
+    fn test_call_function() {
         let mut i = Runtime::new();
-        let result = i.register_fn("test_function2", test_function2);
-        assert_eq!(result, Err(InteropError::FunRegistrationUnknownTypeArg(0).wrap()));
+        i.eval_module(r#"
+module Counter exposing (..)
+
+type Msg = Increment Int | Reset
+
+update : Msg -> Int -> Int
+update msg model =
+    case msg of
+        Increment amount -> model + amount
+        Reset -> 0
+
+describe : String -> Int -> String
+describe label n = label ++ String.fromInt n
+
+isPositive : Int -> Bool
+isPositive n = n > 0
+"#, "Counter").unwrap();
+
+        let msg = i.eval_expr("Counter.Increment 3").unwrap();
+        let model = i.call_fn("Counter.update", (msg, 4 as Int)).unwrap();
+        assert_eq!(model.downcast_ref::<Int>(), Some(&7));
+
+        let text = i.call_fn("Counter.describe", ("n = ".to_string(), 5 as Int)).unwrap();
+        assert_eq!(text.downcast_ref::<String>(), Some(&"n = 5".to_string()));
+
+        let positive = i.call_fn("Counter.isPositive", (-1 as Int,)).unwrap();
+        assert_eq!(positive.downcast_ref::<bool>(), Some(&false));
     }
 
-    fn test_function(a: i32) -> i32 { a }
+    #[test]
+    fn test_call_function_mismatch() {
+        let mut i = Runtime::new();
+        i.eval_statement("double : Int -> Int\ndouble x = x * 2").unwrap();
 
-    fn test_function2(a: Type) -> Type { a }
+        assert_eq!(i.call_fn("double", ("2".to_string(),)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+        assert_eq!(i.call_fn("double", (1 as Int, 2 as Int)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+        assert_eq!(i.call_fn("Missing.double", (1 as Int,)).err(), Some(InteropError::FunctionNotFound("Missing.double".to_string()).wrap()));
+
+        let result = i.call_fn("double", (3 as Int,)).unwrap();
+        assert_eq!(result.downcast_ref::<Int>(), Some(&6));
+    }
+
+    #[test]
+    fn test_call_function_value_args() {
+        let mut i = Runtime::new();
+        i.eval_statement("scale : Int -> Maybe Int -> Int\nscale factor m = Maybe.withDefault 0 m * factor").unwrap();
+        i.eval_statement("triple = scale 3").unwrap();
+
+        let just = i.eval_expr("Just 2").unwrap();
+        let text = i.eval_expr("\"2\"").unwrap();
+
+        let result = i.call_fn("triple", (just.clone(),)).unwrap();
+        assert_eq!(result.downcast_ref::<Int>(), Some(&6));
+
+        assert_eq!(i.call_fn("triple", (text,)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+        assert_eq!(i.call_fn("scale", (just.clone(), 3 as Int)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+        assert_eq!(i.call_fn("triple", (3 as Int,)).err(), Some(InteropError::FunctionArgMismatch.wrap()));
+    }
+
+    fn test_function(a: i32) -> i32 { a }
 }
\ No newline at end of file
@@ -0,0 +1,732 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use serde::de;
+use serde::de::DeserializeOwned;
+use serde::de::DeserializeSeed;
+use serde::de::IntoDeserializer;
+use serde::de::Unexpected;
+use serde::de::Visitor;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::ser;
+use serde::Serialize;
+
+use ast::Float;
+use ast::Int;
+use errors::ElmError;
+use errors::InteropError;
+use errors::Wrappable;
+use loader::Declaration;
+use parsers::Parser;
+use rust_interop::elm_value::ElmValue;
+use rust_interop::elm_value::maybe_value;
+use source::SourceCode;
+use tokenizer::Tokenizer;
+use types::Adt;
+use types::AdtVariant;
+use types::Value;
+use Runtime;
+
+/// Custom types by name, used to create values that are equal to the ones created by Elm code
+pub type CustomTypes = HashMap<String, Arc<Adt>>;
+
+impl ser::Error for ElmError {
+    fn custom<T: Display>(msg: T) -> Self {
+        InteropError::Serde(msg.to_string()).wrap()
+    }
+}
+
+impl de::Error for ElmError {
+    fn custom<T: Display>(msg: T) -> Self {
+        InteropError::Serde(msg.to_string()).wrap()
+    }
+}
+
+/// Converts a serializable Rust value into an Elm value: structs become records, enums become
+/// custom types, `Option` becomes `Maybe`, sequences become lists and tuples stay tuples.
+/// Maps become lists of `( key, value )` tuples, like the result of `Dict.toList`
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, ElmError> {
+    to_value_with(value, &CustomTypes::new())
+}
+
+/// Same as `to_value`, but the custom types found in `types` are used instead of creating
+/// new ones
+pub fn to_value_with<T: Serialize + ?Sized>(value: &T, types: &CustomTypes) -> Result<Value, ElmError> {
+    value.serialize(ValueSerializer { types })
+}
+
+/// Converts an Elm value into a Rust value, this is the inverse of `to_value`
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, ElmError> {
+    T::deserialize(value)
+}
+
+impl Runtime {
+    /// Converts a serializable Rust value into an Elm value, enums use the custom types declared
+    /// in the loaded modules if they have the same name
+    pub fn to_value<T: Serialize + ?Sized>(&self, value: &T) -> Result<Value, ElmError> {
+        to_value_with(value, &self.custom_types())
+    }
+
+    /// Converts an Elm value into a Rust value that implements `Deserialize`
+    pub fn from_value<'de, T: Deserialize<'de>>(&self, value: &'de Value) -> Result<T, ElmError> {
+        from_value(value)
+    }
+
+    /// Allows to use a serde type in the arguments of `call_fn`, `elm_type` is the source of
+    /// the equivalent Elm type, like `{ name : String, tags : List String }`.
+    /// To use the type in `register_fn` and ports implement `ElmValue` with `to_value` and
+    /// `from_value`
+    pub fn register_serde_type<T: Serialize + DeserializeOwned + Any>(&mut self, elm_type: &str) -> Result<(), ElmError> {
+        let code = SourceCode::from_str(elm_type);
+        let ty = Parser::new(Tokenizer::new(&code)).parse_type()?;
+        let ty = self.analyzer.with(code).check_type((0, 0), ty)?;
+        let types = self.custom_types();
+
+        self.types.register::<T>(
+            ty,
+            Box::new(move |any| any.downcast_ref::<T>().and_then(|value| to_value_with(value, &types).ok())),
+        );
+        Ok(())
+    }
+
+    fn custom_types(&self) -> CustomTypes {
+        self.analyzed_modules.values()
+            .flat_map(|module| module.all_declarations.iter())
+            .filter_map(|decl| {
+                match decl {
+                    Declaration::Adt(name, adt) => Some((name.clone(), adt.clone())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+fn custom_value(types: &CustomTypes, name: &str, variant: &str, values: Vec<Value>) -> Value {
+    let adt = match types.get(name) {
+        Some(adt) => adt.clone(),
+        // The other variants are unknown, but values are matched by the variant name
+        None => Arc::new(Adt {
+            name: name.to_string(),
+            types: vec![],
+            variants: vec![AdtVariant { name: variant.to_string(), types: vec![] }],
+        }),
+    };
+
+    Value::Adt(variant.to_string(), values, adt)
+}
+
+fn int_value<T: TryInto<Int> + Display + Copy>(value: T) -> Result<Value, ElmError> {
+    value.try_into()
+        .map(Value::Int)
+        .map_err(|_| ser::Error::custom(format!("{} doesn't fit in an Int", value)))
+}
+
+struct ValueSerializer<'a> {
+    types: &'a CustomTypes,
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = RecordSerializer<'a>;
+    type SerializeStructVariant = RecordSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, ElmError> {
+        Ok(v.to_elm())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, ElmError> {
+        int_value(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, ElmError> {
+        Ok(Value::Float(v as Float))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, ElmError> {
+        Ok(Value::Float(v as Float))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, ElmError> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, ElmError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, ElmError> {
+        Ok(Value::List(v.iter().map(|byte| Value::Int(*byte as Int)).collect::<Vec<_>>().into()))
+    }
+
+    fn serialize_none(self) -> Result<Value, ElmError> {
+        Ok(maybe_value(None))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, ElmError> {
+        Ok(maybe_value(Some(value.serialize(self)?)))
+    }
+
+    fn serialize_unit(self) -> Result<Value, ElmError> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ElmError> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_variant(self, name: &'static str, _index: u32, variant: &'static str) -> Result<Value, ElmError> {
+        Ok(custom_value(self.types, name, variant, vec![]))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Value, ElmError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Value, ElmError> {
+        let types = self.types;
+        Ok(custom_value(types, name, variant, vec![value.serialize(self)?]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'a>, ElmError> {
+        Ok(SeqSerializer::new(self.types, SeqKind::List, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>, ElmError> {
+        Ok(SeqSerializer::new(self.types, SeqKind::Tuple, len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<SeqSerializer<'a>, ElmError> {
+        Ok(SeqSerializer::new(self.types, SeqKind::Variant(name, name), len))
+    }
+
+    fn serialize_tuple_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<SeqSerializer<'a>, ElmError> {
+        Ok(SeqSerializer::new(self.types, SeqKind::Variant(name, variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a>, ElmError> {
+        Ok(MapSerializer { types: self.types, entries: vec![], key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<RecordSerializer<'a>, ElmError> {
+        Ok(RecordSerializer { types: self.types, fields: Vec::with_capacity(len), variant: None })
+    }
+
+    fn serialize_struct_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<RecordSerializer<'a>, ElmError> {
+        Ok(RecordSerializer { types: self.types, fields: Vec::with_capacity(len), variant: Some((name, variant)) })
+    }
+}
+
+enum SeqKind {
+    List,
+    Tuple,
+    /// Tuple structs and tuple variants, the type name and the variant name
+    Variant(&'static str, &'static str),
+}
+
+struct SeqSerializer<'a> {
+    types: &'a CustomTypes,
+    kind: SeqKind,
+    values: Vec<Value>,
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn new(types: &'a CustomTypes, kind: SeqKind, len: usize) -> Self {
+        SeqSerializer { types, kind, values: Vec::with_capacity(len) }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        self.values.push(value.serialize(ValueSerializer { types: self.types })?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, ElmError> {
+        Ok(match self.kind {
+            SeqKind::List => Value::List(self.values.into()),
+            SeqKind::Tuple => Value::Tuple(self.values),
+            SeqKind::Variant(name, variant) => custom_value(self.types, name, variant, self.values),
+        })
+    }
+}
+
+impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+struct MapSerializer<'a> {
+    types: &'a CustomTypes,
+    entries: Vec<Value>,
+    key: Option<Value>,
+}
+
+impl<'a> ser::SerializeMap for MapSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ElmError> {
+        self.key = Some(key.serialize(ValueSerializer { types: self.types })?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ElmError> {
+        let key = self.key.take()
+            .ok_or_else(|| <ElmError as ser::Error>::custom("map value without a key"))?;
+        let value = value.serialize(ValueSerializer { types: self.types })?;
+
+        self.entries.push(Value::Tuple(vec![key, value]));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        Ok(Value::List(self.entries.into()))
+    }
+}
+
+struct RecordSerializer<'a> {
+    types: &'a CustomTypes,
+    fields: Vec<(String, Value)>,
+    /// Struct variants are custom types with a record as the only value
+    variant: Option<(&'static str, &'static str)>,
+}
+
+impl<'a> RecordSerializer<'a> {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ElmError> {
+        self.fields.push((key.to_string(), value.serialize(ValueSerializer { types: self.types })?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, ElmError> {
+        let record = Value::Record(Arc::new(self.fields));
+
+        Ok(match self.variant {
+            Some((name, variant)) => custom_value(self.types, name, variant, vec![record]),
+            None => record,
+        })
+    }
+}
+
+impl<'a> ser::SerializeStruct for RecordSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ElmError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for RecordSerializer<'a> {
+    type Ok = Value;
+    type Error = ElmError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ElmError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, ElmError> {
+        self.finish()
+    }
+}
+
+impl<'de> Deserializer<'de> for &'de Value {
+    type Error = ElmError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            Value::Unit => visitor.visit_unit(),
+            Value::Number(value) | Value::Int(value) => visitor.visit_i32(*value),
+            Value::Float(value) => visitor.visit_f32(*value),
+            Value::String(value) => visitor.visit_borrowed_str(value),
+            Value::Char(value) => visitor.visit_char(*value),
+            Value::List(list) => visitor.visit_seq(SeqAccess(Box::new(list.iter()))),
+            Value::Array(array) => visitor.visit_seq(SeqAccess(Box::new(array.iter()))),
+            Value::Tuple(values) => visitor.visit_seq(SeqAccess(Box::new(values.iter()))),
+            Value::Json(json) => (&**json).deserialize_any(visitor).map_err(de::Error::custom),
+            Value::Record(fields) => visitor.visit_map(RecordAccess { fields: fields.iter(), value: None }),
+            Value::Adt(variant, values, adt) => {
+                match (adt.name.as_str(), variant.as_str(), values.as_slice()) {
+                    ("Bool", "True", []) => visitor.visit_bool(true),
+                    ("Bool", "False", []) => visitor.visit_bool(false),
+                    ("Maybe", "Nothing", []) => visitor.visit_none(),
+                    ("Maybe", "Just", [value]) => visitor.visit_some(value),
+                    _ => visitor.visit_enum(EnumAccess { variant, values }),
+                }
+            }
+            Value::Fun { .. } => Err(de::Error::invalid_type(Unexpected::Other("function"), &visitor)),
+            Value::Opaque(opaque) => Err(de::Error::invalid_type(Unexpected::Other(&opaque.type_name), &visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            Value::Adt(variant, values, adt) if adt.name == "Maybe" => {
+                match (variant.as_str(), values.as_slice()) {
+                    ("Just", [value]) => visitor.visit_some(value),
+                    _ => visitor.visit_none(),
+                }
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            // Custom types like `type Marker = Marker`
+            Value::Adt(_, values, _) if values.is_empty() => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ElmError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            Value::Adt(_, values, _) => visitor.visit_seq(SeqAccess(Box::new(values.iter()))),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            Value::List(list) => visitor.visit_map(PairsAccess { pairs: Box::new(list.iter()), value: None }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, ElmError> {
+        match self {
+            Value::Adt(variant, values, _) => visitor.visit_enum(EnumAccess { variant, values }),
+            Value::String(variant) => visitor.visit_enum(EnumAccess { variant, values: &[] }),
+            _ => Err(de::Error::invalid_type(Unexpected::Other("non custom type value"), &visitor)),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ElmError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit seq tuple struct identifier
+    }
+}
+
+struct SeqAccess<'de>(Box<Iterator<Item = &'de Value> + 'de>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = ElmError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, ElmError> {
+        match self.0.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct RecordAccess<'de> {
+    fields: std::slice::Iter<'de, (String, Value)>,
+    value: Option<&'de Value>,
+}
+
+impl<'de> de::MapAccess<'de> for RecordAccess<'de> {
+    type Error = ElmError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ElmError> {
+        match self.fields.next() {
+            Some((name, value)) => {
+                self.value = Some(value);
+                seed.deserialize(name.as_str().into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ElmError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("record value without a field")),
+        }
+    }
+}
+
+/// Lists of `( key, value )` tuples used as maps
+struct PairsAccess<'de> {
+    pairs: Box<Iterator<Item = &'de Value> + 'de>,
+    value: Option<&'de Value>,
+}
+
+impl<'de> de::MapAccess<'de> for PairsAccess<'de> {
+    type Error = ElmError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ElmError> {
+        match self.pairs.next() {
+            Some(Value::Tuple(pair)) if pair.len() == 2 => {
+                self.value = Some(&pair[1]);
+                seed.deserialize(&pair[0]).map(Some)
+            }
+            Some(_) => Err(de::Error::custom("expected a list of ( key, value ) tuples")),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ElmError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("map value without a key")),
+        }
+    }
+}
+
+struct EnumAccess<'de> {
+    variant: &'de str,
+    values: &'de [Value],
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = ElmError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), ElmError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumAccess<'de> {
+    type Error = ElmError;
+
+    fn unit_variant(self) -> Result<(), ElmError> {
+        match self.values {
+            [] => Ok(()),
+            _ => Err(de::Error::invalid_length(self.values.len(), &"a variant without values")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ElmError> {
+        match self.values {
+            [value] => seed.deserialize(value),
+            _ => Err(de::Error::invalid_length(self.values.len(), &"a variant with one value")),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ElmError> {
+        visitor.visit_seq(SeqAccess(Box::new(self.values.iter())))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, ElmError> {
+        match self.values {
+            [value] => value.deserialize_struct("", fields, visitor),
+            _ => Err(de::Error::invalid_length(self.values.len(), &"a variant with a record")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ast::Type;
+    use rust_interop::function_call::CallFn;
+    use rust_interop::function_register::RegisterFn;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    struct Task {
+        title: String,
+        estimated_hours: Option<f32>,
+        status: Status,
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    enum Status {
+        Todo,
+        Blocked(String),
+        Done { by: String, at: (i32, i32) },
+    }
+
+    fn task() -> Task {
+        Task {
+            title: "Write tests".to_string(),
+            estimated_hours: Some(1.5),
+            status: Status::Done { by: "ana".to_string(), at: (10, 30) },
+            tags: vec!["rust".to_string(), "elm".to_string()],
+        }
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let value = to_value(&task()).unwrap();
+        assert_eq!(from_value::<Task>(&value).unwrap(), task());
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), vec![Status::Todo, Status::Blocked("b".to_string())]);
+        assert_eq!(from_value::<BTreeMap<String, Vec<Status>>>(&to_value(&map).unwrap()).unwrap(), map);
+
+        assert!(to_value(&(1u64 << 40)).is_err());
+        assert!(from_value::<Status>(&Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn check_elm_values() {
+        let mut runtime = Runtime::new();
+        runtime.eval_module(r#"
+module Tasks exposing (..)
+
+type Status = Todo | Blocked String | Done { by : String, at : ( Int, Int ) }
+
+task = { title = "Write tests", estimatedHours = Just 1.5, status = Done { by = "ana", at = ( 10, 30 ) }, tags = [ "rust", "elm" ] }
+
+isDone status =
+    case status of
+        Done _ -> True
+        _ -> False
+"#, "Tasks").unwrap();
+
+        let value = runtime.eval_expr("Tasks.task").unwrap();
+        assert_eq!(runtime.from_value::<Task>(&value).unwrap(), task());
+
+        // Enums use the custom type declared in the module
+        let todo = runtime.to_value(&Status::Todo).unwrap();
+        assert_eq!(todo, runtime.eval_expr("Tasks.Todo").unwrap());
+        assert_eq!(runtime.to_value(&None::<Int>).unwrap(), runtime.eval_expr("Nothing").unwrap());
+        assert_eq!(runtime.to_value(&true).unwrap(), runtime.eval_expr("True").unwrap());
+    }
+
+    // The serde conversions are enough to implement `ElmValue`
+    impl ElmValue for Status {
+        fn elm_type() -> Type {
+            Type::Tag("Status".to_string(), vec![])
+        }
+
+        fn to_elm(&self) -> Value {
+            to_value(self).unwrap()
+        }
+
+        fn from_elm(value: &Value) -> Result<Self, ElmError> {
+            from_value(value)
+        }
+    }
+
+    #[test]
+    fn check_register_serde_type() {
+        let mut runtime = Runtime::new();
+        runtime.eval_statement("type Status = Todo | Blocked String | Done { by : String, at : ( Int, Int ) }").unwrap();
+        runtime.register_serde_type::<Task>("{ title : String, estimatedHours : Maybe Float, status : Status, tags : List String }").unwrap();
+        runtime.eval_statement("title : { title : String, estimatedHours : Maybe Float, status : Status, tags : List String } -> String\ntitle task = task.title").unwrap();
+
+        let title = runtime.call_fn("title", (task(),)).unwrap();
+        assert_eq!(title.downcast_ref::<String>(), Some(&"Write tests".to_string()));
+    }
+
+    #[test]
+    fn check_serde_elm_value() {
+        let mut runtime = Runtime::new();
+        runtime.eval_statement("type Status = Todo | Blocked String | Done { by : String, at : ( Int, Int ) }").unwrap();
+
+        runtime.register_fn("block", |reason: String| Status::Blocked(reason)).unwrap();
+        runtime.register_fn("reason", |status: Status| {
+            match status {
+                Status::Blocked(reason) => reason,
+                _ => String::new(),
+            }
+        }).unwrap();
+
+        assert_eq!(runtime.eval_expr("reason (block \"tests\")").unwrap(), Value::String("tests".to_string()));
+        assert_eq!(runtime.eval_expr("reason Todo").unwrap(), Value::String(String::new()));
+    }
+}
@@ -1,18 +1,50 @@
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
 use std::str::Chars;
 use std::sync::Arc;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+use ast::Span;
 
 /// Internal container for ELM source code,
 /// the main use of this container is to avoid duplication of large files
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct SourceCode(Arc<SourceCodeImpl>);
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 struct SourceCodeImpl {
+    id: FileId,
     // file path or 'inline'
     source: String,
     code: String,
+    // offset of the first char of every line
+    line_starts: Vec<Location>,
+}
+
+/// Identifies a SourceCode instance, every file gets a different id even if the path is the same
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+/// Line and column in the source code, both start at 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LineColumn {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A span resolved to a file, the columns are measured in UTF-16 code units like editors do
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceSpan {
+    pub file: FileId,
+    pub path: String,
+    pub start: LineColumn,
+    pub end: LineColumn,
 }
 
+static NEXT_FILE_ID: AtomicU32 = AtomicU32::new(0);
+
 /// Identifies a position in the stream of chars in the source code
 pub type Location = u32;
 
@@ -29,9 +61,18 @@ impl SourceCode {
             code.push('\0');
         }
 
+        let mut line_starts = vec![0];
+        for (index, byte) in code.bytes().enumerate().take(code.len() - SOURCE_CODE_PADDING) {
+            if byte == b'\n' {
+                line_starts.push(index as Location + 1);
+            }
+        }
+
         SourceCode(Arc::new(SourceCodeImpl {
+            id: FileId(NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed)),
             code,
             source: path.to_string(),
+            line_starts,
         }))
     }
 
@@ -57,6 +98,11 @@ impl SourceCode {
         Self::from_string(String::from_utf8_lossy(&bytes).to_string(), "inline")
     }
 
+    /// Returns the path of the file, or 'inline' if the code didn't come from a file
+    pub fn path(&self) -> &str {
+        &self.0.source
+    }
+
     /// Returns a real size of the source code
     pub fn len(&self) -> usize {
         self.0.code.len() - SOURCE_CODE_PADDING
@@ -76,4 +122,146 @@ impl SourceCode {
     pub fn as_str(&self) -> &str {
         self.0.code.as_str()
     }
-}
\ No newline at end of file
+
+    /// Returns the unique id of this file
+    pub fn id(&self) -> FileId {
+        self.0.id
+    }
+
+    /// Returns the offset of the first char of every line
+    pub fn line_starts(&self) -> &[Location] {
+        &self.0.line_starts
+    }
+
+    /// Returns the line and column of an offset, the column is measured in bytes
+    pub fn position(&self, offset: Location) -> LineColumn {
+        let (line, start, offset) = self.line_of(offset);
+        LineColumn { line: line as u32 + 1, column: offset - start + 1 }
+    }
+
+    /// Returns the line and column of an offset, the column is measured in UTF-16 code units
+    pub fn position_utf16(&self, offset: Location) -> LineColumn {
+        let (line, start, offset) = self.line_of(offset);
+        let column = self.as_str()[start as usize..offset as usize].encode_utf16().count();
+        LineColumn { line: line as u32 + 1, column: column as u32 + 1 }
+    }
+
+    /// Returns the offset of a line and a column measured in bytes, positions outside the
+    /// code are moved to the end of the line or the end of the code
+    pub fn offset(&self, position: LineColumn) -> Location {
+        let (start, end) = self.line_bounds(position.line);
+        (start + position.column.max(1) - 1).min(end)
+    }
+
+    /// Returns the offset of a line and a column measured in UTF-16 code units
+    pub fn offset_utf16(&self, position: LineColumn) -> Location {
+        let (start, end) = self.line_bounds(position.line);
+        let mut units = 0;
+
+        for (index, c) in self.as_str()[start as usize..end as usize].char_indices() {
+            if units + 1 >= position.column.max(1) {
+                return start + index as Location;
+            }
+            units += c.len_utf16() as u32;
+        }
+
+        end
+    }
+
+    /// Resolves a span to the path, line and column of its start and end
+    pub fn locate(&self, span: Span) -> SourceSpan {
+        SourceSpan {
+            file: self.id(),
+            path: self.path().to_string(),
+            start: self.position_utf16(span.0),
+            end: self.position_utf16(span.1.max(span.0)),
+        }
+    }
+
+    /// Returns the index of the line, the offset where it starts and the offset moved to a char
+    /// boundary inside the code
+    fn line_of(&self, offset: Location) -> (usize, Location, Location) {
+        let code = self.as_str();
+        let mut offset = (offset as usize).min(self.len());
+
+        while !code.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let offset = offset as Location;
+        let line = match self.0.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+
+        (line, self.0.line_starts[line], offset)
+    }
+
+    /// Returns the offsets of the start and the end of a line, without the line break
+    fn line_bounds(&self, line: u32) -> (Location, Location) {
+        let starts = &self.0.line_starts;
+        let index = (line.max(1) as usize - 1).min(starts.len() - 1);
+        let end = starts.get(index + 1).map(|next| next - 1).unwrap_or(self.len() as Location);
+
+        (starts[index], end)
+    }
+}
+
+/// Two instances are equal if they have the same path and code, the id is not compared
+impl PartialEq for SourceCode {
+    fn eq(&self, other: &SourceCode) -> bool {
+        self.0.source == other.0.source && self.0.code == other.0.code
+    }
+}
+
+impl Display for SourceSpan {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}:{}:{}", self.path, self.start.line, self.start.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_positions() {
+        let code = SourceCode::from_str("ab\ncd\n\né=\"𝄞x\"");
+
+        assert_eq!(code.line_starts(), &[0, 3, 6, 7]);
+        assert_eq!(code.position(0), LineColumn { line: 1, column: 1 });
+        assert_eq!(code.position(4), LineColumn { line: 2, column: 2 });
+        assert_eq!(code.position(6), LineColumn { line: 3, column: 1 });
+        // `x` after `é="𝄞`
+        assert_eq!(code.position(15), LineColumn { line: 4, column: 9 });
+        assert_eq!(code.position_utf16(15), LineColumn { line: 4, column: 6 });
+        // inside `é`, moved to the start of the char
+        assert_eq!(code.position(8), LineColumn { line: 4, column: 1 });
+        assert_eq!(code.position(100), code.position(code.len() as Location));
+    }
+
+    #[test]
+    fn check_offsets() {
+        let code = SourceCode::from_str("ab\ncd\n\né=\"𝄞x\"");
+
+        for offset in &[0, 2, 3, 5, 6, 7, 9, 10, 11, 15, 16, 17] {
+            assert_eq!(code.offset(code.position(*offset)), *offset);
+            assert_eq!(code.offset_utf16(code.position_utf16(*offset)), *offset);
+        }
+
+        assert_eq!(code.offset(LineColumn { line: 1, column: 10 }), 2);
+        assert_eq!(code.offset_utf16(LineColumn { line: 9, column: 1 }), 7);
+    }
+
+    #[test]
+    fn check_locate() {
+        let main = SourceCode::from_string("module Main\n\nx = 1".to_string(), "src/Main.elm");
+        let other = SourceCode::from_string("module Main\n\nx = 1".to_string(), "src/Main.elm");
+        let location = main.locate((13, 14));
+
+        assert_ne!(main.id(), other.id());
+        assert_eq!(location.file, main.id());
+        assert_eq!(location.end, LineColumn { line: 3, column: 2 });
+        assert_eq!(location.to_string(), "src/Main.elm:3:1");
+    }
+}
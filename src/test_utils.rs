@@ -13,6 +13,14 @@ pub struct Test;
 
 #[cfg(test)]
 impl Test {
+    /// Runtimes for every backend, used to check that all of them give the same results
+    pub fn runtimes() -> Vec<::Runtime> {
+        vec![
+            ::Runtime::with_backend(::interpreter::Backend::TreeWalker),
+            ::Runtime::with_backend(::interpreter::Backend::Bytecode),
+        ]
+    }
+
     pub fn tokens(code: &str) -> Vec<TokenInfo> {
         Tokenizer::new(&SourceCode::from_str(code)).tokenize().unwrap()
     }
@@ -237,6 +237,11 @@ impl Tokenizer {
         let mut offset = 0;
 
         loop {
+            // Unclosed comments go until the end of the code
+            if self.pos + offset >= self.code.len() {
+                return self.code.len() - self.pos;
+            }
+
             if self.byte(offset) == b'{' && self.byte(offset + 1) == b'-' {
                 nesting += 1;
             }
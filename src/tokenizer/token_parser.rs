@@ -90,7 +90,14 @@ named!(read_char<Token>, delimited!(char!('\''), char_char, char!('\'')));
 named!(char_char<Token>, map!(none_of!("\n\'"), |c| LitChar(c)));
 
 named!(string_char<char>, alt!(
-    none_of!("\n\"") | preceded!(char!('\\'), char!('\"'))
+    preceded!(char!('\\'), escaped_char) | none_of!("\n\"")
+));
+
+named!(escaped_char<char>, alt!(
+    map!(char!('n'), |_c| '\n') |
+    map!(char!('t'), |_c| '\t') |
+    map!(char!('r'), |_c| '\r') |
+    one_of!("\"\\'")
 ));
 
 named!(here_doc_char<char>, alt!(
@@ -203,6 +210,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_escaped_string() {
+        assert_ok!(
+            read_line_string(b"\"say \\\"hi\\\"\\n\\\\\""),
+            LitString("say \"hi\"\n\\".s())
+        );
+    }
+
     #[test]
     fn check_multiline_string() {
         assert_ok!(
@@ -1,8 +1,13 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
 use ast::{Int, Span};
 use ast::Type;
 use constructors::{type_char, type_int, type_string};
+use interpreter::runtime_stack::GlobalId;
+use source::SourceCode;
+use source::SourceSpan;
 use types::Value;
 
 // An unevaluated expression tree
@@ -34,6 +39,40 @@ pub enum TypedExpr {
     Application(Span, Type, Box<TypedExpr>, Box<TypedExpr>),
     /* A let definition, allows to create local functions to use in the final expression */
     Let(Span, Type, Vec<LetEntry>, Box<TypedExpr>),
+    /* A reference already resolved to the place where the value is stored, see `interpreter::resolver` */
+    Resolved(Span, Type, String, VarSlot),
+    /* Creation of a resolved lambda, the slots point to the values to capture */
+    Closure(Span, Type, Arc<FunctionGroup>, Vec<VarSlot>),
+}
+
+/// Location of a variable at runtime
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VarSlot {
+    /// Local variable in the frame of the current function call
+    Local(usize),
+    /// Value captured by the current function when it was created
+    Capture(usize),
+    /// Function of the same group as the current function, including itself
+    Sibling(usize),
+    /// Module level definition
+    Global(GlobalId),
+}
+
+/// Functions created together, they share the captured values and can call each other
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionGroup {
+    pub functions: Vec<ResolvedFunction>,
+    /// Names of the captured values
+    pub captures: Vec<String>,
+}
+
+/// A function definition or lambda with all the references of its body resolved
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResolvedFunction {
+    pub name: Option<String>,
+    pub header: Type,
+    pub patterns: Vec<TypedPattern>,
+    pub expr: TypedExpr,
 }
 
 /// A function definition
@@ -50,13 +89,15 @@ pub struct TypedDefinition {
 pub enum LetEntry {
     Definition(TypedDefinition),
     Pattern(TypedPattern, TypedExpr),
+    /// Local functions after the resolution pass, the slots point to the values to capture
+    Functions(Arc<FunctionGroup>, Vec<VarSlot>),
 }
 
 // A pattern that represents 1 or more function arguments
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum TypedPattern {
     Var(Span, Type, String),
-    Adt(Span, Type, Type, Vec<TypedPattern>),
+    Adt(Span, Type, Type, String, Vec<TypedPattern>),
     Wildcard(Span),
     Unit(Span),
     Tuple(Span, Type, Vec<TypedPattern>),
@@ -73,7 +114,7 @@ impl TypedPattern {
     pub fn get_span(&self) -> Span {
         *match self {
             TypedPattern::Var(span, _, _) => span,
-            TypedPattern::Adt(span, _, _, _) => span,
+            TypedPattern::Adt(span, _, _, _, _) => span,
             TypedPattern::Wildcard(span) => span,
             TypedPattern::Unit(span) => span,
             TypedPattern::Tuple(span, _, _) => span,
@@ -89,7 +130,7 @@ impl TypedPattern {
     pub fn get_type(&self) -> Type {
         match self {
             TypedPattern::Var(_, ty, _) => ty.clone(),
-            TypedPattern::Adt(_, ty, _, _) => ty.clone(),
+            TypedPattern::Adt(_, ty, _, _, _) => ty.clone(),
             TypedPattern::Wildcard(_) => Type::Var("_".to_string()),
             TypedPattern::Unit(_) => Type::Unit,
             TypedPattern::Tuple(_, ty, _) => ty.clone(),
@@ -120,9 +161,16 @@ impl TypedExpr {
             TypedExpr::Lambda(span, _, _, _) => span,
             TypedExpr::Application(span, _, _, _) => span,
             TypedExpr::Let(span, _, _, _) => span,
+            TypedExpr::Resolved(span, _, _, _) => span,
+            TypedExpr::Closure(span, _, _, _) => span,
         }
     }
 
+    /// Resolves the span to a file, line and column, `code` must be the code that was analyzed
+    pub fn get_location(&self, code: &SourceCode) -> SourceSpan {
+        code.locate(self.get_span())
+    }
+
     pub fn get_type(&self) -> Type {
         match self {
             TypedExpr::Const(_, ty, _) => ty.clone(),
@@ -138,6 +186,8 @@ impl TypedExpr {
             TypedExpr::Lambda(_, ty, _, _) => ty.clone(),
             TypedExpr::Application(_, ty, _, _) => ty.clone(),
             TypedExpr::Let(_, ty, _, _) => ty.clone(),
+            TypedExpr::Resolved(_, ty, _, _) => ty.clone(),
+            TypedExpr::Closure(_, ty, _, _) => ty.clone(),
         }
     }
 }
@@ -189,6 +239,12 @@ impl PartialEq for TypedExpr {
             TypedExpr::Ref(_, _, a) => {
                 if let TypedExpr::Ref(_, _, a2) = other { a == a2 } else { false }
             }
+            TypedExpr::Resolved(_, _, a, b) => {
+                if let TypedExpr::Resolved(_, _, a2, b2) = other { a == a2 && b == b2 } else { false }
+            }
+            TypedExpr::Closure(_, _, a, b) => {
+                if let TypedExpr::Closure(_, _, a2, b2) = other { a == a2 && b == b2 } else { false }
+            }
         }
     }
 }
\ No newline at end of file
@@ -1,20 +1,25 @@
-use std::collections::HashMap;
+use std::any::Any;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
+use im::Vector;
 use serde::{Deserialize, Serialize};
 use serde::Deserializer;
 use serde::Serializer;
+use serde_json::Value as JsonValue;
 
 use analyzer::type_of_value;
 use ast::*;
 use errors::*;
+use interpreter::bytecode::ClosureEnv;
 use interpreter::Interpreter;
-use rust_interop::FnAny;
-use typed_ast::{TypedExpr, TypedPattern};
+use interpreter::runtime_stack::Globals;
+use interpreter::stack_trace::CodeOrigin;
+use typed_ast::FunctionGroup;
+use util::persistent_list::List;
 use util::transmute_float_to_int;
 
 // Represents the final value after the evaluation of an expression tree
@@ -32,12 +37,17 @@ pub enum Value {
     String(String),
     /// Unicode character
     Char(char),
-    /// Collection of values of the same type
-    List(Vec<Value>),
+    /// Collection of values of the same type, the tails are shared between lists
+    List(List<Value>),
+    /// Indexed collection of values of the same type, updates share most of the structure
+    Array(Vector<Value>),
+    /// A JSON value, built with Json.Encode or received from the host
+    Json(Arc<JsonValue>),
     /// Collection of values of different types
     Tuple(Vec<Value>),
-    /// A map between keys and values, where keys are identifiers
-    Record(Vec<(String, Value)>),
+    /// A map between keys and values, where keys are identifiers, the entries are shared between
+    /// copies of the record
+    Record(Arc<Vec<(String, Value)>>),
     /// A custom type a.k.a enum a.k.a algebraic data type
     Adt(String, Vec<Value>, Arc<Adt>),
     /// A function value, contains values from partial application
@@ -46,6 +56,9 @@ pub enum Value {
         args: Vec<Value>,
         fun: Arc<Function>,
     },
+    /// Data owned by the host, like an entity or a texture handle
+    #[serde(skip)]
+    Opaque(Opaque),
 }
 
 /// Represents a function call,
@@ -70,30 +83,96 @@ pub fn next_fun_id() -> FunId {
 
 pub type ElmFn = fn(&mut Interpreter, &[Value]) -> Result<Value, ElmError>;
 
+/// Same as `ElmFn`, but it can capture state
+pub type ElmClosure = Fn(&mut Interpreter, &[Value]) -> Result<Value, ElmError> + Send + Sync;
+
 pub struct ExternalFunc {
     pub name: String,
     pub fun: ElmFn,
 }
 
-pub struct WrapperFunc {
+pub struct ClosureFunc {
     pub name: String,
-    pub fun: Box<FnAny>,
+    pub fun: Box<ElmClosure>,
 }
 
 /// Represents a function that can be a definition or builtin
 #[derive(Debug)]
 pub enum Function {
     External(FunId, ExternalFunc, Type),
-    Wrapper(FunId, WrapperFunc, Type),
+    /// Rust function registered with `RegisterFn`, `RegisterClosure` or `RegisterContextFn`
+    Closure(FunId, ClosureFunc, Type),
+    /// Function evaluated by the tree walker, `index` selects the function inside the group
     Definition {
         id: FunId,
-        patterns: Vec<TypedPattern>,
-        expression: TypedExpr,
-        function_type: Type,
-        captures: HashMap<String, Value>,
+        origin: Arc<CodeOrigin>,
+        group: Arc<FunctionGroup>,
+        index: usize,
+        captures: Arc<Vec<Value>>,
+        globals: Arc<Globals>,
+    },
+    /// Function compiled to bytecode, `index` selects the function inside the closure group
+    Compiled {
+        id: FunId,
+        env: Arc<ClosureEnv>,
+        index: usize,
     },
 }
 
+/// Data owned by the host, Elm code can store it, compare it and pass it back to the host, but
+/// it cannot look inside
+#[derive(Clone)]
+pub struct Opaque {
+    /// Name of the Elm type, declared with `Runtime::register_opaque_type`
+    pub type_name: String,
+    pub data: Arc<Any + Send + Sync>,
+    eq: fn(&Any, &Any) -> bool,
+    hash: fn(&Any, &mut Hasher),
+}
+
+impl Opaque {
+    /// Creates a value that is compared and hashed using the implementations of `T`
+    pub fn new<T: Any + Send + Sync + PartialEq + Hash>(type_name: &str, value: T) -> Self {
+        Opaque {
+            type_name: type_name.to_string(),
+            data: Arc::new(value),
+            eq: |a, b| a.downcast_ref::<T>() == b.downcast_ref::<T>(),
+            hash: |value, mut state| value.downcast_ref::<T>().hash(&mut state),
+        }
+    }
+
+    /// Wraps shared data, only copies of the same `Arc` are equal
+    pub fn from_arc(type_name: &str, data: Arc<Any + Send + Sync>) -> Self {
+        Opaque {
+            type_name: type_name.to_string(),
+            data,
+            eq: |a, b| data_address(a) == data_address(b),
+            hash: |value, state| state.write_usize(data_address(value)),
+        }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref::<T>()
+    }
+}
+
+fn data_address(value: &Any) -> usize {
+    value as *const Any as *const u8 as usize
+}
+
+impl PartialEq for Opaque {
+    fn eq(&self, other: &Opaque) -> bool {
+        self.type_name == other.type_name && (self.eq)(&*self.data, &*other.data)
+    }
+}
+
+impl Hash for Opaque {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_name.hash(state);
+        (self.hash)(&*self.data, state);
+    }
+}
+
 /// Represents an Adt type with all the information about the variants
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Hash)]
 pub struct Adt {
@@ -143,6 +222,8 @@ impl Hash for Value {
             Value::String(i) => { i.hash(state) }
             Value::Char(i) => { state.write_u32(*i as u32) }
             Value::List(i) => { i.hash(state) }
+            Value::Array(i) => { i.hash(state) }
+            Value::Json(i) => { i.to_string().hash(state) }
             Value::Tuple(i) => { i.hash(state) }
             Value::Record(i) => { i.hash(state) }
             Value::Adt(a, b, c) => {
@@ -156,6 +237,7 @@ impl Hash for Value {
 
                 state.write_usize(fun.get_id());
             }
+            Value::Opaque(i) => { i.hash(state) }
         }
     }
 }
@@ -177,16 +259,18 @@ impl Function {
     fn get_id(&self) -> FunId {
         match self {
             Function::External(id, ..) => *id,
-            Function::Wrapper(id, ..) => *id,
+            Function::Closure(id, ..) => *id,
             Function::Definition { id, .. } => *id,
+            Function::Compiled { id, .. } => *id,
         }
     }
 
     pub fn get_type(&self) -> Type {
         match self {
             Function::External(_, _, ty, ..) => ty.clone(),
-            Function::Wrapper(_, _, ty, ..) => ty.clone(),
-            Function::Definition { function_type, .. } => function_type.clone(),
+            Function::Closure(_, _, ty, ..) => ty.clone(),
+            Function::Definition { group, index, .. } => group.functions[*index].header.clone(),
+            Function::Compiled { env, index, .. } => env.group.members[*index].function_type.clone(),
         }
     }
 }
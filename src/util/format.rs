@@ -8,9 +8,10 @@ use ast::*;
 use tokenizer::Token;
 use typed_ast::TypedExpr;
 use typed_ast::TypedPattern;
+use types::ClosureFunc;
 use types::ExternalFunc;
+use types::Opaque;
 use types::Value;
-use types::WrapperFunc;
 use util::expression_fold::create_expr_tree;
 
 impl Debug for ExternalFunc {
@@ -19,9 +20,15 @@ impl Debug for ExternalFunc {
     }
 }
 
-impl Debug for WrapperFunc {
+impl Debug for Opaque {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "<wrapper fun '{}'>", self.name)
+        write!(f, "<{}>", self.type_name)
+    }
+}
+
+impl Debug for ClosureFunc {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "<closure fun '{}'>", self.name)
     }
 }
 
@@ -196,6 +203,12 @@ fn print_tree(f: &mut Formatter, expr: &TypedExpr, indent: u32) -> Result<(), Er
             print_tree(f, val2.as_ref(), indent + 1)?;
             write!(f, "\n{nl}}}", nl = newline)?;
         }
+        TypedExpr::Resolved(_, ty, val1, val2) => {
+            write!(f, "{nl}Resolved {{ {}, {} => {:?} }}", ty, val1, val2, nl = newline)?;
+        }
+        TypedExpr::Closure(_, ty, val1, val2) => {
+            write!(f, "{nl}Closure {{ {},\n{nl}{:?}\n{nl}{:?}\n{nl}}}", ty, val1, val2, nl = newline)?;
+        }
     }
 
     Ok(())
@@ -290,9 +303,27 @@ impl Display for Value {
             Value::Char(it) => write!(f, "'{}'", it)?,
             Value::List(items) => {
                 write!(f, "[")?;
-                print_vec(f, items)?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Array(items) => {
+                write!(f, "Array.fromList [")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
                 write!(f, "]")?;
             }
+            Value::Json(json) => write!(f, "{}", json)?,
+            // The internals of the host data are not visible from Elm
+            Value::Opaque(opaque) => write!(f, "<{}>", opaque.type_name)?,
             Value::Tuple(items) => {
                 write!(f, "(")?;
                 print_vec(f, items)?;
@@ -338,8 +369,8 @@ fn print_pattern(f: &mut Formatter, pat: &TypedPattern, indent: u32) -> Result<(
         TypedPattern::Var(_, ty, name) => {
             write!(f, "{nl}Var {{ {}, {} }}", ty, name, nl = newline)?;
         }
-        TypedPattern::Adt(_, ty, own_ty, items) => {
-            write!(f, "{nl}Adt {{ {},\n{nl} {},\n{nl}", ty, own_ty, nl = newline)?;
+        TypedPattern::Adt(_, ty, own_ty, name, items) => {
+            write!(f, "{nl}Adt {{ {}, {},\n{nl} {},\n{nl}", name, ty, own_ty, nl = newline)?;
             for item in items {
                 print_pattern(f, item, indent + 1)?;
                 write!(f, ",\n{nl}", nl = newline)?;
@@ -9,6 +9,7 @@ pub mod format;
 pub mod expression_fold;
 pub mod visitors;
 pub mod sort;
+pub mod persistent_list;
 
 #[cfg(test)]
 macro_rules! assert_ok {
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Immutable singly linked list, the nodes are shared between all the lists that contain them,
+/// so adding an element at the front or taking the tail don't copy anything
+pub struct List<T> {
+    head: Option<Arc<Node<T>>>,
+    len: usize,
+}
+
+struct Node<T> {
+    value: T,
+    next: Option<Arc<Node<T>>>,
+}
+
+pub struct Iter<'a, T: 'a> {
+    next: Option<&'a Node<T>>,
+    len: usize,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List { head: None, len: 0 }
+    }
+
+    /// Returns a new list with `value` as first element followed by the elements of this list
+    pub fn prepend(&self, value: T) -> Self {
+        List {
+            head: Some(Arc::new(Node { value, next: self.head.clone() })),
+            len: self.len + 1,
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    /// The list without its first element
+    pub fn tail(&self) -> Option<Self> {
+        self.head.as_ref().map(|node| List { head: node.next.clone(), len: self.len - 1 })
+    }
+
+    pub fn split_first(&self) -> Option<(&T, Self)> {
+        self.head.as_ref().map(|node| (&node.value, List { head: node.next.clone(), len: self.len - 1 }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        Iter { next: self.head.as_ref().map(|node| node.as_ref()), len: self.len }
+    }
+}
+
+impl<T: Clone> List<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List { head: self.head.clone(), len: self.len }
+    }
+}
+
+// The default drop is recursive and overflows the stack with long lists
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut next = self.head.take();
+
+        while let Some(node) = next {
+            match Arc::try_unwrap(node) {
+                Ok(mut node) => next = node.next.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        List::new()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.next.map(|node| {
+            self.next = node.next.as_ref().map(|node| node.as_ref());
+            self.len -= 1;
+            &node.value
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
+        let items = iter.into_iter().collect::<Vec<_>>();
+        List::from(items)
+    }
+}
+
+impl<T> From<Vec<T>> for List<T> {
+    fn from(items: Vec<T>) -> Self {
+        let mut list = List::new();
+
+        for item in items.into_iter().rev() {
+            list = list.prepend(item);
+        }
+
+        list
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &List<T>) -> bool {
+        self.len == other.len && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for item in self {
+            item.hash(state);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Serialize> Serialize for List<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        Vec::<T>::deserialize(deserializer).map(List::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_shared_tail() {
+        let tail = List::from(vec![2, 3]);
+        let list = tail.prepend(1);
+
+        assert_eq!(list.to_vec(), vec![1, 2, 3]);
+        assert_eq!(list.head(), Some(&1));
+        assert_eq!(list.tail(), Some(tail.clone()));
+        assert_eq!(list.len(), 3);
+        assert_eq!(tail.len(), 2);
+        assert_eq!(List::<i32>::new().tail(), None);
+    }
+
+    #[test]
+    fn check_drop_long_list() {
+        let list = (0..1_000_000).collect::<List<_>>();
+
+        assert_eq!(list.iter().count(), 1_000_000);
+        drop(list);
+    }
+}
@@ -92,4 +92,94 @@ pub fn sort_dependencies(graph: HashMap<String, Vec<String>>) -> Result<Vec<Stri
     }
 
     Ok(res)
-}
\ No newline at end of file
+}
+/// Groups the nodes of a graph in strongly connected components, `edges[node]` are the nodes
+/// it depends on. The components are sorted so every component comes after the components it
+/// depends on, the rest of the nodes keep their order when possible
+pub fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut tarjan = Tarjan {
+        edges,
+        index: vec![None; edges.len()],
+        low_link: vec![0; edges.len()],
+        on_stack: vec![false; edges.len()],
+        stack: vec![],
+        next_index: 0,
+        components: vec![],
+    };
+
+    for node in 0..edges.len() {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+
+    tarjan.components
+}
+
+struct Tarjan<'a> {
+    edges: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, node: usize) {
+        self.index[node] = Some(self.next_index);
+        self.low_link[node] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for &dep in &self.edges[node] {
+            match self.index[dep] {
+                None => {
+                    self.visit(dep);
+                    self.low_link[node] = self.low_link[node].min(self.low_link[dep]);
+                }
+                Some(index) if self.on_stack[dep] => {
+                    self.low_link[node] = self.low_link[node].min(index);
+                }
+                _ => {}
+            }
+        }
+
+        if Some(self.low_link[node]) == self.index[node] {
+            let mut component = vec![];
+
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+
+                if member == node {
+                    break;
+                }
+            }
+
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_strongly_connected_components() {
+        // 0 -> 2, 1 <-> 3, 3 -> 0, 4 alone
+        let edges = vec![vec![2], vec![3], vec![], vec![1, 0], vec![]];
+
+        assert_eq!(strongly_connected_components(&edges), vec![
+            vec![2],
+            vec![0],
+            vec![1, 3],
+            vec![4],
+        ]);
+    }
+}